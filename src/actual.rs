@@ -0,0 +1,116 @@
+//! Uploading transactions to a self-hosted Actual Budget server.
+//!
+//! Actual has no HTTP API of its own, so transactions are imported through
+//! [actual-http-api](https://github.com/jhonderson/actual-http-api), which
+//! runs next to the Actual server. Each account's transactions are imported
+//! with one request, and get the same import ids as YNAB uploads as their
+//! `imported_id`, so Actual skips transactions that were already imported.
+
+use crate::{
+    http,
+    json::{self, Value},
+    parse_nda_date,
+    ynab::{import_ids, AccountBatch, AccountResult},
+};
+use std::{collections::HashMap, error::Error};
+
+/// Connection to an actual-http-api server
+pub struct Server<'a> {
+    /// Address of the server, e.g. `http://localhost:5007`
+    pub url: &'a str,
+    pub api_key: &'a str,
+    /// Sync id of the budget, shown in Actual's advanced settings
+    pub budget_id: &'a str,
+}
+
+/// Imports the transactions of each batch, whose account ids are Actual
+/// account ids. Returns the results keyed by account id, failing on the first
+/// account that could not be imported.
+pub fn upload(
+    server: &Server,
+    batches: &[AccountBatch],
+) -> Result<HashMap<String, AccountResult>, Box<dyn Error>> {
+    let mut results = HashMap::new();
+    for batch in batches {
+        let ids = import_ids(batch.rows)?;
+        let transactions = batch
+            .rows
+            .iter()
+            .zip(ids)
+            .map(|(r, imported_id)| {
+                let date = parse_nda_date(&r.date)?;
+                Ok(Value::object([
+                    ("account", batch.account_id.into()),
+                    ("date", date.format("%Y-%m-%d").to_string().into()),
+                    ("amount", r.amount.cents().into()),
+                    ("payee_name", r.payee.as_str().into()),
+                    ("notes", r.memo.as_str().into()),
+                    ("imported_id", imported_id.into()),
+                    ("cleared", (!r.pending).into()),
+                ]))
+            })
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+
+        let mut result = AccountResult::default();
+        if !transactions.is_empty() {
+            let count = transactions.len();
+            let added = import(server, batch.account_id, transactions)?;
+            result.created = added;
+            result.duplicates = count.saturating_sub(added);
+        }
+        results.insert(batch.account_id.to_string(), result);
+    }
+    Ok(results)
+}
+
+/// Imports transactions into an account, returning the number of
+/// transactions added.
+fn import(
+    server: &Server,
+    account_id: &str,
+    transactions: Vec<Value>,
+) -> Result<usize, Box<dyn Error>> {
+    let body = Value::object([("transactions", Value::Array(transactions))]);
+    let response = http::request(
+        "POST",
+        &format!(
+            "{}/v1/budgets/{}/accounts/{}/transactions/import",
+            server.url.trim_end_matches('/'),
+            http::encode(server.budget_id),
+            http::encode(account_id)
+        ),
+        &[
+            format!("x-api-key: {}", server.api_key),
+            "Content-Type: application/json".to_string(),
+        ],
+        Some(&body.to_string()),
+    )?;
+
+    let json =
+        json::parse(&response.body).map_err(|e| format!("Invalid response from Actual: {}", e))?;
+    if !response.is_success() {
+        let detail = json
+            .get("error")
+            .and_then(Value::as_str)
+            .unwrap_or("unknown error");
+        return Err(format!("Actual API error ({}): {}", response.status, detail).into());
+    }
+
+    let data = json.get("data").ok_or("Invalid response from Actual")?;
+    if let Some(error) = data
+        .get("errors")
+        .and_then(Value::as_array)
+        .and_then(|errors| errors.first())
+    {
+        let message = error
+            .get("message")
+            .and_then(Value::as_str)
+            .unwrap_or("unknown error");
+        return Err(format!("Actual could not import the transactions: {}", message).into());
+    }
+
+    Ok(data
+        .get("added")
+        .and_then(Value::as_array)
+        .map_or(0, <[Value]>::len))
+}
@@ -0,0 +1,67 @@
+//! Aggregation of small purchases.
+//!
+//! Card payments below a threshold, e.g. coffee and bus tickets, are merged
+//! into a single transaction per day with the payee [`PAYEE`], so that they
+//! do not clutter the register. The original payees and amounts are listed
+//! in the memo.
+
+use crate::{amount::Amount, YnabRow};
+use std::collections::HashMap;
+
+/// Payee of the aggregated transactions
+pub const PAYEE: &str = "Misc small purchases";
+
+/// Merges the outflows smaller than `below` of each day into one row, in the
+/// place of the first of them. Days with a single small outflow are left as
+/// they are. Returns the number of rows merged.
+pub fn aggregate_small(rows: &mut Vec<YnabRow>, below: Amount) -> usize {
+    let is_small = |r: &YnabRow| r.amount.is_negative() && -r.amount < below;
+    let mut per_day: HashMap<String, usize> = HashMap::new();
+    for r in rows.iter().filter(|r| is_small(r)) {
+        *per_day.entry(r.date.clone()).or_default() += 1;
+    }
+
+    let mut merged = 0;
+    // Position of the aggregated row of each day
+    let mut aggregates: HashMap<String, usize> = HashMap::new();
+    let mut out = Vec::with_capacity(rows.len());
+    for r in rows.drain(..) {
+        if !is_small(&r) || per_day[&r.date] < 2 {
+            out.push(r);
+            continue;
+        }
+
+        merged += 1;
+        let item = format!("{} {}", r.payee, r.amount);
+        match aggregates.get(&r.date) {
+            Some(&i) => {
+                let aggregate: &mut YnabRow = &mut out[i];
+                aggregate.amount = Amount::from_cents(aggregate.amount.cents() + r.amount.cents());
+                aggregate.memo = format!("{}, {}", aggregate.memo, item);
+                // The category is only kept if all of them had the same one
+                if aggregate.category != r.category {
+                    aggregate.category = None;
+                }
+                // Uncleared until all of them are booked
+                aggregate.pending |= r.pending;
+            }
+            None => {
+                aggregates.insert(r.date.clone(), out.len());
+                out.push(YnabRow {
+                    date: r.date,
+                    payee: PAYEE.to_string(),
+                    memo: item,
+                    amount: r.amount,
+                    category: r.category,
+                    flag: None,
+                    reference: String::new(),
+                    pending: r.pending,
+                    earlier_occurrences: 0,
+                });
+            }
+        }
+    }
+
+    *rows = out;
+    merged
+}
@@ -0,0 +1,174 @@
+//! Parsing and formatting of monetary amounts.
+//!
+//! Amounts are stored as a whole number of cents. Parsing accepts both comma
+//! and period decimal separators, thousands separators (period, comma, space,
+//! non-breaking space or apostrophe) and leading or trailing signs, and
+//! rejects anything that cannot be represented exactly. A lone separator
+//! followed by exactly three digits, as in "1.000", is rejected as ambiguous
+//! unless the decimal separator of the export's locale is known.
+
+use std::{
+    fmt,
+    ops::{Add, AddAssign, Neg},
+    str::FromStr,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Amount {
+    cents: i64,
+}
+
+impl Amount {
+    pub fn from_cents(cents: i64) -> Amount {
+        Amount { cents }
+    }
+
+    pub fn cents(&self) -> i64 {
+        self.cents
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.cents < 0
+    }
+
+    /// Parses `s` like [`str::parse`], reading an ambiguous amount like
+    /// "1.000" by `decimal`, the decimal separator of the export's locale,
+    /// if known.
+    pub fn parse_with_decimal(s: &str, decimal: Option<char>) -> Result<Amount, String> {
+        let (negative, digits) = split_sign(s);
+        if decimal.is_none() && is_ambiguous(&digits) {
+            return Err(format!(
+                "Ambiguous amount '{}', the separator may be a decimal or a thousands separator",
+                s
+            ));
+        }
+        let amount =
+            parse(negative, &digits, decimal).ok_or_else(|| format!("Invalid amount '{}'", s))?;
+
+        // Make sure nothing was lost in the conversion
+        let (negative, digits) = split_sign(&amount.to_string());
+        if parse(negative, &digits, None) != Some(amount) {
+            return Err(format!("Amount '{}' does not round-trip", s));
+        }
+
+        Ok(amount)
+    }
+}
+
+impl Neg for Amount {
+    type Output = Amount;
+
+    fn neg(self) -> Amount {
+        Amount { cents: -self.cents }
+    }
+}
+
+impl Add for Amount {
+    type Output = Amount;
+
+    fn add(self, other: Amount) -> Amount {
+        Amount {
+            cents: self.cents + other.cents,
+        }
+    }
+}
+
+impl AddAssign for Amount {
+    fn add_assign(&mut self, other: Amount) {
+        self.cents += other.cents;
+    }
+}
+
+/// Formats the amount with a period decimal separator and two decimals, e.g.
+/// "-1234.50", which is what YNAB's CSV importer expects.
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.cents < 0 { "-" } else { "" };
+        let abs = self.cents.unsigned_abs();
+        // Padded as a whole, so that amounts line up in columns
+        f.pad(&format!("{}{}.{:02}", sign, abs / 100, abs % 100))
+    }
+}
+
+impl FromStr for Amount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Amount, String> {
+        Amount::parse_with_decimal(s, None)
+    }
+}
+
+/// Returns whether `s` is negative, and its digits and separators without the
+/// sign and spaces.
+fn split_sign(s: &str) -> (bool, String) {
+    let s: String = s
+        .chars()
+        .filter(|c| !c.is_whitespace() && !matches!(c, '\u{a0}' | '\u{202f}' | '\''))
+        .map(|c| if c == '\u{2212}' { '-' } else { c })
+        .collect();
+
+    if let Some(rest) = s.strip_prefix('-') {
+        (true, rest.to_string())
+    } else if let Some(rest) = s.strip_suffix('-') {
+        (true, rest.to_string())
+    } else {
+        (false, s.strip_prefix('+').unwrap_or(&s).to_string())
+    }
+}
+
+/// Returns whether the only separator of `s` is followed by exactly three
+/// digits, so that "1.000" could be one or a thousand. A leading zero, or
+/// more than three digits before it, cannot be thousands.
+fn is_ambiguous(s: &str) -> bool {
+    let Some(pos) = s.find([',', '.']) else {
+        return false;
+    };
+    let (integer, fraction) = (&s[..pos], &s[pos + 1..]);
+    (1..=3).contains(&integer.len())
+        && !integer.starts_with('0')
+        && integer.chars().all(|c| c.is_ascii_digit())
+        && fraction.len() == 3
+        && fraction.chars().all(|c| c.is_ascii_digit())
+}
+
+fn parse(negative: bool, s: &str, decimal: Option<char>) -> Option<Amount> {
+    // The last separator is the decimal separator, unless the same separator
+    // appears several times, in which case it separates thousands, or it is
+    // known to be the thousands separator of an ambiguous amount
+    let decimal_pos = s.rfind([',', '.']).filter(|&pos| {
+        let separator = s[pos..].chars().next().unwrap();
+        s.matches(separator).count() == 1
+            && !(is_ambiguous(s) && decimal.is_some_and(|d| d != separator))
+    });
+    let (integer, fraction) = match decimal_pos {
+        Some(pos) => (&s[..pos], &s[pos + 1..]),
+        None => (s, ""),
+    };
+
+    let integer: String = integer.chars().filter(|&c| c != ',' && c != '.').collect();
+    if integer.is_empty() && fraction.is_empty() {
+        return None;
+    }
+    if !integer.chars().all(|c| c.is_ascii_digit()) || !fraction.chars().all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+
+    // Only allow more than two decimals if the extra digits are zeros
+    let (fraction, extra) = fraction.split_at(fraction.len().min(2));
+    if extra.chars().any(|c| c != '0') {
+        return None;
+    }
+
+    let whole: i64 = if integer.is_empty() {
+        0
+    } else {
+        integer.parse().ok()?
+    };
+    let fraction: i64 = format!("{:0<2}", fraction).parse().ok()?;
+    let cents = whole.checked_mul(100)?.checked_add(fraction)?;
+
+    Some(Amount {
+        cents: if negative { -cents } else { cents },
+    })
+}
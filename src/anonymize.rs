@@ -0,0 +1,265 @@
+//! Anonymizing Nordea exports with `nda2ynab anonymize`, so that they can be
+//! attached to bug reports.
+//!
+//! Payees, messages, references, account numbers and amounts are replaced,
+//! while the dates, the columns, the delimiter and the lines above the header
+//! are kept, so that the anonymized export is read the same way as the
+//! original. Letters are replaced with letters and digits with digits, and
+//! amounts keep their sign and separators. A value is replaced the same way
+//! wherever it appears in the files anonymized together, so identical rows
+//! and the overlap of consecutive exports are kept too. The replacements are
+//! keyed by a random secret of each run, so they cannot be reversed by trying
+//! out values. Balances are replaced like amounts and no longer add up.
+
+use csv::{ReaderBuilder, StringRecord, Terminator, WriterBuilder};
+use nda2ynab::{
+    amount::Amount,
+    encoding::{self, Encoding},
+    iban_pattern, info,
+    locale::Locale,
+    parser,
+};
+use regex::Regex;
+use std::{
+    collections::hash_map::RandomState,
+    error::Error,
+    fs,
+    hash::{BuildHasher, Hash, Hasher},
+    path::PathBuf,
+};
+
+#[derive(clap::Args, Debug)]
+pub struct AnonymizeArgs {
+    /// Nordea CSV exports to anonymize, all with the same replacements
+    #[clap(required = true)]
+    files: Vec<PathBuf>,
+
+    /// Directory to write the anonymized exports to, named like the
+    /// originals with the IBAN replaced
+    #[clap(short, long, value_name = "DIR", default_value = ".")]
+    output_dir: PathBuf,
+
+    /// Text encoding of the exports [default: auto]
+    #[clap(short, long, arg_enum)]
+    encoding: Option<Encoding>,
+}
+
+pub fn run(args: &AnonymizeArgs) -> Result<(), Box<dyn Error>> {
+    let scrambler = Scrambler::new();
+    for path in &args.files {
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| format!("{} is not a file", path.display()))?
+            .to_string_lossy();
+        let bytes =
+            fs::read(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let contents = encoding::decode(&bytes, args.encoding.unwrap_or(Encoding::Auto))?;
+        let anonymized = anonymize(&contents, &scrambler)
+            .map_err(|e| format!("Could not anonymize {}: {}", path.display(), e))?;
+
+        let output = args.output_dir.join(scrambler.file_name(&file_name));
+        if output.exists() {
+            return Err(format!("{} already exists, remove it first", output.display()).into());
+        }
+        fs::write(&output, anonymized)
+            .map_err(|e| format!("Could not write {}: {}", output.display(), e))?;
+        info!("{} anonymized to {}.", path.display(), output.display());
+    }
+    Ok(())
+}
+
+/// What a column holds, by the header it is translated to
+enum Column {
+    /// Dates and the currency are kept
+    Kept,
+    Amount,
+    Account,
+    Text,
+}
+
+impl Column {
+    fn of(field: &str) -> Column {
+        match field {
+            "date" | "value_date" | "payment_date" | "currency" | "original_currency" => {
+                Column::Kept
+            }
+            "amount" | "balance" | "original_amount" => Column::Amount,
+            "counterparty_account" => Column::Account,
+            _ => Column::Text,
+        }
+    }
+}
+
+/// Returns `contents` of a Nordea CSV export with its values replaced.
+fn anonymize(contents: &str, scrambler: &Scrambler) -> Result<String, Box<dyn Error>> {
+    let layout = parser::sniff_csv(contents, |header| Locale::detect(header).is_some())
+        .ok_or("it does not have the header row of a Nordea export")?;
+    let line_ending = if contents.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+
+    let mut out = String::new();
+    for line in contents[..layout.start].lines() {
+        out += &scrambler.text(line);
+        out += line_ending;
+    }
+
+    let mut rdr = ReaderBuilder::new()
+        .delimiter(layout.delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(&contents.as_bytes()[layout.start..]);
+    let mut records = rdr.records();
+    let header = records.next().ok_or("it has no header row")??;
+    let locale = Locale::detect(&header).ok_or("its columns were not recognised")?;
+    let columns: Vec<Column> = locale
+        .normalize_header(&header)
+        .iter()
+        .map(Column::of)
+        .collect();
+
+    let mut wtr = WriterBuilder::new()
+        .delimiter(layout.delimiter)
+        .flexible(true)
+        .terminator(match line_ending {
+            "\r\n" => Terminator::CRLF,
+            _ => Terminator::Any(b'\n'),
+        })
+        .from_writer(vec![]);
+    wtr.write_record(&header)?;
+    for record in records {
+        let record = record?;
+        let anonymized: StringRecord = record
+            .iter()
+            .enumerate()
+            .map(|(i, value)| match columns.get(i).unwrap_or(&Column::Text) {
+                _ if value.trim().is_empty() => value.to_string(),
+                Column::Kept => value.to_string(),
+                Column::Amount => scrambler.amount(value),
+                Column::Account => scrambler.ibans(value),
+                Column::Text => scrambler.text(value),
+            })
+            .collect();
+        wtr.write_record(&anonymized)?;
+    }
+    out += &String::from_utf8(wtr.into_inner().map_err(|e| e.to_string())?)?;
+    Ok(out)
+}
+
+/// Replaces values by keyed hashes of them
+struct Scrambler {
+    keys: RandomState,
+    iban: Regex,
+}
+
+impl Scrambler {
+    fn new() -> Scrambler {
+        Scrambler {
+            keys: RandomState::new(),
+            iban: Regex::new(&iban_pattern(None)).unwrap(),
+        }
+    }
+
+    /// A stream of pseudo-random numbers seeded by `value`
+    fn stream(&self, kind: &str, value: &impl Hash) -> impl FnMut() -> u64 {
+        let mut hasher = self.keys.build_hasher();
+        kind.hash(&mut hasher);
+        value.hash(&mut hasher);
+        let mut state = hasher.finish();
+        // splitmix64
+        move || {
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        }
+    }
+
+    /// Replaces the letters and digits of `value`, keeping their case and
+    /// the other characters.
+    fn text(&self, value: &str) -> String {
+        let mut next = self.stream("text", &value);
+        value.chars().map(|c| replace_char(c, next())).collect()
+    }
+
+    /// Replaces the digits of an amount, keeping its sign and separators.
+    /// Amounts of the same size are replaced the same way, also when written
+    /// with other separators.
+    fn amount(&self, value: &str) -> String {
+        let Ok(amount) = value.parse::<Amount>() else {
+            return self.text(value);
+        };
+        let mut next = self.stream("amount", &amount.cents().unsigned_abs());
+        let mut leading = true;
+        value
+            .chars()
+            .map(|c| match c.to_digit(10) {
+                // Without leading zeros, so that the amount is read alike
+                Some(d) if leading && d != 0 => {
+                    leading = false;
+                    char::from(b'1' + (next() % 9) as u8)
+                }
+                Some(_) if leading => c,
+                Some(_) => char::from(b'0' + (next() % 10) as u8),
+                None => c,
+            })
+            .collect()
+    }
+
+    /// Replaces an IBAN, keeping its country code and spaces. IBANs are
+    /// replaced the same way with or without spaces.
+    fn iban(&self, iban: &str) -> String {
+        let compact: String = iban.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut next = self.stream("iban", &compact.to_uppercase());
+        let mut position = 0;
+        iban.chars()
+            .map(|c| {
+                if c.is_whitespace() {
+                    return c;
+                }
+                position += 1;
+                let random = next();
+                if position <= 2 {
+                    c
+                } else {
+                    replace_char(c, random)
+                }
+            })
+            .collect()
+    }
+
+    /// Replaces the IBANs in `value`, or all of it if it has none.
+    fn ibans(&self, value: &str) -> String {
+        if self.iban.is_match(value) {
+            self.iban
+                .replace_all(value, |captures: &regex::Captures| self.iban(&captures[0]))
+                .to_string()
+        } else {
+            self.text(value)
+        }
+    }
+
+    /// Replaces the IBAN in an export file name.
+    fn file_name(&self, file_name: &str) -> String {
+        self.iban
+            .replace_all(file_name, |captures: &regex::Captures| {
+                self.iban(&captures[0])
+            })
+            .to_string()
+    }
+}
+
+fn replace_char(c: char, random: u64) -> char {
+    if c.is_ascii_digit() {
+        char::from(b'0' + (random % 10) as u8)
+    } else if c.is_uppercase() {
+        char::from(b'A' + (random % 26) as u8)
+    } else if c.is_alphabetic() {
+        char::from(b'a' + (random % 26) as u8)
+    } else {
+        c
+    }
+}
@@ -0,0 +1,139 @@
+//! Appending converted rows to a master CSV file with `--append`.
+//!
+//! The master file is created with a header row on the first run, and later
+//! runs append their rows to it. Rows identical to a row already in the
+//! file are skipped, so that converting an export again adds nothing. Import
+//! ids are numbered per conversion, so an appended row whose import id is
+//! already taken by another transaction of the same date and amount gets the
+//! next free one, as it would have when converted together with it. The
+//! file is rewritten atomically with the rows appended, so that a failed run
+//! does not leave a half-written row at its end.
+
+use crate::{
+    atomic::{self, AtomicFile},
+    columns::{Account, Column},
+    write_ynab_csv, CsvOptions, YnabRow,
+};
+use csv::{ReaderBuilder, WriterBuilder};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs,
+    io::Write,
+    path::Path,
+};
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Appended {
+    pub written: usize,
+    /// Rows left out as already in the file
+    pub duplicates: usize,
+}
+
+/// Appends the rows of `account` to the YNAB CSV file at `path`, creating it
+/// if it does not exist or is empty. The columns of the file must be the
+/// ones of `opts`.
+pub fn append_ynab_csv(
+    path: &Path,
+    rows: &[YnabRow],
+    opts: &CsvOptions,
+    account: &Account,
+) -> Result<Appended, Box<dyn Error>> {
+    let mut new = vec![];
+    write_ynab_csv(rows, opts, account, &mut new)?;
+
+    let existing = match fs::read(path) {
+        Ok(existing) if !existing.is_empty() => existing,
+        _ => {
+            atomic::write(path, &new)
+                .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+            return Ok(Appended {
+                written: rows.len(),
+                duplicates: 0,
+            });
+        }
+    };
+
+    let (header, records) = read_records(&new)?;
+    let (master_header, master) =
+        read_records(&existing).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    if master_header != header {
+        return Err(format!(
+            "The columns of {} are {}, not {} as would be written",
+            path.display(),
+            master_header.join(", "),
+            header.join(", ")
+        )
+        .into());
+    }
+
+    let import_id = opts.schema().iter().position(|&c| c == Column::ImportId);
+    let mut taken: HashSet<String> = import_id
+        .map(|i| master.iter().map(|r| r[i].clone()).collect())
+        .unwrap_or_default();
+    let mut remaining: HashMap<Record, usize> = HashMap::new();
+    for record in master {
+        *remaining.entry(record).or_default() += 1;
+    }
+
+    let mut appended = vec![];
+    let mut duplicates = 0;
+    for mut record in records {
+        if let Some(count) = remaining.get_mut(&record).filter(|count| **count > 0) {
+            *count -= 1;
+            duplicates += 1;
+            continue;
+        }
+        if let Some(i) = import_id {
+            while taken.contains(&record[i]) {
+                match next_occurrence(&record[i]) {
+                    Some(next) => record[i] = next,
+                    None => break,
+                }
+            }
+            taken.insert(record[i].clone());
+        }
+        appended.push(record);
+    }
+
+    let error = |e| format!("Could not write {}: {}", path.display(), e);
+    let mut file = AtomicFile::create(path).map_err(error)?;
+    file.write_all(&existing).map_err(error)?;
+    if !existing.ends_with(b"\n") {
+        file.write_all(b"\n").map_err(error)?;
+    }
+    let mut wtr = WriterBuilder::new().from_writer(file);
+    for record in &appended {
+        wtr.write_record(record)?;
+    }
+    wtr.into_inner()
+        .map_err(|e| e.to_string())?
+        .commit()
+        .map_err(error)?;
+
+    Ok(Appended {
+        written: appended.len(),
+        duplicates,
+    })
+}
+
+type Record = Vec<String>;
+
+/// Reads the header and the records of a CSV file.
+fn read_records(csv: &[u8]) -> Result<(Record, Vec<Record>), Box<dyn Error>> {
+    let mut reader = ReaderBuilder::new().from_reader(csv);
+    let header = reader.headers()?.iter().map(String::from).collect();
+    let records = reader
+        .records()
+        .map(|r| Ok(r?.iter().map(String::from).collect()))
+        .collect::<Result<_, csv::Error>>()?;
+    Ok((header, records))
+}
+
+/// Returns the import id of the next transaction of the same date and amount,
+/// e.g. `YNAB:-4000:2024-01-04:2` for `YNAB:-4000:2024-01-04:1`.
+fn next_occurrence(import_id: &str) -> Option<String> {
+    let (prefix, occurrence) = import_id.rsplit_once(':')?;
+    let occurrence: usize = occurrence.parse().ok()?;
+    Some(format!("{}:{}", prefix, occurrence + 1))
+}
@@ -0,0 +1,131 @@
+//! Archiving of processed exports.
+//!
+//! After a successful export, the consumed Nordea files are moved (or copied)
+//! into an archive directory under a normalized name such as
+//! `FI1234567890123456-2022-03-31_12.30.00.csv`, keeping the extension of the
+//! original file. Archived files are still
+//! considered when looking for the previously processed file.
+//!
+//! Another export of the same account and time, such as a repeated download,
+//! is archived with a number after the date, e.g.
+//! `FI1234567890123456-2022-03-31_12.30.00-2.csv`, unless it has the same
+//! contents as the one archived before.
+
+use crate::{group_iban, info, system::FileSystem, ParsedFileName};
+use chrono::NaiveDateTime;
+use regex::Regex;
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+const DATE_FORMAT: &str = "%Y-%m-%d_%H.%M.%S";
+
+pub fn archived_name(file: &ParsedFileName) -> String {
+    numbered_name(file, 1)
+}
+
+/// The archived name of the `number`th export of the same account and time
+fn numbered_name(file: &ParsedFileName, number: usize) -> String {
+    let extension = file
+        .path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("csv");
+    let suffix = match number {
+        1 => String::new(),
+        n => format!("-{}", n),
+    };
+
+    format!(
+        "{}-{}{}.{}",
+        file.iban.replace(' ', ""),
+        file.date.format(DATE_FORMAT),
+        suffix,
+        extension
+    )
+}
+
+/// Parses an archived file name into the IBAN, in the usual groups of four
+/// characters, and the export date.
+pub fn parse_archived_name(file_name: &str) -> Option<(String, NaiveDateTime)> {
+    let re = Regex::new(
+        r"^([A-Z]{2}\d{2}[0-9A-Z]+)-(\d{4}-\d{2}-\d{2}_\d{2}\.\d{2}\.\d{2})(?:-\d+)?\.(csv|xml)$",
+    )
+    .unwrap();
+    let captures = re.captures(file_name)?;
+
+    let iban = group_iban(captures.get(1)?.as_str());
+    let date = NaiveDateTime::parse_from_str(captures.get(2)?.as_str(), DATE_FORMAT).ok()?;
+
+    Some((iban, date))
+}
+
+/// Moves `files` into `dir`, or copies them if `copy` is set. Files that are
+/// already in the archive are left alone, and files with the same contents as
+/// an archived one are only removed when moving.
+pub fn archive_files(
+    file_system: &dyn FileSystem,
+    files: &[&ParsedFileName],
+    dir: &Path,
+    copy: bool,
+) -> Result<(), Box<dyn Error>> {
+    file_system.create_dir_all(dir).map_err(|e| {
+        format!(
+            "Could not create archive directory {}: {}",
+            dir.display(),
+            e
+        )
+    })?;
+
+    for file in files {
+        if file.path.parent() == Some(dir) {
+            continue;
+        }
+
+        let error = |e| format!("Could not archive {}: {}", file.file_name, e);
+        let Some(target) = target(file_system, file, dir).map_err(error)? else {
+            if !copy {
+                file_system.remove_file(&file.path).map_err(error)?;
+            }
+            info!("{} is already archived", file.file_name);
+            continue;
+        };
+        let result = if copy {
+            file_system.copy(&file.path, &target)
+        } else {
+            // Fall back to copying if the archive is on another file system
+            file_system.rename(&file.path, &target).or_else(|_| {
+                file_system
+                    .copy(&file.path, &target)
+                    .and_then(|_| file_system.remove_file(&file.path))
+            })
+        };
+
+        result.map_err(error)?;
+        info!("Archived {} as {}", file.file_name, target.display());
+    }
+
+    Ok(())
+}
+
+/// Returns the first free archived name of `file` in `dir`, or `None` if a
+/// file with the same contents is archived under one of the names taken.
+fn target(
+    file_system: &dyn FileSystem,
+    file: &ParsedFileName,
+    dir: &Path,
+) -> std::io::Result<Option<PathBuf>> {
+    let contents = file_system.read(&file.path)?;
+    let mut number = 1;
+    loop {
+        let target = dir.join(numbered_name(file, number));
+        if !file_system.exists(&target) {
+            return Ok(Some(target));
+        }
+        if file_system.read(&target)? == contents {
+            return Ok(None);
+        }
+        number += 1;
+    }
+}
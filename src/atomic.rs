@@ -0,0 +1,120 @@
+//! Writing files atomically.
+//!
+//! Outputs and the state file are written to a temporary file next to them,
+//! which is synced to disk and then renamed over the target. A run that
+//! crashes, is killed or runs out of disk space in the middle of writing
+//! leaves the previous file as it was, rather than a truncated one that YNAB
+//! would partially import. The temporary file is removed if the write is not
+//! committed.
+
+use std::{
+    error::Error,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// A file being written, which replaces the file at its path on
+/// [`AtomicFile::commit`].
+#[derive(Debug)]
+pub struct AtomicFile {
+    path: PathBuf,
+    temp: PathBuf,
+    /// `None` once committed
+    file: Option<File>,
+}
+
+impl AtomicFile {
+    /// Starts writing the file at `path`.
+    pub fn create(path: &Path) -> io::Result<AtomicFile> {
+        AtomicFile::open(path, false)
+    }
+
+    /// Starts writing the file at `path`, readable and writable by the user
+    /// only, for files holding tokens.
+    pub fn create_private(path: &Path) -> io::Result<AtomicFile> {
+        AtomicFile::open(path, true)
+    }
+
+    fn open(path: &Path, private: bool) -> io::Result<AtomicFile> {
+        let temp = temp_path(path);
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        if private {
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        }
+        #[cfg(not(unix))]
+        let _ = private;
+        let file = options.open(&temp)?;
+        Ok(AtomicFile {
+            path: path.to_path_buf(),
+            temp,
+            file: Some(file),
+        })
+    }
+
+    /// Syncs what was written to disk and replaces the file with it.
+    pub fn commit(mut self) -> io::Result<()> {
+        let file = self.file.take().expect("not committed");
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&self.temp, &self.path)
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.as_mut().expect("not committed").write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.as_mut().expect("not committed").flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.temp);
+        }
+    }
+}
+
+/// Replaces the file at `path` with `contents`.
+pub fn write(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = AtomicFile::create(path)?;
+    file.write_all(contents)?;
+    file.commit()
+}
+
+/// Checks that files can be created in the directory of each of `paths`, so
+/// that a read-only directory fails the run before anything is written or
+/// uploaded.
+pub fn check_writable<P: AsRef<Path>>(paths: &[P]) -> Result<(), Box<dyn Error>> {
+    for path in paths {
+        let path = path.as_ref();
+        let probe = temp_path(path);
+        File::create(&probe)
+            .and_then(|_| fs::remove_file(&probe))
+            .map_err(|e| {
+                let dir = match path.parent().filter(|d| !d.as_os_str().is_empty()) {
+                    Some(dir) => dir.display().to_string(),
+                    None => ".".to_string(),
+                };
+                format!("Cannot write {} to {}: {}", path.display(), dir, e)
+            })?;
+        if fs::metadata(path).is_ok_and(|m| m.permissions().readonly()) {
+            return Err(format!("Cannot write {}: the file is read-only", path.display()).into());
+        }
+    }
+    Ok(())
+}
+
+/// Hidden temporary file next to `path`, unique to this process
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map_or("output".into(), |n| n.to_string_lossy());
+    path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()))
+}
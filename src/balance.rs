@@ -0,0 +1,89 @@
+//! Checking the running balance of exports that have a balance column.
+//!
+//! Nordea's detailed exports have the balance of the account after each
+//! transaction. The rows are newest first, so the balance of a row less its
+//! amount must be the balance of the row below it. A row that does not add
+//! up means that a transaction is missing from the export, or that an amount
+//! was read wrong. The balance of the newest row is the balance at the end
+//! of the export, which can be compared with the balance in YNAB.
+
+use crate::{amount::Amount, Transaction, DATE_FORMAT};
+use chrono::NaiveDate;
+use itertools::Itertools;
+
+/// Number of rows that do not add up listed in warnings
+const MAX_LISTED: usize = 5;
+
+/// A row whose balance is not the balance of the row above it less that
+/// row's amount
+#[derive(Debug, PartialEq)]
+pub struct Mismatch {
+    pub date: NaiveDate,
+    pub description: String,
+    pub balance: Amount,
+    /// Balance the row should have by the row above it
+    pub expected: Amount,
+}
+
+/// The balance column of an export, given its rows one at a time.
+#[derive(Debug, Default)]
+pub struct BalanceCheck {
+    /// Balance of the newest row, if any row had a balance
+    pub ending: Option<Amount>,
+    /// Balance before the previous row given
+    expected: Option<Amount>,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl BalanceCheck {
+    /// Checks the balance of `row`. The rows must be given in their original
+    /// order, newest first. Rows without a balance are skipped.
+    pub fn add(&mut self, row: &Transaction) {
+        let Some(balance) = row.balance else {
+            return;
+        };
+        self.ending.get_or_insert(balance);
+        if let Some(expected) = self.expected.filter(|expected| *expected != balance) {
+            self.mismatches.push(Mismatch {
+                date: row.date,
+                description: row.description.clone(),
+                balance,
+                expected,
+            });
+        }
+        self.expected = Some(balance + -row.amount);
+    }
+
+    /// Returns the warning about the rows of `file_name` that do not add up,
+    /// if any.
+    pub fn warning(&self, file_name: &str) -> Option<String> {
+        if self.mismatches.is_empty() {
+            return None;
+        }
+        let listed = self
+            .mismatches
+            .iter()
+            .take(MAX_LISTED)
+            .map(|m| {
+                format!(
+                    "{} {}: balance {}, expected {}",
+                    m.date.format(DATE_FORMAT),
+                    m.description,
+                    m.balance,
+                    m.expected
+                )
+            })
+            .join("; ");
+        let more = match self.mismatches.len().saturating_sub(MAX_LISTED) {
+            0 => String::new(),
+            n => format!(" and {} more", n),
+        };
+        Some(format!(
+            "The balance of {} does not add up with the amounts at {} row(s), transactions may be missing from it: {}{}",
+            file_name,
+            self.mismatches.len(),
+            listed,
+            more
+        ))
+    }
+}
@@ -0,0 +1,212 @@
+//! ISO 20022 camt.053 (account statement) and camt.054 (debit/credit
+//! notification) XML files, offered by Nordea's corporate netbank.
+//!
+//! The account and creation time are read from the file itself, as the file
+//! names do not contain them. Pending entries are treated like the
+//! authorisation holds of CSV exports. Unlike the CSV exports, consecutive
+//! statements do not overlap, so a statement is not expected to contain
+//! transactions of the previous one.
+
+use crate::{
+    amount::Amount,
+    group_iban,
+    parser::{BankParser, FileInfo, Rows},
+    report, unicode, xml, Transaction,
+};
+use chrono::{NaiveDate, NaiveDateTime};
+use std::{error::Error, path::Path};
+
+pub struct Camt;
+
+/// Statement elements of camt.053 and camt.054 documents
+const STATEMENTS: [(&str, &str); 2] = [
+    ("BkToCstmrStmt", "Stmt"),
+    ("BkToCstmrDbtCdtNtfctn", "Ntfctn"),
+];
+
+/// Returns the statements of the document.
+fn statements(document: &xml::Element) -> Vec<&xml::Element> {
+    STATEMENTS
+        .iter()
+        .filter_map(|(message, statement)| Some(document.child(message)?.children(statement)))
+        .flatten()
+        .collect()
+}
+
+fn account(statement: &xml::Element) -> Option<String> {
+    statement.text_at(&["Acct", "Id", "IBAN"]).map(group_iban)
+}
+
+fn creation_time(document: &xml::Element, statement: &xml::Element) -> Option<NaiveDateTime> {
+    let text = statement.text_at(&["CreDtTm"]).or_else(|| {
+        STATEMENTS
+            .iter()
+            .find_map(|(message, _)| document.text_at(&[message, "GrpHdr", "CreDtTm"]))
+    })?;
+
+    // Ignore fractional seconds and the UTC offset
+    NaiveDateTime::parse_from_str(text.get(..19)?, "%Y-%m-%dT%H:%M:%S").ok()
+}
+
+fn parse_document(contents: &str) -> Option<xml::Element> {
+    let document = xml::parse(contents).ok()?;
+    (document.name == "Document").then_some(document)
+}
+
+/// Converts an entry into a transaction, or returns `None` for informational
+/// entries. Pending entries are dated by their value date if they have no
+/// booking date.
+fn transaction(entry: &xml::Element, path: &Path) -> Result<Option<Transaction>, Box<dyn Error>> {
+    let status = entry
+        .text_at(&["Sts", "Cd"])
+        .or_else(|| entry.text_at(&["Sts"]));
+    let pending = status == Some("PDNG");
+    if status.is_some_and(|s| s != "BOOK" && s != "PDNG") {
+        report::warn(format!(
+            "Skipping {} entry in {}.",
+            status.unwrap_or_default(),
+            path.display()
+        ));
+        return Ok(None);
+    }
+
+    let date_at = |element: &str| {
+        entry
+            .text_at(&[element, "Dt"])
+            .or_else(|| entry.text_at(&[element, "DtTm"]).and_then(|d| d.get(..10)))
+    };
+    let booking_date = date_at("BookgDt")
+        .or_else(|| date_at("ValDt").filter(|_| pending))
+        .ok_or_else(|| format!("Entry without a booking date in {}", path.display()))?;
+    let date = NaiveDate::parse_from_str(booking_date, "%Y-%m-%d")
+        .map_err(|e| format!("Invalid booking date '{}': {}", booking_date, e))?;
+    let value_date = date_at("ValDt").and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok());
+
+    let amount_element = entry
+        .child("Amt")
+        .ok_or_else(|| format!("Entry without an amount in {}", path.display()))?;
+    let amount = amount_element
+        .text
+        .trim()
+        .parse::<Amount>()
+        .map_err(|e| format!("Invalid amount in {}: {}", path.display(), e))?;
+    let debit = match entry.text_at(&["CdtDbtInd"]) {
+        Some("DBIT") => true,
+        Some("CRDT") => false,
+        other => {
+            return Err(format!(
+                "Invalid credit/debit indicator {:?} in {}",
+                other,
+                path.display()
+            )
+            .into())
+        }
+    };
+
+    // Details of the first transaction of the entry, with the counterparty
+    // being the creditor of debits and the debtor of credits
+    let details = entry.find(&["NtryDtls", "TxDtls"]);
+    let (party, party_account) = if debit {
+        ("Cdtr", "CdtrAcct")
+    } else {
+        ("Dbtr", "DbtrAcct")
+    };
+    let detail = |path: &[&str]| details.and_then(|d| d.text_at(path)).unwrap_or_default();
+
+    let name = [
+        detail(&["RltdPties", party, "Nm"]),
+        detail(&["RltdPties", party, "Pty", "Nm"]),
+    ]
+    .into_iter()
+    .find(|n| !n.is_empty());
+    let description = name
+        .or_else(|| entry.text_at(&["AddtlNtryInf"]))
+        .unwrap_or_default();
+
+    let message = details
+        .and_then(|d| d.child("RmtInf"))
+        .map(|r| {
+            r.children("Ustrd")
+                .map(|u| u.text.trim())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .unwrap_or_default();
+
+    Ok(Some(Transaction {
+        date,
+        amount: if debit { -amount } else { amount },
+        currency: amount_element
+            .attribute("Ccy")
+            .unwrap_or_default()
+            .to_uppercase(),
+        description: unicode::canonicalize(description),
+        reference: unicode::canonicalize(detail(&["RmtInf", "Strd", "CdtrRefInf", "Ref"])),
+        message: unicode::canonicalize(&message),
+        counterparty_account: group_iban(detail(&["RltdPties", party_account, "Id", "IBAN"])),
+        value_date,
+        payment_date: None,
+        balance: None,
+        original_amount: None,
+        original_currency: String::new(),
+        pending,
+        columns: vec![],
+    }))
+}
+
+impl BankParser for Camt {
+    fn name(&self) -> &'static str {
+        "camt"
+    }
+
+    fn parse_file_name(&self, _file_name: &str) -> Option<FileInfo> {
+        None
+    }
+
+    fn inspects(&self, file_name: &str) -> bool {
+        file_name.to_lowercase().ends_with(".xml")
+    }
+
+    fn parse_file_info(&self, contents: &str) -> Option<FileInfo> {
+        let document = parse_document(contents)?;
+        let statement = *statements(&document).first()?;
+
+        Some(FileInfo {
+            iban: account(statement)?,
+            date: creation_time(&document, statement)?,
+        })
+    }
+
+    fn detect(&self, contents: &str) -> bool {
+        parse_document(contents).is_some_and(|d| !statements(&d).is_empty())
+    }
+
+    fn exports_overlap(&self) -> bool {
+        false
+    }
+
+    fn rows<'a>(&'a self, contents: &'a str, path: &Path) -> Result<Rows<'a>, Box<dyn Error>> {
+        let document = xml::parse(contents)
+            .map_err(|e| format!("Could not parse {}: {}", path.display(), e))?;
+        let statements = statements(&document);
+        let iban = statements
+            .first()
+            .and_then(|s| account(s))
+            .ok_or_else(|| format!("No account statement found in {}", path.display()))?;
+
+        // Only the first account of files with statements of several accounts
+        // is converted. Entries are listed oldest first.
+        let mut rows = vec![];
+        for statement in statements
+            .iter()
+            .filter(|s| account(s).as_ref() == Some(&iban))
+        {
+            for entry in statement.children("Ntry") {
+                rows.extend(transaction(entry, path)?);
+            }
+        }
+        rows.reverse();
+
+        Ok(Box::new(rows.into_iter().map(Ok)))
+    }
+}
@@ -0,0 +1,134 @@
+//! Columns of the CSV output.
+//!
+//! By default the output has YNAB's columns, but the `[csv]` section of the
+//! config file can choose the columns and their order, rename their headers
+//! and add columns YNAB does not read, e.g. for spreadsheets or other tools:
+//!
+//! ```toml
+//! [csv]
+//! columns = ["Date", "Account", "Payee", "Amount", "Reference"]
+//! headers = { Payee = "Description" }
+//! ```
+
+use crate::{YnabFormat, YnabRow};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Column {
+    Date,
+    Payee,
+    Memo,
+    /// Signed amount
+    Amount,
+    /// Amount of outflows, as a positive number
+    Outflow,
+    /// Amount of inflows
+    Inflow,
+    ImportId,
+    Category,
+    Flag,
+    /// Name of the account, or its IBAN if it has no name
+    Account,
+    Iban,
+    /// Payment reference number
+    Reference,
+    Currency,
+}
+
+pub const ALL: &[Column] = &[
+    Column::Date,
+    Column::Payee,
+    Column::Memo,
+    Column::Amount,
+    Column::Outflow,
+    Column::Inflow,
+    Column::ImportId,
+    Column::Category,
+    Column::Flag,
+    Column::Account,
+    Column::Iban,
+    Column::Reference,
+    Column::Currency,
+];
+
+impl Column {
+    /// Default header of the column
+    pub fn name(self) -> &'static str {
+        match self {
+            Column::Date => "Date",
+            Column::Payee => "Payee",
+            Column::Memo => "Memo",
+            Column::Amount => "Amount",
+            Column::Outflow => "Outflow",
+            Column::Inflow => "Inflow",
+            Column::ImportId => "Import ID",
+            Column::Category => "Category",
+            Column::Flag => "Flag",
+            Column::Account => "Account",
+            Column::Iban => "IBAN",
+            Column::Reference => "Reference",
+            Column::Currency => "Currency",
+        }
+    }
+
+    /// Parses a column by its default header, ignoring case, spaces and
+    /// underscores.
+    pub fn parse(name: &str) -> Result<Column, String> {
+        let compact = |s: &str| s.replace([' ', '_'], "").to_lowercase();
+        ALL.iter()
+            .copied()
+            .find(|c| compact(c.name()) == compact(name))
+            .ok_or_else(|| {
+                let names: Vec<_> = ALL.iter().map(|c| c.name()).collect();
+                format!(
+                    "unknown column '{}', expected one of {}",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// The account whose rows are written, for the account columns
+pub struct Account<'a> {
+    pub iban: &'a str,
+    pub name: &'a str,
+    pub currency: &'a str,
+}
+
+/// Returns YNAB's columns, with the amount in `format`.
+pub fn ynab(format: YnabFormat, category: bool, flag: bool) -> Vec<Column> {
+    let mut columns = vec![Column::Date, Column::Payee, Column::Memo];
+    match format {
+        YnabFormat::Amount => columns.push(Column::Amount),
+        YnabFormat::OutflowInflow => columns.extend([Column::Outflow, Column::Inflow]),
+    }
+    columns.push(Column::ImportId);
+    if category {
+        columns.push(Column::Category);
+    }
+    if flag {
+        columns.push(Column::Flag);
+    }
+    columns
+}
+
+/// Returns the value of `column` for row `r` of `account`.
+pub fn value(column: Column, r: &YnabRow, import_id: &str, account: &Account) -> String {
+    match column {
+        Column::Date => r.date.clone(),
+        Column::Payee => r.payee.clone(),
+        Column::Memo => r.memo.clone(),
+        Column::Amount => r.amount.to_string(),
+        // Both columns hold positive amounts, the other one is left empty
+        Column::Outflow if r.amount.is_negative() => (-r.amount).to_string(),
+        Column::Inflow if !r.amount.is_negative() => r.amount.to_string(),
+        Column::Outflow | Column::Inflow => String::new(),
+        Column::ImportId => import_id.to_string(),
+        Column::Category => r.category.clone().unwrap_or_default(),
+        Column::Flag => r.flag.map(|f| f.as_str()).unwrap_or_default().to_string(),
+        Column::Account => account.name.to_string(),
+        Column::Iban => account.iban.to_string(),
+        Column::Reference => r.reference.clone(),
+        Column::Currency => account.currency.to_string(),
+    }
+}
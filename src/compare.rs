@@ -0,0 +1,103 @@
+//! Comparing two exports with `nda2ynab diff`, for finding out why a
+//! transaction was or was not converted.
+//!
+//! The rows are matched the way the new rows of an export are told apart
+//! from the previous export's when converting, by their date, amount and
+//! description, counting identical rows separately. Pending transactions are
+//! left out, as they are never converted.
+
+use nda2ynab::{
+    diff::subtract_previous,
+    encoding::Encoding,
+    explicit_export_files,
+    parser::Registry,
+    report,
+    system::{Clock, Disk},
+    ParsedFileName, Transaction, DATE_FORMAT,
+};
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+#[derive(clap::Args, Debug)]
+pub struct DiffArgs {
+    /// The older export
+    old: PathBuf,
+
+    /// The newer export
+    new: PathBuf,
+
+    /// Text encoding of the exports [default: auto]
+    #[clap(short, long, arg_enum)]
+    encoding: Option<Encoding>,
+}
+
+pub fn run(clock: &dyn Clock, args: &DiffArgs) -> Result<(), Box<dyn Error>> {
+    let registry = Registry::builtin(None, clock);
+    let (old_file, old) = read(&args.old, &registry, args.encoding)?;
+    let (new_file, new) = read(&args.new, &registry, args.encoding)?;
+    if old_file.iban != new_file.iban {
+        report::warn(format!(
+            "{} is an export of {} but {} is of {}",
+            old_file.file_name, old_file.iban, new_file.file_name, new_file.iban
+        ));
+    }
+
+    let added = subtract_previous(&new, &old);
+    let removed = subtract_previous(&old, &new);
+    print_rows(
+        &format!("Only in {}", old_file.file_name),
+        '-',
+        &removed.new_rows,
+    );
+    print_rows(
+        &format!("Only in {}", new_file.file_name),
+        '+',
+        &added.new_rows,
+    );
+    println!("{} transaction(s) in both.", added.overlapping);
+    Ok(())
+}
+
+/// Reads the booked transactions of the export at `path`.
+fn read(
+    path: &Path,
+    registry: &Registry,
+    encoding: Option<Encoding>,
+) -> Result<(ParsedFileName, Vec<Transaction>), Box<dyn Error>> {
+    let encoding = encoding.unwrap_or(Encoding::Auto);
+    let file = explicit_export_files(&Disk, path, None, registry, encoding)?.remove(0);
+    let parsed = registry.read(&Disk, file.bank, &file.path, encoding)?;
+    if !parsed.errors.is_empty() {
+        report::warn(format!(
+            "{} row(s) of {} could not be parsed and are not compared",
+            parsed.errors.len(),
+            file.file_name
+        ));
+    }
+    let (pending, booked): (Vec<_>, Vec<_>) =
+        parsed.transactions.into_iter().partition(|t| t.pending);
+    if !pending.is_empty() {
+        report::warn(format!(
+            "{} pending transaction(s) of {} are not compared",
+            pending.len(),
+            file.file_name
+        ));
+    }
+    Ok((file, booked))
+}
+
+fn print_rows(title: &str, sign: char, rows: &[Transaction]) {
+    println!("{} ({}):", title, rows.len());
+    for row in rows {
+        println!(
+            "{} {}  {:>10}  {}",
+            sign,
+            row.date.format(DATE_FORMAT),
+            row.amount,
+            row.description
+        );
+    }
+    println!();
+}
@@ -0,0 +1,246 @@
+//! Shell completion scripts, generated from the command line definition so
+//! that they stay in sync as options are added.
+//!
+//! Options with a fixed set of values, e.g. `--format`, complete to those
+//! values, and other options taking a value complete to file names. Only one
+//! level of subcommands is supported, which is all the command line has.
+
+use clap::{Arg, ArgEnum, Command};
+use std::fmt::Write;
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Returns the completion script of `cmd` for `shell`.
+pub fn generate(shell: Shell, cmd: &mut Command) -> String {
+    // Adds the help and version flags and the global options of subcommands
+    cmd._build_all();
+    match shell {
+        Shell::Bash => bash(cmd),
+        Shell::Zsh => zsh(cmd),
+        Shell::Fish => fish(cmd),
+    }
+}
+
+fn options<'a, 'help>(cmd: &'a Command<'help>) -> impl Iterator<Item = &'a Arg<'help>> {
+    cmd.get_arguments()
+        .filter(|a| !a.is_positional() && !a.is_hide_set())
+}
+
+fn subcommands<'a, 'help>(cmd: &'a Command<'help>) -> impl Iterator<Item = &'a Command<'help>> {
+    cmd.get_subcommands()
+        .filter(|s| !s.is_hide_set() && s.get_name() != "help")
+}
+
+fn possible_values<'a>(arg: &'a Arg) -> Option<Vec<&'a str>> {
+    let values = arg.get_possible_values()?;
+    Some(
+        values
+            .iter()
+            .filter(|v| !v.is_hide_set())
+            .map(|v| v.get_name())
+            .collect(),
+    )
+}
+
+/// First line of the help of `arg` or `cmd`
+fn summary(help: Option<&str>) -> &str {
+    help.and_then(|h| h.lines().next()).unwrap_or_default()
+}
+
+fn flags(arg: &Arg) -> Vec<String> {
+    let short = arg.get_short().map(|s| format!("-{}", s));
+    let long = arg.get_long().map(|l| format!("--{}", l));
+    short.into_iter().chain(long).collect()
+}
+
+fn bash(cmd: &Command) -> String {
+    let name = cmd.get_name();
+    let function = format!("_{}", name.replace('-', "_"));
+    let mut out = String::new();
+    writeln!(out, "{}() {{", function).unwrap();
+    out += "    local cur prev cmd i opts\n";
+    out += "    cur=\"${COMP_WORDS[COMP_CWORD]}\"\n";
+    out += "    prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n";
+    out += "    cmd=\"\"\n";
+    out += "    for ((i = 1; i < COMP_CWORD; i++)); do\n";
+    out += "        case \"${COMP_WORDS[i]}\" in\n";
+    let names: Vec<_> = subcommands(cmd).map(Command::get_name).collect();
+    if !names.is_empty() {
+        writeln!(
+            out,
+            "            {}) cmd=\"${{COMP_WORDS[i]}}\"; break ;;",
+            names.join("|")
+        )
+        .unwrap();
+    }
+    out += "        esac\n    done\n\n    case \"$cmd\" in\n";
+
+    bash_command(&mut out, "\"\"", cmd, &names);
+    for sub in subcommands(cmd) {
+        bash_command(&mut out, sub.get_name(), sub, &[]);
+    }
+
+    out += "    esac\n\n";
+    out += "    COMPREPLY=($(compgen -W \"$opts\" -- \"$cur\"))\n}\n\n";
+    writeln!(
+        out,
+        "complete -F {} -o bashdefault -o default {}",
+        function, name
+    )
+    .unwrap();
+    out
+}
+
+fn bash_command(out: &mut String, pattern: &str, cmd: &Command, words: &[&str]) {
+    let opts: Vec<String> = options(cmd)
+        .flat_map(flags)
+        .chain(words.iter().map(|w| w.to_string()))
+        .collect();
+    writeln!(out, "        {})", pattern).unwrap();
+    writeln!(out, "            opts=\"{}\"", opts.join(" ")).unwrap();
+    out.push_str("            case \"$prev\" in\n");
+    for arg in options(cmd).filter(|a| a.is_takes_value_set()) {
+        let completion = match possible_values(arg) {
+            Some(values) => format!(
+                "COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return ;;",
+                values.join(" ")
+            ),
+            // Left to the default completion of file names
+            None => "return ;;".to_string(),
+        };
+        writeln!(
+            out,
+            "                {}) {}",
+            flags(arg).join("|"),
+            completion
+        )
+        .unwrap();
+    }
+    out.push_str("            esac\n            ;;\n");
+}
+
+/// Escapes `s` for a single quoted zsh `_arguments` spec.
+fn zsh_escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+fn zsh(cmd: &Command) -> String {
+    let name = cmd.get_name();
+    let mut out = format!("#compdef {}\n\n_{}() {{\n", name, name);
+    out += "    local line state\n\n    _arguments -C \\\n";
+    zsh_options(&mut out, cmd);
+    out += "        '1: :->command' \\\n        '*:: :->args'\n\n";
+    out += "    case $state in\n        command)\n";
+    out += "            local -a commands\n            commands=(\n";
+    for sub in subcommands(cmd) {
+        writeln!(
+            out,
+            "                '{}:{}'",
+            sub.get_name(),
+            zsh_escape(summary(sub.get_about()))
+        )
+        .unwrap();
+    }
+    out += "            )\n            _describe 'command' commands\n            _files\n            ;;\n";
+    out += "        args)\n            case $line[1] in\n";
+    for sub in subcommands(cmd) {
+        writeln!(out, "                {})", sub.get_name()).unwrap();
+        out += "                    _arguments \\\n";
+        zsh_options(&mut out, sub);
+        out += "                        '*:file:_files'\n                    ;;\n";
+    }
+    out += "            esac\n            ;;\n    esac\n}\n\n";
+    writeln!(out, "_{} \"$@\"", name).unwrap();
+    out
+}
+
+fn zsh_options(out: &mut String, cmd: &Command) {
+    let indent = if cmd.has_subcommands() { 8 } else { 24 };
+    for arg in options(cmd) {
+        let help = zsh_escape(summary(arg.get_help()));
+        let value = if !arg.is_takes_value_set() {
+            String::new()
+        } else {
+            let value_name = arg
+                .get_value_names()
+                .and_then(|v| v.first().copied())
+                .unwrap_or(arg.get_id());
+            match possible_values(arg) {
+                Some(values) => format!(":{}:({})", value_name, values.join(" ")),
+                None => format!(":{}:_files", value_name),
+            }
+        };
+        let repeat = if arg.is_multiple_occurrences_set() {
+            "*"
+        } else {
+            ""
+        };
+        for flag in flags(arg) {
+            writeln!(
+                out,
+                "{:indent$}'{}{}[{}]{}' \\",
+                "",
+                repeat,
+                flag,
+                help,
+                value,
+                indent = indent
+            )
+            .unwrap();
+        }
+    }
+}
+
+/// Escapes `s` for a single quoted fish string.
+fn fish_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+fn fish(cmd: &Command) -> String {
+    let name = cmd.get_name();
+    let mut out = String::new();
+    fish_options(&mut out, name, "__fish_use_subcommand", cmd);
+    for sub in subcommands(cmd) {
+        writeln!(
+            out,
+            "complete -c {} -n '__fish_use_subcommand' -f -a {} -d '{}'",
+            name,
+            sub.get_name(),
+            fish_escape(summary(sub.get_about()))
+        )
+        .unwrap();
+    }
+    for sub in subcommands(cmd) {
+        let condition = format!("__fish_seen_subcommand_from {}", sub.get_name());
+        fish_options(&mut out, name, &condition, sub);
+    }
+    out
+}
+
+fn fish_options(out: &mut String, name: &str, condition: &str, cmd: &Command) {
+    for arg in options(cmd) {
+        write!(out, "complete -c {} -n '{}'", name, condition).unwrap();
+        if let Some(short) = arg.get_short() {
+            write!(out, " -s {}", short).unwrap();
+        }
+        if let Some(long) = arg.get_long() {
+            write!(out, " -l {}", long).unwrap();
+        }
+        if arg.is_takes_value_set() {
+            out.push_str(" -r");
+            if let Some(values) = possible_values(arg) {
+                write!(out, " -f -a '{}'", values.join(" ")).unwrap();
+            }
+        }
+        writeln!(out, " -d '{}'", fish_escape(summary(arg.get_help()))).unwrap();
+    }
+}
@@ -0,0 +1,633 @@
+//! Configuration file support.
+//!
+//! Defaults for command line options can be stored in `nda2ynab.toml`, which
+//! is looked up in the current directory and then in
+//! `$XDG_CONFIG_HOME/nda2ynab/` (or `~/.config/nda2ynab/`). Options given on
+//! the command line take precedence. Relative paths are resolved relative to
+//! the directory of the config file.
+//!
+//! ```toml
+//! path = "~/Downloads"
+//! format = "csv"
+//! ynab_format = "outflow-inflow"
+//! date_field = "value"
+//! locale = "fi"
+//! timezone = "local"
+//! file_name_patterns = ['^Kontoutdrag (?P<iban>FI[0-9 ]+) (?P<date>\d{8})\.csv$']
+//! rules = "rules.toml"
+//! memo = "{message} {reference}"
+//! title_case = true
+//! enrich = true
+//! aggregate_below = 2
+//! max_export_age = 35
+//! post_process = "python3 ~/bin/categorize.py"
+//! input_map = "date=Booking date,amount=Amount,payee=Text"
+//! archive = "~/Documents/nordea"
+//! state_backend = "sqlite"
+//! convert_to = "EUR"
+//! rates = "rates.toml"
+//!
+//! [ynab]
+//! token = "..."
+//! budget_id = "..."
+//! match_payees = true
+//!
+//! [actual]
+//! url = "http://localhost:5007"
+//! api_key = "..."
+//! budget_id = "..."
+//!
+//! [firefly]
+//! url = "https://firefly.example.com"
+//! token = "..."
+//!
+//! [ledger]
+//! placeholder = "Expenses:Uncategorized"
+//!
+//! [csv]
+//! columns = ["Date", "Payee", "Memo", "Amount", "Account", "Reference"]
+//! headers = { Payee = "Description" }
+//!
+//! [mail]
+//! url = "imaps://imap.example.com/INBOX"
+//! user = "me@example.com"
+//! password = "..."
+//!
+//! [nordea]
+//! client_id = "..."
+//! client_secret = "..."
+//! redirect_uri = "https://localhost/callback"
+//! country = "FI"
+//!
+//! [accounts."FI12 3456 7890 1234 56"]
+//! name = "Joint checking"
+//! ynab_account_id = "..."
+//! actual_account_id = "..."
+//! firefly_account_id = "..."
+//! ledger_account = "Assets:Nordea:Checking"
+//! gnucash_account = "Assets:Current Assets:Checking Account"
+//! type = "checking"
+//!
+//! [accounts."FI65 4321 0987 6543 21"]
+//! type = "credit"
+//! invert_amounts = true
+//! ```
+
+use crate::{
+    amount::Amount,
+    columns::Column,
+    encoding::Encoding,
+    group_iban,
+    history::StateBackend,
+    locale::Locale,
+    report,
+    timezone::Timezone,
+    toml::{self, Table, Value},
+    DateField, Format, UploadTarget, YnabFormat,
+};
+use clap::ArgEnum;
+use std::{
+    collections::BTreeMap,
+    env,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub const FILE_NAME: &str = "nda2ynab.toml";
+
+#[derive(Debug, Default)]
+pub struct Config {
+    /// Directory containing the exported csv files
+    pub path: Option<String>,
+    pub output: Option<String>,
+    pub format: Option<Format>,
+    /// Amount columns of YNAB CSV output
+    pub ynab_format: Option<YnabFormat>,
+    /// Date used as the date of the output
+    pub date_field: Option<DateField>,
+    pub encoding: Option<Encoding>,
+    pub locale: Option<Locale>,
+    /// Time zone of the times in export file names
+    pub timezone: Option<Timezone>,
+    /// Extra patterns for the file names of Nordea exports
+    pub file_name_patterns: Vec<String>,
+    pub rules: Option<PathBuf>,
+    /// Memo template
+    pub memo: Option<String>,
+    /// Title-case payees written in all capitals
+    pub title_case: bool,
+    /// Use the bundled merchant list, see `--enrich`
+    pub enrich: bool,
+    /// Outflows smaller than this are merged into one transaction per day
+    pub aggregate_below: Option<Amount>,
+    /// Days after which the most recent export is warned about as stale
+    pub max_export_age: Option<u32>,
+    /// Command the converted transactions are passed through
+    pub post_process: Option<String>,
+    /// Column mapping of the exports of other banks, inline or the path of a
+    /// mapping file
+    pub input_map: Option<String>,
+    pub state: Option<PathBuf>,
+    /// Where the state between runs is kept
+    pub state_backend: Option<StateBackend>,
+    /// Directory to archive processed exports into
+    pub archive: Option<PathBuf>,
+    /// Currency to convert amounts into
+    pub convert_to: Option<String>,
+    /// Exchange rate table
+    pub rates: Option<PathBuf>,
+    /// Service to upload to
+    pub target: Option<UploadTarget>,
+    /// Settings for uploading via the YNAB API
+    pub ynab: YnabConfig,
+    /// Settings for uploading to Actual Budget
+    pub actual: ActualConfig,
+    /// Settings for uploading to Firefly III
+    pub firefly: FireflyConfig,
+    /// Settings for Beancount and Ledger output
+    pub ledger: LedgerConfig,
+    /// Columns of CSV output
+    pub csv: CsvConfig,
+    /// Settings for fetching transactions via Nordea Open Banking
+    pub nordea: NordeaConfig,
+    /// Mailbox that `fetch-mail` reads exports from
+    pub mail: MailConfig,
+    /// Per account settings, keyed by IBAN
+    pub accounts: BTreeMap<String, AccountConfig>,
+}
+
+#[derive(Debug, Default)]
+pub struct YnabConfig {
+    pub token: Option<String>,
+    pub budget_id: Option<String>,
+    /// Use the budget's existing payees that match the converted ones
+    pub match_payees: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct ActualConfig {
+    /// Address of the actual-http-api server
+    pub url: Option<String>,
+    pub api_key: Option<String>,
+    /// Sync id of the budget
+    pub budget_id: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct FireflyConfig {
+    /// Address of the Firefly III instance
+    pub url: Option<String>,
+    /// Personal access token
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct LedgerConfig {
+    /// Account for the balancing posting of each transaction
+    pub placeholder: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct CsvConfig {
+    /// Columns to write instead of YNAB's
+    pub columns: Option<Vec<Column>>,
+    /// Headers to write instead of the default ones of the columns
+    pub headers: Vec<(Column, String)>,
+}
+
+#[derive(Debug, Default)]
+pub struct NordeaConfig {
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub redirect_uri: Option<String>,
+    /// Country of the netbank, e.g. "FI"
+    pub country: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct MailConfig {
+    /// IMAP address of the mailbox, e.g. "imaps://imap.example.com/INBOX"
+    pub url: Option<String>,
+    pub user: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct AccountConfig {
+    /// Name shown in messages and used in output file names, e.g. "Joint
+    /// checking"
+    pub name: Option<String>,
+    pub ynab_account_id: Option<String>,
+    pub actual_account_id: Option<String>,
+    /// Asset account in Firefly III
+    pub firefly_account_id: Option<String>,
+    /// Asset account in Beancount and Ledger output
+    pub ledger_account: Option<String>,
+    /// Account in GnuCash output [default: the ledger account]
+    pub gnucash_account: Option<String>,
+    /// The account's exports have inflows as negative amounts and outflows
+    /// as positive ones
+    pub invert_amounts: bool,
+    /// Kind of account, for warning about amounts that look inverted
+    pub kind: Option<AccountKind>,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccountKind {
+    Checking,
+    Savings,
+    Credit,
+}
+
+impl Config {
+    /// Returns the settings of an account, ignoring spaces in the IBAN.
+    pub fn account(&self, iban: &str) -> Option<&AccountConfig> {
+        self.accounts
+            .iter()
+            .find(|(key, _)| group_iban(key) == group_iban(iban))
+            .map(|(_, account)| account)
+    }
+
+    /// Returns the configured name of an account.
+    pub fn account_name(&self, iban: &str) -> Option<&str> {
+        self.account(iban)?.name.as_deref()
+    }
+
+    /// Returns the account for messages: its name followed by the IBAN, or
+    /// just the IBAN if it has no name.
+    pub fn account_label(&self, iban: &str) -> String {
+        match self.account_name(iban) {
+            Some(name) => format!("{} ({})", name, iban),
+            None => iban.to_string(),
+        }
+    }
+
+    /// Returns whether the amounts of an account are inverted in its exports.
+    pub fn invert_amounts(&self, iban: &str) -> bool {
+        self.account(iban).is_some_and(|a| a.invert_amounts)
+    }
+
+    /// Returns the YNAB account id configured for an IBAN.
+    pub fn ynab_account_id(&self, iban: &str) -> Option<&str> {
+        self.account(iban)?.ynab_account_id.as_deref()
+    }
+
+    /// Returns the Actual account id configured for an IBAN.
+    pub fn actual_account_id(&self, iban: &str) -> Option<&str> {
+        self.account(iban)?.actual_account_id.as_deref()
+    }
+
+    /// Returns the Firefly III asset account id configured for an IBAN.
+    pub fn firefly_account_id(&self, iban: &str) -> Option<&str> {
+        self.account(iban)?.firefly_account_id.as_deref()
+    }
+
+    /// Loads the config file from the default locations, or returns an empty
+    /// config if there is none.
+    pub fn discover() -> Result<Config, Box<dyn Error>> {
+        let config_dir = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+
+        let candidates = [
+            Some(PathBuf::from(FILE_NAME)),
+            config_dir.map(|d| d.join("nda2ynab").join(FILE_NAME)),
+        ];
+
+        match candidates.into_iter().flatten().find(|p| p.is_file()) {
+            Some(path) => Config::load(&path),
+            None => Ok(Config::default()),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Config, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read config file {}: {}", path.display(), e))?;
+        let doc = toml::parse(&contents)
+            .map_err(|e| format!("Could not parse config file {}: {}", path.display(), e))?;
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+
+        Config::from_table(&doc, base)
+            .map_err(|e| format!("In config file {}: {}", path.display(), e).into())
+    }
+
+    fn from_table(doc: &Table, base: &Path) -> Result<Config, String> {
+        warn_unknown_keys(
+            doc,
+            "",
+            &[
+                "path",
+                "output",
+                "format",
+                "ynab_format",
+                "date_field",
+                "encoding",
+                "locale",
+                "timezone",
+                "file_name_patterns",
+                "rules",
+                "memo",
+                "title_case",
+                "enrich",
+                "aggregate_below",
+                "max_export_age",
+                "post_process",
+                "input_map",
+                "archive",
+                "state",
+                "state_backend",
+                "convert_to",
+                "rates",
+                "target",
+                "ynab",
+                "actual",
+                "firefly",
+                "ledger",
+                "csv",
+                "nordea",
+                "mail",
+                "accounts",
+            ],
+        );
+
+        let resolve = |p: String| resolve_path(base, &p).to_string_lossy().into_owned();
+
+        let ynab = match doc.get("ynab") {
+            Some(value) => {
+                let table = value.as_table().ok_or("'ynab' must be a table")?;
+                warn_unknown_keys(table, "ynab.", &["token", "budget_id", "match_payees"]);
+                YnabConfig {
+                    token: string(table, "token")?,
+                    budget_id: string(table, "budget_id")?,
+                    match_payees: boolean(table, "match_payees")?.unwrap_or(false),
+                }
+            }
+            None => YnabConfig::default(),
+        };
+
+        let actual = match doc.get("actual") {
+            Some(value) => {
+                let table = value.as_table().ok_or("'actual' must be a table")?;
+                warn_unknown_keys(table, "actual.", &["url", "api_key", "budget_id"]);
+                ActualConfig {
+                    url: string(table, "url")?,
+                    api_key: string(table, "api_key")?,
+                    budget_id: string(table, "budget_id")?,
+                }
+            }
+            None => ActualConfig::default(),
+        };
+
+        let firefly = match doc.get("firefly") {
+            Some(value) => {
+                let table = value.as_table().ok_or("'firefly' must be a table")?;
+                warn_unknown_keys(table, "firefly.", &["url", "token"]);
+                FireflyConfig {
+                    url: string(table, "url")?,
+                    token: string(table, "token")?,
+                }
+            }
+            None => FireflyConfig::default(),
+        };
+
+        let mail = match doc.get("mail") {
+            Some(value) => {
+                let table = value.as_table().ok_or("'mail' must be a table")?;
+                warn_unknown_keys(table, "mail.", &["url", "user", "password"]);
+                MailConfig {
+                    url: string(table, "url")?,
+                    user: string(table, "user")?,
+                    password: string(table, "password")?,
+                }
+            }
+            None => MailConfig::default(),
+        };
+
+        let ledger = match doc.get("ledger") {
+            Some(value) => {
+                let table = value.as_table().ok_or("'ledger' must be a table")?;
+                warn_unknown_keys(table, "ledger.", &["placeholder"]);
+                LedgerConfig {
+                    placeholder: string(table, "placeholder")?,
+                }
+            }
+            None => LedgerConfig::default(),
+        };
+
+        let csv = match doc.get("csv") {
+            Some(value) => {
+                let table = value.as_table().ok_or("'csv' must be a table")?;
+                warn_unknown_keys(table, "csv.", &["columns", "headers"]);
+                let columns = match table.get("columns") {
+                    Some(_) => Some(
+                        strings(table, "columns")?
+                            .iter()
+                            .map(|name| {
+                                Column::parse(name).map_err(|e| format!("csv.columns: {}", e))
+                            })
+                            .collect::<Result<_, _>>()?,
+                    ),
+                    None => None,
+                };
+                let headers = match table.get("headers") {
+                    Some(value) => value
+                        .as_table()
+                        .ok_or("'csv.headers' must be a table")?
+                        .iter()
+                        .map(|(name, header)| {
+                            let column =
+                                Column::parse(name).map_err(|e| format!("csv.headers: {}", e))?;
+                            match header {
+                                Value::String(header) => Ok((column, header.clone())),
+                                _ => Err(format!("'csv.headers.{}' must be a string", name)),
+                            }
+                        })
+                        .collect::<Result<_, String>>()?,
+                    None => vec![],
+                };
+                CsvConfig { columns, headers }
+            }
+            None => CsvConfig::default(),
+        };
+
+        let nordea = match doc.get("nordea") {
+            Some(value) => {
+                let table = value.as_table().ok_or("'nordea' must be a table")?;
+                warn_unknown_keys(
+                    table,
+                    "nordea.",
+                    &["client_id", "client_secret", "redirect_uri", "country"],
+                );
+                NordeaConfig {
+                    client_id: string(table, "client_id")?,
+                    client_secret: string(table, "client_secret")?,
+                    redirect_uri: string(table, "redirect_uri")?,
+                    country: string(table, "country")?,
+                }
+            }
+            None => NordeaConfig::default(),
+        };
+
+        let mut accounts = BTreeMap::new();
+        if let Some(value) = doc.get("accounts") {
+            let table = value.as_table().ok_or("'accounts' must be a table")?;
+            for (iban, value) in table {
+                let account = value
+                    .as_table()
+                    .ok_or_else(|| format!("account '{}' must be a table", iban))?;
+                warn_unknown_keys(
+                    account,
+                    &format!("accounts.\"{}\".", iban),
+                    &[
+                        "name",
+                        "ynab_account_id",
+                        "actual_account_id",
+                        "firefly_account_id",
+                        "ledger_account",
+                        "gnucash_account",
+                        "invert_amounts",
+                        "type",
+                    ],
+                );
+                accounts.insert(
+                    iban.clone(),
+                    AccountConfig {
+                        name: string(account, "name")?,
+                        ynab_account_id: string(account, "ynab_account_id")?,
+                        actual_account_id: string(account, "actual_account_id")?,
+                        firefly_account_id: string(account, "firefly_account_id")?,
+                        ledger_account: string(account, "ledger_account")?,
+                        gnucash_account: string(account, "gnucash_account")?,
+                        invert_amounts: boolean(account, "invert_amounts")?.unwrap_or(false),
+                        kind: arg_enum(account, "type")?,
+                    },
+                );
+            }
+        }
+
+        Ok(Config {
+            path: string(doc, "path")?.map(resolve),
+            output: string(doc, "output")?.map(resolve),
+            format: arg_enum(doc, "format")?,
+            ynab_format: arg_enum(doc, "ynab_format")?,
+            date_field: arg_enum(doc, "date_field")?,
+            encoding: arg_enum(doc, "encoding")?,
+            locale: arg_enum(doc, "locale")?,
+            timezone: string(doc, "timezone")?.map(|tz| tz.parse()).transpose()?,
+            file_name_patterns: strings(doc, "file_name_patterns")?,
+            rules: string(doc, "rules")?.map(|p| resolve_path(base, &p)),
+            memo: string(doc, "memo")?,
+            title_case: boolean(doc, "title_case")?.unwrap_or(false),
+            enrich: boolean(doc, "enrich")?.unwrap_or(false),
+            aggregate_below: amount(doc, "aggregate_below")?,
+            max_export_age: days(doc, "max_export_age")?,
+            post_process: string(doc, "post_process")?,
+            input_map: string(doc, "input_map")?.map(|map| {
+                let path = resolve_path(base, &map);
+                if path.is_file() {
+                    path.display().to_string()
+                } else {
+                    map
+                }
+            }),
+            archive: string(doc, "archive")?.map(|p| resolve_path(base, &p)),
+            state: string(doc, "state")?.map(|p| resolve_path(base, &p)),
+            state_backend: arg_enum(doc, "state_backend")?,
+            convert_to: string(doc, "convert_to")?,
+            rates: string(doc, "rates")?.map(|p| resolve_path(base, &p)),
+            target: arg_enum(doc, "target")?,
+            ynab,
+            actual,
+            firefly,
+            ledger,
+            csv,
+            nordea,
+            mail,
+            accounts,
+        })
+    }
+}
+
+fn warn_unknown_keys(table: &Table, prefix: &str, known: &[&str]) {
+    for key in table.keys().filter(|k| !known.contains(&k.as_str())) {
+        report::warn(format!("unknown config key '{}{}'", prefix, key));
+    }
+}
+
+fn string(table: &Table, key: &str) -> Result<Option<String>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(_) => Err(format!("'{}' must be a string", key)),
+    }
+}
+
+/// Reads a string or an array of strings.
+fn strings(table: &Table, key: &str) -> Result<Vec<String>, String> {
+    let invalid = || format!("'{}' must be a string or an array of strings", key);
+    match table.get(key) {
+        None => Ok(vec![]),
+        Some(Value::String(s)) => Ok(vec![s.clone()]),
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| match item {
+                Value::String(s) => Ok(s.clone()),
+                _ => Err(invalid()),
+            })
+            .collect(),
+        Some(_) => Err(invalid()),
+    }
+}
+
+fn boolean(table: &Table, key: &str) -> Result<Option<bool>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Boolean(b)) => Ok(Some(*b)),
+        Some(_) => Err(format!("'{}' must be true or false", key)),
+    }
+}
+
+/// Reads an amount given as a number or a string, e.g. `2` or `"2,50"`.
+fn amount(table: &Table, key: &str) -> Result<Option<Amount>, String> {
+    let text = match table.get(key) {
+        None => return Ok(None),
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Integer(i)) => i.to_string(),
+        Some(Value::Float(f)) => f.to_string(),
+        Some(_) => return Err(format!("'{}' must be an amount", key)),
+    };
+    text.parse()
+        .map(Some)
+        .map_err(|e| format!("invalid value for '{}': {}", key, e))
+}
+
+fn days(table: &Table, key: &str) -> Result<Option<u32>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Integer(i)) => u32::try_from(*i)
+            .map(Some)
+            .map_err(|_| format!("'{}' must be a number of days", key)),
+        Some(_) => Err(format!("'{}' must be a number of days", key)),
+    }
+}
+
+fn arg_enum<T: ArgEnum>(table: &Table, key: &str) -> Result<Option<T>, String> {
+    string(table, key)?
+        .map(|s| T::from_str(&s, true).map_err(|_| format!("invalid value '{}' for '{}'", s, key)))
+        .transpose()
+}
+
+/// Expands a leading `~` and makes relative paths relative to `base`.
+fn resolve_path(base: &Path, path: &str) -> PathBuf {
+    let expanded = match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    };
+
+    if expanded.is_relative() {
+        base.join(expanded)
+    } else {
+        expanded
+    }
+}
@@ -0,0 +1,80 @@
+//! Styling of the messages printed on stderr.
+//!
+//! The output of a run is printed in sections: the files used, the
+//! transactions of each account, where they were written, and a recap of the
+//! warnings. Headings, warnings, errors and results are colored when stderr
+//! is a terminal, unless `--no-color` is given or the `NO_COLOR` environment
+//! variable is set (see <https://no-color.org/>).
+
+use std::{
+    env,
+    io::{self, IsTerminal},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Width of the labels of `field`
+const LABEL_WIDTH: usize = 14;
+
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Style {
+    /// Section headings
+    Heading,
+    Warning,
+    Error,
+    /// Transactions written or uploaded
+    Success,
+    /// Details of less interest, such as the previous file
+    Dim,
+}
+
+impl Style {
+    fn code(&self) -> &'static str {
+        match self {
+            Style::Heading => "1",
+            Style::Warning => "1;33",
+            Style::Error => "1;31",
+            Style::Success => "32",
+            Style::Dim => "2",
+        }
+    }
+}
+
+/// Enables colors if stderr is a terminal, `no_color` is not set and
+/// `NO_COLOR` is not set to a non-empty value.
+pub fn init(no_color: bool) {
+    let no_color_env = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    set_color(!no_color && !no_color_env && io::stderr().is_terminal());
+}
+
+pub fn set_color(enabled: bool) {
+    COLOR.store(enabled, Ordering::Relaxed);
+}
+
+pub fn color_enabled() -> bool {
+    COLOR.load(Ordering::Relaxed)
+}
+
+/// Returns `text` in `style`, or as is without colors.
+pub fn paint(style: Style, text: &str) -> String {
+    if color_enabled() && !text.is_empty() {
+        format!("\x1b[{}m{}\x1b[0m", style.code(), text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Returns `text` with its first line as a heading.
+pub fn section(text: &str) -> String {
+    match text.split_once('\n') {
+        Some((heading, rest)) => format!("{}\n{}", paint(Style::Heading, heading), rest),
+        None => paint(Style::Heading, text),
+    }
+}
+
+/// Returns an indented line of a section with its label aligned with the
+/// other fields.
+pub fn field(label: &str, value: &str) -> String {
+    format!("  {:<LABEL_WIDTH$} {}", label, value)
+}
@@ -0,0 +1,179 @@
+//! Nordea credit card CSV exports.
+//!
+//! Card exports are named after the masked card number, e.g.
+//! `Luottokorttitapahtumat 5412 34XX XXXX 1234 - 2022-03-31 12.30.00.csv`,
+//! which identifies the account in place of an IBAN. The contents are
+//! semicolon separated like account exports, but with their own columns:
+//! purchases are dated by the purchase date rather than the billing date,
+//! the merchant is the payee, and purchases made in another currency note the
+//! original amount in the message.
+
+use crate::{
+    amount::Amount,
+    parser::{source_columns, BankParser, FileInfo, RowError, Rows},
+    RawTransaction, Transaction,
+};
+use chrono::NaiveDateTime;
+use csv::{ReaderBuilder, StringRecord};
+use regex::Regex;
+use std::{error::Error, path::Path};
+
+/// Column headers of the fields we read
+const PURCHASE_DATE: &str = "Ostopäivä";
+const BILLING_DATE: &str = "Veloituspäivä";
+const MERCHANT: &str = "Kauppias";
+const AMOUNT: &str = "Määrä";
+const ORIGINAL_AMOUNT: &str = "Alkuperäinen määrä";
+const ORIGINAL_CURRENCY: &str = "Alkuperäinen valuutta";
+
+/// Currency of the card account
+const CARD_CURRENCY: &str = "EUR";
+
+pub struct NordeaCard {
+    file_name_re: Regex,
+}
+
+impl NordeaCard {
+    pub fn new() -> NordeaCard {
+        NordeaCard {
+            file_name_re: Regex::new(r".+ (\d{4} \d{2}XX XXXX \d{4}) - (.+)\.csv").unwrap(),
+        }
+    }
+
+    fn reader(contents: &str) -> csv::Reader<&[u8]> {
+        ReaderBuilder::new()
+            .delimiter(b';')
+            .flexible(true)
+            .from_reader(contents.as_bytes())
+    }
+}
+
+/// Returns whether `account` is the masked number of a card, which card
+/// exports use in place of an IBAN, e.g. "5412 34XX XXXX 1234".
+pub fn is_card_number(account: &str) -> bool {
+    Regex::new(r"^\d{4} \d{2}XX XXXX \d{4}$")
+        .unwrap()
+        .is_match(account)
+}
+
+impl Default for NordeaCard {
+    fn default() -> NordeaCard {
+        NordeaCard::new()
+    }
+}
+
+/// Positions of the columns we read in the header row
+struct Columns {
+    header: StringRecord,
+    /// Number of columns
+    len: usize,
+    purchase_date: usize,
+    merchant: usize,
+    amount: usize,
+    original_amount: Option<usize>,
+    original_currency: Option<usize>,
+}
+
+impl Columns {
+    fn new(header: &StringRecord) -> Option<Columns> {
+        let position = |name: &str| header.iter().position(|h| h == name);
+        Some(Columns {
+            header: header.clone(),
+            len: header.len(),
+            purchase_date: position(PURCHASE_DATE)?,
+            merchant: position(MERCHANT)?,
+            amount: position(AMOUNT)?,
+            original_amount: position(ORIGINAL_AMOUNT),
+            original_currency: position(ORIGINAL_CURRENCY),
+        })
+    }
+
+    fn transaction(&self, record: &StringRecord) -> Result<Transaction, String> {
+        let field = |i: usize| {
+            record
+                .get(i)
+                .ok_or_else(|| format!("expected {} fields, found {}", self.len, record.len()))
+        };
+        let optional = |i: Option<usize>| i.and_then(|i| record.get(i)).unwrap_or_default();
+
+        let currency = optional(self.original_currency);
+        let message = if currency.is_empty() || currency == CARD_CURRENCY {
+            String::new()
+        } else {
+            let original = optional(self.original_amount);
+            let original = original
+                .parse::<Amount>()
+                .map_or_else(|_| original.to_string(), |a| a.to_string());
+            format!("{} {}", original, currency)
+        };
+
+        RawTransaction {
+            date: field(self.purchase_date)?.to_string(),
+            amount: field(self.amount)?.to_string(),
+            description: field(self.merchant)?.to_string(),
+            reference: String::new(),
+            message,
+            counterparty_account: String::new(),
+            value_date: String::new(),
+            payment_date: String::new(),
+            balance: String::new(),
+            currency: String::new(),
+            original_amount: String::new(),
+            original_currency: String::new(),
+            pending: false,
+            columns: source_columns(&self.header, record),
+        }
+        .normalize()
+    }
+}
+
+impl BankParser for NordeaCard {
+    fn name(&self) -> &'static str {
+        "nordea-card"
+    }
+
+    fn parse_file_name(&self, file_name: &str) -> Option<FileInfo> {
+        let captures = self.file_name_re.captures(file_name)?;
+        let date =
+            NaiveDateTime::parse_from_str(captures.get(2)?.as_str(), "%Y-%m-%d %H.%M.%S").ok()?;
+
+        Some(FileInfo {
+            iban: captures.get(1)?.as_str().to_string(),
+            date,
+        })
+    }
+
+    fn detect(&self, contents: &str) -> bool {
+        match NordeaCard::reader(contents).headers() {
+            Ok(header) => {
+                header.iter().any(|h| h == BILLING_DATE) && Columns::new(header).is_some()
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn rows<'a>(&'a self, contents: &'a str, path: &Path) -> Result<Rows<'a>, Box<dyn Error>> {
+        let mut rdr = NordeaCard::reader(contents);
+        let columns = Columns::new(rdr.headers()?).ok_or_else(|| {
+            format!(
+                "{} is missing the {}, {} or {} column",
+                path.display(),
+                PURCHASE_DATE,
+                MERCHANT,
+                AMOUNT
+            )
+        })?;
+
+        Ok(Box::new(rdr.into_records().map(move |result| {
+            let line = match &result {
+                Ok(record) => record.position(),
+                Err(e) => e.position(),
+            }
+            .map_or(0, |p| p.line());
+            result
+                .map_err(|e| e.to_string())
+                .and_then(|record| columns.transaction(&record))
+                .map_err(|message| RowError { line, message })
+        })))
+    }
+}
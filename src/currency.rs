@@ -0,0 +1,150 @@
+//! Conversion of amounts into the budget currency.
+//!
+//! Exchange rates are given as units of each currency per euro, the way the
+//! ECB publishes its reference rates. A rate table file maps currency codes
+//! to rates:
+//!
+//! ```toml
+//! SEK = 11.45
+//! USD = 1.085
+//! ```
+//!
+//! Alternatively the latest ECB reference rates can be fetched. Their address
+//! can be overridden with the `NDA2YNAB_ECB_RATES_URL` environment variable.
+
+use crate::{amount::Amount, http, toml, xml, YnabRow};
+use std::{collections::BTreeMap, env, error::Error, fs, path::Path};
+
+pub const ECB_RATES_URL: &str = "https://www.ecb.europa.eu/stats/eurofxref/eurofxref-daily.xml";
+
+fn ecb_rates_url() -> String {
+    env::var("NDA2YNAB_ECB_RATES_URL").unwrap_or_else(|_| ECB_RATES_URL.to_string())
+}
+
+/// Returns the currency of an account: the one its name ends with for Revolut
+/// and Wise accounts, e.g. "Revolut USD", or otherwise the currency of the
+/// IBAN's country.
+pub fn account_currency(iban: &str) -> &str {
+    if iban.starts_with("Revolut ") || iban.starts_with("Wise ") {
+        if let Some(code) = iban.rsplit(' ').next() {
+            return code;
+        }
+    }
+
+    match iban.get(..2) {
+        Some("SE") => "SEK",
+        Some("DK") => "DKK",
+        Some("NO") => "NOK",
+        _ => "EUR",
+    }
+}
+
+#[derive(Debug)]
+pub struct Rates {
+    /// Units of each currency per euro
+    per_euro: BTreeMap<String, f64>,
+    /// Where the rates came from, for messages
+    source: String,
+}
+
+impl Rates {
+    fn new(mut per_euro: BTreeMap<String, f64>, source: String) -> Rates {
+        per_euro.insert("EUR".to_string(), 1.0);
+        Rates { per_euro, source }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Rates, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read rate table {}: {}", path.display(), e))?;
+        let doc = toml::parse(&contents)
+            .map_err(|e| format!("Could not parse rate table {}: {}", path.display(), e))?;
+
+        let per_euro = doc
+            .into_iter()
+            .map(|(currency, value)| {
+                let rate = match value {
+                    toml::Value::Float(f) => f,
+                    toml::Value::Integer(i) => i as f64,
+                    _ => {
+                        return Err(format!(
+                            "In rate table {}: rate of {} must be a number",
+                            path.display(),
+                            currency
+                        ))
+                    }
+                };
+                Ok((currency.to_uppercase(), rate))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Rates::new(per_euro, path.display().to_string()))
+    }
+
+    /// Fetches the latest ECB euro reference rates.
+    pub fn fetch_ecb() -> Result<Rates, Box<dyn Error>> {
+        let response = http::request("GET", &ecb_rates_url(), &[], None)?;
+        if !response.is_success() {
+            return Err(format!("Could not fetch ECB rates: HTTP {}", response.status).into());
+        }
+        let document =
+            xml::parse(&response.body).map_err(|e| format!("Could not parse ECB rates: {}", e))?;
+
+        // <Cube><Cube time="..."><Cube currency="SEK" rate="11.45"/>...
+        let daily = document
+            .find(&["Cube", "Cube"])
+            .ok_or("No rates found in the ECB response")?;
+        let per_euro = daily
+            .children("Cube")
+            .filter_map(|c| {
+                let rate = c.attribute("rate")?.parse().ok()?;
+                Some((c.attribute("currency")?.to_string(), rate))
+            })
+            .collect();
+        let date = daily.attribute("time").unwrap_or("unknown date");
+
+        Ok(Rates::new(per_euro, format!("ECB rates of {}", date)))
+    }
+
+    /// Returns the number of units of `to` per unit of `from`.
+    pub fn rate(&self, from: &str, to: &str) -> Result<f64, Box<dyn Error>> {
+        let per_euro = |currency: &str| {
+            self.per_euro
+                .get(currency)
+                .copied()
+                .filter(|rate| *rate > 0.0)
+                .ok_or_else(|| format!("No exchange rate for {} in {}", currency, self.source))
+        };
+        Ok(per_euro(to)? / per_euro(from)?)
+    }
+}
+
+/// Converts the amounts of `rows` from `from` to `to`, noting the original
+/// amount and the rate in the memo.
+pub fn convert(
+    rows: &mut [YnabRow],
+    from: &str,
+    to: &str,
+    rates: &Rates,
+) -> Result<(), Box<dyn Error>> {
+    if from == to {
+        return Ok(());
+    }
+
+    let rate = rates.rate(from, to)?;
+    for r in rows {
+        let note = format!("({} {} @ {})", r.amount, from, format_rate(rate));
+        r.amount = Amount::from_cents((r.amount.cents() as f64 * rate).round() as i64);
+        r.memo = if r.memo.is_empty() {
+            note
+        } else {
+            format!("{} {}", r.memo, note)
+        };
+    }
+
+    Ok(())
+}
+
+fn format_rate(rate: f64) -> String {
+    let s = format!("{:.6}", rate);
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
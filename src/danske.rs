@@ -0,0 +1,147 @@
+//! Danske Bank Finland CSV exports.
+//!
+//! Exports of a date range are read from files named like
+//! `Danske_FI12 3456 7890 1234 56_20220301-20220331.csv`, with or without
+//! spaces in the IBAN. The end of the range is used as the export date. The
+//! contents are comma separated with quoted fields, `31.03.2022` dates and
+//! signed amounts with a decimal comma, listed most recent first.
+
+use crate::{
+    group_iban,
+    parser::{source_columns, BankParser, FileInfo, RowError, Rows},
+    RawTransaction, Transaction,
+};
+use chrono::NaiveDate;
+use csv::{ReaderBuilder, StringRecord};
+use regex::Regex;
+use std::{error::Error, path::Path};
+
+const DATE: &str = "Pvm";
+const AMOUNT: &str = "Määrä EUROA";
+const COUNTERPARTY: &str = "Saaja/Maksaja";
+
+pub struct Danske {
+    file_name_re: Regex,
+}
+
+impl Danske {
+    pub fn new() -> Danske {
+        Danske {
+            file_name_re: Regex::new(
+                r"(?i)^danske[ _](FI\d{2}(?: ?\d{4}){3} ?\d{2})[ _]\d{8}-(\d{8})\.csv$",
+            )
+            .unwrap(),
+        }
+    }
+
+    fn reader(contents: &str) -> csv::Reader<&[u8]> {
+        ReaderBuilder::new()
+            .delimiter(b',')
+            .flexible(true)
+            .from_reader(contents.as_bytes())
+    }
+}
+
+impl Default for Danske {
+    fn default() -> Danske {
+        Danske::new()
+    }
+}
+
+/// Positions of the columns we read in the header row
+struct Columns {
+    header: StringRecord,
+    /// Number of columns
+    len: usize,
+    date: usize,
+    amount: usize,
+    counterparty: usize,
+}
+
+impl Columns {
+    fn new(header: &StringRecord) -> Option<Columns> {
+        let position = |name: &str| header.iter().position(|h| h.trim() == name);
+        Some(Columns {
+            header: header.clone(),
+            len: header.len(),
+            date: position(DATE)?,
+            amount: position(AMOUNT)?,
+            counterparty: position(COUNTERPARTY)?,
+        })
+    }
+
+    fn transaction(&self, record: &StringRecord) -> Result<Transaction, String> {
+        let field = |i: usize| {
+            record
+                .get(i)
+                .map(str::trim)
+                .ok_or_else(|| format!("expected {} fields, found {}", self.len, record.len()))
+        };
+
+        RawTransaction {
+            date: field(self.date)?.to_string(),
+            amount: field(self.amount)?.to_string(),
+            description: field(self.counterparty)?.to_string(),
+            reference: String::new(),
+            message: String::new(),
+            counterparty_account: String::new(),
+            value_date: String::new(),
+            payment_date: String::new(),
+            balance: String::new(),
+            currency: String::new(),
+            original_amount: String::new(),
+            original_currency: String::new(),
+            pending: false,
+            columns: source_columns(&self.header, record),
+        }
+        .normalize()
+    }
+}
+
+impl BankParser for Danske {
+    fn name(&self) -> &'static str {
+        "danske"
+    }
+
+    fn parse_file_name(&self, file_name: &str) -> Option<FileInfo> {
+        let captures = self.file_name_re.captures(file_name)?;
+        let end = NaiveDate::parse_from_str(captures.get(2)?.as_str(), "%Y%m%d").ok()?;
+
+        Some(FileInfo {
+            iban: group_iban(captures.get(1)?.as_str()),
+            date: end.and_hms_opt(0, 0, 0)?,
+        })
+    }
+
+    fn detect(&self, contents: &str) -> bool {
+        match Danske::reader(contents).headers() {
+            Ok(header) => Columns::new(header).is_some(),
+            Err(_) => false,
+        }
+    }
+
+    fn rows<'a>(&'a self, contents: &'a str, path: &Path) -> Result<Rows<'a>, Box<dyn Error>> {
+        let mut rdr = Danske::reader(contents);
+        let columns = Columns::new(rdr.headers()?).ok_or_else(|| {
+            format!(
+                "{} is missing the {}, {} or {} column",
+                path.display(),
+                DATE,
+                AMOUNT,
+                COUNTERPARTY
+            )
+        })?;
+
+        Ok(Box::new(rdr.into_records().map(move |result| {
+            let line = match &result {
+                Ok(record) => record.position(),
+                Err(e) => e.position(),
+            }
+            .map_or(0, |p| p.line());
+            result
+                .map_err(|e| e.to_string())
+                .and_then(|record| columns.transaction(&record))
+                .map_err(|message| RowError { line, message })
+        })))
+    }
+}
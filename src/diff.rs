@@ -0,0 +1,117 @@
+//! Removal of already processed transactions from a newer export.
+//!
+//! Each row is identified by its date, amount and description plus an
+//! ordinal that counts identical rows from the oldest one onwards, so that
+//! e.g. two identical coffee purchases on the same day are treated as
+//! distinct transactions.
+//! All keys seen in the previous export are then subtracted from the newer
+//! export. Unlike comparing only the newest row of the previous export, this
+//! is robust to rows that disappear or get reordered between exports, such as
+//! authorisation holds.
+//!
+//! Only the number of occurrences of each distinct row is kept, so that the
+//! rows of large exports can be compared one at a time instead of holding
+//! every row of both exports in memory.
+
+use crate::Transaction;
+use std::collections::HashMap;
+
+#[derive(Debug)]
+pub struct Diff {
+    /// Rows of the newer export not present in the previous export, in the
+    /// original order
+    pub new_rows: Vec<Transaction>,
+
+    /// Number of rows of the newer export that were present in the previous
+    /// export
+    pub overlapping: usize,
+}
+
+/// Identifying fields of a row
+type Key = (String, String, String);
+
+fn key(row: &Transaction) -> Key {
+    let (date, amount, description) = row.key();
+    (
+        date.to_string(),
+        amount.to_string(),
+        description.to_string(),
+    )
+}
+
+/// Number of occurrences of each distinct row of an export.
+#[derive(Clone, Debug, Default)]
+pub struct Counts(HashMap<Key, usize>);
+
+impl Counts {
+    pub fn add(&mut self, row: &Transaction) {
+        *self.0.entry(key(row)).or_insert(0) += 1;
+    }
+
+    /// Returns the number of rows the exports have in common.
+    pub fn common(&self, other: &Counts) -> usize {
+        self.0
+            .iter()
+            .map(|(key, n)| (*n).min(other.0.get(key).copied().unwrap_or(0)))
+            .sum()
+    }
+}
+
+/// Tells apart the new rows of the newer export, given its rows one at a time.
+pub struct Subtraction<'a> {
+    previous: &'a Counts,
+    /// Occurrences of each row of the newer export not given yet, which is
+    /// the ordinal of the next one counting from the oldest
+    remaining: Counts,
+    /// Number of rows given that were present in the previous export
+    pub overlapping: usize,
+}
+
+impl<'a> Subtraction<'a> {
+    /// Starts subtracting the rows counted in `previous` from the rows
+    /// counted in `newest`.
+    pub fn new(newest: Counts, previous: &'a Counts) -> Subtraction<'a> {
+        Subtraction {
+            previous,
+            remaining: newest,
+            overlapping: 0,
+        }
+    }
+
+    /// Returns whether `row` is new. The rows of the newer export must be
+    /// given in their original order, newest first.
+    pub fn is_new(&mut self, row: &Transaction) -> bool {
+        let key = key(row);
+        let previous = self.previous.0.get(&key).copied().unwrap_or(0);
+        let remaining = self.remaining.0.entry(key).or_insert(1);
+        let ordinal = *remaining;
+        *remaining = remaining.saturating_sub(1);
+
+        let new = ordinal > previous;
+        if !new {
+            self.overlapping += 1;
+        }
+        new
+    }
+}
+
+pub fn subtract_previous(newest: &[Transaction], previous: &[Transaction]) -> Diff {
+    let count = |rows: &[Transaction]| {
+        let mut counts = Counts::default();
+        rows.iter().for_each(|r| counts.add(r));
+        counts
+    };
+    let previous = count(previous);
+    let mut subtraction = Subtraction::new(count(newest), &previous);
+
+    let new_rows = newest
+        .iter()
+        .filter(|r| subtraction.is_new(r))
+        .cloned()
+        .collect();
+
+    Diff {
+        new_rows,
+        overlapping: subtraction.overlapping,
+    }
+}
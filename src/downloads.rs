@@ -0,0 +1,66 @@
+//! Locating the Downloads directory for `--auto`.
+//!
+//! On Windows the Downloads folder may have been moved into OneDrive, so
+//! `%OneDrive%\Downloads` is tried before `%USERPROFILE%\Downloads`. On
+//! other systems the XDG download directory is used, from
+//! `$XDG_DOWNLOAD_DIR` or `~/.config/user-dirs.dirs`, where it may have a
+//! translated name such as `~/Lataukset`, and otherwise `~/Downloads`.
+
+use crate::system::{Disk, FileSystem};
+use std::{env, path::PathBuf};
+
+/// Returns the directories that may be the Downloads directory, most likely
+/// first. `var` returns the value of an environment variable.
+pub fn candidates(
+    files: &dyn FileSystem,
+    var: &dyn Fn(&str) -> Option<String>,
+    windows: bool,
+) -> Vec<PathBuf> {
+    let mut dirs = vec![];
+    if windows {
+        for onedrive in ["OneDrive", "OneDriveConsumer", "OneDriveCommercial"] {
+            if let Some(dir) = var(onedrive) {
+                dirs.push(PathBuf::from(dir).join("Downloads"));
+            }
+        }
+        if let Some(profile) = var("USERPROFILE") {
+            dirs.push(PathBuf::from(profile).join("Downloads"));
+        }
+    } else {
+        let home = var("HOME");
+        let expand = |dir: &str| match &home {
+            Some(home) => dir.replace("$HOME", home),
+            None => dir.to_string(),
+        };
+        if let Some(dir) = var("XDG_DOWNLOAD_DIR") {
+            dirs.push(PathBuf::from(expand(&dir)));
+        }
+        let config_home = var("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home.as_ref().map(|h| PathBuf::from(h).join(".config")));
+        if let Some(contents) = config_home
+            .and_then(|dir| files.read(&dir.join("user-dirs.dirs")).ok())
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+        {
+            // Lines like XDG_DOWNLOAD_DIR="$HOME/Downloads"
+            let dir = contents.lines().find_map(|line| {
+                let value = line.trim().strip_prefix("XDG_DOWNLOAD_DIR=")?;
+                Some(value.trim_matches('"').to_string())
+            });
+            if let Some(dir) = dir {
+                dirs.push(PathBuf::from(expand(&dir)));
+            }
+        }
+        if let Some(home) = &home {
+            dirs.push(PathBuf::from(home).join("Downloads"));
+        }
+    }
+    dirs
+}
+
+/// Returns the Downloads directory of the current user, if it exists.
+pub fn find() -> Option<PathBuf> {
+    candidates(&Disk, &|name| env::var(name).ok(), cfg!(windows))
+        .into_iter()
+        .find(|dir| Disk.is_dir(dir))
+}
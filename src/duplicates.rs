@@ -0,0 +1,42 @@
+//! Identical adjacent rows within a single export.
+//!
+//! Nordea has been seen to export the same transaction twice in a row. Two
+//! identical purchases on the same day look just the same, so such rows are
+//! only merged into one when asked to.
+
+use crate::Transaction;
+use clap::ArgEnum;
+use std::error::Error;
+
+/// What to do with identical adjacent rows
+#[derive(ArgEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Duplicates {
+    /// Keep all of the rows, warning about them
+    #[default]
+    Keep,
+    /// Keep only one of the rows
+    Merge,
+    /// Ask whether to merge the rows
+    Ask,
+}
+
+/// Replaces each run of identical adjacent rows with a single row if `merge`
+/// returns true, given the row and the number of identical rows. Returns the
+/// number of rows removed.
+pub fn merge_adjacent(
+    rows: &mut Vec<Transaction>,
+    mut merge: impl FnMut(&Transaction, usize) -> Result<bool, Box<dyn Error>>,
+) -> Result<usize, Box<dyn Error>> {
+    let mut kept = Vec::with_capacity(rows.len());
+    for run in rows.chunk_by(|a, b| a == b) {
+        if run.len() > 1 && merge(&run[0], run.len())? {
+            kept.push(run[0].clone());
+        } else {
+            kept.extend_from_slice(run);
+        }
+    }
+
+    let removed = rows.len() - kept.len();
+    *rows = kept;
+    Ok(removed)
+}
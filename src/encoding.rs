@@ -0,0 +1,71 @@
+//! Text encoding detection and decoding of Nordea CSV exports.
+//!
+//! Exports are usually UTF-8, but some (notably from the desktop netbank)
+//! are Windows-1252 / ISO-8859-1 encoded. In automatic mode, files that are
+//! not valid UTF-8 are decoded as Windows-1252, which is a superset of the
+//! printable range of ISO-8859-1.
+
+use clap::ArgEnum;
+use std::error::Error;
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    /// Detect from byte order mark and UTF-8 validity
+    Auto,
+    #[clap(name = "utf-8")]
+    Utf8,
+    #[clap(name = "windows-1252")]
+    Windows1252,
+    #[clap(name = "iso-8859-1")]
+    Latin1,
+}
+
+/// Characters for bytes 0x80..=0x9F in Windows-1252. Undefined positions map
+/// to the corresponding C1 control characters, like ISO-8859-1.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{81}', '\u{201A}', '\u{192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2C6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8D}', '\u{17D}', '\u{8F}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2DC}', '\u{2122}', '\u{161}', '\u{203A}', '\u{153}', '\u{9D}', '\u{17E}', '\u{178}',
+];
+
+/// Decodes `bytes` into a string using `encoding`, removing any byte order mark.
+pub fn decode(bytes: &[u8], encoding: Encoding) -> Result<String, Box<dyn Error>> {
+    match encoding {
+        Encoding::Auto => {
+            if let Some(utf16) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+                decode_utf16le(utf16)
+            } else {
+                let bytes = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes);
+                match std::str::from_utf8(bytes) {
+                    Ok(s) => Ok(s.to_string()),
+                    Err(_) => Ok(decode_windows_1252(bytes)),
+                }
+            }
+        }
+        Encoding::Utf8 => {
+            let bytes = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes);
+            Ok(String::from_utf8(bytes.to_vec())?)
+        }
+        Encoding::Windows1252 => Ok(decode_windows_1252(bytes)),
+        Encoding::Latin1 => Ok(bytes.iter().map(|&b| b as char).collect()),
+    }
+}
+
+fn decode_windows_1252(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| match b {
+            0x80..=0x9F => WINDOWS_1252_HIGH[(b - 0x80) as usize],
+            _ => b as char,
+        })
+        .collect()
+}
+
+fn decode_utf16le(bytes: &[u8]) -> Result<String, Box<dyn Error>> {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    Ok(String::from_utf16(&units)?)
+}
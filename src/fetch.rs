@@ -0,0 +1,233 @@
+//! Fetching transactions via Nordea Open Banking instead of reading exports.
+//!
+//! Every account covered by the granted access is fetched from the booking
+//! date of the most recent transaction of the previous sync, or from
+//! `--since` (by default 30 days back) on the first sync. Transactions that
+//! were already fetched are remembered in the state file and left out; the
+//! rest are converted and written like the transactions of an export.
+
+use crate::{load_rules, write_accounts, ynab_rows, Consumed, ConvertArgs};
+use chrono::Duration;
+use nda2ynab::{
+    config::Config,
+    count_occurrence, debug, holds, info,
+    lock::Lock,
+    openbanking::{self, Client, Credentials},
+    report::{self, Summary},
+    state::{AccountSync, State, Token},
+    system::{Clock, Disk},
+    Occurrences,
+};
+use std::{
+    error::Error,
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+#[derive(clap::Args, Debug)]
+pub struct FetchArgs {
+    #[clap(flatten)]
+    pub convert: ConvertArgs,
+}
+
+/// Days fetched on the first sync if `--since` is not given
+const FIRST_SYNC_DAYS: i64 = 30;
+
+pub fn run(
+    clock: &dyn Clock,
+    args: &mut ConvertArgs,
+    config: &Config,
+    summary: &mut Summary,
+) -> Result<(), Box<dyn Error>> {
+    if args.current.is_some() {
+        return Err("--current cannot be used when fetching".into());
+    }
+
+    let credentials = Credentials::from_config(&config.nordea)?;
+    let state_path = args.state_path()?;
+    let _lock = Lock::acquire(&state_path.with_extension("lock"), args.wait_for_lock)?;
+    let mut state = State::load(&state_path)?;
+    let token = access_token(clock, &credentials, &mut state, &state_path)?;
+    let client = Client::new(&credentials, &token.access_token);
+
+    let accounts = client.accounts()?;
+    if accounts.is_empty() {
+        return Err("No accounts are accessible with the granted access".into());
+    }
+    // Write one file per account, as with --all-accounts
+    args.all_accounts |= accounts.len() > 1;
+
+    let rules = load_rules(args)?;
+    let rates = args.rates()?;
+    let today = clock.now().date();
+
+    let mut converted = vec![];
+    let mut syncs = vec![];
+    for account in &accounts {
+        let sync = state.sync.get(&account.iban);
+        let from = args
+            .since
+            .or_else(|| sync.map(|s| s.last_date))
+            .unwrap_or_else(|| today - Duration::days(FIRST_SYNC_DAYS));
+        info!(
+            "Fetching transactions of {} since {}",
+            config.account_label(&account.iban),
+            from
+        );
+
+        let mut fetched = client.transactions(&account.id, from)?;
+        fetched.sort_by_key(|f| std::cmp::Reverse(f.transaction.date));
+        fetched.retain(|f| {
+            let date = f.transaction.date;
+            (f.transaction.pending || date >= from) && args.until.is_none_or(|u| date <= u)
+        });
+        let (booked, pending): (Vec<_>, Vec<_>) =
+            fetched.into_iter().partition(|f| !f.transaction.pending);
+
+        let seen = sync.map(|s| s.seen.as_slice()).unwrap_or_default();
+        let total = booked.len();
+        let (old, new): (Vec<_>, Vec<_>) = booked.iter().partition(|f| seen.contains(&f.id));
+        // Numbered after the ones fetched before on the same dates
+        let mut earlier = Occurrences::new();
+        for f in old {
+            count_occurrence(&mut earlier, &f.transaction);
+        }
+        debug!("{} transactions fetched, {} new", total, new.len());
+
+        summary.accounts.push(report::AccountSummary {
+            iban: account.iban.clone(),
+            name: config.account_name(&account.iban).map(String::from),
+            file: "Nordea Open Banking".to_string(),
+            transactions_skipped: total - new.len(),
+            ..Default::default()
+        });
+
+        let mut transactions: Vec<_> = new.iter().map(|f| f.transaction.clone()).collect();
+        let holds: Vec<_> = pending.into_iter().map(|f| f.transaction).collect();
+        if args.track_holds {
+            let tracked = state.holds.entry(account.iban.clone()).or_default();
+            holds::reconcile(tracked, &holds, &transactions, today);
+        }
+        if args.include_pending {
+            let tracked = state
+                .holds
+                .get(&account.iban)
+                .filter(|_| args.track_holds)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            transactions.splice(0..0, holds::pending_rows(&holds, tracked, clock));
+        }
+
+        syncs.push((account.iban.clone(), next_sync(sync, &booked)));
+        let summary_account = summary.accounts.last_mut().expect("account summary");
+        let rows = ynab_rows(
+            args,
+            config,
+            &rules,
+            rates.as_ref(),
+            summary_account,
+            transactions,
+            &earlier,
+        )?;
+        converted.push((account.iban.clone(), rows));
+    }
+
+    let consumed = Consumed {
+        files: vec![vec![]; converted.len()],
+        archive_dir: None,
+        file_system: &Disk,
+    };
+    write_accounts(clock, args, config, &rules, converted, consumed, summary)?;
+
+    if !args.dry_run {
+        for (iban, sync) in syncs {
+            if let Some(sync) = sync {
+                state.sync.insert(iban, sync);
+            }
+        }
+        state.save(&state_path)?;
+    }
+
+    Ok(())
+}
+
+/// Returns the sync progress after fetching `booked`, which are sorted most
+/// recent first.
+fn next_sync(
+    previous: Option<&AccountSync>,
+    booked: &[openbanking::FetchedTransaction],
+) -> Option<AccountSync> {
+    let last_date = booked.first().map(|f| f.transaction.date);
+    let last_date = match (last_date, previous) {
+        (Some(date), Some(p)) if p.last_date > date => p.last_date,
+        (Some(date), _) => date,
+        (None, p) => return p.cloned(),
+    };
+
+    let mut seen: Vec<String> = previous
+        .filter(|p| p.last_date == last_date)
+        .map(|p| p.seen.clone())
+        .unwrap_or_default();
+    for f in booked.iter().filter(|f| f.transaction.date == last_date) {
+        if !seen.contains(&f.id) {
+            seen.push(f.id.clone());
+        }
+    }
+
+    Some(AccountSync { last_date, seen })
+}
+
+/// Returns a valid access token, refreshing it or asking the user to
+/// authorise access if needed. New tokens are saved right away.
+fn access_token(
+    clock: &dyn Clock,
+    credentials: &Credentials,
+    state: &mut State,
+    state_path: &Path,
+) -> Result<Token, Box<dyn Error>> {
+    let now = clock.now();
+    if let Some(token) = state
+        .token
+        .as_ref()
+        .filter(|t| t.expires_at > now + Duration::minutes(1))
+    {
+        return Ok(token.clone());
+    }
+
+    let refreshed = state
+        .token
+        .as_ref()
+        .and_then(|t| t.refresh_token.as_deref())
+        .and_then(
+            |refresh_token| match credentials.refresh(refresh_token, clock) {
+                Ok(token) => Some(token),
+                Err(e) => {
+                    debug!("Could not refresh the access token: {}", e);
+                    None
+                }
+            },
+        );
+
+    let token = match refreshed {
+        Some(token) => token,
+        None => {
+            eprintln!(
+                "Open this address to grant access to your accounts:\n\n{}\n",
+                credentials.authorize_url()
+            );
+            eprint!("Paste the address you were redirected to: ");
+            io::stderr().flush()?;
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line)?;
+            let code = openbanking::code_from_redirect(&line);
+            if code.is_empty() {
+                return Err("No authorisation code given".into());
+            }
+            credentials.exchange_code(&code, clock)?
+        }
+    };
+
+    state.token = Some(token.clone());
+    state.save(state_path)?;
+    Ok(token)
+}
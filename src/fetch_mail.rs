@@ -0,0 +1,77 @@
+//! Saving the exports attached to unread mail with `nda2ynab fetch-mail`,
+//! and converting them like the exports of a directory.
+//!
+//! Only attachments named like exports are saved, and ones already in the
+//! directory are left alone, so forwarding the same export twice does not
+//! overwrite the file that was converted.
+
+use crate::{run_convert, ConvertArgs};
+use nda2ynab::{
+    atomic,
+    config::Config,
+    debug, info,
+    mail::{self, Mailbox},
+    report::Summary,
+    system::{Clock, Disk},
+};
+use std::{error::Error, path::Path};
+
+#[derive(clap::Args, Debug)]
+pub struct FetchMailArgs {
+    /// Directory to save the exports in and convert them from [default: the
+    /// current directory]
+    pub path: Option<String>,
+
+    #[clap(flatten)]
+    pub convert: ConvertArgs,
+}
+
+pub fn run(
+    clock: &dyn Clock,
+    args: &FetchMailArgs,
+    config: &Config,
+    summary: &mut Summary,
+) -> Result<(), Box<dyn Error>> {
+    if args.convert.current.is_some() {
+        return Err("--current cannot be used when fetching mail".into());
+    }
+
+    let mailbox = Mailbox::from_config(&config.mail)?;
+    let registry = args.convert.registry(clock)?;
+    let dir = args.path.as_deref().unwrap_or(".");
+
+    let unread = mailbox.unread()?;
+    info!("{} unread message(s) in the mailbox", unread.len());
+    let mut saved = 0;
+    for uid in unread {
+        for attachment in mail::attachments(&mailbox.message(uid)?) {
+            // Only the name, in case the sender put a path in it
+            let Some(file_name) = Path::new(&attachment.file_name)
+                .file_name()
+                .and_then(|n| n.to_str())
+            else {
+                continue;
+            };
+            if registry.parse_file_name(file_name).is_none() {
+                debug!("Skipping attachment {}, not an export", file_name);
+                continue;
+            }
+            let path = Path::new(dir).join(file_name);
+            if path.exists() {
+                info!("{} is already in {}", file_name, dir);
+                continue;
+            }
+            atomic::write(&path, &attachment.data)
+                .map_err(|e| format!("Could not save {}: {}", path.display(), e))?;
+            info!("Saved {}", path.display());
+            saved += 1;
+        }
+    }
+
+    if saved == 0 {
+        info!("No new exports in the mailbox.");
+        return Ok(());
+    }
+    info!();
+    run_convert(&Disk, clock, dir, &args.convert, config, None, summary)
+}
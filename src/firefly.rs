@@ -0,0 +1,188 @@
+//! Uploading transactions to a Firefly III instance.
+//!
+//! Transactions are created through Firefly III's API with a personal access
+//! token, one request per transaction, in the asset account configured for
+//! each IBAN. Withdrawals go to an expense account and deposits come from a
+//! revenue account named after the payee, which Firefly III creates if
+//! needed. Each transaction gets the same import id as YNAB uploads as its
+//! `external_id`. The account's transactions on the dates of the upload are
+//! looked up first, and the ones whose `external_id` is already there are
+//! skipped, so a failed upload can simply be run again.
+
+use crate::{
+    amount::Amount,
+    http,
+    json::{self, Value},
+    parse_nda_date,
+    ynab::{import_ids, AccountBatch, AccountResult},
+    YnabRow, OUTPUT_DATE_FORMAT,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+};
+
+/// Connection to a Firefly III instance
+pub struct Server<'a> {
+    /// Address of the instance, e.g. `https://firefly.example.com`
+    pub url: &'a str,
+    /// Personal access token, created under Options > Profile > OAuth
+    pub token: &'a str,
+}
+
+impl Server<'_> {
+    fn request(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<Value, Box<dyn Error>> {
+        let response = http::request(
+            method,
+            &format!("{}/api/v1{}", self.url.trim_end_matches('/'), path),
+            &[
+                format!("Authorization: Bearer {}", self.token),
+                "Accept: application/json".to_string(),
+                "Content-Type: application/json".to_string(),
+            ],
+            body.map(Value::to_string).as_deref(),
+        )?;
+        let json = json::parse(&response.body)
+            .map_err(|e| format!("Invalid response from Firefly III: {}", e))?;
+        if !response.is_success() {
+            let detail = json
+                .get("message")
+                .and_then(Value::as_str)
+                .unwrap_or("unknown error");
+            return Err(format!("Firefly III API error ({}): {}", response.status, detail).into());
+        }
+        Ok(json)
+    }
+}
+
+/// Creates the transactions of each batch, whose account ids are Firefly III
+/// asset account ids. Returns the results keyed by account id, failing on the
+/// first transaction that could not be created.
+pub fn upload(
+    server: &Server,
+    batches: &[AccountBatch],
+) -> Result<HashMap<String, AccountResult>, Box<dyn Error>> {
+    let mut results = HashMap::new();
+    for batch in batches {
+        let mut result = AccountResult::default();
+        if !batch.rows.is_empty() {
+            let existing = external_ids(server, batch.account_id, batch.rows)?;
+            for (row, id) in batch.rows.iter().zip(import_ids(batch.rows)?) {
+                if existing.contains(&id) {
+                    result.duplicates += 1;
+                    continue;
+                }
+                let body = Value::object([
+                    ("error_if_duplicate_hash", false.into()),
+                    ("apply_rules", true.into()),
+                    (
+                        "transactions",
+                        Value::Array(vec![transaction(row, batch.account_id, &id)]),
+                    ),
+                ]);
+                server.request("POST", "/transactions", Some(&body))?;
+                result.created += 1;
+            }
+        }
+        results.insert(batch.account_id.to_string(), result);
+    }
+    Ok(results)
+}
+
+/// The Firefly III transaction of a row of asset account `account_id`
+fn transaction(row: &YnabRow, account_id: &str, external_id: &str) -> Value {
+    let mut fields = vec![
+        ("date", row.date.as_str().into()),
+        // Unsigned, the direction is given by the type
+        (
+            "amount",
+            Amount::from_cents(row.amount.cents().abs())
+                .to_string()
+                .into(),
+        ),
+        ("description", row.payee.as_str().into()),
+        ("notes", row.memo.as_str().into()),
+        ("external_id", external_id.into()),
+    ];
+    if row.amount.cents() < 0 {
+        fields.push(("type", "withdrawal".into()));
+        fields.push(("source_id", account_id.into()));
+        fields.push(("destination_name", row.payee.as_str().into()));
+    } else {
+        fields.push(("type", "deposit".into()));
+        fields.push(("source_name", row.payee.as_str().into()));
+        fields.push(("destination_id", account_id.into()));
+    }
+    if let Some(category) = &row.category {
+        fields.push(("category_name", category.as_str().into()));
+    }
+    Value::object(fields)
+}
+
+/// Returns the external ids of the transactions of account `account_id` on
+/// the dates of `rows`.
+fn external_ids(
+    server: &Server,
+    account_id: &str,
+    rows: &[YnabRow],
+) -> Result<HashSet<String>, Box<dyn Error>> {
+    let dates = rows
+        .iter()
+        .map(|r| parse_nda_date(&r.date))
+        .collect::<Result<Vec<_>, _>>()?;
+    let (Some(start), Some(end)) = (dates.iter().min(), dates.iter().max()) else {
+        return Ok(HashSet::new());
+    };
+    let (start, end) = (
+        start.format(OUTPUT_DATE_FORMAT).to_string(),
+        end.format(OUTPUT_DATE_FORMAT).to_string(),
+    );
+
+    let mut ids = HashSet::new();
+    let mut page = 1;
+    loop {
+        let page_number = page.to_string();
+        let json = server.request(
+            "GET",
+            &format!(
+                "/accounts/{}/transactions?{}",
+                http::encode(account_id),
+                http::form(&[("start", &start), ("end", &end), ("page", &page_number)])
+            ),
+            None,
+        )?;
+        for group in json
+            .get("data")
+            .and_then(Value::as_array)
+            .unwrap_or_default()
+        {
+            let journals = group
+                .get("attributes")
+                .and_then(|a| a.get("transactions"))
+                .and_then(Value::as_array)
+                .unwrap_or_default();
+            ids.extend(
+                journals
+                    .iter()
+                    .filter_map(|t| t.get("external_id")?.as_str())
+                    .map(String::from),
+            );
+        }
+
+        let total_pages = json
+            .get("meta")
+            .and_then(|m| m.get("pagination"))
+            .and_then(|p| p.get("total_pages"))
+            .and_then(Value::as_i64)
+            .unwrap_or(1);
+        if page >= total_pages {
+            return Ok(ids);
+        }
+        page += 1;
+    }
+}
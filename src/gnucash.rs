@@ -0,0 +1,43 @@
+//! CSV output in the column layout of GnuCash's transaction importer: Date,
+//! Description, Deposit, Withdrawal and Account, with dates as
+//! `2022-03-31`.
+
+use crate::{config::Config, YnabRow};
+use csv::WriterBuilder;
+use std::{error::Error, io::Write};
+
+/// Returns the GnuCash account of `iban`, falling back to the Beancount and
+/// Ledger account and then the IBAN itself.
+pub fn account_name(config: &Config, iban: &str) -> String {
+    config
+        .account(iban)
+        .and_then(|a| {
+            a.gnucash_account
+                .clone()
+                .or_else(|| a.ledger_account.clone())
+        })
+        .unwrap_or_else(|| iban.to_string())
+}
+
+pub fn write_gnucash<W: Write>(
+    rows: &[YnabRow],
+    account: &str,
+    writer: W,
+) -> Result<(), Box<dyn Error>> {
+    let mut wtr = WriterBuilder::new().from_writer(writer);
+    wtr.write_record(["Date", "Description", "Deposit", "Withdrawal", "Account"])?;
+
+    for r in rows {
+        // Both columns hold positive amounts, the other one is left empty
+        let (deposit, withdrawal) = if r.amount.is_negative() {
+            (String::new(), (-r.amount).to_string())
+        } else {
+            (r.amount.to_string(), String::new())
+        };
+        wtr.write_record([&r.date, &r.payee, &deposit, &withdrawal, account])?;
+    }
+
+    wtr.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,284 @@
+//! History of every transaction converted, per account, kept in a SQLite
+//! database with `--state-backend sqlite`.
+//!
+//! Transactions are remembered once they have been written, and left out of
+//! later conversions, so that exports may overlap in any way, or be
+//! converted again, without creating duplicates. A transaction is identified
+//! by its booking date, amount, description, reference and message, and by
+//! the number of identical ones before it in the same export, so that e.g.
+//! two identical bus tickets on the same day are both kept. Pending
+//! transactions are not remembered, as they may still change.
+
+use crate::{sqlite, Transaction, OUTPUT_DATE_FORMAT};
+use chrono::NaiveDate;
+use clap::ArgEnum;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+/// File name of the database, next to the state file
+pub const FILE_NAME: &str = "history.sqlite";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS transactions (
+    iban TEXT NOT NULL,
+    date TEXT NOT NULL,
+    amount TEXT NOT NULL,
+    description TEXT NOT NULL,
+    reference TEXT NOT NULL,
+    message TEXT NOT NULL,
+    occurrence INTEGER NOT NULL,
+    file TEXT NOT NULL,
+    first_seen TEXT NOT NULL,
+    PRIMARY KEY (iban, date, amount, description, reference, message, occurrence)
+);
+CREATE INDEX IF NOT EXISTS transactions_date ON transactions (date);
+";
+
+/// Where the state between runs is kept.
+#[derive(ArgEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StateBackend {
+    /// The JSON state file only
+    #[default]
+    Json,
+    /// The JSON state file, and the transaction history in a SQLite database
+    Sqlite,
+}
+
+/// Identity of a transaction in the history
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Key {
+    /// Booking date in [`OUTPUT_DATE_FORMAT`]
+    pub date: String,
+    pub amount: String,
+    pub description: String,
+    pub reference: String,
+    pub message: String,
+    /// Number of identical transactions before this one in the export
+    pub occurrence: usize,
+}
+
+/// A transaction in the history
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub iban: String,
+    pub key: Key,
+    /// Export the transaction was first converted from
+    pub file: String,
+    pub first_seen: String,
+}
+
+impl Entry {
+    /// The transaction as far as the history has it: its booking date,
+    /// amount, description, reference and message.
+    pub fn transaction(&self) -> Result<Transaction, Box<dyn Error>> {
+        Ok(Transaction {
+            date: NaiveDate::parse_from_str(&self.key.date, OUTPUT_DATE_FORMAT)?,
+            amount: self.key.amount.parse()?,
+            description: self.key.description.clone(),
+            reference: self.key.reference.clone(),
+            message: self.key.message.clone(),
+            currency: String::new(),
+            counterparty_account: String::new(),
+            value_date: None,
+            payment_date: None,
+            balance: None,
+            original_amount: None,
+            original_currency: String::new(),
+            pending: false,
+            columns: vec![],
+        })
+    }
+}
+
+/// Returns the keys of `rows`, in the same order.
+pub fn keys(rows: &[Transaction]) -> Vec<Key> {
+    let mut counts: HashMap<Key, usize> = HashMap::new();
+    rows.iter()
+        .map(|r| {
+            let mut key = Key {
+                date: r.date.format(OUTPUT_DATE_FORMAT).to_string(),
+                amount: r.amount.to_string(),
+                description: r.description.clone(),
+                reference: r.reference.clone(),
+                message: r.message.clone(),
+                occurrence: 0,
+            };
+            let count = counts.entry(key.clone()).or_default();
+            key.occurrence = *count;
+            *count += 1;
+            key
+        })
+        .collect()
+}
+
+/// The transactions [`remove_seen`] removed and the keys of the ones it kept
+#[derive(Debug, Default)]
+pub struct Removed {
+    pub rows: Vec<Transaction>,
+    /// Keys of the booked transactions kept, numbered among all the rows
+    /// before removing any, for [`History::record`]
+    pub kept: Vec<Key>,
+}
+
+/// Removes the booked transactions in `seen` from `rows` and returns them,
+/// with the keys of the booked transactions left in `rows`.
+pub fn remove_seen(rows: &mut Vec<Transaction>, seen: &HashSet<Key>) -> Removed {
+    let keys = keys(rows);
+    let (removed, kept): (Vec<_>, Vec<_>) = rows
+        .drain(..)
+        .zip(keys)
+        .partition(|(r, key)| !r.pending && seen.contains(key));
+    let (kept, kept_keys): (Vec<_>, Vec<_>) = kept.into_iter().unzip();
+    *rows = kept;
+    Removed {
+        kept: rows
+            .iter()
+            .zip(kept_keys)
+            .filter(|(r, _)| !r.pending)
+            .map(|(_, key)| key)
+            .collect(),
+        rows: removed.into_iter().map(|(r, _)| r).collect(),
+    }
+}
+
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    /// Returns the location of the history kept next to the state file
+    /// `state_path`.
+    pub fn path_for(state_path: &Path) -> PathBuf {
+        state_path.with_file_name(FILE_NAME)
+    }
+
+    /// Opens the database at `path`, creating it if it does not exist.
+    pub fn open(path: &Path) -> Result<History, Box<dyn Error>> {
+        sqlite::execute(path, SCHEMA)?;
+        Ok(History {
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the keys of the transactions of account `iban` in the history
+    /// that are on or after the earliest date of `rows`.
+    pub fn seen(&self, iban: &str, rows: &[Transaction]) -> Result<HashSet<Key>, Box<dyn Error>> {
+        let Some(since) = keys(rows).into_iter().map(|k| k.date).min() else {
+            return Ok(HashSet::new());
+        };
+        let sql = format!(
+            "SELECT date, amount, description, reference, message, occurrence
+             FROM transactions WHERE iban = {} AND date >= {};",
+            sqlite::quote(iban),
+            sqlite::quote(&since)
+        );
+        sqlite::execute(&self.path, &sql)?
+            .into_iter()
+            .map(|row| key(&row))
+            .collect()
+    }
+
+    /// Remembers the booked transactions with `keys` of account `iban`,
+    /// converted from the export `file` on `today`. The keys must be
+    /// numbered among all the rows of the export, as [`remove_seen`] returns
+    /// them, so that an identical transaction after ones already in the
+    /// history is not taken for them. Returns the number of transactions
+    /// added.
+    pub fn record(
+        &self,
+        iban: &str,
+        file: &str,
+        keys: &[Key],
+        today: NaiveDate,
+    ) -> Result<usize, Box<dyn Error>> {
+        let mut sql = String::from("BEGIN;\n");
+        for key in keys {
+            writeln!(
+                sql,
+                "INSERT OR IGNORE INTO transactions VALUES ({}, {}, {}, {}, {}, {}, {}, {}, {});",
+                sqlite::quote(iban),
+                sqlite::quote(&key.date),
+                sqlite::quote(&key.amount),
+                sqlite::quote(&key.description),
+                sqlite::quote(&key.reference),
+                sqlite::quote(&key.message),
+                key.occurrence,
+                sqlite::quote(file),
+                sqlite::quote(&today.format(OUTPUT_DATE_FORMAT).to_string())
+            )
+            .unwrap();
+        }
+        // Ignored inserts of transactions already in the history are not
+        // counted as changes
+        sql.push_str("COMMIT;\nSELECT total_changes();\n");
+        let added = sqlite::execute(&self.path, &sql)?;
+        Ok(added
+            .last()
+            .and_then(|row| row.first())
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(0))
+    }
+
+    /// Removes the transactions of account `iban` that were first converted
+    /// from the export `file` on `date`, when undoing that run. Returns the
+    /// number of transactions removed.
+    pub fn forget(&self, iban: &str, file: &str, date: NaiveDate) -> Result<usize, Box<dyn Error>> {
+        let sql = format!(
+            "DELETE FROM transactions WHERE iban = {} AND file = {} AND first_seen = {};\nSELECT changes();\n",
+            sqlite::quote(iban),
+            sqlite::quote(file),
+            sqlite::quote(&date.format(OUTPUT_DATE_FORMAT).to_string())
+        );
+        let removed = sqlite::execute(&self.path, &sql)?;
+        Ok(removed
+            .last()
+            .and_then(|row| row.first())
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(0))
+    }
+
+    /// Returns the transactions of every account booked on or after `since`,
+    /// oldest first.
+    pub fn since(&self, since: NaiveDate) -> Result<Vec<Entry>, Box<dyn Error>> {
+        let sql = format!(
+            "SELECT date, amount, description, reference, message, occurrence, iban, file, first_seen
+             FROM transactions WHERE date >= {} ORDER BY iban, date, rowid;",
+            sqlite::quote(&since.format(OUTPUT_DATE_FORMAT).to_string())
+        );
+        sqlite::execute(&self.path, &sql)?
+            .into_iter()
+            .map(|row| match &row[..] {
+                [.., iban, file, first_seen] if row.len() == 9 => Ok(Entry {
+                    key: key(&row)?,
+                    iban: iban.clone(),
+                    file: file.clone(),
+                    first_seen: first_seen.clone(),
+                }),
+                _ => Err(format!("Unexpected row in the transaction history: {:?}", row).into()),
+            })
+            .collect()
+    }
+}
+
+/// Reads a key from the first columns of a row of a query.
+fn key(row: &[String]) -> Result<Key, Box<dyn Error>> {
+    match row {
+        [date, amount, description, reference, message, occurrence, ..] => Ok(Key {
+            date: date.clone(),
+            amount: amount.clone(),
+            description: description.clone(),
+            reference: reference.clone(),
+            message: message.clone(),
+            occurrence: occurrence.trim().parse()?,
+        }),
+        _ => Err(format!("Unexpected row in the transaction history: {:?}", row).into()),
+    }
+}
@@ -0,0 +1,115 @@
+//! Reconciliation of authorisation holds.
+//!
+//! Card purchases first show up in exports as holds without a booking date,
+//! and are booked as regular transactions a few days later. Holds are
+//! remembered in the state file until a booked transaction with the same
+//! amount and description appears, so that holds that disappear without
+//! being booked, or stay pending for a long time, can be pointed out.
+
+use crate::{amount::Amount, info, report, state::Hold, system::Clock, Transaction, DATE_FORMAT};
+use chrono::NaiveDate;
+
+/// Holds pending for longer than this are warned about
+pub const MAX_PENDING_DAYS: i64 = 14;
+
+fn same_amount(a: &str, b: Amount) -> bool {
+    a.parse::<Amount>() == Ok(b)
+}
+
+fn same_description(a: &str, b: &str) -> bool {
+    let normalize = |s: &str| {
+        s.split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    };
+    normalize(a) == normalize(b)
+}
+
+fn matches(hold: &Hold, transaction: &Transaction) -> bool {
+    same_amount(&hold.amount, transaction.amount)
+        && same_description(&hold.description, &transaction.description)
+}
+
+/// Updates the tracked holds of an account with the holds and newly booked
+/// transactions of an export made on `export_date`. Returns the number of
+/// holds that settled.
+pub fn reconcile(
+    tracked: &mut Vec<Hold>,
+    holds: &[Transaction],
+    booked: &[Transaction],
+    export_date: NaiveDate,
+) -> usize {
+    let mut settled = 0;
+    let mut used = vec![false; booked.len()];
+    // Holds of the export that are not tracked yet
+    let mut untracked: Vec<&Transaction> = holds.iter().collect();
+    let mut still_pending = vec![];
+
+    for hold in tracked.drain(..) {
+        let booking = booked
+            .iter()
+            .enumerate()
+            .find(|(i, t)| !used[*i] && matches(&hold, t));
+
+        if let Some((i, transaction)) = booking {
+            used[i] = true;
+            settled += 1;
+            info!(
+                "Authorisation hold {} {} settled on {}.",
+                hold.description,
+                hold.amount,
+                transaction.date.format(DATE_FORMAT)
+            );
+        } else if let Some(pos) = untracked.iter().position(|t| matches(&hold, t)) {
+            untracked.remove(pos);
+            let days = (export_date - hold.first_seen).num_days();
+            if days > MAX_PENDING_DAYS {
+                report::warn(format!(
+                    "Authorisation hold {} {} has been pending for {} days.",
+                    hold.description, hold.amount, days
+                ));
+            }
+            still_pending.push(hold);
+        } else {
+            report::warn(format!(
+                "Authorisation hold {} {} (first seen {}) disappeared without being booked.",
+                hold.description, hold.amount, hold.first_seen
+            ));
+        }
+    }
+
+    still_pending.extend(untracked.into_iter().map(|h| Hold {
+        amount: h.amount.to_string(),
+        description: h.description.clone(),
+        first_seen: export_date,
+    }));
+
+    *tracked = still_pending;
+    settled
+}
+
+/// Returns the holds converted with `--include-pending`, dated by their value
+/// date, or else by the day they were first seen if they are `tracked`, or
+/// today by `clock`. Tracked holds keep their date, and with it their import
+/// id, in the runs until they are booked.
+pub fn pending_rows(
+    holds: &[Transaction],
+    tracked: &[Hold],
+    clock: &dyn Clock,
+) -> Vec<Transaction> {
+    let today = clock.now().date();
+    holds
+        .iter()
+        .map(|hold| {
+            let first_seen = tracked
+                .iter()
+                .find(|t| matches(t, hold))
+                .map(|t| t.first_seen);
+            Transaction {
+                date: hold.value_date.or(first_seen).unwrap_or(today),
+                ..hold.clone()
+            }
+        })
+        .collect()
+}
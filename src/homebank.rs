@@ -0,0 +1,34 @@
+//! CSV output in HomeBank's import format: semicolon separated date,
+//! payment mode, info, payee, memo, amount, category and tags, with dates as
+//! `2022-03-31` (to be imported with the y-m-d date order).
+
+use crate::YnabRow;
+use csv::WriterBuilder;
+use std::{error::Error, io::Write};
+
+/// HomeBank's payment mode for transactions of no particular kind
+const PAYMODE_NONE: &str = "0";
+
+pub fn write_homebank<W: Write>(rows: &[YnabRow], writer: W) -> Result<(), Box<dyn Error>> {
+    let mut wtr = WriterBuilder::new().delimiter(b';').from_writer(writer);
+    wtr.write_record([
+        "date", "paymode", "info", "payee", "memo", "amount", "category", "tags",
+    ])?;
+
+    for r in rows {
+        wtr.write_record([
+            r.date.as_str(),
+            PAYMODE_NONE,
+            "",
+            &r.payee,
+            &r.memo,
+            &r.amount.to_string(),
+            r.category.as_deref().unwrap_or_default(),
+            "",
+        ])?;
+    }
+
+    wtr.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,188 @@
+//! A self-contained HTML page describing a run, for reviewing unattended
+//! runs in a browser.
+//!
+//! For each account the page shows the exports used, and the transactions of
+//! the most recent export as a diff against the previously processed one:
+//! included transactions in green and the ones skipped as already processed,
+//! or outside the date range, in grey behind a toggle. Warnings and errors
+//! of the run are listed at the top.
+
+use crate::{
+    report::{AccountSummary, RunError, Summary},
+    OUTPUT_DATE_FORMAT,
+};
+use chrono::NaiveDateTime;
+use std::{error::Error, fmt::Write};
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; color: #222; }
+.status { padding: 0.5em 1em; border-radius: 4px; }
+.ok { background: #e3f5e1; }
+.warning { background: #fff4d6; }
+.error { background: #fbe0e0; }
+table { border-collapse: collapse; margin: 0.5em 0; }
+th, td { padding: 0.2em 0.8em; text-align: left; }
+td.amount { text-align: right; font-family: monospace; }
+tr.included { background: #e3f5e1; }
+tr.skipped { background: #eee; color: #777; }
+summary { cursor: pointer; font-weight: bold; margin: 0.5em 0; }
+";
+
+/// Renders the report of a run that ended with `error`, if any.
+pub fn report(
+    summary: &Summary,
+    warnings: &[String],
+    error: Option<&(dyn Error + 'static)>,
+    generated: NaiveDateTime,
+) -> String {
+    let mut out = String::new();
+    out += "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n";
+    out += "<title>nda2ynab run report</title>\n";
+    writeln!(out, "<style>{}</style>\n</head>\n<body>", STYLE).unwrap();
+    writeln!(
+        out,
+        "<h1>nda2ynab run report</h1>\n<p>{}</p>",
+        generated.format("%Y-%m-%d %H:%M:%S")
+    )
+    .unwrap();
+
+    match error {
+        Some(e) => {
+            let kind = e.downcast_ref::<RunError>().map_or("error", RunError::kind);
+            writeln!(
+                out,
+                "<p class=\"status error\">Failed ({}): {}</p>",
+                kind,
+                escape(&e.to_string())
+            )
+            .unwrap();
+        }
+        None if !warnings.is_empty() => {
+            writeln!(
+                out,
+                "<p class=\"status warning\">Finished with {} warning(s)</p>",
+                warnings.len()
+            )
+            .unwrap();
+        }
+        None => out += "<p class=\"status ok\">Finished</p>\n",
+    }
+
+    if !warnings.is_empty() {
+        out += "<h2>Warnings</h2>\n<ul class=\"warning\">\n";
+        for warning in warnings {
+            writeln!(out, "<li><pre>{}</pre></li>", escape(warning)).unwrap();
+        }
+        out += "</ul>\n";
+    }
+
+    for account in &summary.accounts {
+        write_account(&mut out, account);
+    }
+
+    if !summary.files_considered.is_empty() {
+        out += "<details>\n<summary>Files considered</summary>\n<ul>\n";
+        for file in &summary.files_considered {
+            writeln!(out, "<li>{}</li>", escape(file)).unwrap();
+        }
+        out += "</ul>\n</details>\n";
+    }
+
+    out += "</body>\n</html>\n";
+    out
+}
+
+fn write_account(out: &mut String, account: &AccountSummary) {
+    let label = match &account.name {
+        Some(name) => format!("{} ({})", name, account.iban),
+        None => account.iban.clone(),
+    };
+    writeln!(out, "<h2>{}</h2>", escape(&label)).unwrap();
+    if let Some(error) = &account.error {
+        writeln!(out, "<p class=\"status error\">{}</p>", escape(error)).unwrap();
+    }
+
+    out.push_str("<table>\n");
+    let fields = [
+        ("Export", account.file.clone()),
+        (
+            "Previous export",
+            account.previous_file.clone().unwrap_or("none".into()),
+        ),
+        ("Output", account.output.clone().unwrap_or("none".into())),
+        ("Written", account.transactions_written.to_string()),
+        ("Ignored", account.transactions_ignored.to_string()),
+        (
+            "Already processed",
+            account.transactions_skipped.to_string(),
+        ),
+    ];
+    for (name, value) in fields {
+        writeln!(out, "<tr><th>{}</th><td>{}</td></tr>", name, escape(&value)).unwrap();
+    }
+    out.push_str("</table>\n");
+
+    writeln!(
+        out,
+        "<details open>\n<summary>{} included transaction(s)</summary>",
+        account.rows.len()
+    )
+    .unwrap();
+    write_header(out, "Memo");
+    for r in &account.rows {
+        let amount = r.amount.to_string();
+        write_row(out, "included", [&r.date, &r.payee, &r.memo, &amount]);
+    }
+    out.push_str("</table>\n</details>\n");
+
+    writeln!(
+        out,
+        "<details>\n<summary>{} skipped transaction(s)</summary>",
+        account.skipped_rows.len()
+    )
+    .unwrap();
+    write_header(out, "Message");
+    for r in &account.skipped_rows {
+        // Shown in the same format as the included transactions
+        let date = r.date.format(OUTPUT_DATE_FORMAT).to_string();
+        write_row(
+            out,
+            "skipped",
+            [&date, &r.description, &r.message, &r.amount.to_string()],
+        );
+    }
+    out.push_str("</table>\n</details>\n");
+}
+
+fn write_header(out: &mut String, memo: &str) {
+    writeln!(
+        out,
+        "<table>\n<tr><th></th><th>Date</th><th>Payee</th><th>{}</th><th>Amount</th></tr>",
+        memo
+    )
+    .unwrap();
+}
+
+/// Writes a transaction row of its date, payee, memo and amount, marked like
+/// a line of a diff.
+fn write_row(out: &mut String, class: &str, [date, payee, memo, amount]: [&str; 4]) {
+    let marker = if class == "included" { "+" } else { "" };
+    writeln!(
+        out,
+        "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td class=\"amount\">{}</td></tr>",
+        class,
+        marker,
+        escape(date),
+        escape(payee),
+        escape(memo),
+        escape(amount)
+    )
+    .unwrap();
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
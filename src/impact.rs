@@ -0,0 +1,127 @@
+//! How uploading would change the amounts available in the budget's
+//! categories this month, shown by `--impact`.
+//!
+//! The rows are matched to the budget's categories by the names their rules
+//! give them, ignoring case. Outflows reduce the amount available in their
+//! category and inflows add to it, as they would in YNAB once uploaded and
+//! categorized.
+
+use crate::{amount::Amount, ynab::Category, YnabRow};
+use std::fmt::Write;
+
+/// Change of a category of the budget
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    pub category: Category,
+    /// Number of rows in the category
+    pub count: usize,
+    pub total: Amount,
+}
+
+impl Change {
+    pub fn after(&self) -> Amount {
+        self.category.available + self.total
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Impact {
+    /// Changed categories, in the order of the budget
+    pub changes: Vec<Change>,
+    /// Category names of rows that are not in the budget, with the number of
+    /// rows and their total
+    pub unknown: Vec<(String, usize, Amount)>,
+    /// Number and total of rows without a category
+    pub uncategorized: (usize, Amount),
+}
+
+/// Returns how `rows` would change the amounts available in `categories`.
+pub fn impact(rows: &[YnabRow], categories: &[Category]) -> Impact {
+    let mut impact = Impact::default();
+    let mut changes: Vec<Option<Change>> = vec![None; categories.len()];
+    for r in rows {
+        let Some(name) = &r.category else {
+            impact.uncategorized.0 += 1;
+            impact.uncategorized.1 += r.amount;
+            continue;
+        };
+        match categories
+            .iter()
+            .position(|c| c.name.to_lowercase() == name.to_lowercase())
+        {
+            Some(i) => {
+                let change = changes[i].get_or_insert_with(|| Change {
+                    category: categories[i].clone(),
+                    count: 0,
+                    total: Amount::default(),
+                });
+                change.count += 1;
+                change.total += r.amount;
+            }
+            None => match impact.unknown.iter_mut().find(|(n, _, _)| n == name) {
+                Some((_, count, total)) => {
+                    *count += 1;
+                    *total += r.amount;
+                }
+                None => impact.unknown.push((name.clone(), 1, r.amount)),
+            },
+        }
+    }
+    impact.changes = changes.into_iter().flatten().collect();
+    impact
+}
+
+/// Formats the impact for reading.
+pub fn report(impact: &Impact) -> String {
+    let mut out = String::from("Impact on this month's available amounts:\n");
+    if impact.changes.is_empty() {
+        out.push_str("  No transactions in the budget's categories.\n");
+    }
+    let label = |c: &Category| match c.group.as_str() {
+        "" => c.name.clone(),
+        group => format!("{}: {}", group, c.name),
+    };
+    let width = impact
+        .changes
+        .iter()
+        .map(|c| label(&c.category).chars().count())
+        .max()
+        .unwrap_or(0);
+    for change in &impact.changes {
+        let warning = if change.after().is_negative() && !change.category.available.is_negative() {
+            "  overspent"
+        } else {
+            ""
+        };
+        writeln!(
+            out,
+            "  {:<width$}  {:>10} -> {:>10}  ({}{}, {} transaction(s)){}",
+            label(&change.category),
+            change.category.available.to_string(),
+            change.after().to_string(),
+            if change.total.is_negative() { "" } else { "+" },
+            change.total,
+            change.count,
+            warning
+        )
+        .unwrap();
+    }
+    for (name, count, total) in &impact.unknown {
+        writeln!(
+            out,
+            "  {} transaction(s) of {} in category \"{}\", which is not in the budget",
+            count, total, name
+        )
+        .unwrap();
+    }
+    let (count, total) = impact.uncategorized;
+    if count > 0 {
+        writeln!(
+            out,
+            "  {} uncategorized transaction(s) of {} not counted",
+            count, total
+        )
+        .unwrap();
+    }
+    out
+}
@@ -0,0 +1,92 @@
+//! Plain text accounting output for Beancount and Ledger.
+//!
+//! Each transaction becomes an entry with two postings: the amount on the
+//! asset account of the bank account, and the balancing amount on a
+//! placeholder account, to be replaced with the actual expense or income
+//! account when reviewing the entries. The asset account can be configured
+//! per IBAN and defaults to `Assets:Nordea:<IBAN>`.
+
+use crate::{config::Config, parse_nda_date, YnabRow};
+use std::{error::Error, io::Write};
+
+pub const DEFAULT_PLACEHOLDER: &str = "Expenses:Uncategorized";
+
+pub struct Accounts {
+    pub asset: String,
+    pub placeholder: String,
+}
+
+impl Accounts {
+    pub fn for_iban(config: &Config, iban: &str) -> Accounts {
+        let asset = config
+            .account(iban)
+            .and_then(|a| a.ledger_account.clone())
+            .unwrap_or_else(|| format!("Assets:Nordea:{}", iban.replace(' ', "")));
+        let placeholder = config
+            .ledger
+            .placeholder
+            .clone()
+            .unwrap_or_else(|| DEFAULT_PLACEHOLDER.to_string());
+
+        Accounts { asset, placeholder }
+    }
+}
+
+pub fn write_beancount<W: Write>(
+    rows: &[YnabRow],
+    currency: &str,
+    accounts: &Accounts,
+    mut w: W,
+) -> Result<(), Box<dyn Error>> {
+    // Oldest transactions first, as is customary in ledger files
+    for r in rows.iter().rev() {
+        let date = parse_nda_date(&r.date)?;
+
+        writeln!(
+            w,
+            "{} * \"{}\" \"{}\"",
+            date.format("%Y-%m-%d"),
+            quote(&r.payee),
+            quote(&r.memo)
+        )?;
+        writeln!(w, "  {}  {} {}", accounts.asset, r.amount, currency)?;
+        writeln!(w, "  {}", accounts.placeholder)?;
+        writeln!(w)?;
+    }
+
+    w.flush()?;
+
+    Ok(())
+}
+
+pub fn write_ledger<W: Write>(
+    rows: &[YnabRow],
+    currency: &str,
+    accounts: &Accounts,
+    mut w: W,
+) -> Result<(), Box<dyn Error>> {
+    for r in rows.iter().rev() {
+        let date = parse_nda_date(&r.date)?;
+
+        writeln!(w, "{} * {}", date.format("%Y/%m/%d"), single_line(&r.payee))?;
+        if !r.memo.is_empty() {
+            writeln!(w, "    ; {}", single_line(&r.memo))?;
+        }
+        writeln!(w, "    {}  {} {}", accounts.asset, r.amount, currency)?;
+        writeln!(w, "    {}", accounts.placeholder)?;
+        writeln!(w)?;
+    }
+
+    w.flush()?;
+
+    Ok(())
+}
+
+/// Escapes a Beancount string.
+fn quote(s: &str) -> String {
+    single_line(s).replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn single_line(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
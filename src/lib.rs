@@ -0,0 +1,1463 @@
+//! Conversion of bank CSV exports to YNAB's import formats.
+//!
+//! Exports are found and parsed by the [`parser`] backends, already processed
+//! transactions are removed by comparing the newest export of an account with
+//! the previous one, and the remaining transactions are written as YNAB CSV,
+//! OFX or QIF.
+
+pub mod actual;
+pub mod aggregate;
+pub mod amount;
+pub mod append;
+pub mod archive;
+pub mod atomic;
+pub mod balance;
+pub mod camt;
+pub mod columns;
+pub mod completions;
+pub mod config;
+pub mod console;
+pub mod creditcard;
+pub mod currency;
+pub mod danske;
+pub mod diff;
+pub mod downloads;
+pub mod duplicates;
+pub mod encoding;
+pub mod firefly;
+pub mod gnucash;
+pub mod history;
+pub mod holds;
+pub mod homebank;
+pub mod html;
+pub mod http;
+pub mod impact;
+pub mod json;
+pub mod ledger;
+pub mod locale;
+pub mod lock;
+pub mod log;
+#[cfg(feature = "fetch-mail")]
+pub mod mail;
+pub mod manpage;
+pub mod mapped;
+pub mod memo;
+pub mod memory;
+pub mod nordea;
+pub mod notify;
+pub mod ofx;
+pub mod op;
+pub mod openbanking;
+pub mod output;
+pub mod parser;
+pub mod payees;
+#[cfg(feature = "pdf")]
+pub mod pdf;
+pub mod postprocess;
+pub mod progress;
+pub mod qif;
+pub mod reconcile;
+pub mod recurring;
+pub mod report;
+pub mod revolut;
+pub mod rules;
+pub mod sanitize;
+pub mod spankki;
+pub mod spending;
+pub mod sqlite;
+pub mod state;
+pub mod stats;
+pub mod stitch;
+pub mod system;
+pub mod timezone;
+mod toml;
+pub mod transfers;
+pub mod unicode;
+#[cfg(feature = "self-update")]
+pub mod update;
+pub mod wise;
+pub mod xml;
+pub mod ynab;
+
+use amount::Amount;
+use balance::BalanceCheck;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use clap::ArgEnum;
+use columns::Column;
+use console::Style;
+use csv::WriterBuilder;
+use encoding::Encoding;
+use parser::{Export, Registry, RowError};
+use progress::Progress;
+use report::RunError;
+use rules::{Flag, Rules};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+use system::{Clock, FileSystem};
+
+/// A row of a bank export as read, before its date and amounts are parsed
+/// by [`RawTransaction::normalize`]. The fields hold the values as they appear
+/// in the export, optional columns missing from the export are left empty.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct RawTransaction {
+    pub date: String,
+    pub amount: String,
+    pub description: String,
+
+    #[serde(default)]
+    pub reference: String,
+
+    #[serde(default)]
+    pub message: String,
+
+    #[serde(default)]
+    pub counterparty_account: String,
+
+    #[serde(default)]
+    pub value_date: String,
+
+    #[serde(default)]
+    pub payment_date: String,
+
+    #[serde(default)]
+    pub balance: String,
+
+    #[serde(default)]
+    pub currency: String,
+
+    #[serde(default)]
+    pub original_amount: String,
+
+    #[serde(default)]
+    pub original_currency: String,
+
+    #[serde(skip)]
+    pub pending: bool,
+
+    #[serde(skip)]
+    pub columns: Vec<(String, String)>,
+}
+
+impl RawTransaction {
+    /// Parses the dates and amounts of the row, so that exports made with
+    /// different date and number settings compare equal, and a column that
+    /// cannot be read fails the row instead of being passed on as it is.
+    pub fn normalize(self) -> Result<Transaction, String> {
+        self.normalize_with_decimal(None)
+    }
+
+    /// Like [`RawTransaction::normalize`], reading ambiguous amounts like
+    /// "1.000" by `decimal`, the decimal separator of the export's locale, if
+    /// known.
+    pub fn normalize_with_decimal(self, decimal: Option<char>) -> Result<Transaction, String> {
+        let amount = |amount: &str| Amount::parse_with_decimal(amount, decimal);
+        let date = |date: &str| parse_nda_date(date).map_err(|e| e.to_string());
+        let optional_date = |value: &str| -> Result<Option<NaiveDate>, String> {
+            match value.trim() {
+                "" => Ok(None),
+                value => date(value).map(Some),
+            }
+        };
+        let balance = match self.balance.trim() {
+            "" => None,
+            balance => Some(amount(balance)?),
+        };
+        let original_amount = match self.original_amount.trim() {
+            "" => None,
+            original => Some(amount(original)?),
+        };
+        Ok(Transaction {
+            date: date(&self.date)?,
+            amount: amount(&self.amount)?,
+            currency: self.currency.trim().to_uppercase(),
+            description: unicode::canonicalize(&self.description),
+            reference: unicode::canonicalize(&self.reference),
+            message: unicode::canonicalize(&self.message),
+            counterparty_account: self.counterparty_account,
+            value_date: optional_date(&self.value_date)?,
+            payment_date: optional_date(&self.payment_date)?,
+            balance,
+            original_amount,
+            original_currency: self.original_currency.trim().to_uppercase(),
+            pending: self.pending,
+            columns: self.columns,
+        })
+    }
+}
+
+/// A transaction of a bank export, with its dates and amounts parsed. The
+/// text fields hold the values as they appear in the export, in the
+/// canonical form of [`unicode::canonicalize`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Transaction {
+    /// Booking date, or the date of the export for authorisation holds that
+    /// have not been booked yet
+    pub date: NaiveDate,
+    pub amount: Amount,
+
+    /// Currency of the amount, e.g. `EUR`, if the export has a currency
+    /// column. Empty otherwise, when the amount is in the currency of the
+    /// account.
+    pub currency: String,
+
+    pub description: String,
+
+    /// Payment reference number
+    pub reference: String,
+
+    pub message: String,
+
+    pub counterparty_account: String,
+
+    /// Value date, if the export has one
+    pub value_date: Option<NaiveDate>,
+
+    /// Payment date, if the export has one
+    pub payment_date: Option<NaiveDate>,
+
+    /// Balance of the account after the transaction, if the export has a
+    /// balance column
+    pub balance: Option<Amount>,
+
+    /// Amount in the currency of a card payment or transfer abroad, which
+    /// multicurrency accounts export next to the booked amount
+    pub original_amount: Option<Amount>,
+
+    /// Currency of `original_amount`, e.g. `USD`. Empty if the export has
+    /// no original currency column.
+    pub original_currency: String,
+
+    /// Set for transactions that have not been booked yet, such as
+    /// authorisation holds
+    pub pending: bool,
+
+    /// All columns of the row in a CSV export by their header, as they
+    /// appear in the export, for memo templates. Empty for other sources.
+    pub columns: Vec<(String, String)>,
+}
+
+impl Transaction {
+    /// The fields that identify a transaction when comparing exports. The
+    /// optional columns are left out, as they may be missing from older
+    /// exports of the same transactions.
+    pub fn key(&self) -> (NaiveDate, Amount, &str) {
+        (self.date, self.amount, &self.description)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct YnabRow {
+    pub date: String,
+    pub payee: String,
+    pub memo: String,
+    pub amount: Amount,
+    pub category: Option<String>,
+    /// Flag color set by rules
+    pub flag: Option<Flag>,
+    /// Payment reference number of the transaction
+    pub reference: String,
+    /// Authorisation hold converted with `--include-pending`, uploaded as
+    /// uncleared
+    pub pending: bool,
+    /// Transactions of the export with the same date and amount that were
+    /// left out as already converted, which the occurrence in the row's
+    /// import id is counted on from
+    pub earlier_occurrences: usize,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+pub enum Format {
+    /// YNAB CSV
+    Csv,
+    /// Open Financial Exchange
+    Ofx,
+    /// Quicken Interchange Format
+    Qif,
+    /// Beancount plain text accounting
+    Beancount,
+    /// Ledger plain text accounting
+    Ledger,
+    /// GnuCash CSV import
+    Gnucash,
+    /// HomeBank CSV import
+    Homebank,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        self.writer().extension()
+    }
+}
+
+/// Service that `--upload` sends transactions to
+#[derive(ArgEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum UploadTarget {
+    /// YNAB API
+    #[default]
+    Ynab,
+    /// Actual Budget server, through actual-http-api
+    Actual,
+    /// Firefly III, through its API
+    Firefly,
+}
+
+impl UploadTarget {
+    pub fn name(&self) -> &'static str {
+        self.api().name()
+    }
+}
+
+/// Period by which `--split-by` writes the output into a file each
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+pub enum SplitBy {
+    /// Calendar month of the dates of the rows
+    Month,
+}
+
+impl SplitBy {
+    /// Returns the rows of each period, oldest first, keyed by the period,
+    /// e.g. `2024-03`.
+    pub fn split(&self, rows: &[YnabRow]) -> Vec<(String, Vec<YnabRow>)> {
+        let mut periods: BTreeMap<String, Vec<YnabRow>> = BTreeMap::new();
+        for row in rows {
+            let period = match self {
+                SplitBy::Month => row.date.get(..7).unwrap_or(&row.date),
+            };
+            periods
+                .entry(period.to_string())
+                .or_default()
+                .push(row.clone());
+        }
+        periods.into_iter().collect()
+    }
+
+    /// Returns the path of the file of `period` for the output `path`, e.g.
+    /// `ynab-2024-03.csv` for `ynab.csv`.
+    pub fn path(path: &str, period: &str) -> String {
+        let path = Path::new(path);
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match path.extension() {
+            Some(extension) => format!("{}-{}.{}", stem, period, extension.to_string_lossy()),
+            None => format!("{}-{}", stem, period),
+        };
+        path.with_file_name(name).display().to_string()
+    }
+}
+
+/// Column layout of YNAB CSV files
+#[derive(ArgEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum YnabFormat {
+    /// Date, Payee, Memo and a signed Amount
+    #[default]
+    Amount,
+    /// Date, Payee, Memo, Outflow and Inflow
+    OutflowInflow,
+}
+
+/// Date of a transaction used as the date of the output
+#[derive(ArgEnum, Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DateField {
+    /// Booking date
+    #[default]
+    Booking,
+    /// Value date, or the booking date if the export has none
+    Value,
+    /// Payment date, or the booking date if the export has none
+    Payment,
+}
+
+impl DateField {
+    pub fn date(&self, transaction: &Transaction) -> NaiveDate {
+        let date = match self {
+            DateField::Booking => None,
+            DateField::Value => transaction.value_date,
+            DateField::Payment => transaction.payment_date,
+        };
+        date.unwrap_or(transaction.date)
+    }
+}
+
+/// Options of the YNAB CSV output
+#[derive(Clone, Debug, Default)]
+pub struct CsvOptions {
+    pub columns: YnabFormat,
+    /// Add a Category column, for categories set by rules
+    pub include_category: bool,
+    /// Add a Flag column, for flag colors set by rules
+    pub include_flag: bool,
+    /// Columns to write instead of YNAB's
+    pub schema: Option<Vec<Column>>,
+    /// Headers to write instead of the default ones of the columns
+    pub headers: Vec<(Column, String)>,
+}
+
+impl CsvOptions {
+    /// Returns the columns written.
+    pub fn schema(&self) -> Vec<Column> {
+        self.schema.clone().unwrap_or_else(|| {
+            columns::ynab(self.columns, self.include_category, self.include_flag)
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ParsedFileName {
+    pub file_name: String,
+    pub path: PathBuf,
+    pub date: NaiveDateTime,
+    pub iban: String,
+    /// Name of the parser that reads the file
+    pub bank: &'static str,
+}
+
+/// Default of the age in days after which an export is warned about as stale
+pub const MAX_EXPORT_AGE: u32 = 35;
+
+/// Returns the age of `file` in days by `clock` if it is older than
+/// `max_age` days, and warns about it. A `max_age` of 0 never warns.
+pub fn stale_export_days(file: &ParsedFileName, max_age: u32, clock: &dyn Clock) -> Option<i64> {
+    let age = (clock.now() - file.date).num_days();
+    if max_age == 0 || age <= i64::from(max_age) {
+        return None;
+    }
+    report::warn(format!(
+        "{} was exported {} days ago. Did you forget to download a fresh export? Its transactions may already be in YNAB.",
+        file.file_name, age
+    ));
+    Some(age)
+}
+
+/// Finds exports recognised by `registry` in `path`, and previously archived
+/// exports in `archive` if given, most recent first. Files that are not
+/// recognised by name, such as archived exports, are recognised from their
+/// contents, decoded with `encoding`.
+pub fn find_export_files(
+    files: &dyn FileSystem,
+    path: &str,
+    registry: &Registry,
+    encoding: Encoding,
+    archive: Option<&Path>,
+) -> Result<Vec<ParsedFileName>, Box<dyn Error>> {
+    let mut matches: Vec<ParsedFileName> = files
+        .read_dir(Path::new(path))?
+        .into_iter()
+        .filter_map(|path| {
+            let file_name = path.file_name()?.to_str()?.to_string();
+            let (parser, info) = registry
+                .parse_file_name(&file_name)
+                .or_else(|| registry.inspect(files, &file_name, &path, encoding))?;
+
+            Some(ParsedFileName {
+                file_name,
+                path,
+                date: info.date,
+                iban: info.iban,
+                bank: parser.name(),
+            })
+        })
+        .collect();
+
+    if let Some(archive) = archive.filter(|a| files.is_dir(a)) {
+        let archived = files.read_dir(archive)?.into_iter().filter_map(|path| {
+            let file_name = path.file_name()?.to_str()?.to_string();
+            let (iban, date) = archive::parse_archived_name(&file_name)?;
+            let contents = parser::read_to_string(files, &path, encoding).ok()?;
+            let parser = registry.detect(&contents)?;
+
+            Some(ParsedFileName {
+                file_name,
+                path,
+                date,
+                iban,
+                bank: parser.name(),
+            })
+        });
+
+        matches.extend(archived);
+    }
+
+    // Sort by parsed date in UTC, most recent first, and then by the
+    // modification time
+    let timezone = registry.timezone();
+    let mut keyed: Vec<_> = matches
+        .into_iter()
+        .map(|m| {
+            let modified = files.modified(&m.path).ok();
+            ((timezone.to_utc(m.date, modified), modified), m)
+        })
+        .collect();
+    keyed.sort_by(|(a_key, a), (b_key, b)| {
+        b_key
+            .0
+            .cmp(&a_key.0)
+            .then_with(|| a.iban.cmp(&b.iban))
+            .then_with(|| b_key.1.cmp(&a_key.1))
+    });
+    let mut matches: Vec<ParsedFileName> = keyed.into_iter().map(|(_, m)| m).collect();
+    order_simultaneous_exports(files, &mut matches, registry, encoding);
+
+    Ok(matches)
+}
+
+/// Orders exports of the same account made at the same time, which the file
+/// names cannot tell apart as the current ones only have minute resolution,
+/// by their contents: the export with the most recent transaction, and then
+/// the one with more rows, is taken to be the more recent one. `matches` must
+/// be sorted by date and IBAN.
+fn order_simultaneous_exports(
+    files: &dyn FileSystem,
+    matches: &mut [ParsedFileName],
+    registry: &Registry,
+    encoding: Encoding,
+) {
+    let mut start = 0;
+    while start < matches.len() {
+        let same_time =
+            |m: &ParsedFileName| m.iban == matches[start].iban && m.date == matches[start].date;
+        let len = matches[start..].iter().take_while(|m| same_time(m)).count();
+        let group = &mut matches[start..start + len];
+        start += len;
+        if group.len() < 2 {
+            continue;
+        }
+
+        let contents: Vec<Option<String>> = group
+            .iter()
+            .map(|m| parser::read_to_string(files, &m.path, encoding).ok())
+            .collect();
+        // Copies of the same export, e.g. an export and its archived copy
+        if contents.iter().all(|c| c.is_some() && *c == contents[0]) {
+            continue;
+        }
+
+        let mut keyed: Vec<_> = group
+            .iter()
+            .zip(&contents)
+            .map(|(m, contents)| {
+                let key = contents
+                    .as_deref()
+                    .and_then(|c| content_order(registry, m, c))
+                    .unwrap_or_default();
+                (key, m.path.clone())
+            })
+            .collect();
+        keyed.sort_by_key(|(key, _)| std::cmp::Reverse(*key));
+        group.sort_by_key(|m| keyed.iter().position(|(_, path)| *path == m.path));
+
+        let tied = keyed[0].0 == keyed[1].0;
+        report::warn(format!(
+            "{} exports of {} were made at {}: {}. {}",
+            group.len(),
+            group[0].iban,
+            group[0].date.format("%Y-%m-%d %H:%M"),
+            group
+                .iter()
+                .map(|m| m.file_name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            if tied {
+                format!(
+                    "Their contents do not tell which is more recent, using {}, the most recently modified.",
+                    group[0].file_name
+                )
+            } else {
+                format!(
+                    "Using {} as the most recent one by its transactions.",
+                    group[0].file_name
+                )
+            }
+        ));
+    }
+}
+
+/// Date of the most recent transaction and number of rows of an export, for
+/// ordering exports made at the same time.
+fn content_order(
+    registry: &Registry,
+    file: &ParsedFileName,
+    contents: &str,
+) -> Option<(Option<NaiveDate>, usize)> {
+    let rows = registry.get(file.bank)?.rows(contents, &file.path).ok()?;
+    let mut newest = None;
+    let mut count = 0;
+    for transaction in rows.flatten() {
+        count += 1;
+        newest = newest.max(Some(transaction.date));
+    }
+    Some((newest, count))
+}
+
+/// Identifies explicitly given exports, bypassing the file name patterns.
+/// The bank is detected from the contents, and the account and export time
+/// from the file name or contents if possible, falling back to the file name
+/// and modification time. `previous` is taken to be the previously processed
+/// export of the same account as `current`.
+pub fn explicit_export_files(
+    files: &dyn FileSystem,
+    current: &Path,
+    previous: Option<&Path>,
+    registry: &Registry,
+    encoding: Encoding,
+) -> Result<Vec<ParsedFileName>, Box<dyn Error>> {
+    let identify = |path: &Path| -> Result<ParsedFileName, Box<dyn Error>> {
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| format!("{} is not a file", path.display()))?
+            .to_string_lossy()
+            .into_owned();
+        let contents = parser::read_to_string(files, path, encoding)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let parser = registry
+            .detect(&contents)
+            .ok_or_else(|| format!("{} is not an export of any supported bank", path.display()))?;
+
+        let info = parser
+            .parse_file_name(&file_name)
+            .or_else(|| parser.parse_file_info(&contents));
+        let (iban, date) = match info {
+            Some(info) => (info.iban, info.date),
+            None => {
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                (stem.into_owned(), files.modified(path)?)
+            }
+        };
+
+        Ok(ParsedFileName {
+            file_name,
+            path: path.to_path_buf(),
+            date,
+            iban,
+            bank: parser.name(),
+        })
+    };
+
+    let current = identify(current)?;
+    let previous = previous.map(identify).transpose()?.map(|p| ParsedFileName {
+        iban: current.iban.clone(),
+        ..p
+    });
+
+    Ok(std::iter::once(current).chain(previous).collect())
+}
+
+#[derive(Debug)]
+pub struct ConvertOptions {
+    pub encoding: Encoding,
+    pub since: Option<NaiveDate>,
+    pub until: Option<NaiveDate>,
+    /// Fail instead of skipping rows that cannot be parsed
+    pub strict: bool,
+    /// Draw the progress of reading exports
+    pub progress: bool,
+    /// Convert even if the newest export starts after the previous one ends
+    pub allow_gap: bool,
+    /// Keep the rows left out of conversions, for reports
+    pub keep_skipped: bool,
+    /// Days before the most recent transaction of the previous export within
+    /// which rows are compared with it. Older rows of the newest export are
+    /// taken as already processed.
+    pub overlap_days: u32,
+    /// Pending transactions are converted too, so they are not warned about
+    pub include_pending: bool,
+}
+
+impl ConvertOptions {
+    pub fn has_date_range(&self) -> bool {
+        self.since.is_some() || self.until.is_some()
+    }
+
+    /// Returns whether the transaction date `date` is within the date range.
+    fn in_range(&self, date: NaiveDate) -> bool {
+        let after_since = self.since.is_none_or(|since| date >= since);
+        let before_until = self.until.is_none_or(|until| date <= until);
+        after_since && before_until
+    }
+}
+
+/// Formats an IBAN with or without spaces in the usual groups of four
+/// characters in upper case, e.g. "FI12 3456 7890 1234 56", so that IBANs
+/// written differently compare equal.
+pub fn group_iban(iban: &str) -> String {
+    let compact: Vec<char> = iban
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    compact
+        .chunks(4)
+        .map(|c| c.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Returns a regex matching an IBAN of the country `country`, e.g. "SE", or
+/// of any country, with or without spaces between its characters, e.g.
+/// "SE45 5000 0000 0583 9825 7466" or "DK5000400440116243". IBANs have 15 to
+/// 34 characters.
+pub fn iban_pattern(country: Option<&str>) -> String {
+    format!(
+        r"{}\d{{2}}(?: ?[0-9A-Z]){{11,30}}",
+        country.unwrap_or("[A-Z]{2}")
+    )
+}
+
+/// Date format of transaction dates in messages and memos, e.g. "2022/03/31"
+pub const DATE_FORMAT: &str = "%Y/%m/%d";
+
+/// Date format of [`YnabRow`] dates, e.g. "2022-03-31"
+pub const OUTPUT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Date formats that exports may use depending on their settings. Two digit
+/// years are tried first, as "%Y" would read "24" as the year 24.
+const EXPORT_DATE_FORMATS: &[&str] = &[DATE_FORMAT, OUTPUT_DATE_FORMAT, "%d.%m.%y", "%d.%m.%Y"];
+
+/// Parses a booking date in any of the formats used in Nordea exports, e.g.
+/// "2022/03/31", "2022-03-31" or "31.03.2022".
+pub fn parse_nda_date(date: &str) -> Result<NaiveDate, Box<dyn Error>> {
+    let date = date.trim();
+    EXPORT_DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(date, format).ok())
+        .ok_or_else(|| format!("Could not parse transaction date '{}'", date).into())
+}
+
+/// Numbers of transactions by date and amount
+pub type Occurrences = HashMap<(NaiveDate, Amount), usize>;
+
+/// Adds `row` to the number of transactions on its date with its amount.
+pub fn count_occurrence(occurrences: &mut Occurrences, row: &Transaction) {
+    *occurrences.entry((row.date, row.amount)).or_insert(0) += 1;
+}
+
+#[derive(Debug)]
+pub struct Conversion {
+    pub rows: Vec<Transaction>,
+    /// Number of rows left out because they were already processed
+    pub skipped: usize,
+    /// The booked rows of the most recent file left out, by date and
+    /// amount, so that the import ids of the new rows are numbered after
+    /// the ones the left out rows got before
+    pub earlier: Occurrences,
+    /// The rows left out, if [`ConvertOptions::keep_skipped`] is set
+    pub skipped_rows: Vec<Transaction>,
+    /// Pending transactions of the most recent file, which are not exported
+    pub holds: Vec<Transaction>,
+    /// Balance at the end of the most recent file, if it has a balance column
+    pub balance: Option<Amount>,
+}
+
+impl Conversion {
+    /// Returns whether the export had no transactions at all, e.g. one made
+    /// before anything was booked in the period, which has only its header.
+    pub fn is_empty_export(&self) -> bool {
+        self.rows.is_empty() && self.skipped == 0 && self.holds.is_empty()
+    }
+}
+
+/// Number of unparseable rows listed in warnings
+const MAX_LISTED_ROW_ERRORS: usize = 5;
+
+/// Warns about rows of `file` that could not be parsed, or fails if `strict`.
+fn check_row_errors(
+    errors: &[RowError],
+    file: &ParsedFileName,
+    strict: bool,
+) -> Result<(), Box<dyn Error>> {
+    if errors.is_empty() {
+        return Ok(());
+    }
+
+    let mut message = format!(
+        "{} row(s) of {} could not be parsed:",
+        errors.len(),
+        file.file_name
+    );
+    for e in errors.iter().take(MAX_LISTED_ROW_ERRORS) {
+        message += &format!("\n  line {}: {}", e.line, e.message);
+    }
+    if errors.len() > MAX_LISTED_ROW_ERRORS {
+        message += &format!("\n  and {} more", errors.len() - MAX_LISTED_ROW_ERRORS);
+    }
+
+    if strict {
+        return Err(message.into());
+    }
+    report::warn(message + "\nThese rows were skipped, use --strict to abort instead.");
+    Ok(())
+}
+
+/// Reads the rows of `export`, passing the booked transactions to `f` and
+/// returning the pending ones and the number of rows. Rows that cannot be
+/// parsed are warned about, or fail the export if `strict`.
+fn read_export(
+    export: &Export,
+    file: &ParsedFileName,
+    opts: &ConvertOptions,
+    mut f: impl FnMut(Transaction) -> Result<(), Box<dyn Error>>,
+) -> Result<(Vec<Transaction>, usize), Box<dyn Error>> {
+    let mut progress = Progress::new(opts.progress, &file.file_name, None);
+    let mut transactions = 0;
+    let mut holds = vec![];
+    let mut errors = vec![];
+    for row in export.rows()? {
+        progress.tick();
+        match row {
+            Ok(r) if r.pending => holds.push(r),
+            Ok(r) => {
+                transactions += 1;
+                f(r)?;
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+    progress.finish();
+
+    debug!(
+        "Read {} rows from {} as {}",
+        transactions + holds.len(),
+        file.path.display(),
+        file.bank
+    );
+    check_row_errors(&errors, file, opts.strict)?;
+    let rows = transactions + holds.len() + errors.len();
+    Ok((holds, rows))
+}
+
+fn warn_balance(check: &BalanceCheck, file: &ParsedFileName) {
+    if let Some(warning) = check.warning(&file.file_name) {
+        report::warn(warning);
+    }
+}
+
+fn warn_holds(holds: &[Transaction], file: &ParsedFileName, opts: &ConvertOptions) {
+    if opts.include_pending {
+        if !holds.is_empty() {
+            info!(
+                "Including {} pending transaction(s) of {}",
+                holds.len(),
+                file.file_name
+            );
+        }
+        return;
+    }
+    for hold in holds {
+        report::warn(format!(
+            "Skipping pending transaction in {}, probably an authorisation hold: {} {}",
+            file.file_name, hold.description, hold.amount
+        ));
+    }
+}
+
+/// Returns the booked transactions of `export` within the date range, if
+/// any, without comparing them with a previous export. Used for exports that
+/// have no previous file, e.g. one read from standard input.
+pub fn convert_single(
+    export: &Export,
+    file: &ParsedFileName,
+    opts: &ConvertOptions,
+) -> Result<Conversion, Box<dyn Error>> {
+    let mut rows = vec![];
+    let mut skipped_rows = vec![];
+    let mut earlier = Occurrences::new();
+    let mut total = 0;
+    let mut balance = BalanceCheck::default();
+    let (holds, _) = read_export(export, file, opts, |r| {
+        total += 1;
+        balance.add(&r);
+        if !opts.has_date_range() || opts.in_range(r.date) {
+            rows.push(r);
+            return Ok(());
+        }
+        count_occurrence(&mut earlier, &r);
+        if opts.keep_skipped {
+            skipped_rows.push(r);
+        }
+        Ok(())
+    })?;
+    warn_holds(&holds, file, opts);
+    warn_balance(&balance, file);
+
+    Ok(Conversion {
+        skipped: total - rows.len(),
+        earlier,
+        skipped_rows,
+        rows,
+        holds,
+        balance: balance.ending,
+    })
+}
+
+/// Returns the transactions in the most recent file of `files` that were not
+/// already present in the previous file, reading them from `file_system`.
+/// `files` must all belong to the same account and be sorted most recent
+/// first.
+///
+/// If a date range is given, the previous file is ignored and all
+/// transactions of the most recent file within the range are returned.
+///
+/// Only the rows within [`ConvertOptions::overlap_days`] of the most recent
+/// transaction of the previous file are compared with it, all of them
+/// regardless of their order. Older rows are left out as already processed,
+/// so that e.g. a description Nordea changed later does not make an old
+/// transaction look new.
+///
+/// The most recent file is read twice, first counting its rows and then
+/// keeping only the new ones, so only the new transactions are held in
+/// memory.
+pub fn convert_account(
+    file_system: &dyn FileSystem,
+    files: &[&ParsedFileName],
+    registry: &Registry,
+    opts: &ConvertOptions,
+) -> Result<Conversion, Box<dyn Error>> {
+    let newest_file = files.first().ok_or(RunError::NoFilesFound)?;
+    let exports_overlap = registry
+        .get(newest_file.bank)
+        .is_none_or(|p| p.exports_overlap());
+    let open =
+        |file: &ParsedFileName| registry.open(file_system, file.bank, &file.path, opts.encoding);
+
+    let previous_file = files.get(1).filter(|_| !opts.has_date_range());
+    info!("{}", console::paint(Style::Heading, "Files"));
+    info!("{}", console::field("Main CSV", &newest_file.file_name));
+    if opts.has_date_range() {
+        info!(
+            "{}",
+            console::field("Previous CSV", "none, including all rows in the date range")
+        );
+    } else if let Some(prev_file) = previous_file {
+        info!(
+            "{}",
+            console::field(
+                "Previous CSV",
+                &console::paint(Style::Dim, &prev_file.file_name)
+            )
+        );
+    } else {
+        info!(
+            "{}",
+            console::field("Previous CSV", "none found, including all rows")
+        );
+    }
+
+    let newest = open(newest_file)?;
+    let mut newest_counts = diff::Counts::default();
+    let mut oldest_date = None;
+    let mut balance = BalanceCheck::default();
+    let (holds, newest_len) = read_export(&newest, newest_file, opts, |r| {
+        newest_counts.add(&r);
+        balance.add(&r);
+        // Rows are sorted most recent first, so the last one is the oldest
+        oldest_date = Some(r.date);
+        Ok(())
+    })?;
+    warn_holds(&holds, newest_file, opts);
+    warn_balance(&balance, newest_file);
+    if newest_len == 0 {
+        // Nothing to compare with the previous file
+        info!(
+            "{} has no transactions, only its header.\n",
+            newest_file.file_name
+        );
+        return Ok(Conversion {
+            rows: vec![],
+            skipped: 0,
+            earlier: Occurrences::new(),
+            skipped_rows: vec![],
+            holds,
+            balance: None,
+        });
+    }
+
+    // Try to find previous csv file and remove all previously processed rows
+    let mut previous_counts = diff::Counts::default();
+    let mut window_start = None;
+    if let Some(mut prev_file) = previous_file.copied() {
+        let mut most_recent = None;
+        // An export without transactions does not tell what was processed,
+        // so the one before it is compared with instead
+        for &file in &files[1..] {
+            prev_file = file;
+            read_export(&open(file)?, file, opts, |r| {
+                previous_counts.add(&r);
+                most_recent.get_or_insert(r);
+                Ok(())
+            })?;
+            if most_recent.is_some() {
+                break;
+            }
+            info!("{} has no transactions.", file.file_name);
+        }
+
+        // Without transactions in any previous export, all rows are new
+        if let Some(most_recent) = most_recent.filter(|_| exports_overlap) {
+            let previous_date = most_recent.date;
+            window_start = Some(previous_date - Duration::days(opts.overlap_days.into()));
+            let gap = oldest_date.is_some_and(|oldest| oldest > previous_date);
+            if gap {
+                let message = format!(
+                    "The oldest transaction in {} is from {}, after the most recent one in {} from {}. Transactions in between are missing from both exports.",
+                    newest_file.file_name,
+                    oldest_date.expect("oldest date").format(OUTPUT_DATE_FORMAT),
+                    prev_file.file_name,
+                    previous_date.format(OUTPUT_DATE_FORMAT)
+                );
+                if !opts.allow_gap {
+                    error!("Error: {}", message);
+                    error!("Download an export that reaches back to the previously processed one, or use");
+                    error!("--allow-gap to convert the main CSV anyway.");
+                    error!();
+                    return Err(RunError::Gap.into());
+                }
+                report::warn(message + "\nConverting anyway because of --allow-gap.");
+            } else if newest_counts.common(&previous_counts) == 0 {
+                error!("Error: None of the transactions in the previously processed CSV were found in the main CSV.");
+                error!("Make sure the most recent CSV contains at least the entire last day worth of transactions");
+                error!("from the previously processed CSV file.");
+                error!();
+                error!(
+                    "Most recent previously processed transaction: {:#?}\n",
+                    most_recent
+                );
+                return Err(RunError::NonOverlapping.into());
+            }
+        }
+    }
+    // After the warnings about the files
+    info!();
+
+    let mut subtraction = diff::Subtraction::new(newest_counts, &previous_counts);
+    let mut progress = Progress::new(opts.progress, &newest_file.file_name, Some(newest_len));
+    let mut total = 0;
+    let mut before_window = 0;
+    let mut rows = vec![];
+    let mut skipped_rows = vec![];
+    let mut earlier = Occurrences::new();
+    // Parse errors and holds were already reported when counting the rows
+    for row in newest.rows()? {
+        progress.tick();
+        let r = match row {
+            Ok(r) if !r.pending => r,
+            _ => continue,
+        };
+        total += 1;
+        let old = match window_start {
+            Some(start) => r.date < start,
+            None => false,
+        };
+        before_window += usize::from(old);
+        if old || !subtraction.is_new(&r) || (opts.has_date_range() && !opts.in_range(r.date)) {
+            count_occurrence(&mut earlier, &r);
+            if opts.keep_skipped {
+                skipped_rows.push(r);
+            }
+            continue;
+        }
+
+        trace!("New transaction: {} {} {}", r.date, r.amount, r.description);
+        rows.push(r);
+    }
+    progress.finish();
+
+    if previous_file.is_some() {
+        debug!(
+            "{} rows already processed, {} older than the overlap window, {} new",
+            subtraction.overlapping,
+            before_window,
+            rows.len()
+        );
+    }
+
+    Ok(Conversion {
+        skipped: total - rows.len(),
+        earlier,
+        skipped_rows,
+        rows,
+        holds,
+        balance: balance.ending,
+    })
+}
+
+/// Returns the transactions of all of `files` in `file_system` merged
+/// together, each transaction once, for converting the whole history of an
+/// account at once.
+/// `files` must all belong to the same account and be sorted most recent
+/// first.
+///
+/// The files are stitched into one timeline by the dates they cover with
+/// [`stitch::stitch`], so exports with overlapping date ranges are merged in
+/// whichever order they were downloaded. Dates between the files that none
+/// of them covers, and files without any transactions in common with the
+/// ones covering the same dates, are warned about, as transactions may be
+/// missing.
+pub fn backfill_account(
+    file_system: &dyn FileSystem,
+    files: &[&ParsedFileName],
+    registry: &Registry,
+    opts: &ConvertOptions,
+) -> Result<Conversion, Box<dyn Error>> {
+    let newest_file = files.first().ok_or(RunError::NoFilesFound)?;
+    info!("{}", console::paint(Style::Heading, "Files"));
+    info!(
+        "{}",
+        console::field(
+            "Merging",
+            &format!("{} export(s) of {}", files.len(), newest_file.iban)
+        )
+    );
+    info!("{}", console::field("Most recent", &newest_file.file_name));
+    info!();
+
+    let mut exports = vec![];
+    let mut holds = vec![];
+    let mut balance = None;
+    let mut total = 0;
+    for file in files.iter().rev() {
+        let export = registry.open(file_system, file.bank, &file.path, opts.encoding)?;
+        let mut rows = vec![];
+        let mut check = BalanceCheck::default();
+        let (file_holds, _) = read_export(&export, file, opts, |r| {
+            check.add(&r);
+            rows.push(r);
+            Ok(())
+        })?;
+        debug!("{} transactions in {}", rows.len(), file.file_name);
+        total += rows.len();
+        // The holds and balance of the most recent file, read last
+        holds = file_holds;
+        warn_balance(&check, file);
+        balance = check.ending;
+        exports.push(stitch::Export {
+            file_name: &file.file_name,
+            rows,
+        });
+    }
+
+    // Only the holds of the most recent file are still pending
+    warn_holds(&holds, newest_file, opts);
+
+    let exports_overlap = registry
+        .get(newest_file.bank)
+        .is_none_or(|p| p.exports_overlap());
+    let timeline = stitch::stitch(exports, exports_overlap);
+    for (previous, file) in &timeline.unrelated {
+        report::warn(format!(
+            "None of the transactions of {} were found in {}, transactions between them may be missing",
+            previous, file
+        ));
+    }
+    for gap in &timeline.gaps {
+        report::warn(format!(
+            "No export of {} covers {} to {}, between {} and {}; transactions in between may be missing",
+            newest_file.iban,
+            gap.from.format(DATE_FORMAT),
+            gap.to.format(DATE_FORMAT),
+            gap.after,
+            gap.before
+        ));
+    }
+
+    let mut skipped_rows = if opts.keep_skipped {
+        timeline.duplicates
+    } else {
+        vec![]
+    };
+    let mut rows = vec![];
+    let mut earlier = Occurrences::new();
+    for r in timeline.rows {
+        if opts.has_date_range() && !opts.in_range(r.date) {
+            count_occurrence(&mut earlier, &r);
+            if opts.keep_skipped {
+                skipped_rows.push(r);
+            }
+            continue;
+        }
+        rows.push(r);
+    }
+
+    Ok(Conversion {
+        skipped: total - rows.len(),
+        earlier,
+        skipped_rows,
+        rows,
+        holds,
+        balance,
+    })
+}
+
+/// Converts the files of each account in `file_system` with
+/// [`convert_account`], or [`backfill_account`] if `backfill` is set, using
+/// up to `jobs` threads.
+/// Each account is given with the name shown in messages. The results are
+/// returned in the order of `accounts`, but the messages of accounts
+/// converted at the same time are interleaved.
+pub fn convert_accounts(
+    file_system: &dyn FileSystem,
+    accounts: &[(String, Vec<&ParsedFileName>)],
+    registry: &Registry,
+    opts: &ConvertOptions,
+    backfill: bool,
+    jobs: usize,
+) -> Vec<Result<Conversion, Box<dyn Error>>> {
+    let convert = |(label, files): &(String, Vec<&ParsedFileName>)| {
+        if accounts.len() > 1 {
+            info!(
+                "{}\n",
+                console::paint(Style::Heading, &format!("Converting account {}", label))
+            );
+        }
+        if backfill {
+            backfill_account(file_system, files, registry, opts)
+        } else {
+            convert_account(file_system, files, registry, opts)
+        }
+    };
+    let jobs = jobs.clamp(1, accounts.len().max(1));
+    if jobs == 1 {
+        return accounts.iter().map(convert).collect();
+    }
+
+    // Errors are not Send, so they are passed between threads as strings,
+    // except for the run errors that have their own exit codes
+    let send = |result: Result<Conversion, Box<dyn Error>>| {
+        result.map_err(|e| -> Box<dyn Error + Send + Sync> {
+            match e.downcast::<RunError>() {
+                Ok(e) => e,
+                Err(e) => e.to_string().into(),
+            }
+        })
+    };
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new((0..accounts.len()).map(|_| None).collect::<Vec<_>>());
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| {
+                // Each thread takes the next account not taken yet
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(account) = accounts.get(index) else {
+                        break;
+                    };
+                    let result = send(convert(account));
+                    results.lock().unwrap()[index] = Some(result);
+                }
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| {
+            result
+                .expect("every account converted")
+                .map_err(|e| -> Box<dyn Error> { e })
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+pub struct Filtered {
+    pub rows: Vec<Transaction>,
+    /// Number of ignored transactions per ignore rule name
+    pub ignored: BTreeMap<String, usize>,
+}
+
+/// Removes the transactions matching an ignore rule. Amounts are compared
+/// after inverting them if `invert` is set.
+pub fn remove_ignored(rows: Vec<Transaction>, rules: &Rules, invert: bool) -> Filtered {
+    let mut kept = Vec::with_capacity(rows.len());
+    let mut ignored = BTreeMap::new();
+    for r in rows {
+        let payee = rules.clean_payee(&r.description);
+        let amount = if invert { -r.amount } else { r.amount };
+
+        match rules.ignored(&r.description, &payee, amount) {
+            Some(name) => *ignored.entry(name.to_string()).or_insert(0) += 1,
+            None => kept.push(r),
+        }
+    }
+
+    Filtered {
+        rows: kept,
+        ignored,
+    }
+}
+
+/// Converts the rows to YNAB rows, rendering memos
+/// from `memo_template`. Card purchases matching a cashback rule become the
+/// purchase and a transfer of the cash to the rule's account, and other
+/// transactions matching a split rule become one row per part. If `invert`
+/// is set, the signs of all amounts are flipped. The date of
+/// the rows is taken from `date_field`.
+pub fn to_ynab_rows(
+    rows: Vec<Transaction>,
+    rules: &Rules,
+    memo_template: &str,
+    invert: bool,
+    date_field: DateField,
+) -> Vec<YnabRow> {
+    to_ynab_rows_after(
+        rows,
+        &Occurrences::new(),
+        rules,
+        memo_template,
+        invert,
+        date_field,
+    )
+}
+
+/// Converts the rows to YNAB rows like [`to_ynab_rows`], numbering the
+/// occurrences in their import ids after the `earlier` transactions of the
+/// export with the same date and amount, e.g. the ones in
+/// [`Conversion::earlier`] that were converted by an earlier run.
+pub fn to_ynab_rows_after(
+    rows: Vec<Transaction>,
+    earlier: &Occurrences,
+    rules: &Rules,
+    memo_template: &str,
+    invert: bool,
+    date_field: DateField,
+) -> Vec<YnabRow> {
+    let mut ynab_rows = Vec::with_capacity(rows.len());
+    for r in rows {
+        let earlier_occurrences = earlier.get(&(r.date, r.amount)).copied().unwrap_or(0);
+        let payee = rules.clean_payee(&r.description);
+        let category = rules.category(&r.description, &payee).map(String::from);
+        let amount = if invert { -r.amount } else { r.amount };
+        // Split rows are flagged by the amount of the whole transaction
+        let flag = rules.flag(&r.description, &payee, amount);
+        let tags = rules.tags(&r.description, &payee, amount);
+        let memo = tagged(memo::render(memo_template, &r), &tags);
+        let date = date_field.date(&r).format(OUTPUT_DATE_FORMAT).to_string();
+
+        if let Some((cash, rule)) = rules.cashback(&r.description, &payee, &r.message, amount) {
+            for (payee, amount, category) in [
+                (payee.clone(), amount + -cash, category),
+                (format!("Transfer : {}", rule.account), cash, None),
+            ] {
+                ynab_rows.push(YnabRow {
+                    date: date.clone(),
+                    payee,
+                    memo: memo.clone(),
+                    amount,
+                    category,
+                    flag,
+                    reference: r.reference.clone(),
+                    pending: r.pending,
+                    earlier_occurrences,
+                });
+            }
+            continue;
+        }
+
+        match rules.split(&r.description, &payee) {
+            Some(split) => {
+                for (amount, part) in split.split(amount) {
+                    ynab_rows.push(YnabRow {
+                        date: date.clone(),
+                        payee: payee.clone(),
+                        memo: match &part.memo {
+                            Some(part_memo) => tagged(part_memo.clone(), &tags),
+                            None => memo.clone(),
+                        },
+                        amount,
+                        category: part.category.clone().or_else(|| category.clone()),
+                        flag,
+                        reference: r.reference.clone(),
+                        pending: r.pending,
+                        earlier_occurrences,
+                    });
+                }
+            }
+            None => ynab_rows.push(YnabRow {
+                memo,
+                date,
+                payee,
+                amount,
+                category,
+                flag,
+                reference: r.reference,
+                pending: r.pending,
+                earlier_occurrences,
+            }),
+        }
+    }
+
+    ynab_rows
+}
+
+/// Appends the tags missing from `memo` to it, separated by spaces.
+fn tagged(mut memo: String, tags: &[&str]) -> String {
+    for tag in tags {
+        if !memo.split_whitespace().any(|word| word == *tag) {
+            if !memo.is_empty() {
+                memo.push(' ');
+            }
+            memo.push_str(tag);
+        }
+    }
+    memo
+}
+
+/// Writes the rows of `account` in YNAB's CSV import format, with the
+/// import id of each row. The amount is written in the columns of
+/// `opts.columns`, and the Category and Flag columns are only included if
+/// `opts.include_category` and `opts.include_flag` are set. With
+/// `opts.schema`, its columns are written instead. Line breaks in the values
+/// are written as spaces, also with `--keep-control-chars`, as YNAB's
+/// importer takes them for the end of the row even in quoted fields.
+pub fn write_ynab_csv<W: io::Write>(
+    rows: &[YnabRow],
+    opts: &CsvOptions,
+    account: &columns::Account,
+    writer: W,
+) -> Result<(), Box<dyn Error>> {
+    let mut wtr = WriterBuilder::new().from_writer(writer);
+
+    let schema = opts.schema();
+    let header = schema.iter().map(|column| {
+        opts.headers
+            .iter()
+            .find(|(c, _)| c == column)
+            .map_or(column.name(), |(_, header)| header.as_str())
+    });
+    wtr.write_record(header)?;
+
+    // Same ids as uploads get, so YNAB can tell apart rows that were already
+    // imported from an overlapping file. They are numbered after the rows of
+    // the export imported before, not only among the rows written.
+    let import_ids = ynab::import_ids(rows)?;
+    for (r, import_id) in rows.iter().zip(&import_ids) {
+        let record = schema.iter().map(|&column| {
+            columns::value(column, r, import_id, account).replace(['\r', '\n'], " ")
+        });
+        wtr.write_record(record)?;
+    }
+
+    wtr.flush()?;
+
+    Ok(())
+}
+
+/// Writes the rows of the account of `context` in `format`.
+pub fn write_output<W: io::Write>(
+    rows: &[YnabRow],
+    format: Format,
+    context: &output::Context,
+    mut writer: W,
+) -> Result<(), Box<dyn Error>> {
+    format.writer().write(rows, context, &mut writer)
+}
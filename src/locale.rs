@@ -0,0 +1,233 @@
+//! Localized Nordea export formats.
+//!
+//! Nordea's Finnish, Swedish, Danish and Norwegian netbanks export the same
+//! CSV layout with translated column headers and country specific IBANs in
+//! the file name. Headers are translated to the field names of
+//! [`RawTransaction`](crate::RawTransaction) before deserializing.
+
+use clap::ArgEnum;
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Locale {
+    Fi,
+    Se,
+    Dk,
+    No,
+}
+
+/// Column headers used by a locale for the fields we read.
+pub struct Headers {
+    pub date: &'static str,
+    pub amount: &'static str,
+    pub description: &'static str,
+    /// Names of optional columns, which differ between export versions
+    pub reference: &'static [&'static str],
+    pub message: &'static [&'static str],
+    pub counterparty_account: &'static [&'static str],
+    pub value_date: &'static [&'static str],
+    pub payment_date: &'static [&'static str],
+    pub balance: &'static [&'static str],
+    pub currency: &'static [&'static str],
+    /// Amount and currency of a transaction in a foreign currency, which
+    /// multicurrency accounts export next to the booked amount
+    pub original_amount: &'static [&'static str],
+    pub original_currency: &'static [&'static str],
+    /// Other columns of known export versions, which are not read
+    pub other: &'static [&'static str],
+}
+
+impl Headers {
+    /// The columns an export needs
+    pub fn required(&self) -> [&'static str; 3] {
+        [self.date, self.amount, self.description]
+    }
+
+    /// Returns whether `column` is a column of some version of the exports.
+    pub fn known(&self, column: &str) -> bool {
+        self.required().contains(&column)
+            || [
+                self.reference,
+                self.message,
+                self.counterparty_account,
+                self.value_date,
+                self.payment_date,
+                self.balance,
+                self.currency,
+                self.original_amount,
+                self.original_currency,
+                self.other,
+            ]
+            .iter()
+            .any(|names| names.contains(&column))
+    }
+}
+
+impl Locale {
+    pub const ALL: [Locale; 4] = [Locale::Fi, Locale::Se, Locale::Dk, Locale::No];
+
+    pub fn headers(&self) -> Headers {
+        match self {
+            Locale::Fi => Headers {
+                date: "Kirjauspäivä",
+                amount: "Määrä",
+                description: "Otsikko",
+                reference: &["Viite", "Viitenumero"],
+                message: &["Viesti"],
+                counterparty_account: &["Tilinumero"],
+                value_date: &["Arvopäivä"],
+                payment_date: &["Maksupäivä"],
+                balance: &["Saldo"],
+                currency: &["Valuutta"],
+                original_amount: &["Alkuperäinen summa"],
+                original_currency: &["Alkuperäinen valuutta"],
+                other: &[
+                    "Maksaja",
+                    "Maksunsaaja",
+                    "Nimi",
+                    "Saaja/Maksaja",
+                    "BIC",
+                    "Tapahtuma",
+                    "Maksajan viite",
+                    "Kortinnumero",
+                    "Kuitti",
+                ],
+            },
+            Locale::Se => Headers {
+                date: "Bokföringsdag",
+                amount: "Belopp",
+                description: "Rubrik",
+                reference: &[],
+                message: &[],
+                counterparty_account: &[],
+                value_date: &[],
+                payment_date: &[],
+                balance: &["Saldo"],
+                currency: &["Valuta"],
+                original_amount: &["Ursprungligt belopp"],
+                original_currency: &["Ursprunglig valuta"],
+                other: &[],
+            },
+            Locale::Dk => Headers {
+                date: "Bogføringsdato",
+                amount: "Beløb",
+                description: "Beskrivelse",
+                reference: &[],
+                message: &[],
+                counterparty_account: &[],
+                value_date: &[],
+                payment_date: &[],
+                balance: &["Saldo"],
+                currency: &["Valuta"],
+                original_amount: &["Oprindeligt beløb"],
+                original_currency: &["Oprindelig valuta"],
+                other: &[],
+            },
+            Locale::No => Headers {
+                date: "Bokføringsdato",
+                amount: "Beløp",
+                description: "Tittel",
+                reference: &[],
+                message: &[],
+                counterparty_account: &[],
+                value_date: &[],
+                payment_date: &[],
+                balance: &["Saldo"],
+                currency: &["Valuta"],
+                original_amount: &["Opprinnelig beløp"],
+                original_currency: &["Opprinnelig valuta"],
+                other: &[],
+            },
+        }
+    }
+
+    /// Value of the locale for `--locale`, e.g. "fi"
+    pub fn code(&self) -> &'static str {
+        match self {
+            Locale::Fi => "fi",
+            Locale::Se => "se",
+            Locale::Dk => "dk",
+            Locale::No => "no",
+        }
+    }
+
+    /// Country code of the IBANs of the locale, e.g. "FI"
+    pub fn country(&self) -> &'static str {
+        match self {
+            Locale::Fi => "FI",
+            Locale::Se => "SE",
+            Locale::Dk => "DK",
+            Locale::No => "NO",
+        }
+    }
+
+    /// Decimal separator of amounts in the locale's number format, for reading
+    /// amounts like "1.000" that could be one or a thousand
+    pub fn decimal_separator(&self) -> char {
+        match self {
+            Locale::Fi | Locale::Se | Locale::Dk | Locale::No => ',',
+        }
+    }
+
+    /// Regex matching the IBAN as it is printed in statements, e.g.
+    /// "FI12 3456 7890 1234 56"
+    pub fn iban_pattern(&self) -> &'static str {
+        match self {
+            Locale::Fi => r"FI\d{2} \d{4} \d{4} \d{4} \d{2}",
+            Locale::Se => r"SE\d{2} \d{4} \d{4} \d{4} \d{4} \d{4}",
+            Locale::Dk => r"DK\d{2} \d{4} \d{4} \d{4} \d{2}",
+            Locale::No => r"NO\d{2} \d{4} \d{4} \d{3}",
+        }
+    }
+
+    /// Detects the locale of an export from its header row, which needs the
+    /// date, amount and description columns of the locale. Other Finnish
+    /// banks also have a "Kirjauspäivä" column, for example.
+    pub fn detect(header: &csv::StringRecord) -> Option<Locale> {
+        Locale::ALL.into_iter().find(|locale| {
+            locale
+                .headers()
+                .required()
+                .iter()
+                .all(|name| header.iter().any(|column| column == *name))
+        })
+    }
+
+    /// Translates the header row of an export in this locale to the field
+    /// names of [`RawTransaction`](crate::RawTransaction).
+    pub fn normalize_header(&self, header: &csv::StringRecord) -> csv::StringRecord {
+        let from = self.headers();
+
+        header
+            .iter()
+            .map(|h| {
+                if h == from.date {
+                    "date"
+                } else if h == from.amount {
+                    "amount"
+                } else if h == from.description {
+                    "description"
+                } else if from.reference.contains(&h) {
+                    "reference"
+                } else if from.message.contains(&h) {
+                    "message"
+                } else if from.counterparty_account.contains(&h) {
+                    "counterparty_account"
+                } else if from.value_date.contains(&h) {
+                    "value_date"
+                } else if from.payment_date.contains(&h) {
+                    "payment_date"
+                } else if from.balance.contains(&h) {
+                    "balance"
+                } else if from.currency.contains(&h) {
+                    "currency"
+                } else if from.original_amount.contains(&h) {
+                    "original_amount"
+                } else if from.original_currency.contains(&h) {
+                    "original_currency"
+                } else {
+                    h
+                }
+            })
+            .collect()
+    }
+}
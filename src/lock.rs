@@ -0,0 +1,97 @@
+//! Advisory lock against concurrent runs.
+//!
+//! Runs that write outputs or the state file, e.g. one started from cron and
+//! another by hand, take a lock file next to the state file. The file holds
+//! the process id of the run, so that a lock left behind by a run that was
+//! killed can be recognised and taken over.
+
+use crate::{info, report::RunError};
+use std::{
+    error::Error,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+/// Time between attempts to take a lock held by another run
+const RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A held lock, released when dropped.
+#[derive(Debug)]
+pub struct Lock {
+    path: PathBuf,
+}
+
+impl Lock {
+    /// Takes the lock at `path`. If another run holds it, waits for it to be
+    /// released if `wait` is set, or fails with [`RunError::Locked`].
+    pub fn acquire(path: &Path, wait: bool) -> Result<Lock, Box<dyn Error>> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut waiting = false;
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(path) {
+                Ok(mut file) => {
+                    write!(file, "{}", std::process::id())?;
+                    return Ok(Lock {
+                        path: path.to_path_buf(),
+                    });
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => {
+                    return Err(
+                        format!("Could not create lock file {}: {}", path.display(), e).into(),
+                    )
+                }
+            }
+
+            let holder = fs::read_to_string(path)
+                .ok()
+                .and_then(|pid| pid.trim().parse::<u32>().ok());
+            if holder.is_some_and(|pid| !is_running(pid)) {
+                info!(
+                    "Removing lock {} left behind by process {}.",
+                    path.display(),
+                    holder.unwrap_or_default()
+                );
+                // Another run may have removed it first
+                let _ = fs::remove_file(path);
+                continue;
+            }
+
+            if !wait {
+                info!(
+                    "{} is held by process {}.",
+                    path.display(),
+                    holder.map_or("unknown".to_string(), |pid| pid.to_string())
+                );
+                return Err(RunError::Locked.into());
+            }
+            if !waiting {
+                info!("Waiting for another run to finish...");
+                waiting = true;
+            }
+            thread::sleep(RETRY_INTERVAL);
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Returns whether the process `pid` is running. Where that cannot be told,
+/// processes are assumed to be running.
+fn is_running(pid: u32) -> bool {
+    if cfg!(target_os = "linux") {
+        Path::new("/proc").join(pid.to_string()).exists()
+    } else {
+        true
+    }
+}
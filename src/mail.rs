@@ -0,0 +1,415 @@
+//! Fetching exports mailed to an IMAP mailbox with `nda2ynab fetch-mail`,
+//! only built with the `fetch-mail` feature.
+//!
+//! The mailbox is read with the `curl` command line tool, which speaks IMAP
+//! as well as HTTP. The unread messages are searched for attachments, and
+//! fetching a message marks it as read, so each message is only looked at
+//! once. Attachments are decoded from base64 or quoted-printable, and their
+//! file names from the encodings mail programs use for names that are not
+//! plain ASCII.
+
+use crate::{
+    config::MailConfig,
+    encoding::{self, Encoding},
+    http,
+};
+use std::{
+    error::Error,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// An IMAP mailbox, e.g. `imaps://imap.example.com/INBOX`
+pub struct Mailbox<'a> {
+    url: &'a str,
+    user: &'a str,
+    password: &'a str,
+}
+
+impl<'a> Mailbox<'a> {
+    pub fn from_config(config: &'a MailConfig) -> Result<Mailbox<'a>, Box<dyn Error>> {
+        match (&config.url, &config.user, &config.password) {
+            (Some(url), Some(user), Some(password)) => Ok(Mailbox {
+                url: url.trim_end_matches('/'),
+                user,
+                password,
+            }),
+            _ => Err(
+                "Fetching mail needs mail.url, mail.user and mail.password in the config file"
+                    .into(),
+            ),
+        }
+    }
+
+    /// Returns the UIDs of the unread messages.
+    pub fn unread(&self) -> Result<Vec<u32>, Box<dyn Error>> {
+        let response = self.curl(self.url, Some("UID SEARCH UNSEEN"))?;
+        Ok(parse_search(&String::from_utf8_lossy(&response)))
+    }
+
+    /// Fetches the message `uid`, which marks it as read.
+    pub fn message(&self, uid: u32) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.curl(&format!("{}/;UID={}", self.url, uid), None)
+    }
+
+    /// Runs curl with the credentials on standard input, so that they do not
+    /// show up in the process list.
+    fn curl(&self, url: &str, request: Option<&str>) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut config = format!(
+            "url = {}\nuser = {}\nsilent\nshow-error\n",
+            http::quote(url),
+            http::quote(&format!("{}:{}", self.user, self.password))
+        );
+        if let Some(request) = request {
+            config.push_str(&format!("request = {}\n", http::quote(request)));
+        }
+
+        let mut child = Command::new("curl")
+            .args(["--config", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Could not run curl: {}", e))?;
+        child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(config.as_bytes())?;
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(format!(
+                "Could not read {}: {}",
+                self.url,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        Ok(output.stdout)
+    }
+}
+
+/// Parses the UIDs of a `UID SEARCH` response, e.g. `* SEARCH 3 7`.
+pub fn parse_search(response: &str) -> Vec<u32> {
+    response
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("* SEARCH"))
+        .flat_map(|uids| uids.split_whitespace().filter_map(|uid| uid.parse().ok()))
+        .collect()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Attachment {
+    pub file_name: String,
+    pub data: Vec<u8>,
+}
+
+/// Returns the attachments of a message, including the ones of messages
+/// forwarded as attachments.
+pub fn attachments(message: &[u8]) -> Vec<Attachment> {
+    let mut found = vec![];
+    collect_attachments(message, &mut found);
+    found
+}
+
+fn collect_attachments(part: &[u8], found: &mut Vec<Attachment>) {
+    let (headers, body) = split_headers(part);
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .unwrap_or_default()
+    };
+    let (content_type, type_params) = parameters(header("Content-Type"));
+    let (_, disposition_params) = parameters(header("Content-Disposition"));
+
+    if content_type.starts_with("multipart/") {
+        if let Some(boundary) = parameter(&type_params, "boundary") {
+            for part in split_multipart(body, &boundary) {
+                collect_attachments(part, found);
+            }
+        }
+        return;
+    }
+    if content_type == "message/rfc822" {
+        collect_attachments(body, found);
+        return;
+    }
+
+    let file_name =
+        parameter(&disposition_params, "filename").or_else(|| parameter(&type_params, "name"));
+    if let Some(file_name) = file_name {
+        let data = match header("Content-Transfer-Encoding")
+            .trim()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "base64" => decode_base64(body),
+            "quoted-printable" => decode_quoted_printable(body),
+            _ => body.to_vec(),
+        };
+        found.push(Attachment { file_name, data });
+    }
+}
+
+/// Splits a message or part into its unfolded headers and its body.
+fn split_headers(part: &[u8]) -> (Vec<(String, String)>, &[u8]) {
+    let (head, body) = match find(part, b"\r\n\r\n") {
+        Some(i) => (&part[..i], &part[i + 4..]),
+        None => match find(part, b"\n\n") {
+            Some(i) => (&part[..i], &part[i + 2..]),
+            None => (part, &[][..]),
+        },
+    };
+
+    let mut headers: Vec<(String, String)> = vec![];
+    for line in String::from_utf8_lossy(head).lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    (headers, body)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Returns the parts of a multipart body between the `--boundary` lines.
+fn split_multipart<'b>(body: &'b [u8], boundary: &str) -> Vec<&'b [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = vec![];
+    let mut start = None;
+    let mut offset = 0;
+    for line in body.split_inclusive(|&b| b == b'\n') {
+        let text = String::from_utf8_lossy(line);
+        let text = text.trim_end();
+        if text == delimiter || text == format!("{}--", delimiter) {
+            if let Some(start) = start {
+                // The line break before the delimiter belongs to it
+                let end = trim_line_break(&body[start..offset]).len() + start;
+                parts.push(&body[start..end]);
+            }
+            if text.ends_with("--") && text != delimiter {
+                break;
+            }
+            start = Some(offset + line.len());
+        }
+        offset += line.len();
+    }
+    parts
+}
+
+fn trim_line_break(bytes: &[u8]) -> &[u8] {
+    let bytes = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+    bytes.strip_suffix(b"\r").unwrap_or(bytes)
+}
+
+/// Splits a header value like `attachment; filename="a.csv"` into its
+/// lowercase value and its parameters.
+fn parameters(value: &str) -> (String, Vec<(String, String)>) {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' if quoted => field.extend(chars.next()),
+            ';' if !quoted => fields.push(std::mem::take(&mut field)),
+            // Only quoted whitespace is part of a value
+            c if c.is_whitespace() && !quoted => {}
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    let main = fields[0].to_ascii_lowercase();
+    let params = fields[1..]
+        .iter()
+        .filter_map(|f| {
+            let (name, value) = f.split_once('=')?;
+            Some((name.to_ascii_lowercase(), value.to_string()))
+        })
+        .collect();
+    (main, params)
+}
+
+/// Returns the parameter `name`, which may also be given in the extended
+/// form `name*=utf-8''...`, split into `name*0`, `name*1` and so on, or as
+/// encoded words like `=?utf-8?Q?...?=`.
+fn parameter(params: &[(String, String)], name: &str) -> Option<String> {
+    if let Some((_, value)) = params.iter().find(|(n, _)| n == name) {
+        return Some(decode_words(value));
+    }
+    if let Some((_, value)) = params.iter().find(|(n, _)| *n == format!("{}*", name)) {
+        return Some(decode_extended(value));
+    }
+
+    // Continuations, of which the first one may name the charset
+    let mut charset = None;
+    let mut bytes = vec![];
+    for index in 0.. {
+        let plain = format!("{}*{}", name, index);
+        let extended = format!("{}*{}*", name, index);
+        let Some((n, value)) = params.iter().find(|(n, _)| *n == plain || *n == extended) else {
+            break;
+        };
+        if *n == extended {
+            let value = match value.splitn(3, '\'').collect::<Vec<_>>()[..] {
+                [set, _, rest] if index == 0 => {
+                    charset = Some(set.to_string());
+                    rest
+                }
+                _ => value,
+            };
+            bytes.extend(percent_decode(value));
+        } else {
+            bytes.extend(value.as_bytes());
+        }
+    }
+    if bytes.is_empty() {
+        return None;
+    }
+    Some(decode_charset(
+        &bytes,
+        charset.as_deref().unwrap_or("utf-8"),
+    ))
+}
+
+/// Decodes an extended parameter value like `utf-8''Tapahtumat%20FI...`.
+fn decode_extended(value: &str) -> String {
+    match value.splitn(3, '\'').collect::<Vec<_>>()[..] {
+        [charset, _, rest] => decode_charset(&percent_decode(rest), charset),
+        _ => decode_charset(&percent_decode(value), "utf-8"),
+    }
+}
+
+fn percent_decode(value: &str) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                decoded.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+/// Decodes the encoded words of a header value, e.g.
+/// `=?utf-8?B?VGFwYWh0dW1hdA==?=`.
+fn decode_words(value: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("=?") {
+        let word = rest[start + 2..].splitn(3, '?').collect::<Vec<_>>();
+        let [charset, kind, tail] = word[..] else {
+            break;
+        };
+        let Some(end) = tail.find("?=") else {
+            break;
+        };
+        let text = &tail[..end];
+        let bytes = match kind {
+            "B" | "b" => decode_base64(text.as_bytes()),
+            "Q" | "q" => decode_quoted_printable(text.replace('_', " ").as_bytes()),
+            _ => break,
+        };
+        // Whitespace between encoded words is left out
+        let before = &rest[..start];
+        if decoded.is_empty() || !before.trim().is_empty() {
+            decoded.push_str(before);
+        }
+        decoded.push_str(&decode_charset(&bytes, charset));
+        rest = &tail[end + 2..];
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_charset(bytes: &[u8], charset: &str) -> String {
+    let encoding = match charset.to_ascii_lowercase().as_str() {
+        "iso-8859-1" | "latin1" => Encoding::Latin1,
+        "windows-1252" | "cp1252" => Encoding::Windows1252,
+        _ => Encoding::Auto,
+    };
+    encoding::decode(bytes, encoding)
+        .unwrap_or_else(|_| String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Decodes base64, skipping line breaks and other characters outside the
+/// alphabet.
+pub fn decode_base64(encoded: &[u8]) -> Vec<u8> {
+    let value = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' | b'-' => Some(62),
+        b'/' | b'_' => Some(63),
+        _ => None,
+    };
+    let mut decoded = vec![];
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for v in encoded
+        .iter()
+        .take_while(|&&c| c != b'=')
+        .filter_map(|&c| value(c))
+    {
+        buffer = (buffer << 6) | u32::from(v);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+        }
+    }
+    decoded
+}
+
+/// Decodes quoted-printable, joining soft line breaks.
+pub fn decode_quoted_printable(encoded: &[u8]) -> Vec<u8> {
+    let mut decoded = vec![];
+    let mut i = 0;
+    while i < encoded.len() {
+        if encoded[i] != b'=' {
+            decoded.push(encoded[i]);
+            i += 1;
+            continue;
+        }
+        let rest = &encoded[i + 1..];
+        let hex = rest
+            .get(..2)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        if rest.starts_with(b"\r\n") {
+            i += 3;
+        } else if rest.starts_with(b"\n") {
+            i += 2;
+        } else if let Some(b) = hex {
+            decoded.push(b);
+            i += 3;
+        } else {
+            decoded.push(b'=');
+            i += 1;
+        }
+    }
+    decoded
+}
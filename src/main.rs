@@ -1,36 +1,64 @@
-use chrono::{NaiveDateTime, Utc};
-use clap::Parser;
-use csv::{ReaderBuilder, WriterBuilder};
+mod anonymize;
+mod compare;
+mod fetch;
+#[cfg(feature = "fetch-mail")]
+mod fetch_mail;
+mod reports;
+mod review;
+mod rule_matches;
+mod runs;
+mod status;
+mod validate;
+mod watch;
+
+use chrono::NaiveDate;
+use clap::{CommandFactory, Parser, Subcommand};
 use itertools::Itertools;
-use regex::Regex;
-use serde::{Deserialize, Serialize};
+use nda2ynab::{
+    aggregate,
+    amount::Amount,
+    append, archive,
+    atomic::{self, AtomicFile},
+    columns, completions,
+    config::{AccountKind, Config},
+    console::{self, Style},
+    convert_accounts, convert_single, count_occurrence,
+    currency::{self, Rates},
+    downloads,
+    duplicates::{self, Duplicates},
+    encoding::{self, Encoding},
+    error, explicit_export_files, find_export_files,
+    history::{self, History, StateBackend},
+    holds, html, impact, info,
+    json::Value,
+    locale::Locale,
+    lock::Lock,
+    log, manpage,
+    mapped::{InputMap, Mapped},
+    memo, nordea, notify, output, parse_nda_date,
+    parser::Registry,
+    payees, postprocess, reconcile, recurring, remove_ignored,
+    report::{self, RunError, Summary},
+    rules::Rules,
+    sanitize::{self, SanitizeOptions},
+    stale_export_days,
+    state::{Run, RunAccount, State},
+    stats::Stats,
+    system::{Clock, Disk, FileSystem, SystemClock},
+    timezone::Timezone,
+    to_ynab_rows_after, transfers, write_output, ynab, ConvertOptions, CsvOptions, DateField,
+    Format, Occurrences, ParsedFileName, SplitBy, Transaction, UploadTarget, YnabFormat, YnabRow,
+    MAX_EXPORT_AGE,
+};
 use std::{
+    collections::HashMap,
     error::Error,
-    fs,
+    io::{self, Read},
+    mem,
     path::{Path, PathBuf},
+    process, thread,
 };
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
-struct NdaRow {
-    #[serde(rename = "Kirjauspäivä")]
-    date: String,
-
-    #[serde(rename = "Määrä")]
-    amount: String,
-
-    #[serde(rename = "Otsikko")]
-    description: String,
-}
-
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "PascalCase")]
-struct YnabRow {
-    date: String,
-    payee: String,
-    memo: String,
-    amount: String,
-}
-
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = Some(r"
 This program can be used to convert from Nordea bank's CSV export format to
@@ -39,171 +67,1912 @@ YNAB's CSV format.
 Point it to the directory where your Nordea CSV files are stored (for example
 your Downloads directory), and it will generate a YNAB CSV containing only new
 transactions since the previous export.
-"))]
-struct Args {
-    /// Path to directory containing exported csv files
-    path: String,
-}
-
-#[derive(Debug)]
-struct ParsedFileName {
-    file_name: String,
-    path: PathBuf,
-    date: NaiveDateTime,
-    iban: String,
-}
-
-#[derive(Debug)]
-struct PrevFileNewestTransaction {
-    transaction: NdaRow,
-    repetitions: usize,
-}
-
-fn read_nda_csv(path: &Path) -> Result<Vec<NdaRow>, Box<dyn Error>> {
-    let mut rdr = ReaderBuilder::new().delimiter(b';').from_path(path)?;
-    let rows: Vec<NdaRow> = rdr
-        .deserialize()
-        .filter_map(|r| r.ok())
-        // "Invalid date" seems to indicate authorisation holds, skip these
-        .filter(|r: &NdaRow| {
-            let invalid_date = r.date == "Invalid date";
-
-            if invalid_date {
-                println!(
-                    "Skipping transaction in {} due to invalid date, probably an authorisation hold.", path.display()
-                );
-                eprintln!("Transaction: {:#?}\n", r);
-            }
+"), args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
 
-            !invalid_date
-        })
-        .collect();
-    Ok(rows)
+    /// Path to the config file [default: ./nda2ynab.toml or
+    /// ~/.config/nda2ynab/nda2ynab.toml]
+    #[clap(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Print more details about what is being done (-vv for every
+    /// transaction)
+    #[clap(short, long, global = true, parse(from_occurrences))]
+    verbose: u64,
+
+    /// Only print errors
+    #[clap(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Print without colors, also when NO_COLOR is set [default: colors on
+    /// a terminal]
+    #[clap(long, global = true)]
+    no_color: bool,
+
+    /// Without a subcommand, the arguments of `convert`
+    #[clap(flatten)]
+    convert: ConvertCommand,
+}
+
+impl Cli {
+    /// Returns whether a JSON summary was requested.
+    fn json(&self) -> bool {
+        match &self.command {
+            None => self.convert.convert.json,
+            Some(Command::Convert(c) | Command::Upload(c)) => c.convert.json,
+            Some(_) => false,
+        }
+    }
+
+    fn notify(&self) -> bool {
+        match &self.command {
+            None => self.convert.convert.notify,
+            Some(Command::Convert(c) | Command::Upload(c)) => c.convert.notify,
+            Some(Command::Fetch(f)) => f.convert.notify,
+            Some(_) => false,
+        }
+    }
+
+    /// Returns the path of the HTML report, if one was requested.
+    fn report_html(&self) -> Option<PathBuf> {
+        match &self.command {
+            None => self.convert.convert.report_html.clone(),
+            Some(Command::Convert(c) | Command::Upload(c)) => c.convert.report_html.clone(),
+            Some(_) => None,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Convert the new transactions of the most recent export (the default)
+    Convert(ConvertCommand),
+    /// Convert the new transactions of the most recent export and upload
+    /// them to YNAB via the API, like convert --upload
+    Upload(ConvertCommand),
+    /// Watch a directory and convert new Nordea exports as they are downloaded
+    Watch(watch::WatchArgs),
+    /// Fetch new transactions via Nordea Open Banking instead of reading
+    /// exports
+    Fetch(fetch::FetchArgs),
+    /// Save the exports attached to unread messages of an IMAP mailbox and
+    /// convert them
+    #[cfg(feature = "fetch-mail")]
+    FetchMail(fetch_mail::FetchMailArgs),
+    /// Show the saved state and the most recent export of each account
+    Status(status::StatusArgs),
+    /// List the recorded runs that wrote or uploaded transactions
+    History(runs::RunsArgs),
+    /// Undo the most recent run: delete the transactions it uploaded to YNAB
+    /// and restore the state from before it
+    UndoLast(runs::RunsArgs),
+    /// List the transactions of one export that are not in the other,
+    /// matched as when converting
+    Diff(compare::DiffArgs),
+    /// Replace the payees, account numbers and amounts of Nordea exports,
+    /// keeping their layout and dates, e.g. for attaching to a bug report
+    Anonymize(anonymize::AnonymizeArgs),
+    /// Check a YNAB CSV file against the constraints of YNAB's import, and
+    /// list the problems found by line
+    Validate(validate::ValidateArgs),
+    /// Summarize transactions, e.g. spending per payee and month
+    Report {
+        #[clap(subcommand)]
+        command: reports::ReportCommand,
+    },
+    /// Try out payee, category and ignore rules on an export
+    Rules {
+        #[clap(subcommand)]
+        command: rule_matches::RulesCommand,
+    },
+    /// Print a completion script for a shell, e.g. to
+    /// ~/.local/share/bash-completion/completions/nda2ynab
+    Completions {
+        #[clap(arg_enum)]
+        shell: completions::Shell,
+    },
+    /// Print the man page in roff format, e.g. to nda2ynab.1
+    Man,
+    /// Replace this binary with the latest release from GitHub
+    #[cfg(feature = "self-update")]
+    SelfUpdate {
+        /// Only tell whether a newer release is available
+        #[clap(long)]
+        check: bool,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+struct ConvertCommand {
+    /// Path to directory containing exported csv files, or - to convert a
+    /// single export read from stdin [default: path from the config file]
+    path: Option<String>,
+
+    #[clap(flatten)]
+    convert: ConvertArgs,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ConvertArgs {
+    /// Convert every account found in the directory, writing one
+    /// out-<IBAN>.<format> file per account
+    #[clap(short, long)]
+    all_accounts: bool,
+
+    /// Look for exports in the Downloads directory when no path is given on
+    /// the command line or in the config file. On Windows a Downloads folder
+    /// moved into OneDrive is found too
+    #[clap(long)]
+    auto: bool,
+
+    /// Convert this export instead of the most recent one found in the
+    /// directory, regardless of its file name
+    #[clap(long, value_name = "FILE", conflicts_with = "all-accounts")]
+    current: Option<PathBuf>,
+
+    /// Compare with this previously processed export instead of looking for
+    /// one in the directory
+    #[clap(long, value_name = "FILE", requires = "current")]
+    previous: Option<PathBuf>,
+
+    /// Convert the most recent export even if it starts after the previous
+    /// one ends, leaving a gap of missing transactions
+    #[clap(long)]
+    allow_gap: bool,
+
+    /// Compare the rows of the most recent export from up to N days before
+    /// the last transaction of the previous export with it, taking older
+    /// rows as already processed
+    #[clap(long, value_name = "N", default_value = "3")]
+    overlap_days: u32,
+
+    /// Warn if the most recent export is older than this many days, as a
+    /// fresh one was probably not downloaded; 0 to never warn [default: 35]
+    #[clap(long, value_name = "DAYS")]
+    max_export_age: Option<u32>,
+
+    /// Merge every export of the account found in the directory into one
+    /// output, e.g. when setting up a new budget
+    #[clap(long, conflicts_with = "current")]
+    backfill: bool,
+
+    /// Path of the output file to write, which may have {iban},
+    /// {account_name}, {export_date}, {from_date}, {to_date} and {ext}
+    /// placeholders [default: out.<format>, or out-{account_name}.<format>
+    /// with --all-accounts]
+    #[clap(short, long, conflicts_with = "stdout")]
+    output: Option<String>,
+
+    /// Output file format [default: csv]
+    #[clap(short, long, arg_enum)]
+    format: Option<Format>,
+
+    /// Amount columns of YNAB CSV output: a signed amount, or separate
+    /// outflow and inflow columns [default: amount]
+    #[clap(long, arg_enum, value_name = "COLUMNS")]
+    ynab_format: Option<YnabFormat>,
+
+    /// Date used as the date of the output, for exports with value or
+    /// payment dates besides the booking date [default: booking]
+    #[clap(long, arg_enum, value_name = "FIELD")]
+    date_field: Option<DateField>,
+
+    /// Write the output to standard output instead of a file
+    #[clap(long, conflicts_with = "all-accounts")]
+    stdout: bool,
+
+    /// Write a file for each period, e.g. ynab-2024-03.csv for
+    /// `--output ynab.csv` and month
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "PERIOD",
+        conflicts_with_all = &["stdout", "append", "upload"]
+    )]
+    split_by: Option<SplitBy>,
+
+    /// Append the new transactions to this YNAB CSV file, creating it if
+    /// needed, leaving out the ones already in it
+    #[clap(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = &["output", "stdout", "all-accounts", "upload"]
+    )]
+    append: Option<PathBuf>,
+
+    /// Overwrite output files that already exist
+    #[clap(long)]
+    force: bool,
+
+    /// Text encoding of the exported csv files [default: auto]
+    #[clap(short, long, arg_enum)]
+    encoding: Option<Encoding>,
+
+    /// Locale of the Nordea exports, detected from the file if not given
+    #[clap(short, long, arg_enum)]
+    locale: Option<Locale>,
+
+    /// Time zone of the times in export file names, for ordering them:
+    /// local, utc or an offset like +03:00 [default: local]
+    #[clap(long, value_name = "ZONE")]
+    timezone: Option<Timezone>,
+
+    /// Also recognise Nordea exports with file names matching this regular
+    /// expression, which needs named groups "iban" and "date"; may be given
+    /// more than once
+    #[clap(
+        long = "file-name-pattern",
+        value_name = "REGEX",
+        multiple_occurrences = true
+    )]
+    file_name_patterns: Vec<String>,
+
+    /// Only export transactions booked on or after this date (YYYY-MM-DD),
+    /// ignoring the previously processed file
+    #[clap(long)]
+    since: Option<NaiveDate>,
+
+    /// Only export transactions booked on or before this date (YYYY-MM-DD),
+    /// ignoring the previously processed file
+    #[clap(long)]
+    until: Option<NaiveDate>,
+
+    /// Flip the signs of all amounts, for accounts where inflows are
+    /// exported as negative amounts
+    #[clap(long)]
+    invert: bool,
+
+    /// Convert amounts into this currency, e.g. the currency of the YNAB
+    /// budget, noting the original amount in the memo
+    #[clap(long, value_name = "CURRENCY")]
+    convert_to: Option<String>,
+
+    /// Pass the converted transactions of each account through a shell
+    /// command as JSON lines, one per transaction, replacing each with the
+    /// line the command writes back, or leaving it out for `null`
+    #[clap(long, value_name = "CMD")]
+    post_process: Option<String>,
+
+    /// Read the exports of another bank by declaring their columns, e.g.
+    /// "date=Booking date,amount=Amount,payee=Text", with optional memo,
+    /// reference, currency, balance, account, date_format and delimiter
+    /// keys, or the path of a TOML file with the same keys
+    #[clap(long, value_name = "MAP")]
+    input_map: Option<String>,
+
+    /// TOML file with exchange rates per euro, used with --convert-to
+    #[clap(long, value_name = "FILE", requires = "convert-to")]
+    rates: Option<PathBuf>,
+
+    /// Fetch the latest ECB reference rates for --convert-to
+    #[clap(long, requires = "convert-to", conflicts_with = "rates")]
+    ecb_rates: bool,
+
+    /// Abort if any row of an export cannot be parsed, instead of skipping
+    /// the row with a warning
+    #[clap(long)]
+    strict: bool,
+
+    /// What to do with identical adjacent rows of an export, which Nordea
+    /// sometimes exports twice: keep them, merge them into one, or ask
+    /// [default: keep]
+    #[clap(long, arg_enum, value_name = "ACTION")]
+    duplicates: Option<Duplicates>,
+
+    /// Draw a progress bar while reading large exports
+    #[clap(long)]
+    progress: bool,
+
+    /// Number of accounts converted at the same time with --all-accounts
+    /// [default: number of CPUs, 1 with --progress]
+    #[clap(short, long, value_name = "N")]
+    jobs: Option<usize>,
+
+    /// Template for the memo, with {reference}, {message}, {account}
+    /// (counterparty account) or any column of the export by its header as
+    /// placeholders [default: "{reference} {message} {original}"]
+    #[clap(long, value_name = "TEMPLATE")]
+    memo: Option<String>,
+
+    /// Cut payees longer than this many characters, 0 for no limit
+    /// [default: 200]
+    #[clap(long, value_name = "N")]
+    max_payee_length: Option<usize>,
+
+    /// Cut memos longer than this many characters, 0 for no limit [default:
+    /// 500]
+    #[clap(long, value_name = "N")]
+    max_memo_length: Option<usize>,
+
+    /// Keep control characters in payees and memos instead of removing them
+    #[clap(long)]
+    keep_control_chars: bool,
+
+    /// Keep runs of whitespace in payees and memos instead of collapsing
+    /// them into single spaces
+    #[clap(long)]
+    keep_whitespace: bool,
+
+    /// Title-case payees written in all capitals, e.g. "K-MARKET" becomes
+    /// "K-Market"
+    #[clap(long)]
+    title_case: bool,
+
+    /// Do not detect transfers between accounts in --all-accounts mode
+    #[clap(long)]
+    no_transfers: bool,
+
+    /// Merge the outflows smaller than this amount, e.g. 2.00, into one
+    /// "Misc small purchases" transaction per day
+    #[clap(long, value_name = "AMOUNT")]
+    aggregate_below: Option<Amount>,
+
+    /// Wait for another run using the same state file to finish instead of
+    /// failing
+    #[clap(long)]
+    wait_for_lock: bool,
+
+    /// Remember authorisation holds in the state file and report when they
+    /// settle, or if they disappear without being booked
+    #[clap(long)]
+    track_holds: bool,
+
+    /// Write an empty output for an export without any transactions, instead
+    /// of writing nothing
+    #[clap(long)]
+    allow_empty: bool,
+
+    /// Convert authorisation holds too, dated by their value date or the
+    /// day they were first seen, and upload them as uncleared
+    #[clap(long)]
+    include_pending: bool,
+
+    /// Path to the state file [default:
+    /// ~/.local/state/nda2ynab/state.json]
+    #[clap(long, value_name = "FILE")]
+    state: Option<PathBuf>,
+
+    /// With sqlite, also remember every converted transaction in a SQLite
+    /// database next to the state file, and leave out the ones converted
+    /// before [default: json]
+    #[clap(long, arg_enum, value_name = "BACKEND")]
+    state_backend: Option<StateBackend>,
+
+    /// After exporting, move the processed exports into this directory
+    /// [default: <PATH>/archive]
+    #[clap(long)]
+    archive: Option<Option<PathBuf>>,
+
+    /// Copy the processed exports into the archive instead of moving them
+    #[clap(long, requires = "archive")]
+    archive_copy: bool,
+
+    /// Print a JSON summary of the run to stdout and use distinct exit codes:
+    /// 3 when no files were found, 4 for non-overlapping files and 5 when
+    /// there was nothing new to export
+    #[clap(long, conflicts_with_all = &["stdout", "dry-run"])]
+    json: bool,
+
+    /// Show a desktop notification of what was written or uploaded for each
+    /// account, or of the error, when the run ends; with watch, after each
+    /// new export
+    #[clap(long)]
+    notify: bool,
+
+    /// Write an HTML page showing the exports used, the transactions
+    /// included and skipped, and any warnings of the run to this file
+    #[clap(long, value_name = "FILE")]
+    report_html: Option<PathBuf>,
+
+    /// Review, exclude and edit transactions interactively before exporting
+    #[clap(short, long)]
+    interactive: bool,
+
+    /// Show how the transactions would change the amounts available in the
+    /// YNAB budget's categories this month, using the YNAB settings of the
+    /// config file
+    #[clap(long)]
+    impact: bool,
+
+    /// Compare the balance at the end of the export with the cleared balance
+    /// of the YNAB account, using the YNAB settings of the config file
+    #[clap(long)]
+    check_balance: bool,
+
+    /// Upload the transactions to YNAB via the API instead of writing a file,
+    /// using the token, budget and account mapping from the config file
+    #[clap(long, conflicts_with_all = &["stdout", "output", "format"])]
+    upload: bool,
+
+    /// Service to upload to [default: ynab]
+    #[clap(long, arg_enum, value_name = "SERVICE")]
+    target: Option<UploadTarget>,
+
+    /// When uploading to YNAB, use the budget's existing payees that match
+    /// the converted ones instead of creating near-duplicates of them
+    #[clap(long)]
+    match_payees: bool,
+
+    /// Print the transactions that would be exported instead of writing them
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Path to a TOML file with payee cleanup rules, applied after the
+    /// built-in rules
+    #[clap(short, long)]
+    rules: Option<PathBuf>,
+
+    /// Do not apply the built-in payee cleanup rules
+    #[clap(long)]
+    no_default_rules: bool,
+
+    /// Give common Finnish merchants, e.g. K-MARKET or HSL, clean payee
+    /// names and suggested categories from the bundled merchant list
+    #[clap(long)]
+    enrich: bool,
+
+    /// Write the summary statistics of the converted transactions to this
+    /// file instead of printing them
+    #[clap(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+
+    /// List likely recurring payments, such as subscriptions, among the
+    /// converted transactions; use with --backfill to look at all exports
+    #[clap(long)]
+    recurring: bool,
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
-    let dir = fs::read_dir(args.path)?;
-    let re = Regex::new(r".+ (FI\d{2} \d{4} \d{4} \d{4} \d{2}) - (.+)\.csv").unwrap();
-
-    let mut matches: Vec<ParsedFileName> = dir
-        .filter_map(|p| p.ok())
-        .filter_map(|p| {
-            let path = p.path();
-            let file_name = path.file_name()?.to_str()?.to_string();
-            let iban = re.captures(&file_name)?.get(1)?.as_str().to_string();
-            let date_match = re.captures(&file_name)?.get(2)?.as_str();
-
-            // Nordea recently changed the filename format of csv exports, try both
-            let date = NaiveDateTime::parse_from_str(date_match, "%Y-%m-%d %H.%M.%S").ok().or_else(|| {
-                NaiveDateTime::parse_from_str(date_match, "%Y.%m.%d %H.%M").ok()
-            })?;
-
-            Some(ParsedFileName {
-                file_name,
-                path,
-                date,
+impl ConvertArgs {
+    /// Fills in options not given on the command line from the config file.
+    fn apply_config(&mut self, config: &Config) {
+        // A single output file of the config file is not used for several
+        // accounts
+        self.output = self.output.take().or_else(|| {
+            config
+                .output
+                .clone()
+                .filter(|output| !self.all_accounts || has_account_placeholder(output))
+        });
+        self.format = self.format.or(config.format);
+        self.ynab_format = self.ynab_format.or(config.ynab_format);
+        self.target = self.target.or(config.target);
+        self.match_payees |= config.ynab.match_payees;
+        self.date_field = self.date_field.or(config.date_field);
+        self.encoding = self.encoding.or(config.encoding);
+        self.locale = self.locale.or(config.locale);
+        self.timezone = self.timezone.or(config.timezone);
+        if self.file_name_patterns.is_empty() {
+            self.file_name_patterns = config.file_name_patterns.clone();
+        }
+        self.rules = self.rules.take().or_else(|| config.rules.clone());
+        self.memo = self.memo.take().or_else(|| config.memo.clone());
+        self.title_case |= config.title_case;
+        self.enrich |= config.enrich;
+        self.aggregate_below = self.aggregate_below.or(config.aggregate_below);
+        self.max_export_age = self.max_export_age.or(config.max_export_age);
+        self.post_process = self
+            .post_process
+            .take()
+            .or_else(|| config.post_process.clone());
+        self.input_map = self.input_map.take().or_else(|| config.input_map.clone());
+        self.convert_to = self.convert_to.take().or_else(|| config.convert_to.clone());
+        if !self.ecb_rates {
+            self.rates = self.rates.take().or_else(|| config.rates.clone());
+        }
+        self.state = self.state.take().or_else(|| config.state.clone());
+        self.state_backend = self.state_backend.or(config.state_backend);
+        if self.archive.is_none() {
+            self.archive = config.archive.clone().map(Some);
+        }
+    }
+
+    /// Returns the archive directory if archiving is enabled.
+    fn archive_dir(&self, path: &str) -> Option<PathBuf> {
+        self.archive.as_ref().map(|dir| {
+            dir.clone()
+                .unwrap_or_else(|| Path::new(path).join("archive"))
+        })
+    }
+
+    fn state_path(&self) -> Result<PathBuf, Box<dyn Error>> {
+        self.state
+            .clone()
+            .or_else(State::default_path)
+            .ok_or_else(|| "Could not determine the state file location, use --state".into())
+    }
+
+    /// Opens the transaction history if it is kept.
+    fn history(&self) -> Result<Option<History>, Box<dyn Error>> {
+        match self.state_backend.unwrap_or_default() {
+            StateBackend::Json => Ok(None),
+            StateBackend::Sqlite => Ok(Some(History::open(&History::path_for(
+                &self.state_path()?,
+            ))?)),
+        }
+    }
+
+    /// Takes the lock next to the state file, unless the run writes nothing
+    /// or the state file location is unknown.
+    fn lock(&self) -> Result<Option<Lock>, Box<dyn Error>> {
+        match self.state_path() {
+            Ok(path) if !self.dry_run => {
+                let lock = Lock::acquire(&path.with_extension("lock"), self.wait_for_lock)?;
+                Ok(Some(lock))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn memo(&self) -> &str {
+        self.memo.as_deref().unwrap_or(memo::DEFAULT_TEMPLATE)
+    }
+
+    fn jobs(&self) -> usize {
+        // Progress bars of accounts read at the same time would overwrite
+        // each other
+        let default = if self.progress {
+            1
+        } else {
+            thread::available_parallelism().map_or(1, |n| n.get())
+        };
+        self.jobs.unwrap_or(default)
+    }
+
+    fn convert_options(&self) -> ConvertOptions {
+        ConvertOptions {
+            encoding: self.encoding(),
+            since: self.since,
+            until: self.until,
+            strict: self.strict,
+            progress: self.progress,
+            allow_gap: self.allow_gap,
+            keep_skipped: self.report_html.is_some(),
+            overlap_days: self.overlap_days,
+            include_pending: self.include_pending,
+        }
+    }
+
+    fn sanitize_options(&self) -> SanitizeOptions {
+        let limit = |length: Option<usize>, default| match length.unwrap_or(default) {
+            0 => None,
+            length => Some(length),
+        };
+        SanitizeOptions {
+            max_payee_length: limit(self.max_payee_length, sanitize::MAX_PAYEE_LENGTH),
+            max_memo_length: limit(self.max_memo_length, sanitize::MAX_MEMO_LENGTH),
+            strip_control: !self.keep_control_chars,
+            collapse_whitespace: !self.keep_whitespace,
+            title_case: self.title_case,
+        }
+    }
+
+    /// Loads the exchange rates if amounts are to be converted.
+    fn rates(&self) -> Result<Option<Rates>, Box<dyn Error>> {
+        if self.convert_to.is_none() {
+            Ok(None)
+        } else if self.ecb_rates {
+            Ok(Some(Rates::fetch_ecb()?))
+        } else if let Some(path) = &self.rates {
+            Ok(Some(Rates::from_file(path)?))
+        } else {
+            Err("--convert-to needs exchange rates from --rates FILE or --ecb-rates".into())
+        }
+    }
+
+    /// Returns the currency the amounts of account `iban` are written in.
+    fn currency(&self, iban: &str) -> String {
+        self.convert_to
+            .as_ref()
+            .map(|c| c.to_uppercase())
+            .unwrap_or_else(|| currency::account_currency(iban).to_string())
+    }
+
+    /// Output file of account `iban`, by default named after the account's
+    /// configured name or its IBAN with --all-accounts. `export_date` is the
+    /// date of the most recent export of the account.
+    fn output_path(
+        &self,
+        iban: &str,
+        config: &Config,
+        rows: &[YnabRow],
+        export_date: Option<NaiveDate>,
+    ) -> Result<String, Box<dyn Error>> {
+        let template = match &self.output {
+            Some(output) => output.as_str(),
+            None if self.all_accounts => "out-{account_name}.{ext}",
+            None => "out.{ext}",
+        };
+        output::file_name(
+            template,
+            &output::FileNameFields {
                 iban,
-            })
+                account_name: config.account_name(iban),
+                export_date,
+                rows,
+                extension: self.format().extension(),
+            },
+        )
+    }
+
+    /// Output files of account `iban` with the rows written to each, one
+    /// per period with --split-by
+    fn output_files(
+        &self,
+        iban: &str,
+        config: &Config,
+        rows: &[YnabRow],
+        export_date: Option<NaiveDate>,
+    ) -> Result<OutputFiles, Box<dyn Error>> {
+        Ok(match self.split_by {
+            Some(split_by) => split_by
+                .split(rows)
+                .into_iter()
+                .map(|(period, rows)| -> Result<_, Box<dyn Error>> {
+                    let output = self.output_path(iban, config, &rows, export_date)?;
+                    Ok((SplitBy::path(&output, &period), rows))
+                })
+                .collect::<Result<_, _>>()?,
+            None => vec![(
+                self.output_path(iban, config, rows, export_date)?,
+                rows.to_vec(),
+            )],
         })
-        .collect();
+    }
 
-    // Sort by parsed date
-    matches.sort_by(|a, b| b.date.cmp(&a.date));
+    fn format(&self) -> Format {
+        self.format.unwrap_or(Format::Csv)
+    }
 
-    // Select the most recent matching csv file
-    let newest_file = matches.first().ok_or("Could not find any matching files")?;
+    fn csv_options(&self, rules: &Rules, config: &Config) -> CsvOptions {
+        CsvOptions {
+            columns: self.ynab_format.unwrap_or_default(),
+            // The command may set categories and flags the rules do not
+            include_category: rules.has_categories() || self.post_process.is_some(),
+            include_flag: rules.has_flags() || self.post_process.is_some(),
+            schema: config.csv.columns.clone(),
+            headers: config.csv.headers.clone(),
+        }
+    }
+
+    fn encoding(&self) -> Encoding {
+        self.encoding.unwrap_or(Encoding::Auto)
+    }
+
+    /// Returns the parsers for the exports, recognising the file name
+    /// patterns given and the exports of the input map, if any.
+    fn registry(&self, clock: &dyn Clock) -> Result<Registry, Box<dyn Error>> {
+        let patterns = nordea::file_name_patterns(&self.file_name_patterns)?;
+        let mut registry = Registry::with_file_name_patterns(self.locale, patterns, clock)
+            .with_timezone(self.timezone.unwrap_or_default());
+        if let Some(map) = &self.input_map {
+            registry.register(Box::new(Mapped::new(InputMap::load(map)?)));
+        }
+        Ok(registry)
+    }
+}
+
+/// Prints an aligned table of the transactions to stdout.
+fn print_table(rows: &[YnabRow]) {
+    let width = |header: &str, field: fn(&YnabRow) -> String| {
+        rows.iter()
+            .map(|r| field(r).chars().count())
+            .chain([header.len()])
+            .max()
+            .unwrap_or(0)
+    };
+    let date_w = width("Date", |r| r.date.clone());
+    let payee_w = width("Payee", |r| r.payee.clone());
+    let amount_w = width("Amount", |r| r.amount.to_string());
 
     println!(
-        "Using most recent file as main CSV:\n{}\n",
-        newest_file.file_name
+        "{:<date_w$}  {:<payee_w$}  {:>amount_w$}",
+        "Date", "Payee", "Amount"
     );
-
-    // Try to find previous csv file with matching iban and read most recent transactions
-    let prev_file = matches.iter().skip(1).find(|m| m.iban == newest_file.iban);
-    let prev_file_trx = if let Some(prev_file) = prev_file {
+    println!("{}", "-".repeat(date_w + payee_w + amount_w + 4));
+    for r in rows {
         println!(
-            "Comparing transactions with previously processed file:\n{}\n",
-            prev_file.file_name
+            "{:<date_w$}  {:<payee_w$}  {:>amount_w$}",
+            r.date,
+            r.payee,
+            r.amount.to_string()
         );
+    }
+}
 
-        let rows = read_nda_csv(&prev_file.path)?;
+/// Converts the exports in `path`. If `iban` is given, only that account is
+/// converted. Details of the run are recorded in `summary`.
+fn run_convert(
+    files: &dyn FileSystem,
+    clock: &dyn Clock,
+    path: &str,
+    args: &ConvertArgs,
+    config: &Config,
+    iban: Option<&str>,
+    summary: &mut Summary,
+) -> Result<(), Box<dyn Error>> {
+    let _lock = args.lock()?;
+    let archive_dir = args.archive_dir(path);
+    let registry = args.registry(clock)?;
+    let matches = match &args.current {
+        Some(current) => explicit_export_files(
+            files,
+            current,
+            args.previous.as_deref(),
+            &registry,
+            args.encoding(),
+        )?,
+        None => find_export_files(
+            files,
+            path,
+            &registry,
+            args.encoding(),
+            archive_dir.as_deref(),
+        )?,
+    };
+    summary.files_considered = matches.iter().map(|m| m.file_name.clone()).collect();
 
-        let first_row = rows.first().ok_or(format!(
-            "{} does not contain any valid rows",
-            newest_file.file_name
-        ))?;
+    let rules = load_rules(args)?;
 
-        // Count how many rows identical to first_row exist
-        let repetitions = rows.iter().filter(|r| r == &first_row).count();
+    let opts = args.convert_options();
 
-        Some(PrevFileNewestTransaction {
-            transaction: first_row.clone(),
-            repetitions,
-        })
+    // Select the account of the most recent matching csv file, or every account
+    let newest_file = matches.first().ok_or(RunError::NoFilesFound)?;
+    let ibans: Vec<&str> = if let Some(iban) = iban {
+        vec![iban]
+    } else if args.all_accounts {
+        matches.iter().map(|m| m.iban.as_str()).unique().collect()
     } else {
-        println!("No previously processed file found, including all rows from the main CSV file");
+        vec![&newest_file.iban]
+    };
 
+    let rates = args.rates()?;
+
+    let mut state = if args.track_holds {
+        Some(State::load(&args.state_path()?)?)
+    } else {
         None
     };
+    // For undoing the run. Without a readable state file the run is only
+    // left out of the runs that can be undone.
+    let before = match (&state, args.dry_run) {
+        (_, true) => None,
+        (Some(state), false) => Some(state.snapshot()),
+        (None, false) => match args.state_path().and_then(|path| State::load(&path)) {
+            Ok(state) => Some(state.snapshot()),
+            Err(e) => {
+                report::warn(format!("The run cannot be undone: {}", e));
+                None
+            }
+        },
+    };
+    let history = args.history()?;
+
+    let account_files: Vec<(String, Vec<&ParsedFileName>)> = ibans
+        .iter()
+        .map(|iban| {
+            let files = matches.iter().filter(|m| m.iban == *iban).collect();
+            (config.account_label(iban), files)
+        })
+        .collect();
+    let conversions = convert_accounts(
+        files,
+        &account_files,
+        &registry,
+        &opts,
+        args.backfill,
+        args.jobs(),
+    );
+
+    let mut accounts = vec![];
+    let mut consumed = vec![];
+    let mut converted = vec![];
+    // Accounts that could not be converted with --all-accounts, reported
+    // after the others have been written
+    let mut failed = vec![];
+    for ((_, files), conversion) in account_files.into_iter().zip(conversions) {
+        let iban = files[0].iban.as_str();
+        let mut conversion = match conversion {
+            Ok(conversion) => conversion,
+            Err(e) if args.all_accounts => {
+                failed.push(failed_account(config, files[0], e));
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        merge_duplicates(args, &files[0].file_name, &mut conversion.rows)?;
+        if let Some(history) = &history {
+            let seen = history.seen(iban, &conversion.rows)?;
+            let history::Removed {
+                rows: removed,
+                kept,
+            } = history::remove_seen(&mut conversion.rows, &seen);
+            if !removed.is_empty() {
+                info!(
+                    "Skipping {} transaction(s) of {} already in the transaction history",
+                    removed.len(),
+                    iban
+                );
+            }
+            conversion.skipped += removed.len();
+            for r in &removed {
+                count_occurrence(&mut conversion.earlier, r);
+            }
+            if opts.keep_skipped {
+                conversion.skipped_rows.extend(removed);
+            }
+            converted.push((iban.to_string(), files[0].file_name.clone(), kept));
+        }
+        summary.accounts.push(report::AccountSummary {
+            iban: iban.to_string(),
+            name: config.account_name(iban).map(String::from),
+            file: files[0].file_name.clone(),
+            previous_file: files
+                .get(1)
+                .filter(|_| !(opts.has_date_range() || args.backfill))
+                .map(|f| f.file_name.clone()),
+            transactions_skipped: conversion.skipped,
+            empty_export: conversion.is_empty_export(),
+            stale_export_days: stale_export_days(
+                files[0],
+                args.max_export_age.unwrap_or(MAX_EXPORT_AGE),
+                clock,
+            ),
+            balance: conversion.balance,
+            skipped_rows: mem::take(&mut conversion.skipped_rows),
+            ..Default::default()
+        });
 
-    let newest_rows = read_nda_csv(&newest_file.path)?;
+        if let Some(state) = &mut state {
+            let tracked = state.holds.entry(iban.to_string()).or_default();
+            let export_date = files[0].date.date();
+            holds::reconcile(tracked, &conversion.holds, &conversion.rows, export_date);
+        }
+        if args.include_pending {
+            let tracked = state
+                .as_ref()
+                .and_then(|s| s.holds.get(iban))
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            let pending = holds::pending_rows(&conversion.holds, tracked, clock);
+            conversion.rows.splice(0..0, pending);
+        }
+
+        let account = summary.accounts.last_mut().expect("account summary");
+        let rows = match ynab_rows(
+            args,
+            config,
+            &rules,
+            rates.as_ref(),
+            account,
+            conversion.rows,
+            &conversion.earlier,
+        ) {
+            Ok(rows) => rows,
+            Err(e) if args.all_accounts => {
+                summary.accounts.pop();
+                converted.retain(|(i, _, _)| i != iban);
+                failed.push(failed_account(config, files[0], e));
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        accounts.push((iban.to_string(), rows));
+        // The main and previous file are the ones consumed by the conversion,
+        // or every file when backfilling
+        let used = if args.backfill { files.len() } else { 2 };
+        consumed.push(files.into_iter().take(used).collect::<Vec<_>>());
+    }
 
-    // Remove all previously processed rows from newest_rows
-    let first_previously_processed_index = if let Some(prev_file_trx) = prev_file_trx {
-        let positions_matches: Vec<usize> = newest_rows
-            .iter()
-            .positions(|r| r == &prev_file_trx.transaction)
-            .collect();
+    if let Some(state) = state.filter(|_| !args.dry_run) {
+        state.save(&args.state_path()?)?;
+    }
 
-        let match_count = positions_matches.len();
+    let converted_count = accounts.len();
+    let consumed = Consumed {
+        files: consumed,
+        archive_dir: archive_dir.as_deref(),
+        file_system: files,
+    };
+    write_accounts(clock, args, config, &rules, accounts, consumed, summary)?;
 
-        if match_count < prev_file_trx.repetitions {
-            eprintln!("Error: The most recent transaction in the previously processed CSV was found in the main CSV");
-            eprintln!("{} time(s), expected to find it {} time(s). Make sure the most recent CSV contains at least the", match_count, prev_file_trx.repetitions);
-            eprintln!(
-                "entire last day worth of transactions from the previously processed CSV file."
+    // Only remembered once written, so that a failed run can be repeated
+    let mut history_added = HashMap::new();
+    if let Some(history) = history.filter(|_| !args.dry_run) {
+        let today = clock.now().date();
+        for (iban, file, keys) in converted {
+            let added = history.record(&iban, &file, &keys, today)?;
+            info!(
+                "Added {} transaction(s) of {} to {}",
+                added,
+                iban,
+                history.path().display()
             );
-            eprintln!();
-            eprintln!("Missing transaction: {:#?}\n", prev_file_trx.transaction);
-            return Err(
-                "Aborting due to non-overlapping transactions in main and previous CSV files."
-                    .into(),
+            history_added.insert(iban, added);
+        }
+    }
+
+    if let Some(before) = before {
+        record_run(clock, args, summary, &history_added, before)?;
+    }
+
+    if !failed.is_empty() {
+        let converted: Vec<_> = summary.accounts.iter().map(|a| a.iban.as_str()).collect();
+        info!("Converted: {}", converted.join(", "));
+        for account in &failed {
+            error!(
+                "Failed: {}: {}",
+                account.iban,
+                account.error.as_deref().unwrap_or_default()
             );
         }
+        let count = failed.len();
+        summary.accounts.extend(failed);
+        return Err(RunError::AccountsFailed {
+            failed: count,
+            total: count + converted_count,
+        }
+        .into());
+    }
+    Ok(())
+}
 
-        positions_matches
-            .into_iter()
-            .rev()
-            .nth(prev_file_trx.repetitions - 1)
-    } else {
-        None
+/// Returns the summary of an account that could not be converted.
+fn failed_account(
+    config: &Config,
+    newest: &ParsedFileName,
+    error: Box<dyn Error>,
+) -> report::AccountSummary {
+    error!("{}: {}", config.account_label(&newest.iban), error);
+    report::AccountSummary {
+        iban: newest.iban.clone(),
+        name: config.account_name(&newest.iban).map(String::from),
+        file: newest.file_name.clone(),
+        error: Some(error.to_string()),
+        ..Default::default()
+    }
+}
+
+fn load_rules(args: &ConvertArgs) -> Result<Rules, Box<dyn Error>> {
+    Rules::load(args.rules.as_deref(), args.no_default_rules, args.enrich)
+}
+
+/// Handles identical adjacent rows of the export `file_name` as chosen with
+/// `--duplicates`.
+fn merge_duplicates(
+    args: &ConvertArgs,
+    file_name: &str,
+    rows: &mut Vec<Transaction>,
+) -> Result<(), Box<dyn Error>> {
+    let mode = args.duplicates.unwrap_or_default();
+    let merged = duplicates::merge_adjacent(rows, |r, count| {
+        let row = format!("{} {} {}", r.date, r.amount, r.description);
+        match mode {
+            Duplicates::Keep => {
+                report::warn(format!(
+                    "{} has {} identical rows in a row: {}. Kept all of them, use --duplicates merge to keep only one.",
+                    file_name, count, row
+                ));
+                Ok(false)
+            }
+            Duplicates::Merge => Ok(true),
+            Duplicates::Ask => review::confirm(&format!(
+                "{} has {} identical rows in a row: {}. Merge them into one?",
+                file_name, count, row
+            )),
+        }
+    })?;
+    if merged > 0 {
+        info!("Merged {} identical row(s) of {}", merged, file_name);
+    }
+    Ok(())
+}
+
+/// Share of inflows above which the amounts of a checking account look
+/// inverted
+const INVERTED_INFLOW_SHARE: f64 = 0.9;
+
+/// Fewest transactions for which the share of inflows is checked
+const INVERTED_MIN_TRANSACTIONS: usize = 10;
+
+/// Warns if nearly all transactions of a checking account are inflows, which
+/// usually means that its export has the signs of the amounts inverted.
+fn check_inverted(rows: &[YnabRow], iban: &str, config: &Config) {
+    let checking = config.account(iban).and_then(|a| a.kind) == Some(AccountKind::Checking);
+    if !checking || rows.len() < INVERTED_MIN_TRANSACTIONS {
+        return;
+    }
+
+    let inflows = rows.iter().filter(|r| r.amount.cents() > 0).count();
+    if inflows as f64 > rows.len() as f64 * INVERTED_INFLOW_SHARE {
+        report::warn(format!(
+            "{} of {} transactions of checking account {} are inflows, the amounts may be inverted. Set invert_amounts = true for the account in the config file if so.",
+            inflows,
+            rows.len(),
+            iban
+        ));
+    }
+}
+
+/// Converts the new transactions of an account to YNAB rows, leaving out
+/// ignored transactions, in the output currency if amounts are converted.
+/// Amounts are inverted with `--invert` or if the account is configured to
+/// have inverted amounts.
+fn ynab_rows(
+    args: &ConvertArgs,
+    config: &Config,
+    rules: &Rules,
+    rates: Option<&Rates>,
+    account: &mut report::AccountSummary,
+    transactions: Vec<Transaction>,
+    earlier: &Occurrences,
+) -> Result<Vec<YnabRow>, Box<dyn Error>> {
+    let iban = account.iban.as_str();
+    let invert = args.invert || config.invert_amounts(iban);
+    let filtered = remove_ignored(transactions, rules, invert);
+    account.transactions_ignored = filtered.ignored.values().sum();
+    if account.transactions_ignored > 0 {
+        info!(
+            "Ignored {} transaction(s): {}",
+            account.transactions_ignored,
+            filtered
+                .ignored
+                .iter()
+                .map(|(name, count)| format!("{} ({})", name, count))
+                .join(", ")
+        );
+    }
+
+    let date_field = args.date_field.unwrap_or_default();
+    let mut rows = to_ynab_rows_after(
+        filtered.rows,
+        earlier,
+        rules,
+        args.memo(),
+        invert,
+        date_field,
+    );
+    check_inverted(&rows, iban, config);
+    if let Some(rates) = rates {
+        currency::convert(
+            &mut rows,
+            currency::account_currency(iban),
+            &args.currency(iban),
+            rates,
+        )?;
+    }
+    if let Some(below) = args.aggregate_below {
+        let merged = aggregate::aggregate_small(&mut rows, below);
+        if merged > 0 {
+            info!(
+                "Merged {} small purchase(s) into daily \"{}\" transactions.",
+                merged,
+                aggregate::PAYEE
+            );
+        }
+    }
+    // Before sanitizing, so that what the command writes is cleaned up too
+    if let Some(command) = &args.post_process {
+        rows = postprocess::post_process(command, iban, rows)?;
+    }
+    sanitize::sanitize(&mut rows, &args.sanitize_options());
+    Ok(rows)
+}
+
+/// The exports the accounts given to [`write_accounts`] were converted from
+struct Consumed<'a> {
+    /// Files of each account, in the order of the accounts
+    files: Vec<Vec<&'a ParsedFileName>>,
+    /// Where the files are archived once the rows of their account are
+    /// written, if anywhere
+    archive_dir: Option<&'a Path>,
+    /// File system the files are in
+    file_system: &'a dyn FileSystem,
+}
+
+/// Writes or uploads the rows of each account, and archives the files the
+/// account was converted from once its rows are written. `summary` must
+/// already list the accounts, in the same order.
+fn write_accounts(
+    clock: &dyn Clock,
+    args: &ConvertArgs,
+    config: &Config,
+    rules: &Rules,
+    mut accounts: Vec<(String, Vec<YnabRow>)>,
+    consumed: Consumed,
+    summary: &mut Summary,
+) -> Result<(), Box<dyn Error>> {
+    let Consumed {
+        files: consumed,
+        archive_dir,
+        file_system,
+    } = consumed;
+    if accounts.len() > 1 && !args.no_transfers {
+        let count = transfers::mark_transfers(&mut accounts);
+        info!("Detected {} transfer(s) between accounts.\n", count);
+    }
+
+    if args.append.is_some() && !matches!(args.format(), Format::Csv) {
+        return Err("--append can only be used with --format csv".into());
+    }
+
+    // Check before writing anything, so that a refused file or a read-only
+    // directory does not leave the other accounts written and their exports
+    // archived
+    if !(args.dry_run || args.upload || args.stdout) {
+        let mut outputs: Vec<PathBuf> = args.report.iter().cloned().collect();
+        match &args.append {
+            Some(master) => outputs.push(master.clone()),
+            None => {
+                for (index, (iban, rows)) in accounts.iter().enumerate() {
+                    if summary.accounts[index].empty_export && !args.allow_empty {
+                        continue;
+                    }
+                    let export_date = consumed[index].first().map(|f| f.date.date());
+                    for (output, _) in args.output_files(iban, config, rows, export_date)? {
+                        if !args.force && Path::new(&output).exists() {
+                            return Err(format!(
+                                "{} already exists and may not have been imported yet, use --force to overwrite it",
+                                output
+                            )
+                            .into());
+                        }
+                        outputs.push(output.into());
+                    }
+                }
+            }
+        }
+        atomic::check_writable(&outputs)?;
+    }
+
+    let mut uploads = vec![];
+    let mut reports = vec![];
+    let mut impact_rows = vec![];
+    // Sum of the rows of each account, which are not in YNAB unless uploaded
+    let mut totals = vec![];
+    for (index, ((iban, rows), files)) in accounts.into_iter().zip(consumed).enumerate() {
+        let account = &mut summary.accounts[index];
+        let rows = if args.interactive {
+            match review::review(rows)? {
+                Some(rows) => rows,
+                None => {
+                    info!("Nothing written for {}.", config.account_label(&iban));
+                    continue;
+                }
+            }
+        } else {
+            rows
+        };
+
+        if args.report_html.is_some() {
+            account.rows = rows.clone();
+        }
+
+        let report = Stats::new(&rows).report(&config.account_label(&iban));
+        if args.report.is_some() {
+            reports.push(report);
+        } else {
+            info!("{}", console::section(&report));
+        }
+        if args.recurring {
+            let recurring = recurring::detect(&rows);
+            info!(
+                "{}",
+                recurring::report(&recurring, &config.account_label(&iban))
+            );
+        }
+
+        if args.impact {
+            impact_rows.extend(rows.iter().cloned());
+        }
+        if args.check_balance {
+            let total = rows
+                .iter()
+                .fold(Amount::default(), |total, r| total + r.amount);
+            totals.push((index, total));
+        }
+
+        // Rows already in the file are not written when appending
+        let mut written = rows.len();
+        if account.empty_export && !args.allow_empty {
+            info!(
+                "{}",
+                console::paint(
+                    Style::Success,
+                    &format!(
+                        "No new transactions, {} has none. Nothing written.",
+                        account.file
+                    )
+                )
+            );
+        } else if args.dry_run
+            && args.upload
+            && args.target.unwrap_or_default() == UploadTarget::Ynab
+        {
+            preview_upload(config, &iban, &rows)?;
+        } else if args.dry_run {
+            print_table(&rows);
+            info!(
+                "\n{}",
+                console::paint(
+                    Style::Success,
+                    &format!("{} transactions would be exported (dry run).", rows.len())
+                )
+            );
+        } else if args.upload {
+            // Uploaded together with the other accounts below
+            uploads.push((index, iban, rows, files));
+            continue;
+        } else if let Some(master) = &args.append {
+            let account_name = config.account_name(&iban).unwrap_or(&iban);
+            let currency = args.currency(&iban);
+            let csv_account = columns::Account {
+                iban: &iban,
+                name: account_name,
+                currency: &currency,
+            };
+            let appended = append::append_ynab_csv(
+                master,
+                &rows,
+                &args.csv_options(rules, config),
+                &csv_account,
+            )?;
+            info!(
+                "{}",
+                console::paint(
+                    Style::Success,
+                    &format!(
+                        "{} transactions appended to {}, {} already in it.",
+                        appended.written,
+                        master.display(),
+                        appended.duplicates
+                    )
+                )
+            );
+            account.output = Some(master.display().to_string());
+            written = appended.written;
+        } else if args.stdout {
+            let currency = args.currency(&iban);
+            let csv = args.csv_options(rules, config);
+            let context = output::Context {
+                iban: &iban,
+                currency: &currency,
+                csv: &csv,
+                config,
+                now: clock.now(),
+            };
+            write_output(&rows, args.format(), &context, io::stdout().lock())?;
+            info!(
+                "{}",
+                console::paint(
+                    Style::Success,
+                    &format!("{} transactions written to stdout.", rows.len())
+                )
+            );
+        } else {
+            let currency = args.currency(&iban);
+            let csv = args.csv_options(rules, config);
+            let context = output::Context {
+                iban: &iban,
+                currency: &currency,
+                csv: &csv,
+                config,
+                now: clock.now(),
+            };
+            let mut outputs = vec![];
+            let export_date = files.first().map(|f| f.date.date());
+            for (output, rows) in args.output_files(&iban, config, &rows, export_date)? {
+                let mut file = AtomicFile::create(Path::new(&output))
+                    .map_err(|e| format!("Could not write {}: {}", output, e))?;
+                write_output(&rows, args.format(), &context, &mut file)?;
+                file.commit()
+                    .map_err(|e| format!("Could not write {}: {}", output, e))?;
+                info!(
+                    "{}",
+                    console::paint(
+                        Style::Success,
+                        &format!("{} transactions written to {}.", rows.len(), output)
+                    )
+                );
+                outputs.push(output);
+            }
+            account.output = (!outputs.is_empty()).then(|| outputs.join(", "));
+        }
+        account.transactions_written = written;
+
+        if let Some(dir) = archive_dir.filter(|_| !args.dry_run) {
+            archive::archive_files(file_system, &files, dir, args.archive_copy)?;
+        }
+
+        if args.all_accounts {
+            info!();
+        }
+    }
+
+    if let Some(path) = &args.report {
+        atomic::write(path, reports.join("\n").as_bytes())
+            .map_err(|e| format!("Could not write report {}: {}", path.display(), e))?;
+    }
+
+    // Before uploading, so that the output can be read while YNAB works
+    if args.impact {
+        let (token, budget_id) = ynab::credentials(config)?;
+        let categories = ynab::month_categories(token, budget_id)?;
+        // On stderr like the other reports, so that it does not mix with
+        // --stdout or --json output
+        info!(
+            "{}",
+            console::section(&impact::report(&impact::impact(&impact_rows, &categories)))
+        );
+    }
+
+    if !uploads.is_empty() {
+        let target = args.target.unwrap_or_default();
+        let api = target.api();
+        let account_id = |iban: &str| api.account_id(config, iban);
+
+        if target == UploadTarget::Ynab && args.match_payees {
+            let (token, budget_id) = ynab::credentials(config)?;
+            let existing = ynab::payees(token, budget_id)?;
+            for (_, iban, rows, _) in &mut uploads {
+                let matched = payees::match_payees(rows, &existing);
+                if matched > 0 {
+                    info!(
+                        "{}: {} payee(s) replaced by matching YNAB payees.",
+                        iban, matched
+                    );
+                }
+            }
+        }
+
+        if target == UploadTarget::Ynab {
+            let (token, budget_id) = ynab::credentials(config)?;
+            let today = clock.now().date();
+            for (_, iban, rows, _) in &mut uploads {
+                let Some(account_id) = account_id(iban) else {
+                    continue;
+                };
+                // Only a safeguard, the upload itself may still succeed
+                let reconciled = match ynab::last_reconciled(token, budget_id, account_id) {
+                    Ok(reconciled) => reconciled,
+                    Err(e) => {
+                        report::warn(format!(
+                            "{}: could not look up when the account was last reconciled in YNAB: {}",
+                            iban, e
+                        ));
+                        None
+                    }
+                };
+                let floor = ynab::date_floor(reconciled, today);
+                let before = rows.len();
+                rows.retain(|r| parse_nda_date(&r.date).map_or(true, |date| date >= floor));
+                if rows.len() < before {
+                    report::warn(match reconciled {
+                        Some(reconciled) if reconciled == floor => format!(
+                            "{}: left out {} transaction(s) from before the account was last reconciled in YNAB on {}.",
+                            iban,
+                            before - rows.len(),
+                            reconciled
+                        ),
+                        _ => format!(
+                            "{}: left out {} transaction(s) from before {}, which YNAB does not accept.",
+                            iban,
+                            before - rows.len(),
+                            floor
+                        ),
+                    });
+                }
+            }
+        }
+
+        let mut batches = vec![];
+        for (index, iban, rows, _) in &uploads {
+            match account_id(iban) {
+                Some(account_id) => batches.push(ynab::AccountBatch { account_id, rows }),
+                None => {
+                    summary.accounts[*index].error = Some(format!(
+                        "No {} account configured for {}",
+                        target.name(),
+                        iban
+                    ));
+                }
+            }
+        }
+
+        let results = api.upload(config, &batches, &args.state_path()?)?;
+
+        let mut failed = 0;
+        for (index, iban, _, files) in &uploads {
+            let account = &mut summary.accounts[*index];
+            if account.error.is_none() {
+                let account_id = account_id(iban).expect("mapped account");
+                match &results {
+                    Ok(results) => {
+                        let result = &results[account_id];
+                        account.transactions_written = result.created;
+                        account.uploaded_ids = result.ids.clone();
+                        account.output = Some(target.name().to_lowercase());
+                        info!(
+                            "{}",
+                            console::paint(
+                                Style::Success,
+                                &format!(
+                                    "{}: {} transactions created, {} already in {}.",
+                                    iban,
+                                    result.created,
+                                    result.duplicates,
+                                    target.name()
+                                )
+                            )
+                        );
+
+                        if let Some(dir) = archive_dir {
+                            archive::archive_files(file_system, files, dir, args.archive_copy)?;
+                        }
+                        continue;
+                    }
+                    Err(e) => account.error = Some(e.to_string()),
+                }
+            }
+
+            failed += 1;
+            info!(
+                "{}: {} {}",
+                iban,
+                console::paint(Style::Error, "upload failed:"),
+                account.error.as_deref().unwrap_or_default()
+            );
+        }
+
+        if failed > 0 {
+            return Err(format!("Upload failed for {} account(s)", failed).into());
+        }
+    }
+
+    if args.check_balance {
+        check_balances(args, config, summary, &totals)?;
+    }
+
+    Ok(())
+}
+
+/// Compares the balance at the end of each account's export with the
+/// cleared balance of its YNAB account, less `totals` of the rows that were
+/// not uploaded to YNAB.
+fn check_balances(
+    args: &ConvertArgs,
+    config: &Config,
+    summary: &Summary,
+    totals: &[(usize, Amount)],
+) -> Result<(), Box<dyn Error>> {
+    let (token, budget_id) = ynab::credentials(config)?;
+    for (index, total) in totals {
+        let account = &summary.accounts[*index];
+        let iban = account.iban.as_str();
+        let label = config.account_label(iban);
+        let Some(balance) = account.balance else {
+            info!("{}: the export has no balance to compare with YNAB.", label);
+            continue;
+        };
+        let Some(account_id) = config.ynab_account_id(iban) else {
+            info!(
+                "{}: no YNAB account configured to compare the balance with.",
+                label
+            );
+            continue;
+        };
+        if args.currency(iban) != currency::account_currency(iban) {
+            info!(
+                "{}: the amounts were converted, the balance is not compared with YNAB.",
+                label
+            );
+            continue;
+        }
+
+        let balance = if args.invert || config.invert_amounts(iban) {
+            -balance
+        } else {
+            balance
+        };
+        let uploaded = account.output.as_deref() == Some("ynab");
+        let expected = if uploaded { balance } else { balance + -*total };
+        let cleared = ynab::cleared_balance(token, budget_id, account_id)?;
+        if cleared == expected {
+            info!(
+                "{}: the balance matches the cleared balance in YNAB, {}.",
+                label, cleared
+            );
+        } else if uploaded {
+            report::warn(format!(
+                "The balance of {} at the end of {} is {}, but its cleared balance in YNAB is {}",
+                label, account.file, balance, cleared
+            ));
+        } else {
+            report::warn(format!(
+                "The balance of {} at the end of {} is {}, which is {} before importing the transactions written, but its cleared balance in YNAB is {}",
+                label, account.file, balance, expected, cleared
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Prints how the rows of account `iban` compare with the transactions in
+/// its YNAB account, instead of uploading them.
+fn preview_upload(config: &Config, iban: &str, rows: &[YnabRow]) -> Result<(), Box<dyn Error>> {
+    let label = config.account_label(iban);
+    let Some(since) = rows
+        .iter()
+        .filter_map(|r| parse_nda_date(&r.date).ok())
+        .min()
+    else {
+        info!("{}: nothing to upload (dry run).", label);
+        return Ok(());
     };
+    let account_id = config
+        .ynab_account_id(iban)
+        .ok_or_else(|| format!("No YNAB account configured for {}", iban))?;
+    let (token, budget_id) = ynab::credentials(config)?;
 
-    let rows = if let Some(first_previously_processed_index) = first_previously_processed_index {
-        newest_rows[0..first_previously_processed_index].to_vec()
-    } else {
-        newest_rows
+    let existing = ynab::account_transactions(token, budget_id, account_id, since)?;
+    let import_ids = ynab::import_ids(rows)?;
+    let preview = reconcile::preview(rows, &import_ids, &existing);
+    print!("{}", reconcile::report(&preview, &label));
+    Ok(())
+}
+
+fn run(cli: Cli, clock: &dyn Clock, summary: &mut Summary) -> Result<(), Box<dyn Error>> {
+    // These do not need a config file
+    match cli.command {
+        Some(Command::Completions { shell }) => {
+            print!("{}", completions::generate(shell, &mut Cli::command()));
+            return Ok(());
+        }
+        Some(Command::Man) => {
+            print!("{}", manpage::generate(&mut Cli::command()));
+            return Ok(());
+        }
+        #[cfg(feature = "self-update")]
+        Some(Command::SelfUpdate { check }) => return self_update(check),
+        _ => {}
+    }
+
+    let config = match &cli.config {
+        Some(path) => Config::load(path)?,
+        None => Config::discover()?,
     };
 
-    let mut wtr = WriterBuilder::new().from_path("out.csv")?;
+    match cli.command {
+        Some(Command::Watch(mut args)) => {
+            args.convert.apply_config(&config);
+            let path = args.path.take().or_else(|| config.path.clone());
+            let path = path_or_downloads(path, args.convert.auto)?;
+            watch::run(clock, &path, &args, &config)
+        }
+        Some(Command::Fetch(mut args)) => {
+            args.convert.apply_config(&config);
+            fetch::run(clock, &mut args.convert, &config, summary)
+        }
+        #[cfg(feature = "fetch-mail")]
+        Some(Command::FetchMail(mut args)) => {
+            args.convert.apply_config(&config);
+            fetch_mail::run(clock, &args, &config, summary)
+        }
+        Some(Command::Upload(mut command)) => {
+            let args = &command.convert;
+            if args.stdout || args.output.is_some() || args.format.is_some() {
+                return Err("--stdout, --output and --format cannot be used when uploading".into());
+            }
+            command.convert.upload = true;
+            convert(clock, command, &config, summary)
+        }
+        Some(Command::Status(args)) => status::run(clock, &args, &config),
+        Some(Command::Diff(args)) => compare::run(clock, &args),
+        Some(Command::Anonymize(args)) => anonymize::run(&args),
+        Some(Command::Rules { command }) => rule_matches::run(clock, &command, &config),
+        Some(Command::Validate(args)) => validate::run(clock, &args),
+        Some(Command::Report { command }) => reports::run(clock, &command, &config),
+        Some(Command::History(args)) => runs::history(&args, &config),
+        Some(Command::UndoLast(args)) => runs::undo_last(&args, &config),
+        Some(Command::Convert(command)) => convert(clock, command, &config, summary),
+        Some(Command::Completions { .. } | Command::Man) => unreachable!(),
+        #[cfg(feature = "self-update")]
+        Some(Command::SelfUpdate { .. }) => unreachable!(),
+        None => convert(clock, cli.convert, &config, summary),
+    }
+}
 
-    let num_trx = rows.len();
+/// Updates the binary to the latest release, or with `check` only tells if
+/// there is one.
+#[cfg(feature = "self-update")]
+fn self_update(check: bool) -> Result<(), Box<dyn Error>> {
+    use nda2ynab::update;
+    use std::{cmp::Ordering, env::consts};
 
-    let _: Result<Vec<_>, _> = rows
-        .into_iter()
-        .map(|r| YnabRow {
-            date: r.date,
-            payee: r.description,
-            memo: "".to_string(),
-            amount: r.amount,
+    let current = env!("CARGO_PKG_VERSION");
+    let release = update::latest_release()?;
+    if update::compare_versions(&release.version, current) != Ordering::Greater {
+        println!("nda2ynab {} is the latest version.", current);
+        return Ok(());
+    }
+    if check {
+        println!(
+            "nda2ynab {} is available, this is {}. Run nda2ynab self-update to update.",
+            release.version, current
+        );
+        return Ok(());
+    }
+
+    let asset = release.asset_for(consts::OS, consts::ARCH).ok_or_else(|| {
+        format!(
+            "Release {} has no binary for {} on {}",
+            release.version,
+            consts::ARCH,
+            consts::OS
+        )
+    })?;
+    info!("Downloading {}...", asset.name);
+    let path = update::install(asset)?;
+    println!(
+        "Updated {} from {} to {}.",
+        path.display(),
+        current,
+        release.version
+    );
+    Ok(())
+}
+
+/// Records the run in the state file for `nda2ynab history` and `undo-last`,
+/// if it wrote anything. `before` is the state before the run.
+fn record_run(
+    clock: &dyn Clock,
+    args: &ConvertArgs,
+    summary: &Summary,
+    history_added: &HashMap<String, usize>,
+    before: Value,
+) -> Result<(), Box<dyn Error>> {
+    let accounts: Vec<RunAccount> = summary
+        .accounts
+        .iter()
+        .filter(|a| a.error.is_none() && a.transactions_written > 0)
+        .map(|a| RunAccount {
+            iban: a.iban.clone(),
+            file: a.file.clone(),
+            transactions: a.transactions_written,
+            output: a.output.clone(),
+            ynab_ids: a.uploaded_ids.clone(),
+            history_added: history_added.get(&a.iban).copied().unwrap_or(0),
         })
-        .map(|r| wtr.serialize(r))
         .collect();
+    if accounts.is_empty() {
+        return Ok(());
+    }
+
+    let state_path = args.state_path()?;
+    let mut state = State::load(&state_path)?;
+    state.record_run(Run {
+        time: clock.now(),
+        accounts,
+        before,
+    });
+    state.save(&state_path)
+}
 
-    wtr.flush()?;
+/// Returns `path`, or with `--auto` the Downloads directory if no path was
+/// given.
+fn path_or_downloads(path: Option<String>, auto: bool) -> Result<String, Box<dyn Error>> {
+    if let Some(path) = path {
+        return Ok(path);
+    }
+    if !auto {
+        return Err("No path given on the command line or in the config file, \
+                    or use --auto to look in the Downloads directory"
+            .into());
+    }
+    let dir = downloads::find().ok_or("--auto: could not find the Downloads directory")?;
+    info!("Looking for exports in {}", dir.display());
+    Ok(dir.to_string_lossy().into_owned())
+}
 
-    println!("{} transactions written to out.csv.", num_trx);
+fn convert(
+    clock: &dyn Clock,
+    command: ConvertCommand,
+    config: &Config,
+    summary: &mut Summary,
+) -> Result<(), Box<dyn Error>> {
+    let mut args = command.convert;
+    args.apply_config(config);
+    if args.all_accounts {
+        if let Some(output) = args.output.as_ref().filter(|o| !has_account_placeholder(o)) {
+            return Err(format!(
+                "--output {} would be written for every account, name the files after the account with {{account_name}} or {{iban}}",
+                output
+            )
+            .into());
+        }
+    }
+    // With an explicitly given export, the directory is only used for the
+    // default archive location
+    let current_dir = args.current.as_ref().map(|current| {
+        let dir = current.parent().unwrap_or_else(|| Path::new(""));
+        dir.to_string_lossy().into_owned()
+    });
+    let path = command.path.or_else(|| config.path.clone()).or(current_dir);
+    let path = path_or_downloads(path, args.auto)?;
+    if path == "-" {
+        return run_stdin(clock, args, config, summary);
+    }
+    run_convert(&Disk, clock, &path, &args, config, None, summary)
+}
 
-    Ok(())
+/// Paths of output files with the rows written to each
+type OutputFiles = Vec<(String, Vec<YnabRow>)>;
+
+/// Returns whether an output file name template names the file after the
+/// account, so that it can be used for several accounts.
+fn has_account_placeholder(template: &str) -> bool {
+    output::ACCOUNT_PLACEHOLDERS
+        .iter()
+        .any(|placeholder| template.contains(placeholder))
+}
+
+/// Account name used for an export read from stdin, which has no file name
+/// to read the IBAN from
+const STDIN_ACCOUNT: &str = "stdin";
+
+/// Converts all transactions of a single export read from stdin and writes
+/// them to stdout. There is no previous export to compare with, so nothing
+/// is left out as already processed.
+fn run_stdin(
+    clock: &dyn Clock,
+    mut args: ConvertArgs,
+    config: &Config,
+    summary: &mut Summary,
+) -> Result<(), Box<dyn Error>> {
+    if args.all_accounts
+        || args.backfill
+        || args.current.is_some()
+        || args.previous.is_some()
+        || args.output.is_some()
+        || args.archive.is_some()
+        || args.track_holds
+        || args.interactive
+        || args.upload
+        || args.duplicates == Some(Duplicates::Ask)
+    {
+        return Err("--all-accounts, --backfill, --current, --previous, --output, --archive, --track-holds, --interactive, --upload and --duplicates ask cannot be used when reading from stdin".into());
+    }
+    if args.append.is_none() {
+        args.stdout = true;
+    }
+
+    let mut bytes = vec![];
+    io::stdin().read_to_end(&mut bytes)?;
+    let contents = encoding::decode(&bytes, args.encoding())
+        .map_err(|e| format!("Could not decode stdin: {}", e))?;
+    let registry = args.registry(clock)?;
+    let export = registry
+        .detect_export(contents, Path::new(STDIN_ACCOUNT))
+        .ok_or("Could not recognise the export read from stdin")?;
+    let file = ParsedFileName {
+        file_name: STDIN_ACCOUNT.to_string(),
+        path: PathBuf::from(STDIN_ACCOUNT),
+        date: clock.now(),
+        iban: STDIN_ACCOUNT.to_string(),
+        bank: export.bank(),
+    };
+    info!("{}", console::paint(Style::Heading, "Files"));
+    info!(
+        "{}",
+        console::field("Main CSV", &format!("{} export read from stdin", file.bank))
+    );
+    info!();
+
+    let opts = args.convert_options();
+    let mut conversion = convert_single(&export, &file, &opts)?;
+    if args.include_pending {
+        let pending = holds::pending_rows(&conversion.holds, &[], clock);
+        conversion.rows.splice(0..0, pending);
+    }
+    merge_duplicates(&args, &file.file_name, &mut conversion.rows)?;
+    summary.accounts.push(report::AccountSummary {
+        iban: file.iban.clone(),
+        name: config.account_name(&file.iban).map(String::from),
+        file: file.file_name.clone(),
+        transactions_skipped: conversion.skipped,
+        empty_export: conversion.is_empty_export(),
+        skipped_rows: mem::take(&mut conversion.skipped_rows),
+        ..Default::default()
+    });
+
+    let rules = load_rules(&args)?;
+    let rates = args.rates()?;
+    let account = summary.accounts.last_mut().expect("account summary");
+    let rows = ynab_rows(
+        &args,
+        config,
+        &rules,
+        rates.as_ref(),
+        account,
+        conversion.rows,
+        &conversion.earlier,
+    )?;
+    let consumed = Consumed {
+        files: vec![vec![]],
+        archive_dir: None,
+        file_system: &Disk,
+    };
+    write_accounts(
+        clock,
+        &args,
+        config,
+        &rules,
+        vec![(file.iban.clone(), rows)],
+        consumed,
+        summary,
+    )
+}
+
+/// Shows the desktop notification of a run for --notify. A notification
+/// that cannot be shown does not fail the run.
+fn send_notification(summary: &Summary, error: Option<&(dyn Error + 'static)>) {
+    let (title, body) = notify::message(summary, error);
+    if let Err(e) = notify::send(&title, &body) {
+        error!("{} {}", console::paint(Style::Error, "Error:"), e);
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    log::set_level(log::Level::from_flags(cli.quiet, cli.verbose));
+    console::init(cli.no_color);
+    let json = cli.json();
+    let report_html = cli.report_html();
+    let notify = cli.notify();
+
+    let mut summary = Summary::default();
+    let clock = SystemClock;
+    let result = run(cli, &clock, &mut summary);
+
+    let warnings = report::take_warnings();
+    if !json {
+        if let Some(recap) = report::recap(&warnings) {
+            info!("\n{}", console::section(&recap));
+        }
+    }
+    if let Err(e) = &result {
+        error!("{} {}", console::paint(Style::Error, "Error:"), e);
+    }
+
+    let error = result.as_ref().err().map(|e| e.as_ref());
+    let mut report_failed = false;
+    if let Some(path) = &report_html {
+        let page = html::report(&summary, &warnings, error, clock.now());
+        if let Err(e) = atomic::write(path, page.as_bytes()) {
+            error!(
+                "{} Could not write report {}: {}",
+                console::paint(Style::Error, "Error:"),
+                path.display(),
+                e
+            );
+            report_failed = true;
+        }
+    }
+
+    if notify {
+        send_notification(&summary, error);
+    }
+    if json {
+        println!("{}", summary.to_json(warnings, error).to_pretty_string());
+    }
+
+    let code = match &result {
+        Err(e) => report::exit_code(e.as_ref()),
+        Ok(()) if report_failed => report::EXIT_ERROR,
+        Ok(()) if json && summary.stale_export() => report::EXIT_STALE_EXPORT,
+        Ok(()) if json && summary.nothing_new() => report::EXIT_NOTHING_NEW,
+        Ok(()) => 0,
+    };
+    process::exit(code);
 }
@@ -0,0 +1,134 @@
+//! A man page in roff format, generated from the command line definition
+//! like the shell completions.
+
+use clap::{Arg, Command};
+use std::fmt::Write;
+
+/// Returns the man page of `cmd` and its subcommands.
+pub fn generate(cmd: &mut Command) -> String {
+    cmd._build_all();
+    let name = cmd.get_name().to_string();
+    let mut out = String::new();
+    writeln!(
+        out,
+        ".TH {} 1 \"\" \"{} {}\"",
+        name.to_uppercase(),
+        name,
+        cmd.get_version().unwrap_or_default()
+    )
+    .unwrap();
+
+    // Without a package description, the first paragraph of the long one
+    let about = cmd.get_about().map(str::to_string).unwrap_or_else(|| {
+        let long_about = cmd.get_long_about().unwrap_or_default().trim();
+        let paragraph = long_about.split("\n\n").next().unwrap_or_default();
+        paragraph.split_whitespace().collect::<Vec<_>>().join(" ")
+    });
+    out += ".SH NAME\n";
+    writeln!(out, "{} \\- {}", name, escape(&about)).unwrap();
+
+    out += ".SH SYNOPSIS\n";
+    synopsis(&mut out, &name, cmd);
+
+    if let Some(about) = cmd.get_long_about() {
+        out += ".SH DESCRIPTION\n";
+        paragraphs(&mut out, about);
+    }
+
+    out += ".SH OPTIONS\n";
+    options(&mut out, cmd);
+
+    let subcommands: Vec<_> = cmd
+        .get_subcommands()
+        .filter(|s| !s.is_hide_set() && s.get_name() != "help")
+        .collect();
+    if !subcommands.is_empty() {
+        out += ".SH SUBCOMMANDS\n";
+        for sub in subcommands {
+            writeln!(out, ".SS {}", sub.get_name()).unwrap();
+            synopsis(&mut out, &format!("{} {}", name, sub.get_name()), sub);
+            if let Some(about) = sub.get_long_about().or_else(|| sub.get_about()) {
+                paragraphs(&mut out, about);
+            }
+            options(&mut out, sub);
+        }
+    }
+    out
+}
+
+fn synopsis(out: &mut String, name: &str, cmd: &Command) {
+    write!(out, "\\fB{}\\fR [\\fIOPTIONS\\fR]", escape(name)).unwrap();
+    for arg in cmd.get_positionals().filter(|a| !a.is_hide_set()) {
+        let value = escape(&value_name(arg));
+        if arg.is_required_set() {
+            write!(out, " \\fI{}\\fR", value).unwrap();
+        } else {
+            write!(out, " [\\fI{}\\fR]", value).unwrap();
+        }
+    }
+    if cmd.has_subcommands() {
+        out.push_str(" [\\fISUBCOMMAND\\fR]");
+    }
+    out.push('\n');
+}
+
+fn options(out: &mut String, cmd: &Command) {
+    for arg in cmd.get_arguments().filter(|a| !a.is_hide_set()) {
+        out.push_str(".TP\n");
+        if arg.is_positional() {
+            writeln!(out, "\\fI{}\\fR", escape(&value_name(arg))).unwrap();
+        } else {
+            let short = arg.get_short().map(|s| format!("\\fB\\-{}\\fR", s));
+            let long = arg
+                .get_long()
+                .map(|l| format!("\\fB\\-\\-{}\\fR", escape(l)));
+            let flags: Vec<_> = short.into_iter().chain(long).collect();
+            write!(out, "{}", flags.join(", ")).unwrap();
+            if arg.is_takes_value_set() {
+                write!(out, " \\fI{}\\fR", escape(&value_name(arg))).unwrap();
+            }
+            out.push('\n');
+        }
+
+        let help = arg.get_long_help().or_else(|| arg.get_help());
+        let mut help = help.unwrap_or_default().to_string();
+        if let Some(values) = arg.get_possible_values() {
+            let values: Vec<_> = values
+                .iter()
+                .filter(|v| !v.is_hide_set())
+                .map(|v| v.get_name())
+                .collect();
+            write!(help, " [possible values: {}]", values.join(", ")).unwrap();
+        }
+        writeln!(out, "{}", escape(help.trim())).unwrap();
+    }
+}
+
+fn value_name(arg: &Arg) -> String {
+    match arg.get_value_names() {
+        Some(names) => names.join(" "),
+        None => arg.get_id().to_uppercase(),
+    }
+}
+
+/// Writes `text` as paragraphs separated by blank lines.
+fn paragraphs(out: &mut String, text: &str) {
+    for paragraph in text.trim().split("\n\n") {
+        out.push_str(".PP\n");
+        writeln!(out, "{}", escape(paragraph.trim())).unwrap();
+    }
+}
+
+/// Escapes backslashes and dashes, and lines that would start with a roff
+/// control character.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\e")
+        .replace('-', "\\-")
+        .lines()
+        .map(|line| match line.chars().next() {
+            Some('.' | '\'') => format!("\\&{}", line),
+            _ => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,454 @@
+//! CSV exports of any bank, read by a column mapping given with
+//! `--input-map`.
+//!
+//! The mapping names the columns holding the date, the amount (or separate
+//! outflow and inflow columns) and the payee, and optionally the memo,
+//! reference, currency, balance and account, along with the date format and
+//! delimiter of the export. It is given inline, e.g.
+//! `date=Booking date,amount=Amount,payee=Text`, or as the path of a TOML file
+//! with the same keys, which can also name columns with commas in them. The
+//! header row is looked for among the first lines like with the built-in
+//! parsers. Exports without an account column are converted as the account
+//! named after the file, so they are given with `--current`, and exports
+//! with one are rejected if their rows are of more than one account.
+
+use crate::{
+    amount::Amount,
+    group_iban, iban_pattern, parse_nda_date,
+    parser::{self, source_columns, BankParser, FileInfo, RowError, Rows},
+    toml::{self, Value},
+    RawTransaction, Transaction, OUTPUT_DATE_FORMAT,
+};
+use chrono::NaiveDate;
+use csv::{ReaderBuilder, StringRecord};
+use regex::Regex;
+use std::{error::Error, fs, path::Path, str::FromStr};
+
+/// Keys of a mapping naming columns
+const COLUMN_KEYS: &[&str] = &[
+    "date",
+    "amount",
+    "outflow",
+    "inflow",
+    "payee",
+    "memo",
+    "reference",
+    "currency",
+    "balance",
+    "account",
+];
+
+/// Which columns of an export hold the fields of a transaction
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputMap {
+    pub date: String,
+    /// Signed amounts, or `None` with separate outflow and inflow columns
+    pub amount: Option<String>,
+    pub outflow: Option<String>,
+    pub inflow: Option<String>,
+    pub payee: String,
+    pub memo: Option<String>,
+    pub reference: Option<String>,
+    pub currency: Option<String>,
+    pub balance: Option<String>,
+    /// Account, e.g. the IBAN, of each row
+    pub account: Option<String>,
+    /// chrono format of the dates, e.g. `%d/%m/%Y` [default: the formats of
+    /// Nordea exports]
+    pub date_format: Option<String>,
+    /// Delimiter of the fields [default: sniffed]
+    pub delimiter: Option<u8>,
+}
+
+impl InputMap {
+    /// Reads the mapping from the TOML file at `spec` if there is one, or
+    /// parses `spec` as an inline mapping.
+    pub fn load(spec: &str) -> Result<InputMap, Box<dyn Error>> {
+        let path = Path::new(spec);
+        if !path.is_file() {
+            return Ok(spec.parse()?);
+        }
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read input map {}: {}", path.display(), e))?;
+        let doc = toml::parse(&contents)
+            .map_err(|e| format!("Could not parse input map {}: {}", path.display(), e))?;
+        let map = doc
+            .iter()
+            .map(|(key, value)| match value {
+                Value::String(s) => Ok((key.as_str(), s.as_str())),
+                _ => Err(format!("'{}' must be a string", key)),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .and_then(InputMap::from_pairs)
+            .map_err(|e| format!("In input map {}: {}", path.display(), e))?;
+        Ok(map)
+    }
+
+    fn from_pairs<'a>(
+        pairs: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Result<InputMap, String> {
+        let mut map = InputMap::default();
+        let (mut date, mut payee) = (None, None);
+        for (key, value) in pairs {
+            let value = value.trim().to_string();
+            let column = match key.trim() {
+                "date" => &mut date,
+                "amount" => &mut map.amount,
+                "outflow" => &mut map.outflow,
+                "inflow" => &mut map.inflow,
+                "payee" => &mut payee,
+                "memo" => &mut map.memo,
+                "reference" => &mut map.reference,
+                "currency" => &mut map.currency,
+                "balance" => &mut map.balance,
+                "account" => &mut map.account,
+                "date_format" => {
+                    map.date_format = Some(value);
+                    continue;
+                }
+                "delimiter" => {
+                    map.delimiter = Some(parse_delimiter(&value)?);
+                    continue;
+                }
+                key => {
+                    return Err(format!(
+                        "unknown key '{}', expected one of {}, date_format or delimiter",
+                        key,
+                        COLUMN_KEYS.join(", ")
+                    ))
+                }
+            };
+            if value.is_empty() {
+                return Err(format!("no column given for '{}'", key.trim()));
+            }
+            *column = Some(value);
+        }
+
+        map.date = date.ok_or("the date column is required")?;
+        map.payee = payee.ok_or("the payee column is required")?;
+        match (&map.amount, &map.outflow, &map.inflow) {
+            (Some(_), None, None) | (None, Some(_), Some(_)) => Ok(map),
+            (None, None, None) => {
+                Err("an amount column, or both outflow and inflow columns, are required".into())
+            }
+            _ => Err("give either an amount column or both outflow and inflow columns".into()),
+        }
+    }
+
+    /// Names of the columns of the mapping
+    fn columns(&self) -> Vec<&str> {
+        [&self.amount, &self.outflow, &self.inflow]
+            .into_iter()
+            .chain([&self.memo, &self.reference, &self.currency])
+            .chain([&self.balance, &self.account])
+            .flatten()
+            .map(String::as_str)
+            .chain([self.date.as_str(), self.payee.as_str()])
+            .collect()
+    }
+}
+
+/// Parses an inline mapping of comma separated `key=column` pairs.
+impl FromStr for InputMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<InputMap, String> {
+        let pairs = s
+            .split(',')
+            .filter(|pair| !pair.trim().is_empty())
+            .map(|pair| {
+                pair.split_once('=')
+                    .ok_or_else(|| format!("expected key=column, found '{}'", pair.trim()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        InputMap::from_pairs(pairs).map_err(|e| format!("Invalid input map: {}", e))
+    }
+}
+
+/// Parses a delimiter given as a single character or by its name, as a comma
+/// cannot be given in an inline mapping.
+fn parse_delimiter(value: &str) -> Result<u8, String> {
+    match value {
+        "comma" => Ok(b','),
+        "semicolon" => Ok(b';'),
+        "tab" | "\\t" => Ok(b'\t'),
+        "pipe" => Ok(b'|'),
+        value if value.len() == 1 && value.is_ascii() => Ok(value.as_bytes()[0]),
+        value => Err(format!(
+            "invalid delimiter '{}', expected a single character, comma, semicolon, tab or pipe",
+            value
+        )),
+    }
+}
+
+pub struct Mapped {
+    map: InputMap,
+    iban_re: Regex,
+}
+
+impl Mapped {
+    pub fn new(map: InputMap) -> Mapped {
+        Mapped {
+            map,
+            iban_re: Regex::new(&format!("^{}$", iban_pattern(None))).unwrap(),
+        }
+    }
+
+    /// Returns the layout of the export and a reader of its rows from the
+    /// header row on, or `None` if the header does not have the columns of
+    /// the mapping.
+    fn reader<'a>(&self, contents: &'a str) -> Option<(parser::CsvLayout, csv::Reader<&'a [u8]>)> {
+        let delimiters = match self.map.delimiter {
+            Some(delimiter) => vec![delimiter],
+            None => parser::DELIMITERS.to_vec(),
+        };
+        let layout = parser::sniff_csv_with(contents, &delimiters, |header| {
+            Columns::new(&self.map, header).is_ok()
+        })?;
+        let rdr = ReaderBuilder::new()
+            .delimiter(layout.delimiter)
+            .flexible(true)
+            .from_reader(&contents.as_bytes()[layout.start..]);
+        Some((layout, rdr))
+    }
+
+    /// Formats the account of a row, grouping IBANs like the ones of the
+    /// other exports.
+    fn account(&self, account: &str) -> String {
+        match account.trim() {
+            account if self.iban_re.is_match(account) => group_iban(account),
+            account => account.to_string(),
+        }
+    }
+
+    /// Returns the accounts of the rows of the export, each once, in the
+    /// order they first appear.
+    fn accounts(&self, contents: &str) -> Vec<String> {
+        let Some((_, mut rdr)) = self.reader(contents) else {
+            return vec![];
+        };
+        let Some(column) = rdr
+            .headers()
+            .ok()
+            .and_then(|header| Columns::new(&self.map, header).ok())
+            .and_then(|columns| columns.account)
+        else {
+            return vec![];
+        };
+        let mut accounts = vec![];
+        for record in rdr.records().filter_map(Result::ok) {
+            let account = self.account(record.get(column).unwrap_or_default());
+            if !account.is_empty() && !accounts.contains(&account) {
+                accounts.push(account);
+            }
+        }
+        accounts
+    }
+}
+
+/// Positions of the mapped columns in the header row
+struct Columns<'m> {
+    map: &'m InputMap,
+    header: StringRecord,
+    /// Number of columns
+    len: usize,
+    date: usize,
+    amount: Option<usize>,
+    outflow: Option<usize>,
+    inflow: Option<usize>,
+    payee: usize,
+    memo: Option<usize>,
+    reference: Option<usize>,
+    currency: Option<usize>,
+    balance: Option<usize>,
+    account: Option<usize>,
+}
+
+impl<'m> Columns<'m> {
+    /// Finds the columns of `map` in `header`, or returns the names of the
+    /// ones missing from it.
+    fn new(map: &'m InputMap, header: &StringRecord) -> Result<Columns<'m>, Vec<String>> {
+        let position = |name: &str| header.iter().position(|h| h.trim() == name);
+        let missing: Vec<String> = map
+            .columns()
+            .into_iter()
+            .filter(|name| position(name).is_none())
+            .map(String::from)
+            .collect();
+        if !missing.is_empty() {
+            return Err(missing);
+        }
+
+        let optional = |name: &Option<String>| name.as_deref().and_then(position);
+        Ok(Columns {
+            map,
+            header: header.clone(),
+            len: header.len(),
+            date: position(&map.date).unwrap_or_default(),
+            amount: optional(&map.amount),
+            outflow: optional(&map.outflow),
+            inflow: optional(&map.inflow),
+            payee: position(&map.payee).unwrap_or_default(),
+            memo: optional(&map.memo),
+            reference: optional(&map.reference),
+            currency: optional(&map.currency),
+            balance: optional(&map.balance),
+            account: optional(&map.account),
+        })
+    }
+
+    fn field<'r>(&self, record: &'r StringRecord, i: usize) -> Result<&'r str, String> {
+        record
+            .get(i)
+            .map(str::trim)
+            .ok_or_else(|| format!("expected {} fields, found {}", self.len, record.len()))
+    }
+
+    fn optional_field(&self, record: &StringRecord, i: Option<usize>) -> Result<String, String> {
+        i.map_or(Ok(""), |i| self.field(record, i))
+            .map(String::from)
+    }
+
+    fn date(&self, record: &StringRecord) -> Result<String, String> {
+        let date = self.field(record, self.date)?;
+        let parsed = match &self.map.date_format {
+            Some(format) => NaiveDate::parse_from_str(date, format).map_err(|_| {
+                format!(
+                    "Could not parse transaction date '{}' of column '{}' as {}",
+                    date, self.map.date, format
+                )
+            }),
+            None => parse_nda_date(date).map_err(|_| {
+                format!(
+                    "Could not parse transaction date '{}' of column '{}', give its format with date_format in the input map",
+                    date, self.map.date
+                )
+            }),
+        };
+        parsed.map(|date| date.format(OUTPUT_DATE_FORMAT).to_string())
+    }
+
+    /// The signed amount of a row, from the amount column or the difference
+    /// of the inflow and outflow columns, whichever sign the outflows have
+    fn amount(&self, record: &StringRecord) -> Result<String, String> {
+        if let Some(amount) = self.amount {
+            return Ok(self.field(record, amount)?.to_string());
+        }
+        let unsigned = |i: Option<usize>| -> Result<i64, String> {
+            match self.optional_field(record, i)?.as_str() {
+                "" => Ok(0),
+                value => Ok(value.parse::<Amount>()?.cents().abs()),
+            }
+        };
+        let cents = unsigned(self.inflow)? - unsigned(self.outflow)?;
+        Ok(Amount::from_cents(cents).to_string())
+    }
+
+    fn transaction(&self, record: &StringRecord) -> Result<Transaction, String> {
+        RawTransaction {
+            date: self.date(record)?,
+            amount: self.amount(record)?,
+            description: self.field(record, self.payee)?.to_string(),
+            reference: self.optional_field(record, self.reference)?,
+            message: self.optional_field(record, self.memo)?,
+            counterparty_account: String::new(),
+            value_date: String::new(),
+            payment_date: String::new(),
+            balance: self.optional_field(record, self.balance)?,
+            currency: self.optional_field(record, self.currency)?,
+            original_amount: String::new(),
+            original_currency: String::new(),
+            pending: false,
+            columns: source_columns(&self.header, record),
+        }
+        .normalize()
+    }
+}
+
+impl BankParser for Mapped {
+    fn name(&self) -> &'static str {
+        "mapped"
+    }
+
+    fn parse_file_name(&self, _file_name: &str) -> Option<FileInfo> {
+        None
+    }
+
+    /// CSV files of other banks do not say which account they are of in
+    /// their name, only the account column can.
+    fn inspects(&self, file_name: &str) -> bool {
+        self.map.account.is_some() && file_name.to_lowercase().ends_with(".csv")
+    }
+
+    /// The account of the first row, and the newest date of the rows as the
+    /// export time. Files with the rows of several accounts are rejected when
+    /// their rows are read.
+    fn parse_file_info(&self, contents: &str) -> Option<FileInfo> {
+        self.map.account.as_ref()?;
+        let (_, mut rdr) = self.reader(contents)?;
+        let columns = Columns::new(&self.map, rdr.headers().ok()?).ok()?;
+        let transactions: Vec<(String, Transaction)> = rdr
+            .records()
+            .filter_map(Result::ok)
+            .filter_map(|r| {
+                let account = columns.optional_field(&r, columns.account).ok()?;
+                Some((account, columns.transaction(&r).ok()?))
+            })
+            .collect();
+
+        let (account, _) = transactions.iter().find(|(a, _)| !a.is_empty())?;
+        Some(FileInfo {
+            iban: self.account(account),
+            date: transactions
+                .iter()
+                .map(|(_, t)| t.date)
+                .max()?
+                .and_hms_opt(0, 0, 0)?,
+        })
+    }
+
+    fn detect(&self, contents: &str) -> bool {
+        self.reader(contents).is_some()
+    }
+
+    fn rows<'a>(&'a self, contents: &'a str, path: &Path) -> Result<Rows<'a>, Box<dyn Error>> {
+        let header_missing = || {
+            format!(
+                "{} does not have a header row with the columns {} of the input map",
+                path.display(),
+                self.map.columns().join(", ")
+            )
+        };
+        let (layout, mut rdr) = self.reader(contents).ok_or_else(header_missing)?;
+        let columns = Columns::new(&self.map, rdr.headers()?).map_err(|missing| {
+            format!(
+                "{} is missing the {} column(s) of the input map",
+                path.display(),
+                missing.join(", ")
+            )
+        })?;
+        // The file is converted as the account of its first row, which the
+        // rows of any other account would be mixed into
+        let accounts = self.accounts(contents);
+        if accounts.len() > 1 {
+            return Err(format!(
+                "{} has the rows of several accounts ({}), export each account to a file of its own",
+                path.display(),
+                accounts.join(", ")
+            )
+            .into());
+        }
+
+        Ok(Box::new(rdr.into_records().map(move |result| {
+            let line = match &result {
+                Ok(record) => record.position(),
+                Err(e) => e.position(),
+            }
+            .map_or(0, |p| p.line() + layout.preamble_lines);
+            result
+                .map_err(|e| e.to_string())
+                .and_then(|record| columns.transaction(&record))
+                .map_err(|message| RowError { line, message })
+        })))
+    }
+}
@@ -0,0 +1,98 @@
+//! Memo templates.
+//!
+//! The YNAB memo is rendered from a template with placeholders in braces,
+//! which are replaced with fields of the transaction:
+//!
+//! - `{reference}`, `{message}`, `{description}`, `{date}` and `{amount}`
+//! - `{account}` or `{counterparty_iban}` for the counterparty account
+//! - `{original_amount}` and `{original_currency}` for the amount of a
+//!   transaction in a foreign currency on multicurrency accounts, and
+//!   `{original}` for both, e.g. "12.50 USD", when the currency differs from
+//!   the booked one
+//! - any other column of a CSV export by its header, e.g. `{Tapahtumapäivä}`,
+//!   ignoring case
+//!
+//! Columns missing from the export are left empty, and runs of whitespace in
+//! the result are collapsed. If all placeholders are empty, so is the memo.
+
+use crate::{Transaction, DATE_FORMAT};
+use std::borrow::Cow;
+
+pub const DEFAULT_TEMPLATE: &str = "{reference} {message} {original}";
+
+pub fn render(template: &str, transaction: &Transaction) -> String {
+    let mut memo = String::new();
+    let mut placeholders = 0;
+    let mut all_empty = true;
+
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        memo.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('}') else {
+            // An unclosed brace is kept as it is
+            memo.push_str(&rest[start..]);
+            rest = "";
+            break;
+        };
+
+        let value = field(transaction, &after[..end]);
+        placeholders += 1;
+        all_empty &= value.trim().is_empty();
+        memo.push_str(&value);
+        rest = &after[end + 1..];
+    }
+    memo.push_str(rest);
+
+    if placeholders > 0 && all_empty {
+        return String::new();
+    }
+    memo.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn field<'a>(transaction: &'a Transaction, name: &str) -> Cow<'a, str> {
+    match name {
+        "reference" => Cow::from(&transaction.reference),
+        "message" => Cow::from(&transaction.message),
+        "account" | "counterparty_iban" => Cow::from(&transaction.counterparty_account),
+        "description" => Cow::from(&transaction.description),
+        "date" => Cow::from(transaction.date.format(DATE_FORMAT).to_string()),
+        "amount" => Cow::from(transaction.amount.to_string()),
+        "original_amount" => Cow::from(
+            transaction
+                .original_amount
+                .map_or_else(String::new, |a| a.to_string()),
+        ),
+        "original_currency" => Cow::from(&transaction.original_currency),
+        "original" => Cow::from(original(transaction)),
+        _ => Cow::from(
+            transaction
+                .columns
+                .iter()
+                .find(|(column, _)| column.eq_ignore_ascii_case(name))
+                .map_or("", |(_, value)| value),
+        ),
+    }
+}
+
+/// The original amount and currency of a transaction in a foreign currency,
+/// or nothing if it was made in the currency of the account. Exports without
+/// a currency column tell that by the original amount being the booked one.
+fn original(transaction: &Transaction) -> String {
+    let same_currency = if transaction.currency.is_empty() {
+        transaction.original_amount.map(|a| a.cents().abs())
+            == Some(transaction.amount.cents().abs())
+    } else {
+        transaction.original_currency == transaction.currency
+    };
+    match transaction.original_amount {
+        Some(amount) if !transaction.original_currency.is_empty() && !same_currency => {
+            format!(
+                "{} {}",
+                amount.to_string().trim(),
+                transaction.original_currency
+            )
+        }
+        _ => String::new(),
+    }
+}
@@ -0,0 +1,238 @@
+//! Converting exports held in memory, e.g. ones uploaded to a web service,
+//! without reading any files or keeping state between runs.
+//!
+//! The caller keeps track of what was already converted: instead of the
+//! previous export, the conversion is given the most recent transaction
+//! converted before and the number of identical ones on its date, and the
+//! rows of the current export above them are the new ones. [`Options`] can be deserialized with serde, e.g. from the JSON body
+//! of a request.
+//!
+//! ```no_run
+//! use nda2ynab::{
+//!     memory::{self, Options},
+//!     system::SystemClock,
+//! };
+//!
+//! # fn upload() -> Vec<u8> { vec![] }
+//! let export = memory::read_export(&upload(), &SystemClock)?;
+//! let rows = memory::convert_rows(export.transactions, None, &Options::default())?;
+//! let csv = memory::to_ynab_csv(&rows)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::{
+    columns::Account,
+    count_occurrence,
+    encoding::{self, Encoding},
+    memo,
+    parser::{ParsedRows, Registry},
+    remove_ignored,
+    report::RunError,
+    rules::Rules,
+    sanitize::{self, SanitizeOptions},
+    system::Clock,
+    to_ynab_rows_after, write_ynab_csv, CsvOptions, DateField, Occurrences, Transaction, YnabRow,
+};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, path::Path};
+
+/// How rows are converted, the in-memory counterpart of the command line
+/// options. Fields missing when deserializing take their default values.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Options {
+    /// Memo template, see [`crate::memo`]
+    pub memo: String,
+    /// Payee cleanup rules in the format of rules files, applied after the
+    /// built-in rules
+    pub rules: Option<String>,
+    /// Do not apply the built-in payee cleanup rules
+    pub no_default_rules: bool,
+    /// Apply the bundled merchant list
+    pub enrich: bool,
+    /// Title-case payees written in all capitals
+    pub title_case: bool,
+    /// Flip the sign of the amounts, for accounts exported the other way
+    /// around
+    pub invert_amounts: bool,
+    pub date_field: DateField,
+    /// Convert authorisation holds too instead of leaving them out
+    pub include_pending: bool,
+    /// Skip rows that cannot be parsed instead of failing
+    pub skip_invalid_rows: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            memo: memo::DEFAULT_TEMPLATE.to_string(),
+            rules: None,
+            no_default_rules: false,
+            enrich: false,
+            title_case: false,
+            invert_amounts: false,
+            date_field: DateField::Booking,
+            include_pending: false,
+            skip_invalid_rows: false,
+        }
+    }
+}
+
+impl Options {
+    fn rules(&self) -> Result<Rules, Box<dyn Error>> {
+        let mut rules = Rules::load(None, self.no_default_rules, self.enrich)?;
+        if let Some(contents) = &self.rules {
+            rules.extend(Rules::from_toml(contents)?);
+        }
+        Ok(rules)
+    }
+}
+
+/// Parses an export of any supported bank from its bytes, in any of the
+/// encodings Nordea has used. The rows are in the order of the export, most
+/// recent first for Nordea exports. Holds are dated by `clock`, as the
+/// export has no file name to date them by.
+pub fn read_export(bytes: &[u8], clock: &dyn Clock) -> Result<ParsedRows, Box<dyn Error>> {
+    let contents = encoding::decode(bytes, Encoding::Auto)?;
+    let registry = Registry::builtin(None, clock);
+    let parser = registry
+        .detect(&contents)
+        .ok_or("The contents are not an export of any supported bank")?;
+    parser.parse_rows(&contents, Path::new("export"))
+}
+
+/// The most recent transaction converted before, which the caller keeps
+/// track of between conversions
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Previous<'a> {
+    pub newest: &'a Transaction,
+    /// Number of transactions equal to `newest` by date, amount and
+    /// description that were converted, itself included
+    pub occurrences: usize,
+}
+
+impl Previous<'_> {
+    /// The most recent booked transaction of `rows`, the rows converted
+    /// before in the order of the export, most recent first.
+    pub fn of(rows: &[Transaction]) -> Option<Previous<'_>> {
+        let mut booked = rows.iter().filter(|t| !t.pending);
+        let newest = booked.next()?;
+        Some(Previous {
+            newest,
+            occurrences: 1 + booked.filter(|t| t.key() == newest.key()).count(),
+        })
+    }
+}
+
+/// Converts the transactions of `current` that are more recent than
+/// `previous`, the most recent transaction converted before, or all of them
+/// if `None`. `current` must be in the order of the export, most recent
+/// first.
+///
+/// The new rows are the ones above the rows equal to [`Previous::newest`]
+/// by date, amount and description, counting [`Previous::occurrences`] of
+/// them from the oldest, so that an identical transaction booked on the same
+/// day after the previous conversion is new. Fails if there are fewer of
+/// them although `current` reaches back to their date, as some transactions
+/// would be missing or converted twice, and if `current` starts after it,
+/// leaving a gap.
+pub fn convert_rows(
+    current: impl IntoIterator<Item = Transaction>,
+    previous: Option<Previous>,
+    opts: &Options,
+) -> Result<Vec<YnabRow>, Box<dyn Error>> {
+    let (pending, booked): (Vec<_>, Vec<_>) = current.into_iter().partition(|t| t.pending);
+
+    // The converted rows, which the import ids of the new ones are numbered
+    // after
+    let mut earlier = Occurrences::new();
+    let mut rows = match previous {
+        Some(previous) => {
+            let newest = previous.newest;
+            let matches: Vec<usize> = booked
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| t.key() == newest.key())
+                .map(|(i, _)| i)
+                .collect();
+            // The most recent of the identical rows converted before
+            let converted = matches
+                .len()
+                .checked_sub(previous.occurrences.max(1))
+                .map(|new| matches[new]);
+            let Some(position) = converted else {
+                return Err(if booked.iter().any(|t| t.date <= newest.date) {
+                    RunError::NonOverlapping.into()
+                } else {
+                    RunError::Gap.into()
+                });
+            };
+            for r in &booked[position..] {
+                count_occurrence(&mut earlier, r);
+            }
+            booked.into_iter().take(position).collect()
+        }
+        None => booked,
+    };
+    if opts.include_pending {
+        rows.splice(0..0, pending);
+    }
+
+    let rules = opts.rules()?;
+    let rows = remove_ignored(rows, &rules, opts.invert_amounts).rows;
+    let mut rows = to_ynab_rows_after(
+        rows,
+        &earlier,
+        &rules,
+        &opts.memo,
+        opts.invert_amounts,
+        opts.date_field,
+    );
+    sanitize::sanitize(
+        &mut rows,
+        &SanitizeOptions {
+            title_case: opts.title_case,
+            ..SanitizeOptions::default()
+        },
+    );
+    Ok(rows)
+}
+
+/// Converts the export `current` given as bytes. If the previous export is
+/// given, only the transactions after its most recent one are converted.
+pub fn convert_bytes(
+    current: &[u8],
+    previous: Option<&[u8]>,
+    clock: &dyn Clock,
+    opts: &Options,
+) -> Result<Vec<YnabRow>, Box<dyn Error>> {
+    let read = |bytes: &[u8]| -> Result<Vec<Transaction>, Box<dyn Error>> {
+        let parsed = read_export(bytes, clock)?;
+        match parsed.errors.first() {
+            Some(e) if !opts.skip_invalid_rows => Err(format!(
+                "{} row(s) could not be parsed, the first on line {}: {}",
+                parsed.errors.len(),
+                e.line,
+                e.message
+            )
+            .into()),
+            _ => Ok(parsed.transactions),
+        }
+    };
+
+    let previous = previous.map(read).transpose()?;
+    let previous = previous.as_deref().and_then(Previous::of);
+    convert_rows(read(current)?, previous, opts)
+}
+
+/// Writes the rows as a YNAB CSV file.
+pub fn to_ynab_csv(rows: &[YnabRow]) -> Result<String, Box<dyn Error>> {
+    let account = Account {
+        iban: "",
+        name: "",
+        currency: "",
+    };
+    let mut csv = vec![];
+    write_ynab_csv(rows, &CsvOptions::default(), &account, &mut csv)?;
+    Ok(String::from_utf8(csv)?)
+}
@@ -0,0 +1,287 @@
+//! Nordea netbank CSV exports.
+//!
+//! Exports are named like `Tapahtumat FI12 3456 7890 1234 56 - 2022-03-31
+//! 12.30.00.csv`; older exports used `2022.03.31 12.30` for the date. The
+//! contents are usually semicolon separated, with column headers in the
+//! language of the netbank (see [`Locale`]). Exports separated by commas or
+//! tabs, or with lines of text above the header, are recognised too.
+//!
+//! The IBAN may be of any country, with or without spaces, e.g. `Transaktioner
+//! SE4550000000058398257466 - 2022-03-31 12.30.00.csv`. If a locale is
+//! given, only IBANs of its country are recognised.
+//!
+//! Exports saved on Windows sometimes have another dash than `-` before the
+//! date, e.g. an en dash, which is recognised too.
+//!
+//! Exports named differently can be recognised with file name patterns from
+//! the config file, see [`file_name_pattern`].
+
+use crate::{
+    group_iban, iban_pattern,
+    locale::Locale,
+    parser::{self, source_columns, BankParser, CsvLayout, FileInfo, RowError, Rows},
+    system::Clock,
+    RawTransaction, DATE_FORMAT,
+};
+use chrono::{NaiveDate, NaiveDateTime};
+use csv::ReaderBuilder;
+use regex::Regex;
+use std::{error::Error, path::Path};
+
+/// Formats of the date in export file names, with a time
+const FILE_DATE_TIME_FORMATS: &[&str] = &["%Y-%m-%d %H.%M.%S", "%Y.%m.%d %H.%M", "%Y-%m-%d %H.%M"];
+
+/// Formats of the date in export file names, without a time
+const FILE_DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%Y%m%d", "%d.%m.%Y"];
+
+pub struct Nordea {
+    locale: Option<Locale>,
+    /// Patterns with `iban` and `date` groups, tried in order
+    file_name_patterns: Vec<Regex>,
+    /// Date of the holds of exports with no date in their file name
+    now: NaiveDateTime,
+}
+
+impl Nordea {
+    /// If `locale` is given, only exports of that country are recognised.
+    /// Holds of exports with no date in their file name are dated by `clock`.
+    pub fn new(locale: Option<Locale>, clock: &dyn Clock) -> Nordea {
+        let iban_pattern = iban_pattern(locale.map(|l| l.country()));
+
+        Nordea {
+            locale,
+            file_name_patterns: vec![Regex::new(&format!(
+                r"^.+? (?P<iban>{}) [{}] (?P<date>.+)\.csv$",
+                iban_pattern, DASHES
+            ))
+            .unwrap()],
+            now: clock.now(),
+        }
+    }
+
+    /// Also recognises exports named like `patterns`, which are tried before
+    /// the built-in pattern. See [`file_name_pattern`].
+    pub fn with_file_name_patterns(mut self, patterns: Vec<Regex>) -> Nordea {
+        self.file_name_patterns.splice(0..0, patterns);
+        self
+    }
+
+    /// Finds the header row of an export in the locale of the parser, or in
+    /// any locale if none was given.
+    fn sniff(&self, contents: &str) -> Option<CsvLayout> {
+        parser::sniff_csv(contents, |header| {
+            Locale::detect(header)
+                .is_some_and(|detected| self.locale.is_none_or(|locale| locale == detected))
+        })
+    }
+
+    /// Explains why no header row was found in an export: which columns of
+    /// the closest locale are missing, e.g. after Nordea renamed one, and
+    /// which columns were not recognised.
+    fn missing_header(&self, contents: &str, path: &Path) -> String {
+        let intro = format!("Could not find the header row of {}", path.display());
+        // The line with the most columns of any locale is taken as the header
+        let known = |column: &str| Locale::ALL.iter().any(|l| l.headers().known(column));
+        let Some(header) = parser::sniff_csv(contents, |record| {
+            record.iter().filter(|c| known(c.trim())).count() >= 2
+        })
+        .and_then(|layout| {
+            ReaderBuilder::new()
+                .delimiter(layout.delimiter)
+                .from_reader(&contents.as_bytes()[layout.start..])
+                .headers()
+                .ok()
+                .cloned()
+        }) else {
+            return format!(
+                "{}: none of its first lines has the columns of a Nordea export",
+                intro
+            );
+        };
+        let columns: Vec<&str> = header.iter().map(str::trim).collect();
+
+        if let (Some(locale), Some(detected)) = (self.locale, Locale::detect(&header)) {
+            return format!(
+                "{}: it is an export in the {} format, but --locale {} was given",
+                intro,
+                detected.code(),
+                locale.code()
+            );
+        }
+
+        let candidates = match self.locale {
+            Some(locale) => vec![locale],
+            None => Locale::ALL.to_vec(),
+        };
+        let missing = |locale: &Locale| -> Vec<&'static str> {
+            let headers = locale.headers();
+            headers
+                .required()
+                .into_iter()
+                .filter(|name| !columns.contains(name))
+                .collect()
+        };
+        let closest = candidates
+            .iter()
+            .min_by_key(|locale| missing(locale).len())
+            .expect("a locale");
+        let headers = closest.headers();
+        let unrecognised: Vec<&str> = columns
+            .iter()
+            .copied()
+            .filter(|c| !c.is_empty() && !headers.known(c))
+            .collect();
+
+        let mut message = format!(
+            "{}. The closest format is Nordea {} (--locale {}), which needs the column(s) {}",
+            intro,
+            closest.code().to_uppercase(),
+            closest.code(),
+            missing(closest).join(", ")
+        );
+        if !unrecognised.is_empty() {
+            message += &format!(
+                ". Column(s) that were not recognised: {}",
+                unrecognised.join(", ")
+            );
+        }
+        message
+    }
+}
+
+impl BankParser for Nordea {
+    fn name(&self) -> &'static str {
+        "nordea"
+    }
+
+    fn parse_file_name(&self, file_name: &str) -> Option<FileInfo> {
+        self.file_name_patterns.iter().find_map(|re| {
+            let captures = re.captures(file_name)?;
+            let iban = group_iban(captures.name("iban")?.as_str());
+            let date = parse_file_date(captures.name("date")?.as_str())?;
+            Some(FileInfo { iban, date })
+        })
+    }
+
+    fn detect(&self, contents: &str) -> bool {
+        self.sniff(contents).is_some()
+    }
+
+    fn rows<'a>(&'a self, contents: &'a str, path: &Path) -> Result<Rows<'a>, Box<dyn Error>> {
+        let layout = self
+            .sniff(contents)
+            .ok_or_else(|| self.missing_header(contents, path))?;
+        // Descriptions and messages of SEPA payments may have line breaks and
+        // semicolons in quotes, so the fields must stay quoted
+        let mut rdr = ReaderBuilder::new()
+            .delimiter(layout.delimiter)
+            .quoting(true)
+            .double_quote(true)
+            .from_reader(&contents.as_bytes()[layout.start..]);
+        // Line numbers of the rows in the whole file
+        let offset = layout.preamble_lines;
+
+        let source_header = rdr.headers()?.clone();
+        let locale = self
+            .locale
+            .or_else(|| Locale::detect(&source_header))
+            .ok_or_else(|| format!("Could not detect the export locale of {}", path.display()))?;
+        let header = locale.normalize_header(&source_header);
+        // Authorisation holds have no booking date, they are dated by the
+        // export instead
+        let export_date = path
+            .file_name()
+            .and_then(|name| self.parse_file_name(&name.to_string_lossy()))
+            .map_or(self.now, |info| info.date)
+            .format(DATE_FORMAT)
+            .to_string();
+
+        Ok(Box::new(rdr.into_records().map(move |result| {
+            let record = result.map_err(|e| row_error(e, offset))?;
+            let line = record.position().map_or(0, |p| p.line()) + offset;
+            let r = record
+                .deserialize::<RawTransaction>(Some(&header))
+                .map_err(|e| row_error(e, offset))?;
+            // "Invalid date" seems to indicate authorisation holds
+            let pending = r.date == "Invalid date";
+            let booked_date = |date: String| {
+                if date == "Invalid date" {
+                    String::new()
+                } else {
+                    date
+                }
+            };
+            RawTransaction {
+                date: if pending { export_date.clone() } else { r.date },
+                value_date: booked_date(r.value_date),
+                payment_date: booked_date(r.payment_date),
+                pending,
+                columns: source_columns(&source_header, &record),
+                ..r
+            }
+            .normalize_with_decimal(Some(locale.decimal_separator()))
+            .map_err(|message| RowError { line, message })
+        })))
+    }
+}
+
+/// Converts a CSV error into an error of the row at `offset` lines below the
+/// line the reader started at.
+fn row_error(e: csv::Error, offset: u64) -> RowError {
+    RowError {
+        line: e.position().map_or(0, |p| p.line()) + offset,
+        message: match e.kind() {
+            csv::ErrorKind::Deserialize { err, .. } => err.to_string(),
+            csv::ErrorKind::UnequalLengths {
+                expected_len, len, ..
+            } => format!("expected {} fields, found {}", expected_len, len),
+            _ => e.to_string(),
+        },
+    }
+}
+
+/// Compiles a file name pattern for Nordea exports. The pattern must have
+/// named groups `iban` and `date`, e.g.
+/// `^Transactions (?P<iban>FI[0-9 ]+) (?P<date>\d{4}-\d{2}-\d{2})\.csv$`.
+/// The date is read with or without a time, as in Nordea's file names, or
+/// as `2022-03-31`, `20220331` or `31.03.2022`.
+pub fn file_name_pattern(pattern: &str) -> Result<Regex, String> {
+    let re = Regex::new(pattern)
+        .map_err(|e| format!("Invalid file name pattern '{}': {}", pattern, e))?;
+    for group in ["iban", "date"] {
+        if !re.capture_names().flatten().any(|name| name == group) {
+            return Err(format!(
+                "File name pattern '{}' has no named group '{}', e.g. (?P<{}>...)",
+                pattern, group, group
+            ));
+        }
+    }
+    Ok(re)
+}
+
+/// Compiles file name patterns, see [`file_name_pattern`].
+pub fn file_name_patterns(patterns: &[String]) -> Result<Vec<Regex>, String> {
+    patterns.iter().map(|p| file_name_pattern(p)).collect()
+}
+
+/// Hyphen-minus and the other dashes seen in the file names of exports
+const DASHES: &str = "-\u{2010}\u{2011}\u{2012}\u{2013}\u{2014}\u{2212}";
+
+/// Parses the date of a file name, at midnight if it has no time.
+fn parse_file_date(date: &str) -> Option<NaiveDateTime> {
+    let date: String = date
+        .trim()
+        .chars()
+        .map(|c| if DASHES.contains(c) { '-' } else { c })
+        .collect();
+    let date = date.as_str();
+    FILE_DATE_TIME_FORMATS
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(date, f).ok())
+        .or_else(|| {
+            FILE_DATE_FORMATS
+                .iter()
+                .find_map(|f| NaiveDate::parse_from_str(date, f).ok())
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        })
+}
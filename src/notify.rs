@@ -0,0 +1,82 @@
+//! Desktop notifications of runs, for `--notify`.
+//!
+//! Runs started by cron, a systemd timer or `watch` print their results
+//! where nobody reads them, so with `--notify` they end with a notification
+//! of what was written or uploaded for each account, or why the run failed.
+//! Notifications are sent with `notify-send` (libnotify) on Linux and other
+//! Unix desktops and with `osascript` on macOS.
+
+use crate::{report::Summary, UploadTarget};
+use clap::ArgEnum;
+use std::{error::Error, process::Command};
+
+/// Returns the title and body of the notification of a run.
+pub fn message(summary: &Summary, error: Option<&(dyn Error + 'static)>) -> (String, String) {
+    let mut lines: Vec<String> = summary
+        .accounts
+        .iter()
+        .map(|a| {
+            let account = a.name.as_deref().unwrap_or(&a.iban);
+            if let Some(e) = &a.error {
+                return format!("{}: failed, {}", account, e);
+            }
+            // Uploads have the name of the target as their output
+            let uploaded_to = UploadTarget::value_variants()
+                .iter()
+                .map(UploadTarget::name)
+                .find(|name| a.output.as_deref() == Some(name.to_lowercase().as_str()));
+            match (a.transactions_written, uploaded_to, a.output.as_deref()) {
+                (0, _, _) => format!("{}: nothing new", account),
+                (n, Some(target), _) => {
+                    format!(
+                        "{}: {} new transaction(s) uploaded to {}",
+                        account, n, target
+                    )
+                }
+                (n, None, Some(output)) => {
+                    format!(
+                        "{}: {} new transaction(s) written to {}",
+                        account, n, output
+                    )
+                }
+                (n, None, None) => format!("{}: {} new transaction(s) exported", account, n),
+            }
+        })
+        .collect();
+
+    let title = match error {
+        Some(e) => {
+            lines.push(e.to_string());
+            "nda2ynab failed".to_string()
+        }
+        None if summary.nothing_new() => "nda2ynab: nothing new".to_string(),
+        None => "nda2ynab".to_string(),
+    };
+    (title, lines.join("\n"))
+}
+
+/// Shows a desktop notification.
+pub fn send(title: &str, body: &str) -> Result<(), Box<dyn Error>> {
+    let mut command = if cfg!(target_os = "macos") {
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(format!(
+            "display notification {} with title {}",
+            quote(body),
+            quote(title)
+        ));
+        command
+    } else {
+        let mut command = Command::new("notify-send");
+        command.arg("--app-name=nda2ynab").arg(title).arg(body);
+        command
+    };
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command
+        .status()
+        .map_err(|e| format!("Could not run {} to send a notification: {}", program, e))?;
+    if !status.success() {
+        return Err(format!("{} failed to send a notification ({})", program, status).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,127 @@
+//! Open Financial Exchange (OFX) output.
+//!
+//! Transactions are written as an OFX 1.02 bank statement. Each transaction
+//! gets a FITID derived from its date, amount and occurrence count, so
+//! importers can recognise transactions they have already seen even when
+//! exports overlap.
+
+use crate::{amount::Amount, parse_nda_date, YnabRow};
+use chrono::{NaiveDate, NaiveDateTime};
+use std::{collections::HashMap, error::Error, io::Write};
+
+/// Maximum length of the NAME field in OFX 1.x
+const MAX_NAME_LEN: usize = 32;
+
+pub fn write_ofx<W: Write>(
+    rows: &[YnabRow],
+    iban: &str,
+    currency: &str,
+    now: NaiveDateTime,
+    mut w: W,
+) -> Result<(), Box<dyn Error>> {
+    let mut transactions = Vec::with_capacity(rows.len());
+    let mut occurrences: HashMap<(NaiveDate, Amount), usize> = HashMap::new();
+
+    // Oldest transactions first, so that occurrence counters stay stable as
+    // new transactions are added on top of the export
+    for r in rows.iter().rev() {
+        let date = parse_nda_date(&r.date)?;
+        let amount = r.amount;
+
+        let occurrence = occurrences.entry((date, amount)).or_insert(0);
+        *occurrence += 1;
+
+        let fitid = format!(
+            "{}:{}:{}",
+            date.format("%Y%m%d"),
+            amount.cents(),
+            occurrence
+        );
+        transactions.push((date, amount, fitid, r));
+    }
+    transactions.reverse();
+
+    let acctid = iban.replace(' ', "");
+    let now = now.format("%Y%m%d%H%M%S");
+    let start = transactions.iter().map(|t| t.0).min();
+    let end = transactions.iter().map(|t| t.0).max();
+
+    writeln!(w, "OFXHEADER:100")?;
+    writeln!(w, "DATA:OFXSGML")?;
+    writeln!(w, "VERSION:102")?;
+    writeln!(w, "SECURITY:NONE")?;
+    writeln!(w, "ENCODING:UNICODE")?;
+    writeln!(w, "CHARSET:NONE")?;
+    writeln!(w, "COMPRESSION:NONE")?;
+    writeln!(w, "OLDFILEUID:NONE")?;
+    writeln!(w, "NEWFILEUID:NONE")?;
+    writeln!(w)?;
+    writeln!(w, "<OFX>")?;
+    writeln!(w, "<SIGNONMSGSRSV1><SONRS>")?;
+    writeln!(
+        w,
+        "<STATUS><CODE>0</CODE><SEVERITY>INFO</SEVERITY></STATUS>"
+    )?;
+    writeln!(w, "<DTSERVER>{}</DTSERVER>", now)?;
+    writeln!(w, "<LANGUAGE>FIN</LANGUAGE>")?;
+    writeln!(w, "</SONRS></SIGNONMSGSRSV1>")?;
+    writeln!(w, "<BANKMSGSRSV1><STMTTRNRS>")?;
+    writeln!(w, "<TRNUID>1</TRNUID>")?;
+    writeln!(
+        w,
+        "<STATUS><CODE>0</CODE><SEVERITY>INFO</SEVERITY></STATUS>"
+    )?;
+    writeln!(w, "<STMTRS>")?;
+    writeln!(w, "<CURDEF>{}</CURDEF>", currency)?;
+    writeln!(w, "<BANKACCTFROM>")?;
+    writeln!(w, "<BANKID>NDEAFIHH</BANKID>")?;
+    writeln!(w, "<ACCTID>{}</ACCTID>", escape(&acctid))?;
+    writeln!(w, "<ACCTTYPE>CHECKING</ACCTTYPE>")?;
+    writeln!(w, "</BANKACCTFROM>")?;
+    writeln!(w, "<BANKTRANLIST>")?;
+    if let (Some(start), Some(end)) = (start, end) {
+        writeln!(w, "<DTSTART>{}</DTSTART>", start.format("%Y%m%d"))?;
+        writeln!(w, "<DTEND>{}</DTEND>", end.format("%Y%m%d"))?;
+    }
+
+    for (date, amount, fitid, r) in transactions {
+        let trntype = if amount.is_negative() {
+            "DEBIT"
+        } else {
+            "CREDIT"
+        };
+        let name: String = r.payee.chars().take(MAX_NAME_LEN).collect();
+
+        writeln!(w, "<STMTTRN>")?;
+        writeln!(w, "<TRNTYPE>{}</TRNTYPE>", trntype)?;
+        writeln!(w, "<DTPOSTED>{}</DTPOSTED>", date.format("%Y%m%d"))?;
+        writeln!(w, "<TRNAMT>{}</TRNAMT>", amount)?;
+        writeln!(w, "<FITID>{}</FITID>", fitid)?;
+        writeln!(w, "<NAME>{}</NAME>", escape(&name))?;
+        // Keep the full payee around if NAME had to be truncated
+        let memo = if name.len() < r.payee.len() && r.memo.is_empty() {
+            &r.payee
+        } else {
+            &r.memo
+        };
+        if !memo.is_empty() {
+            writeln!(w, "<MEMO>{}</MEMO>", escape(memo))?;
+        }
+        writeln!(w, "</STMTTRN>")?;
+    }
+
+    writeln!(w, "</BANKTRANLIST>")?;
+    writeln!(w, "</STMTRS>")?;
+    writeln!(w, "</STMTTRNRS></BANKMSGSRSV1>")?;
+    writeln!(w, "</OFX>")?;
+
+    w.flush()?;
+
+    Ok(())
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
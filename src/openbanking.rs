@@ -0,0 +1,338 @@
+//! Fetching transactions from Nordea's Open Banking account information API.
+//!
+//! Access is authorised with OAuth: the user opens the authorisation URL,
+//! logs in to the netbank and pastes the address they were redirected to. The
+//! code in it is exchanged for an access token, which is refreshed when it
+//! expires. Registering an application on the Nordea developer portal gives
+//! the client id and secret.
+//!
+//! The API address can be overridden with the `NDA2YNAB_NORDEA_API_URL`
+//! environment variable, e.g. for testing against a sandbox.
+
+use crate::{
+    amount::Amount,
+    config::NordeaConfig,
+    group_iban, http,
+    json::{self, Value},
+    state::Token,
+    system::Clock,
+    unicode, Transaction,
+};
+use chrono::{Duration, NaiveDate};
+use std::{env, error::Error};
+
+pub const API_URL: &str = "https://api.nordeaopenbanking.com/personal/v5";
+
+fn api_url() -> String {
+    env::var("NDA2YNAB_NORDEA_API_URL").unwrap_or_else(|_| API_URL.to_string())
+}
+
+const SCOPES: &str = "ACCOUNTS_BASIC ACCOUNTS_DETAILS ACCOUNTS_TRANSACTIONS";
+
+/// Length of the access granted, in minutes (90 days)
+const CONSENT_DURATION: u32 = 129_600;
+
+/// Client credentials from the `[nordea]` config section
+pub struct Credentials<'a> {
+    client_id: &'a str,
+    client_secret: &'a str,
+    redirect_uri: &'a str,
+    country: &'a str,
+}
+
+impl<'a> Credentials<'a> {
+    pub fn from_config(config: &'a NordeaConfig) -> Result<Credentials<'a>, Box<dyn Error>> {
+        let missing = |key: &str| format!("Fetching needs nordea.{} in the config file", key);
+        Ok(Credentials {
+            client_id: config
+                .client_id
+                .as_deref()
+                .ok_or_else(|| missing("client_id"))?,
+            client_secret: config
+                .client_secret
+                .as_deref()
+                .ok_or_else(|| missing("client_secret"))?,
+            redirect_uri: config
+                .redirect_uri
+                .as_deref()
+                .ok_or_else(|| missing("redirect_uri"))?,
+            country: config.country.as_deref().unwrap_or("FI"),
+        })
+    }
+
+    fn headers(&self) -> Vec<String> {
+        vec![
+            format!("X-IBM-Client-Id: {}", self.client_id),
+            format!("X-IBM-Client-Secret: {}", self.client_secret),
+        ]
+    }
+
+    /// Returns the address the user opens to grant access to their accounts.
+    pub fn authorize_url(&self) -> String {
+        format!(
+            "{}/authorize?{}",
+            api_url(),
+            http::form(&[
+                ("client_id", self.client_id),
+                ("redirect_uri", self.redirect_uri),
+                ("response_type", "code"),
+                ("scope", SCOPES),
+                ("country", self.country),
+                ("duration", &CONSENT_DURATION.to_string()),
+                ("state", "nda2ynab"),
+            ])
+        )
+    }
+
+    /// Exchanges the code of the authorisation redirect for a token.
+    pub fn exchange_code(&self, code: &str, clock: &dyn Clock) -> Result<Token, Box<dyn Error>> {
+        self.token_request(
+            &[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", self.redirect_uri),
+            ],
+            clock,
+        )
+    }
+
+    pub fn refresh(&self, refresh_token: &str, clock: &dyn Clock) -> Result<Token, Box<dyn Error>> {
+        self.token_request(
+            &[
+                ("grant_type", "refresh_token"),
+                ("refresh_token", refresh_token),
+            ],
+            clock,
+        )
+    }
+
+    fn token_request(
+        &self,
+        params: &[(&str, &str)],
+        clock: &dyn Clock,
+    ) -> Result<Token, Box<dyn Error>> {
+        let mut headers = self.headers();
+        headers.push("Content-Type: application/x-www-form-urlencoded".to_string());
+        let response = http::request(
+            "POST",
+            &format!("{}/authorize/token", api_url()),
+            &headers,
+            Some(&http::form(params)),
+        )?;
+        let json = parse_response(&response)?;
+        let token = json.get("response").unwrap_or(&json);
+
+        let expires_in = token.get("expires_in").and_then(Value::as_i64);
+        Ok(Token {
+            access_token: token
+                .get("access_token")
+                .and_then(Value::as_str)
+                .ok_or("No access token in the Nordea response")?
+                .to_string(),
+            refresh_token: token
+                .get("refresh_token")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            expires_at: clock.now() + Duration::seconds(expires_in.unwrap_or(0)),
+        })
+    }
+}
+
+/// Returns the code from the address the authorisation redirected to, or
+/// `input` itself if it is just the code.
+pub fn code_from_redirect(input: &str) -> String {
+    let input = input.trim();
+    let query = input.split_once('?').map_or(input, |(_, q)| q);
+    query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("code="))
+        .map_or_else(|| input.to_string(), decode)
+}
+
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = value.get(i + 1..i + 3);
+        match (bytes[i], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(b)) => {
+                decoded.push(b);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (b, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn parse_response(response: &http::Response) -> Result<Value, Box<dyn Error>> {
+    let json =
+        json::parse(&response.body).map_err(|e| format!("Invalid response from Nordea: {}", e))?;
+    if !response.is_success() {
+        let detail = json
+            .get("error")
+            .and_then(|e| e.get("message").or_else(|| e.get("description")))
+            .or_else(|| json.get("error_description"))
+            .and_then(Value::as_str)
+            .unwrap_or("unknown error");
+        return Err(format!("Nordea API error ({}): {}", response.status, detail).into());
+    }
+    Ok(json)
+}
+
+#[derive(Debug)]
+pub struct Account {
+    pub id: String,
+    /// IBAN in the usual groups of four characters
+    pub iban: String,
+}
+
+/// A fetched transaction with the id the API gave it
+#[derive(Debug)]
+pub struct FetchedTransaction {
+    pub id: String,
+    pub transaction: Transaction,
+}
+
+pub struct Client<'a> {
+    credentials: &'a Credentials<'a>,
+    access_token: &'a str,
+}
+
+impl<'a> Client<'a> {
+    pub fn new(credentials: &'a Credentials<'a>, access_token: &'a str) -> Client<'a> {
+        Client {
+            credentials,
+            access_token,
+        }
+    }
+
+    fn get(&self, path: &str) -> Result<Value, Box<dyn Error>> {
+        let mut headers = self.credentials.headers();
+        headers.push(format!("Authorization: Bearer {}", self.access_token));
+        let response = http::request("GET", &format!("{}{}", api_url(), path), &headers, None)?;
+        parse_response(&response)
+    }
+
+    pub fn accounts(&self) -> Result<Vec<Account>, Box<dyn Error>> {
+        let json = self.get("/accounts")?;
+        let accounts = json
+            .get("response")
+            .and_then(|r| r.get("accounts"))
+            .and_then(Value::as_array)
+            .ok_or("No accounts in the Nordea response")?;
+
+        Ok(accounts
+            .iter()
+            .filter_map(|a| {
+                let iban = a
+                    .get("account_numbers")?
+                    .as_array()?
+                    .iter()
+                    .find(|n| n.get("_type").and_then(Value::as_str) == Some("IBAN"))?
+                    .get("value")?
+                    .as_str()?;
+                Some(Account {
+                    id: a.get("_id")?.as_str()?.to_string(),
+                    iban: group_iban(iban),
+                })
+            })
+            .collect())
+    }
+
+    /// Returns the transactions of an account booked on or after `from`, and
+    /// the pending ones, most recent first.
+    pub fn transactions(
+        &self,
+        account_id: &str,
+        from: NaiveDate,
+    ) -> Result<Vec<FetchedTransaction>, Box<dyn Error>> {
+        let mut transactions = vec![];
+        let mut continuation_key: Option<String> = None;
+
+        loop {
+            let mut params = vec![("from_date", from.to_string())];
+            if let Some(key) = continuation_key.take() {
+                params.push(("continuation_key", key));
+            }
+            let params: Vec<(&str, &str)> = params.iter().map(|(k, v)| (*k, v.as_str())).collect();
+            let json = self.get(&format!(
+                "/accounts/{}/transactions?{}",
+                http::encode(account_id),
+                http::form(&params)
+            ))?;
+
+            let response = json.get("response").ok_or("Invalid response from Nordea")?;
+            for t in response
+                .get("transactions")
+                .and_then(Value::as_array)
+                .unwrap_or_default()
+            {
+                transactions.push(transaction(t).ok_or("Invalid transaction from Nordea")?);
+            }
+
+            match response.get("continuation_key").and_then(Value::as_str) {
+                Some(key) if !key.is_empty() => continuation_key = Some(key.to_string()),
+                _ => break,
+            }
+        }
+
+        Ok(transactions)
+    }
+}
+
+fn transaction(t: &Value) -> Option<FetchedTransaction> {
+    let text = |key: &str| {
+        t.get(key)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+    let pending = t.get("status").and_then(Value::as_str) != Some("booked");
+    // Pending transactions have no booking date yet
+    let date = ["booking_date", "value_date", "transaction_date"]
+        .iter()
+        .find_map(|key| t.get(key)?.as_str()?.parse::<NaiveDate>().ok())?;
+    let value_date = t
+        .get("value_date")
+        .and_then(Value::as_str)
+        .and_then(|d| d.parse::<NaiveDate>().ok());
+    let amount = match t.get("amount")? {
+        Value::String(s) => s.parse().ok()?,
+        Value::Integer(i) => Amount::from_cents(i.checked_mul(100)?),
+        Value::Float(f) => format!("{:.2}", f).parse().ok()?,
+        _ => return None,
+    };
+    let description = [text("counterparty_name"), text("narrative")]
+        .into_iter()
+        .find(|s| !s.is_empty())
+        .unwrap_or_default();
+
+    Some(FetchedTransaction {
+        id: t.get("transaction_id")?.as_str()?.to_string(),
+        transaction: Transaction {
+            date,
+            amount,
+            currency: text("currency").to_uppercase(),
+            description: unicode::canonicalize(&description),
+            reference: unicode::canonicalize(&text("reference")),
+            message: unicode::canonicalize(&text("message")),
+            counterparty_account: text("counterparty_account"),
+            value_date,
+            payment_date: None,
+            balance: None,
+            original_amount: None,
+            original_currency: String::new(),
+            pending,
+            columns: vec![],
+        },
+    })
+}
@@ -0,0 +1,398 @@
+//! Writers of the converted rows, the output side of [`crate::parser`].
+//!
+//! Each file format implements [`OutputWriter`] and each service that
+//! `--upload` sends transactions to implements [`ApiTarget`]. `--format` and
+//! `--target` select one of them through [`Format::writer`] and
+//! [`UploadTarget::api`], so the conversion itself does not depend on where
+//! its rows end up, and adding a format only takes a writer and a variant.
+
+use crate::{
+    actual,
+    columns::Account,
+    config::Config,
+    firefly, gnucash, homebank, info, ledger, ofx, qif,
+    state::State,
+    write_ynab_csv, ynab,
+    ynab::{AccountBatch, AccountResult},
+    CsvOptions, Format, UploadTarget, YnabRow, OUTPUT_DATE_FORMAT,
+};
+use chrono::{NaiveDate, NaiveDateTime};
+use std::{collections::HashMap, error::Error, io, path::Path};
+
+/// Placeholders of output file name templates that tell the accounts apart
+pub const ACCOUNT_PLACEHOLDERS: [&str; 2] = ["{account_name}", "{iban}"];
+
+/// Values of the placeholders of an output file name template
+pub struct FileNameFields<'a> {
+    pub iban: &'a str,
+    /// Name of the account in the config file, if any
+    pub account_name: Option<&'a str>,
+    /// Date of the most recent export the rows are from
+    pub export_date: Option<NaiveDate>,
+    pub rows: &'a [YnabRow],
+    /// Extension of the format written, without the dot
+    pub extension: &'a str,
+}
+
+/// Renders an output file name template, such as
+/// `{account_name}-{from_date}-{to_date}.{ext}`. The placeholders are:
+///
+/// - `{iban}`, the IBAN without spaces
+/// - `{account_name}`, the name of the account in the config file with
+///   characters unsafe in file names replaced by dashes, or the IBAN
+/// - `{export_date}`, the date of the most recent export
+/// - `{from_date}` and `{to_date}`, the dates of the first and last
+///   transaction, or the export date if there are none
+/// - `{ext}`, the extension of the format
+pub fn file_name(template: &str, fields: &FileNameFields) -> Result<String, Box<dyn Error>> {
+    let iban = fields.iban.replace(' ', "");
+    let format_date = |date: Option<NaiveDate>| {
+        date.map_or_else(String::new, |d| d.format(OUTPUT_DATE_FORMAT).to_string())
+    };
+    let dates: Vec<&str> = fields.rows.iter().map(|r| r.date.as_str()).collect();
+    let export_date = format_date(fields.export_date);
+    let range = |date: Option<&&str>| date.map_or_else(|| export_date.clone(), |d| d.to_string());
+
+    let mut name = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        name.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("Unclosed brace in output file name '{}'", template))?;
+        let value = match &rest[start + 1..start + end] {
+            "iban" => iban.clone(),
+            "account_name" => fields
+                .account_name
+                .map(|name| {
+                    name.split(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+                        .filter(|part| !part.is_empty())
+                        .collect::<Vec<_>>()
+                        .join("-")
+                })
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| iban.clone()),
+            "export_date" => export_date.clone(),
+            "from_date" => range(dates.iter().min()),
+            "to_date" => range(dates.iter().max()),
+            "ext" => fields.extension.to_string(),
+            other => {
+                return Err(format!(
+                    "Unknown placeholder {{{}}} in output file name '{}', use {{iban}}, {{account_name}}, {{export_date}}, {{from_date}}, {{to_date}} or {{ext}}",
+                    other, template
+                )
+                .into())
+            }
+        };
+        name.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    name.push_str(rest);
+    Ok(name)
+}
+
+/// What a writer needs to know besides the rows of an account
+pub struct Context<'a> {
+    pub iban: &'a str,
+    /// Currency of the amounts
+    pub currency: &'a str,
+    pub csv: &'a CsvOptions,
+    /// For the account names of the output
+    pub config: &'a Config,
+    /// When the output is written
+    pub now: NaiveDateTime,
+}
+
+pub trait OutputWriter: Send + Sync {
+    /// Extension of the files written, without the dot
+    fn extension(&self) -> &'static str;
+
+    /// Writes the rows of the account of `context`.
+    fn write(
+        &self,
+        rows: &[YnabRow],
+        context: &Context,
+        writer: &mut dyn io::Write,
+    ) -> Result<(), Box<dyn Error>>;
+}
+
+/// Results of an upload keyed by account id, or why it failed
+pub type UploadResults = Result<HashMap<String, AccountResult>, Box<dyn Error>>;
+
+pub trait ApiTarget: Send + Sync {
+    /// Name of the service, e.g. "YNAB"
+    fn name(&self) -> &'static str;
+
+    /// Returns the id of the service's account configured for `iban`.
+    fn account_id<'a>(&self, config: &'a Config, iban: &str) -> Option<&'a str>;
+
+    /// Uploads the batches, whose account ids are the service's. Fails if the
+    /// settings of the service are missing; the result of the upload itself
+    /// is returned for reporting per account. `state_path` is the state
+    /// file, for targets that keep track of their uploads.
+    fn upload(
+        &self,
+        config: &Config,
+        batches: &[AccountBatch],
+        state_path: &Path,
+    ) -> Result<UploadResults, Box<dyn Error>>;
+}
+
+impl Format {
+    pub fn writer(&self) -> &'static dyn OutputWriter {
+        match self {
+            Format::Csv => &YnabCsv,
+            Format::Ofx => &Ofx,
+            Format::Qif => &Qif,
+            Format::Beancount => &Beancount,
+            Format::Ledger => &Ledger,
+            Format::Gnucash => &Gnucash,
+            Format::Homebank => &Homebank,
+        }
+    }
+}
+
+impl UploadTarget {
+    pub fn api(&self) -> &'static dyn ApiTarget {
+        match self {
+            UploadTarget::Ynab => &YnabApi,
+            UploadTarget::Actual => &ActualApi,
+            UploadTarget::Firefly => &FireflyApi,
+        }
+    }
+}
+
+pub struct YnabCsv;
+
+impl OutputWriter for YnabCsv {
+    fn extension(&self) -> &'static str {
+        "csv"
+    }
+
+    fn write(
+        &self,
+        rows: &[YnabRow],
+        context: &Context,
+        writer: &mut dyn io::Write,
+    ) -> Result<(), Box<dyn Error>> {
+        let account = Account {
+            iban: context.iban,
+            name: context
+                .config
+                .account_name(context.iban)
+                .unwrap_or(context.iban),
+            currency: context.currency,
+        };
+        write_ynab_csv(rows, context.csv, &account, writer)
+    }
+}
+
+pub struct Ofx;
+
+impl OutputWriter for Ofx {
+    fn extension(&self) -> &'static str {
+        "ofx"
+    }
+
+    fn write(
+        &self,
+        rows: &[YnabRow],
+        context: &Context,
+        writer: &mut dyn io::Write,
+    ) -> Result<(), Box<dyn Error>> {
+        ofx::write_ofx(rows, context.iban, context.currency, context.now, writer)
+    }
+}
+
+pub struct Qif;
+
+impl OutputWriter for Qif {
+    fn extension(&self) -> &'static str {
+        "qif"
+    }
+
+    fn write(
+        &self,
+        rows: &[YnabRow],
+        context: &Context,
+        writer: &mut dyn io::Write,
+    ) -> Result<(), Box<dyn Error>> {
+        qif::write_qif(rows, context.iban, writer)
+    }
+}
+
+pub struct Beancount;
+
+impl OutputWriter for Beancount {
+    fn extension(&self) -> &'static str {
+        "beancount"
+    }
+
+    fn write(
+        &self,
+        rows: &[YnabRow],
+        context: &Context,
+        writer: &mut dyn io::Write,
+    ) -> Result<(), Box<dyn Error>> {
+        let accounts = ledger::Accounts::for_iban(context.config, context.iban);
+        ledger::write_beancount(rows, context.currency, &accounts, writer)
+    }
+}
+
+pub struct Ledger;
+
+impl OutputWriter for Ledger {
+    fn extension(&self) -> &'static str {
+        "ledger"
+    }
+
+    fn write(
+        &self,
+        rows: &[YnabRow],
+        context: &Context,
+        writer: &mut dyn io::Write,
+    ) -> Result<(), Box<dyn Error>> {
+        let accounts = ledger::Accounts::for_iban(context.config, context.iban);
+        ledger::write_ledger(rows, context.currency, &accounts, writer)
+    }
+}
+
+pub struct Gnucash;
+
+impl OutputWriter for Gnucash {
+    fn extension(&self) -> &'static str {
+        "csv"
+    }
+
+    fn write(
+        &self,
+        rows: &[YnabRow],
+        context: &Context,
+        writer: &mut dyn io::Write,
+    ) -> Result<(), Box<dyn Error>> {
+        let account = gnucash::account_name(context.config, context.iban);
+        gnucash::write_gnucash(rows, &account, writer)
+    }
+}
+
+pub struct Homebank;
+
+impl OutputWriter for Homebank {
+    fn extension(&self) -> &'static str {
+        "csv"
+    }
+
+    fn write(
+        &self,
+        rows: &[YnabRow],
+        _context: &Context,
+        writer: &mut dyn io::Write,
+    ) -> Result<(), Box<dyn Error>> {
+        homebank::write_homebank(rows, writer)
+    }
+}
+
+pub struct YnabApi;
+
+impl ApiTarget for YnabApi {
+    fn name(&self) -> &'static str {
+        "YNAB"
+    }
+
+    fn account_id<'a>(&self, config: &'a Config, iban: &str) -> Option<&'a str> {
+        config.ynab_account_id(iban)
+    }
+
+    /// Queues the batches in the state file until YNAB has confirmed them.
+    fn upload(
+        &self,
+        config: &Config,
+        batches: &[AccountBatch],
+        state_path: &Path,
+    ) -> Result<UploadResults, Box<dyn Error>> {
+        let (token, budget_id) = ynab::credentials(config)?;
+        let mut state = State::load(state_path)?;
+        info!("Uploading to YNAB...");
+        match ynab::upload(token, budget_id, batches, &mut state, state_path) {
+            Ok(upload) => {
+                if upload.resubmitted > 0 {
+                    info!(
+                        "{} transaction(s) of an earlier unconfirmed upload resubmitted.",
+                        upload.resubmitted
+                    );
+                }
+                Ok(Ok(upload.accounts))
+            }
+            Err(e) => {
+                if !state.pending_uploads.is_empty() {
+                    info!(
+                        "{} transaction(s) are queued in {} and will be resubmitted by the next upload.",
+                        state.pending_uploads.len(),
+                        state_path.display()
+                    );
+                }
+                Ok(Err(e))
+            }
+        }
+    }
+}
+
+pub struct ActualApi;
+
+impl ApiTarget for ActualApi {
+    fn name(&self) -> &'static str {
+        "Actual"
+    }
+
+    fn account_id<'a>(&self, config: &'a Config, iban: &str) -> Option<&'a str> {
+        config.actual_account_id(iban)
+    }
+
+    fn upload(
+        &self,
+        config: &Config,
+        batches: &[AccountBatch],
+        _state_path: &Path,
+    ) -> Result<UploadResults, Box<dyn Error>> {
+        let actual = &config.actual;
+        let server = match (&actual.url, &actual.api_key, &actual.budget_id) {
+            (Some(url), Some(api_key), Some(budget_id)) => actual::Server {
+                url,
+                api_key,
+                budget_id,
+            },
+            _ => return Err("Uploading to Actual needs actual.url, actual.api_key and actual.budget_id in the config file".into()),
+        };
+        info!("Uploading to Actual...");
+        Ok(actual::upload(&server, batches))
+    }
+}
+
+pub struct FireflyApi;
+
+impl ApiTarget for FireflyApi {
+    fn name(&self) -> &'static str {
+        "Firefly III"
+    }
+
+    fn account_id<'a>(&self, config: &'a Config, iban: &str) -> Option<&'a str> {
+        config.firefly_account_id(iban)
+    }
+
+    fn upload(
+        &self,
+        config: &Config,
+        batches: &[AccountBatch],
+        _state_path: &Path,
+    ) -> Result<UploadResults, Box<dyn Error>> {
+        let server = match (&config.firefly.url, &config.firefly.token) {
+            (Some(url), Some(token)) => firefly::Server { url, token },
+            _ => return Err(
+                "Uploading to Firefly III needs firefly.url and firefly.token in the config file"
+                    .into(),
+            ),
+        };
+        info!("Uploading to Firefly III...");
+        Ok(firefly::upload(&server, batches))
+    }
+}
@@ -0,0 +1,392 @@
+//! Pluggable parsers for bank exports.
+//!
+//! Each supported bank implements [`BankParser`], which recognises the bank's
+//! export file names and parses their contents into [`Transaction`]s. The
+//! [`Registry`] tries its parsers in order, so supporting another bank only
+//! needs a new parser; the diffing and output code work on transactions
+//! regardless of where they came from.
+
+use crate::{
+    camt::Camt,
+    creditcard::NordeaCard,
+    danske::Danske,
+    encoding::{self, Encoding},
+    locale::Locale,
+    nordea::Nordea,
+    op::Op,
+    revolut::Revolut,
+    spankki::SPankki,
+    system::{Clock, FileSystem},
+    timezone::Timezone,
+    wise::Wise,
+    Transaction,
+};
+use chrono::NaiveDateTime;
+use csv::{ReaderBuilder, StringRecord};
+use regex::Regex;
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+/// Account and export time parsed from the name of an export file.
+#[derive(Debug, PartialEq)]
+pub struct FileInfo {
+    /// IBAN of the account, in the usual groups of four characters
+    pub iban: String,
+    pub date: NaiveDateTime,
+}
+
+/// A row of an export that could not be parsed.
+#[derive(Debug)]
+pub struct RowError {
+    pub line: u64,
+    pub message: String,
+}
+
+/// The rows of an export in the order they appear in it, each parsed into a
+/// transaction or an error.
+pub type Rows<'a> = Box<dyn Iterator<Item = Result<Transaction, RowError>> + 'a>;
+
+/// The transactions of an export, and the rows that could not be parsed.
+#[derive(Debug, Default)]
+pub struct ParsedRows {
+    pub transactions: Vec<Transaction>,
+    pub errors: Vec<RowError>,
+}
+
+pub trait BankParser: Send + Sync {
+    /// Short identifier of the bank, e.g. "nordea".
+    fn name(&self) -> &'static str;
+
+    /// Parses the name of an export file, or returns `None` if it is not an
+    /// export of this bank.
+    fn parse_file_name(&self, file_name: &str) -> Option<FileInfo>;
+
+    /// Returns whether files that `parse_file_name` does not recognise should
+    /// be read and passed to `parse_file_info`, for formats whose file names
+    /// do not identify the account.
+    fn inspects(&self, _file_name: &str) -> bool {
+        false
+    }
+
+    /// Parses the account and export time from the decoded contents of a
+    /// file, or returns `None` if it is not an export of this bank.
+    fn parse_file_info(&self, _contents: &str) -> Option<FileInfo> {
+        None
+    }
+
+    /// Returns whether `contents` look like an export of this bank. Used for
+    /// files whose name does not tell which bank they came from, such as
+    /// archived exports.
+    fn detect(&self, contents: &str) -> bool;
+
+    /// Returns whether consecutive exports contain the transactions of the
+    /// previous export, as the netbank's CSV exports of a date range do. If
+    /// not, no overlap with the previous export is required.
+    fn exports_overlap(&self) -> bool {
+        true
+    }
+
+    /// Parses the decoded contents of an export into transactions one row at
+    /// a time, in the order they appear in the export (most recent first).
+    /// Rows that cannot be parsed are yielded as errors rather than failing
+    /// the whole export. `path` is only used in messages.
+    fn rows<'a>(&'a self, contents: &'a str, path: &Path) -> Result<Rows<'a>, Box<dyn Error>>;
+
+    /// Parses all rows of an export at once.
+    fn parse_rows(&self, contents: &str, path: &Path) -> Result<ParsedRows, Box<dyn Error>> {
+        let mut parsed = ParsedRows::default();
+        for row in self.rows(contents, path)? {
+            match row {
+                Ok(transaction) => parsed.transactions.push(transaction),
+                Err(e) => parsed.errors.push(e),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// A decoded export, whose rows can be read any number of times without
+/// keeping them all in memory.
+pub struct Export<'a> {
+    parser: &'a dyn BankParser,
+    contents: String,
+    path: PathBuf,
+    /// Another parser that recognises the contents when `parser` does not,
+    /// suggested if the rows cannot be read
+    other: Option<&'a dyn BankParser>,
+}
+
+impl Export<'_> {
+    pub fn rows(&self) -> Result<Rows<'_>, Box<dyn Error>> {
+        self.parser
+            .rows(&self.contents, &self.path)
+            .map_err(|e| match self.other {
+                Some(other) => format!(
+                    "{}\nThe file is named like a {} export, but its contents are recognised as {} by their columns.",
+                    e,
+                    self.parser.name(),
+                    other.name()
+                )
+                .into(),
+                None => e,
+            })
+    }
+
+    /// Name of the parser reading the export
+    pub fn bank(&self) -> &'static str {
+        self.parser.name()
+    }
+}
+
+#[derive(Default)]
+pub struct Registry {
+    parsers: Vec<Box<dyn BankParser>>,
+    /// Time zone of the times in file names
+    timezone: Timezone,
+}
+
+impl Registry {
+    /// Returns a registry with the built-in parsers. `locale` restricts the
+    /// Nordea parser to exports of that country, and `clock` dates the holds
+    /// of exports with no date in their file name.
+    pub fn builtin(locale: Option<Locale>, clock: &dyn Clock) -> Registry {
+        Registry::with_file_name_patterns(locale, vec![], clock)
+    }
+
+    /// Returns a registry with the built-in parsers, where the Nordea parser
+    /// also recognises exports named like `patterns`.
+    pub fn with_file_name_patterns(
+        locale: Option<Locale>,
+        patterns: Vec<Regex>,
+        clock: &dyn Clock,
+    ) -> Registry {
+        let mut registry = Registry::default();
+        registry.register(Box::new(
+            Nordea::new(locale, clock).with_file_name_patterns(patterns),
+        ));
+        registry.register(Box::new(NordeaCard::new()));
+        registry.register(Box::new(Op::new()));
+        registry.register(Box::new(SPankki::new()));
+        registry.register(Box::new(Danske::new()));
+        registry.register(Box::new(Revolut));
+        registry.register(Box::new(Wise::new()));
+        registry.register(Box::new(Camt));
+        #[cfg(feature = "pdf")]
+        registry.register(Box::new(crate::pdf::NordeaPdf::new()));
+        registry
+    }
+
+    /// Takes the times in file names to be in `timezone` when ordering
+    /// exports.
+    pub fn with_timezone(mut self, timezone: Timezone) -> Registry {
+        self.timezone = timezone;
+        self
+    }
+
+    pub fn timezone(&self) -> Timezone {
+        self.timezone
+    }
+
+    /// Adds a parser, which is tried after the ones already registered.
+    pub fn register(&mut self, parser: Box<dyn BankParser>) {
+        self.parsers.push(parser);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn BankParser> {
+        self.parsers
+            .iter()
+            .find(|p| p.name() == name)
+            .map(|p| p.as_ref())
+    }
+
+    /// Returns the first parser that recognises the file name, along with the
+    /// parsed file name. Names of repeated downloads, like `export (1).csv`,
+    /// are recognised as the name of the original download.
+    pub fn parse_file_name(&self, file_name: &str) -> Option<(&dyn BankParser, FileInfo)> {
+        let parse = |name: &str| {
+            self.parsers
+                .iter()
+                .find_map(|p| Some((p.as_ref(), p.parse_file_name(name)?)))
+        };
+        parse(file_name).or_else(|| parse(&without_duplicate_suffix(file_name)?))
+    }
+
+    /// Returns the first parser that recognises the file at `path` from its
+    /// contents, for files that none of the parsers recognise by name.
+    pub fn inspect(
+        &self,
+        files: &dyn FileSystem,
+        file_name: &str,
+        path: &Path,
+        encoding: Encoding,
+    ) -> Option<(&dyn BankParser, FileInfo)> {
+        let mut parsers = self
+            .parsers
+            .iter()
+            .filter(|p| p.inspects(file_name))
+            .peekable();
+        parsers.peek()?;
+
+        let contents = read_to_string(files, path, encoding).ok()?;
+        parsers.find_map(|p| Some((p.as_ref(), p.parse_file_info(&contents)?)))
+    }
+
+    /// Returns the first parser that recognises the contents of an export.
+    pub fn detect(&self, contents: &str) -> Option<&dyn BankParser> {
+        self.parsers
+            .iter()
+            .find(|p| p.detect(contents))
+            .map(|p| p.as_ref())
+    }
+
+    /// Reads and decodes the export at `path` in `files` and parses it with
+    /// the parser called `bank`.
+    pub fn read(
+        &self,
+        files: &dyn FileSystem,
+        bank: &str,
+        path: &Path,
+        encoding: Encoding,
+    ) -> Result<ParsedRows, Box<dyn Error>> {
+        let export = self.open(files, bank, path, encoding)?;
+        export.parser.parse_rows(&export.contents, path)
+    }
+
+    /// Reads and decodes the export at `path` in `files` for reading its rows
+    /// with the parser called `bank`.
+    pub fn open(
+        &self,
+        files: &dyn FileSystem,
+        bank: &str,
+        path: &Path,
+        encoding: Encoding,
+    ) -> Result<Export<'_>, Box<dyn Error>> {
+        let parser = self
+            .get(bank)
+            .ok_or_else(|| format!("No parser for {} exports", bank))?;
+        let contents = read_to_string(files, path, encoding)?;
+        let other = if parser.detect(&contents) {
+            None
+        } else {
+            self.detect(&contents)
+        };
+        Ok(Export {
+            parser,
+            contents,
+            path: path.to_path_buf(),
+            other,
+        })
+    }
+
+    /// Returns the export read from elsewhere than a file, e.g. standard
+    /// input, with the first parser that recognises its contents. `path` is
+    /// only used in messages.
+    pub fn detect_export(&self, contents: String, path: &Path) -> Option<Export<'_>> {
+        Some(Export {
+            parser: self.detect(&contents)?,
+            contents,
+            path: path.to_path_buf(),
+            other: None,
+        })
+    }
+}
+
+/// Delimiters tried when sniffing the layout of a CSV export
+pub const DELIMITERS: [u8; 3] = [b';', b',', b'\t'];
+
+/// Number of lines looked at above the header row of a CSV export at most
+const MAX_PREAMBLE_LINES: usize = 5;
+
+/// Where the rows of a CSV export start and how they are separated.
+#[derive(Debug, PartialEq)]
+pub struct CsvLayout {
+    pub delimiter: u8,
+    /// Number of lines above the header row
+    pub preamble_lines: u64,
+    /// Byte offset of the header row
+    pub start: usize,
+}
+
+/// Finds the header row of a CSV export among its first lines, trying each
+/// of the usual delimiters, as exports have been seen with semicolons,
+/// commas and tabs and with a line of text above the header. `is_header`
+/// tells whether a row is the header the parser expects.
+pub fn sniff_csv(contents: &str, is_header: impl Fn(&StringRecord) -> bool) -> Option<CsvLayout> {
+    sniff_csv_with(contents, &DELIMITERS, is_header)
+}
+
+/// Finds the header row of a CSV export like [`sniff_csv`], trying only
+/// `delimiters`.
+pub fn sniff_csv_with(
+    contents: &str,
+    delimiters: &[u8],
+    is_header: impl Fn(&StringRecord) -> bool,
+) -> Option<CsvLayout> {
+    let mut start = 0;
+    for (preamble_lines, line) in contents
+        .split_inclusive('\n')
+        .take(MAX_PREAMBLE_LINES + 1)
+        .enumerate()
+    {
+        for &delimiter in delimiters {
+            let record = ReaderBuilder::new()
+                .delimiter(delimiter)
+                .has_headers(false)
+                .flexible(true)
+                .from_reader(line.as_bytes())
+                .records()
+                .next();
+            if let Some(Ok(record)) = record {
+                if record.len() > 1 && is_header(&record) {
+                    return Some(CsvLayout {
+                        delimiter,
+                        preamble_lines: preamble_lines as u64,
+                        start,
+                    });
+                }
+            }
+        }
+        start += line.len();
+    }
+    None
+}
+
+/// Pairs the fields of a CSV row with the column names of `header`, for
+/// [`Transaction::columns`].
+pub fn source_columns(header: &StringRecord, record: &StringRecord) -> Vec<(String, String)> {
+    header
+        .iter()
+        .zip(record.iter())
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+/// Reads and decodes a file. With the `pdf` feature, the text of PDF
+/// documents is extracted.
+pub fn read_to_string(
+    files: &dyn FileSystem,
+    path: &Path,
+    encoding: Encoding,
+) -> Result<String, Box<dyn Error>> {
+    let bytes = files.read(path)?;
+    #[cfg(feature = "pdf")]
+    if crate::pdf::is_pdf(&bytes) {
+        return crate::pdf::to_text(&bytes)
+            .map_err(|e| format!("{}: {}", path.display(), e).into());
+    }
+    encoding::decode(&bytes, encoding)
+        .map_err(|e| format!("Could not decode {}: {}", path.display(), e).into())
+}
+
+/// Removes the number browsers add to the names of repeated downloads, e.g.
+/// `export (1).csv` or `export(2).csv`, returning `None` if there is none.
+fn without_duplicate_suffix(file_name: &str) -> Option<String> {
+    let (stem, extension) = file_name.rsplit_once('.')?;
+    let (original, number) = stem.strip_suffix(')')?.rsplit_once('(')?;
+    if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(format!("{}.{}", original.trim_end(), extension))
+}
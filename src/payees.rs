@@ -0,0 +1,99 @@
+//! Matching converted payees to the payees already in a YNAB budget.
+//!
+//! Descriptions in exports are often cut short or written in capitals, e.g.
+//! "VERKKOKAUPPA.COM MYYMÄL", and uploading them as they are would create a
+//! new payee next to an existing "Verkkokauppa.com". Payees are compared
+//! ignoring case and punctuation, both whole and by as many of their first
+//! words as the existing payee has, and the most similar existing payee is
+//! used if the normalized Levenshtein similarity is at least
+//! [`MIN_SIMILARITY`].
+
+use crate::YnabRow;
+use std::cmp::Ordering;
+
+/// Similarity from 0 to 1 needed to use an existing payee
+pub const MIN_SIMILARITY: f64 = 0.85;
+
+/// Existing payees shorter than this, once normalized, are never matched, as
+/// they would match too much
+const MIN_LEN: usize = 3;
+
+/// Splits `s` into lowercase words of letters and digits only.
+fn words(s: &str) -> Vec<String> {
+    s.split_whitespace()
+        .map(|w| {
+            w.chars()
+                .filter(|c| c.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        })
+        .filter(|w| !w.is_empty())
+        .collect()
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Returns the similarity of `a` and `b` from 0 (nothing in common) to 1
+/// (equal).
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let len = a.len().max(b.len());
+    if len == 0 {
+        return 1.0;
+    }
+    1.0 - levenshtein(&a, &b) as f64 / len as f64
+}
+
+/// Returns the existing payee most similar to `payee`, if any is similar
+/// enough.
+pub fn best_match<'a>(payee: &str, existing: &'a [String]) -> Option<&'a str> {
+    let payee_words = words(payee);
+    let whole = payee_words.concat();
+    existing
+        .iter()
+        .filter_map(|candidate| {
+            let candidate_words = words(candidate);
+            let joined = candidate_words.concat();
+            if joined.chars().count() < MIN_LEN {
+                return None;
+            }
+            let prefix: String = payee_words
+                .iter()
+                .take(candidate_words.len())
+                .cloned()
+                .collect();
+            let score = similarity(&whole, &joined).max(similarity(&prefix, &joined));
+            (score >= MIN_SIMILARITY).then_some((score, joined.len(), candidate))
+        })
+        // The most similar, and of equally similar ones the most specific
+        .max_by(|a, b| {
+            a.0.partial_cmp(&b.0)
+                .unwrap_or(Ordering::Equal)
+                .then(a.1.cmp(&b.1))
+        })
+        .map(|(_, _, candidate)| candidate.as_str())
+}
+
+/// Replaces the payees of `rows` by the matching existing payees. Returns
+/// the number of rows changed.
+pub fn match_payees(rows: &mut [YnabRow], existing: &[String]) -> usize {
+    let mut changed = 0;
+    for r in rows {
+        if let Some(payee) = best_match(&r.payee, existing).filter(|p| *p != r.payee) {
+            r.payee = payee.to_string();
+            changed += 1;
+        }
+    }
+    changed
+}
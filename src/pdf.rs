@@ -0,0 +1,256 @@
+//! Nordea's PDF account statements (e-tiliote), only built with the `pdf`
+//! feature.
+//!
+//! Closed and older accounts may only have PDF statements left, so these are
+//! read for backfilling history that is no longer available as CSV. The text
+//! is extracted with `pdftotext -layout` from Poppler, like [`crate::http`]
+//! uses curl, which keeps the table of transactions in columns. A transaction
+//! is a line starting with its booking date, optionally followed by its
+//! value date, with the description in between and the amount at the end,
+//! e.g.
+//!
+//! ```text
+//! 02.01.  02.01.  K-MARKET KAMPPI                         12,34-
+//!                 Viite 12345
+//! ```
+//!
+//! Indented lines below a transaction are its message, or its reference.
+//! Dates without a year get the year of the statement period, e.g.
+//! `Jakso 01.12.2023 - 31.01.2024`. Consecutive statements do not overlap.
+
+use crate::{
+    group_iban,
+    locale::Locale,
+    parser::{BankParser, FileInfo, RowError, Rows},
+    RawTransaction, Transaction,
+};
+use chrono::{Datelike, NaiveDate};
+use itertools::Itertools;
+use regex::Regex;
+use std::{
+    error::Error,
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// Returns whether `bytes` are a PDF document.
+pub fn is_pdf(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"%PDF")
+}
+
+/// Extracts the text of a PDF document, keeping its layout.
+pub fn to_text(bytes: &[u8]) -> Result<String, Box<dyn Error>> {
+    let mut child = Command::new("pdftotext")
+        .args(["-layout", "-enc", "UTF-8", "-", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Could not run pdftotext (from Poppler) to read PDF: {}", e))?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(bytes)?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "Could not read PDF: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+pub struct NordeaPdf {
+    iban: Regex,
+    period: Regex,
+    transaction: Regex,
+    reference: Regex,
+}
+
+impl Default for NordeaPdf {
+    fn default() -> Self {
+        NordeaPdf::new()
+    }
+}
+
+impl NordeaPdf {
+    pub fn new() -> NordeaPdf {
+        let iban = Locale::ALL.iter().map(|l| l.iban_pattern()).join("|");
+        NordeaPdf {
+            iban: Regex::new(&format!(r"\b({})\b", iban)).unwrap(),
+            period: Regex::new(
+                r"(\d{1,2}\.\d{1,2}\.\d{4})\s*[-\u{2013}]\s*(\d{1,2}\.\d{1,2}\.\d{4})",
+            )
+            .unwrap(),
+            transaction: Regex::new(
+                r"^\s*(?P<date>\d{1,2}\.\d{1,2}\.(?:\d{2,4})?)\s+(?:(?P<value>\d{1,2}\.\d{1,2}\.(?:\d{2,4})?)\s+)?(?P<description>\S.*?)\s{2,}(?P<amount>[+-]?\d{1,3}(?:[ .\u{a0}]?\d{3})*,\d{2}[+-]?)\s*$",
+            )
+            .unwrap(),
+            reference: Regex::new(r"^(?i:viite|ref\.?|referens|reference)\s*:?\s*(\S+)$")
+                .unwrap(),
+        }
+    }
+
+    fn account(&self, contents: &str) -> Option<String> {
+        let iban = self.iban.captures(contents)?.get(1)?.as_str();
+        Some(group_iban(iban))
+    }
+
+    /// Returns the last day of the statement period.
+    fn period_end(&self, contents: &str) -> Option<NaiveDate> {
+        let end = self.period.captures(contents)?.get(2)?.as_str();
+        NaiveDate::parse_from_str(end, "%d.%m.%Y").ok()
+    }
+}
+
+/// Parses a date of the statement, taking the year from the period ending on
+/// `period_end` if it has none.
+fn statement_date(date: &str, period_end: NaiveDate) -> Option<NaiveDate> {
+    let parts: Vec<&str> = date.split('.').filter(|p| !p.is_empty()).collect();
+    let (day, month): (u32, u32) = (parts.first()?.parse().ok()?, parts.get(1)?.parse().ok()?);
+    let year = match parts.get(2) {
+        Some(year) if year.len() == 2 => 2000 + year.parse::<i32>().ok()?,
+        Some(year) => year.parse().ok()?,
+        // In a period over the new year, the months after the end month are
+        // of the year before
+        None if month > period_end.month() => period_end.year() - 1,
+        None => period_end.year(),
+    };
+    NaiveDate::from_ymd_opt(year, month, day)
+}
+
+/// Rewrites an amount like `1 234,56-` as `-1234,56`.
+fn signed_amount(amount: &str) -> String {
+    let digits: String = amount
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == ',' || *c == '.')
+        .collect();
+    // Thousands separators are periods in some statements
+    let digits = match digits.rsplit_once(',') {
+        Some((whole, cents)) => format!("{},{}", whole.replace('.', ""), cents),
+        None => digits,
+    };
+    if amount.starts_with('-') || amount.ends_with('-') {
+        format!("-{}", digits)
+    } else {
+        digits
+    }
+}
+
+impl BankParser for NordeaPdf {
+    fn name(&self) -> &'static str {
+        "nordea-pdf"
+    }
+
+    fn parse_file_name(&self, _file_name: &str) -> Option<FileInfo> {
+        None
+    }
+
+    fn inspects(&self, file_name: &str) -> bool {
+        file_name.to_lowercase().ends_with(".pdf")
+    }
+
+    fn parse_file_info(&self, contents: &str) -> Option<FileInfo> {
+        if !self.detect(contents) {
+            return None;
+        }
+        Some(FileInfo {
+            iban: self.account(contents)?,
+            date: self.period_end(contents)?.and_hms_opt(0, 0, 0)?,
+        })
+    }
+
+    fn detect(&self, contents: &str) -> bool {
+        contents.to_lowercase().contains("nordea")
+            && self.account(contents).is_some()
+            && self.period_end(contents).is_some()
+    }
+
+    fn exports_overlap(&self) -> bool {
+        false
+    }
+
+    fn rows<'a>(&'a self, contents: &'a str, path: &Path) -> Result<Rows<'a>, Box<dyn Error>> {
+        let period_end = self
+            .period_end(contents)
+            .ok_or_else(|| format!("No statement period found in {}", path.display()))?;
+
+        let mut rows: Vec<Result<Transaction, RowError>> = vec![];
+        // Index of the transaction whose details may follow, and the column
+        // of its description
+        let mut current: Option<(usize, usize)> = None;
+        for (index, line) in contents.lines().enumerate() {
+            let line_number = index as u64 + 1;
+            if let Some(captures) = self.transaction.captures(line) {
+                let date = |name| {
+                    let date = captures.name(name)?;
+                    Some(statement_date(date.as_str(), period_end))
+                };
+                let (Some(Some(booking)), value) = (date("date"), date("value")) else {
+                    rows.push(Err(RowError {
+                        line: line_number,
+                        message: format!("invalid date '{}'", &captures["date"]),
+                    }));
+                    current = None;
+                    continue;
+                };
+                let transaction = RawTransaction {
+                    date: booking.format("%d.%m.%Y").to_string(),
+                    amount: signed_amount(&captures["amount"]),
+                    description: captures["description"].trim().to_string(),
+                    reference: String::new(),
+                    message: String::new(),
+                    counterparty_account: String::new(),
+                    value_date: value
+                        .flatten()
+                        .map(|d| d.format("%d.%m.%Y").to_string())
+                        .unwrap_or_default(),
+                    payment_date: String::new(),
+                    balance: String::new(),
+                    currency: String::new(),
+                    original_amount: String::new(),
+                    original_currency: String::new(),
+                    pending: false,
+                    columns: vec![],
+                }
+                .normalize()
+                .map_err(|message| RowError {
+                    line: line_number,
+                    message,
+                });
+                let column = captures.name("description").map_or(0, |d| d.start());
+                current = transaction.is_ok().then_some((rows.len(), column));
+                rows.push(transaction);
+                continue;
+            }
+
+            // Details are in the description column below the transaction,
+            // anything else ends it, e.g. a balance line or a page break
+            let detail = line.trim();
+            let indent = line.len() - line.trim_start().len();
+            let Some(Ok(transaction)) = current
+                .filter(|&(_, column)| !detail.is_empty() && indent >= column)
+                .map(|(i, _)| &mut rows[i])
+            else {
+                current = None;
+                continue;
+            };
+            match self.reference.captures(detail) {
+                Some(reference) => transaction.reference = reference[1].to_string(),
+                None if transaction.message.is_empty() => transaction.message = detail.to_string(),
+                None => {
+                    transaction.message.push(' ');
+                    transaction.message.push_str(detail);
+                }
+            }
+        }
+
+        // Listed oldest first
+        rows.reverse();
+        Ok(Box::new(rows.into_iter()))
+    }
+}
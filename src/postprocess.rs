@@ -0,0 +1,174 @@
+//! Passing converted transactions through an external command with
+//! `--post-process`, for enrichment the rules cannot do, such as a lookup in
+//! a merchant database of one's own or a machine learning categorizer.
+//!
+//! The command is run with the shell, once per account, and given the
+//! transactions on stdin as JSON lines, one object per transaction:
+//!
+//! ```json
+//! {"account":"FI12 3456 7890 1234 56","date":"2024-01-02","payee":"K-Market","memo":"","amount":"-12.34","category":null,"flag":null,"reference":"","pending":false}
+//! ```
+//!
+//! It must write one line to stdout for each line read, in the same order:
+//! the transaction, changed or not, or `null` to leave it out. Fields left
+//! out of an object keep their value, and `account` is only informational.
+//! Amounts may be given as strings or numbers. Anything the command writes
+//! to stderr is shown as is, and a non-zero exit status fails the run.
+
+use crate::{
+    amount::Amount,
+    json::{self, Value},
+    rules::Flag,
+    YnabRow,
+};
+use std::{
+    error::Error,
+    io::{Read, Write},
+    process::{Command, Stdio},
+    thread,
+};
+
+/// Returns `rows` of account `iban` as replaced by `command`.
+pub fn post_process(
+    command: &str,
+    iban: &str,
+    rows: Vec<YnabRow>,
+) -> Result<Vec<YnabRow>, Box<dyn Error>> {
+    if rows.is_empty() {
+        return Ok(rows);
+    }
+    let input: String = rows
+        .iter()
+        .map(|row| to_json(row, iban).to_string() + "\n")
+        .collect();
+    let output = run(command, input)?;
+
+    let lines: Vec<&str> = output.lines().filter(|l| !l.trim().is_empty()).collect();
+    if lines.len() != rows.len() {
+        return Err(format!(
+            "--post-process command '{}' wrote {} line(s) for {} transaction(s), expected one per transaction",
+            command,
+            lines.len(),
+            rows.len()
+        )
+        .into());
+    }
+    let mut processed = Vec::with_capacity(rows.len());
+    for (i, (line, row)) in lines.into_iter().zip(rows).enumerate() {
+        let replaced = json::parse(line)
+            .and_then(|value| from_json(&value, row))
+            .map_err(|e| {
+                format!(
+                    "--post-process command '{}' wrote an invalid transaction on line {}: {}",
+                    command,
+                    i + 1,
+                    e
+                )
+            })?;
+        processed.extend(replaced);
+    }
+    Ok(processed)
+}
+
+/// Runs `command` with the shell, writing `input` to its stdin, and returns
+/// its stdout.
+fn run(command: &str, input: String) -> Result<String, Box<dyn Error>> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let mut child = shell
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Could not run --post-process command '{}': {}", command, e))?;
+
+    // Written from another thread, so that a command writing its output
+    // before reading all of its input does not block on a full pipe
+    let mut stdin = child.stdin.take().expect("piped stdin");
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let mut output = String::new();
+    child
+        .stdout
+        .take()
+        .expect("piped stdout")
+        .read_to_string(&mut output)?;
+    let status = child.wait()?;
+    // A command that stops reading early is fine as long as it succeeds
+    let _ = writer.join();
+    if !status.success() {
+        return Err(format!("--post-process command '{}' failed: {}", command, status).into());
+    }
+    Ok(output)
+}
+
+fn to_json(row: &YnabRow, iban: &str) -> Value {
+    let optional = |value: Option<&str>| value.map_or(Value::Null, Value::from);
+    Value::object([
+        ("account", iban.into()),
+        ("date", row.date.as_str().into()),
+        ("payee", row.payee.as_str().into()),
+        ("memo", row.memo.as_str().into()),
+        ("amount", row.amount.to_string().into()),
+        ("category", optional(row.category.as_deref())),
+        ("flag", optional(row.flag.map(|f| f.as_str()))),
+        ("reference", row.reference.as_str().into()),
+        ("pending", row.pending.into()),
+    ])
+}
+
+/// Returns `row` with the fields of `value` replaced, or `None` if `value`
+/// is `null`.
+fn from_json(value: &Value, mut row: YnabRow) -> Result<Option<YnabRow>, String> {
+    let fields = match value {
+        Value::Null => return Ok(None),
+        Value::Object(fields) => fields,
+        _ => return Err("expected an object or null".to_string()),
+    };
+    for (key, value) in fields {
+        let string = || {
+            value
+                .as_str()
+                .map(String::from)
+                .ok_or_else(|| format!("'{}' must be a string", key))
+        };
+        let optional = || match value {
+            Value::Null => Ok(None),
+            _ => string().map(Some),
+        };
+        match key.as_str() {
+            "account" => {}
+            "date" => {
+                let date = string()?;
+                chrono::NaiveDate::parse_from_str(&date, crate::OUTPUT_DATE_FORMAT)
+                    .map_err(|_| format!("invalid date '{}', expected YYYY-MM-DD", date))?;
+                row.date = date;
+            }
+            "payee" => row.payee = string()?,
+            "memo" => row.memo = string()?,
+            "amount" => {
+                row.amount = match value {
+                    Value::String(s) => s.parse()?,
+                    Value::Integer(i) => Amount::from_cents(i * 100),
+                    Value::Float(f) => format!("{:.2}", f).parse()?,
+                    _ => return Err("'amount' must be a string or a number".to_string()),
+                }
+            }
+            "category" => row.category = optional()?,
+            "flag" => row.flag = optional()?.map(|f| f.parse::<Flag>()).transpose()?,
+            "reference" => row.reference = string()?,
+            "pending" => match value {
+                Value::Bool(pending) => row.pending = *pending,
+                _ => return Err("'pending' must be true or false".to_string()),
+            },
+            _ => return Err(format!("unknown field '{}'", key)),
+        }
+    }
+    Ok(Some(row))
+}
@@ -0,0 +1,70 @@
+//! Progress of reading large exports, drawn on stderr with `--progress`.
+
+use crate::log::{self, Level};
+use std::io::{self, Write};
+
+/// Rows read between redraws
+const REDRAW_EVERY: usize = 1000;
+
+/// Width of the bar in characters
+const BAR_WIDTH: usize = 30;
+
+pub struct Progress<'a> {
+    label: &'a str,
+    enabled: bool,
+    /// Number of rows to read, if known
+    total: Option<usize>,
+    count: usize,
+    drawn: bool,
+}
+
+impl<'a> Progress<'a> {
+    /// Starts tracking the progress of reading `total` rows, or an unknown
+    /// number of rows. Nothing is drawn unless `enabled` and the log level
+    /// shows info messages.
+    pub fn new(enabled: bool, label: &'a str, total: Option<usize>) -> Progress<'a> {
+        Progress {
+            label,
+            enabled: enabled && log::enabled(Level::Info),
+            total,
+            count: 0,
+            drawn: false,
+        }
+    }
+
+    /// Counts a row read.
+    pub fn tick(&mut self) {
+        self.count += 1;
+        if self.enabled && self.count.is_multiple_of(REDRAW_EVERY) {
+            self.draw();
+        }
+    }
+
+    fn draw(&mut self) {
+        let line = match self.total.filter(|&t| t > 0) {
+            Some(total) => {
+                let filled = (self.count * BAR_WIDTH / total).min(BAR_WIDTH);
+                format!(
+                    "{} [{}{}] {}/{} rows",
+                    self.label,
+                    "#".repeat(filled),
+                    " ".repeat(BAR_WIDTH - filled),
+                    self.count,
+                    total
+                )
+            }
+            None => format!("{}: {} rows", self.label, self.count),
+        };
+        eprint!("\r{}", line);
+        let _ = io::stderr().flush();
+        self.drawn = true;
+    }
+
+    /// Draws the final count and ends the line, if anything was drawn.
+    pub fn finish(mut self) {
+        if self.drawn {
+            self.draw();
+            eprintln!();
+        }
+    }
+}
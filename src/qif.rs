@@ -0,0 +1,37 @@
+//! Quicken Interchange Format (QIF) output, readable by GnuCash, Quicken and
+//! most older budgeting software.
+
+use crate::{parse_nda_date, YnabRow};
+use std::{error::Error, io::Write};
+
+pub fn write_qif<W: Write>(rows: &[YnabRow], iban: &str, mut w: W) -> Result<(), Box<dyn Error>> {
+    writeln!(w, "!Account")?;
+    writeln!(w, "N{}", iban)?;
+    writeln!(w, "TBank")?;
+    writeln!(w, "^")?;
+    writeln!(w, "!Type:Bank")?;
+
+    for r in rows {
+        let date = parse_nda_date(&r.date)?;
+
+        writeln!(w, "D{}", date.format("%m/%d/%Y"))?;
+        writeln!(w, "T{}", r.amount)?;
+        writeln!(w, "P{}", single_line(&r.payee))?;
+        if !r.memo.is_empty() {
+            writeln!(w, "M{}", single_line(&r.memo))?;
+        }
+        if let Some(category) = &r.category {
+            writeln!(w, "L{}", single_line(category))?;
+        }
+        writeln!(w, "^")?;
+    }
+
+    w.flush()?;
+
+    Ok(())
+}
+
+/// QIF fields are line based, so newlines would start a new field
+fn single_line(s: &str) -> String {
+    s.replace(['\r', '\n'], " ")
+}
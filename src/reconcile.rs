@@ -0,0 +1,151 @@
+//! Comparison of the transactions about to be uploaded with the ones already
+//! in the YNAB account, shown by `upload --dry-run`.
+//!
+//! A converted transaction is already in YNAB if a transaction there has its
+//! import id, i.e. it was uploaded or imported before, or else the same date
+//! and amount, e.g. when it was entered by hand. The transactions in YNAB
+//! that match none of the converted ones, from the date of the oldest one
+//! on, are listed too, as they may be missing from the export or be
+//! mistakes in the budget.
+
+use crate::{amount::Amount, parse_nda_date, YnabRow};
+use chrono::NaiveDate;
+use std::fmt::Write;
+
+/// A transaction in a YNAB account
+#[derive(Clone, Debug, PartialEq)]
+pub struct Existing {
+    pub date: NaiveDate,
+    pub amount: Amount,
+    pub payee: String,
+    pub import_id: Option<String>,
+}
+
+/// How a converted transaction relates to the ones in YNAB
+#[derive(Clone, Debug, PartialEq)]
+pub enum Status {
+    /// Not in YNAB, would be created
+    New,
+    /// In YNAB with the same import id, would be skipped by YNAB
+    Uploaded,
+    /// In YNAB with the same date and amount but another import id, e.g.
+    /// entered by hand, so it would likely be created twice
+    Matched(String),
+}
+
+#[derive(Debug)]
+pub struct Preview<'a> {
+    pub rows: Vec<(&'a YnabRow, Status)>,
+    /// Transactions in YNAB not matching any converted one
+    pub only_in_ynab: Vec<Existing>,
+}
+
+/// Compares `rows`, which have the YNAB import ids `import_ids`, with the
+/// transactions `existing` in the YNAB account.
+pub fn preview<'a>(
+    rows: &'a [YnabRow],
+    import_ids: &[String],
+    existing: &[Existing],
+) -> Preview<'a> {
+    let mut used = vec![false; existing.len()];
+    let mut statuses: Vec<Option<Status>> = vec![None; rows.len()];
+
+    // Import ids first, so that matching by date and amount does not take a
+    // transaction that has the import id of another row
+    for (status, import_id) in statuses.iter_mut().zip(import_ids) {
+        let found = existing
+            .iter()
+            .zip(&used)
+            .position(|(e, used)| !used && e.import_id.as_ref() == Some(import_id));
+        if let Some(i) = found {
+            used[i] = true;
+            *status = Some(Status::Uploaded);
+        }
+    }
+    for (status, r) in statuses.iter_mut().zip(rows).filter(|(s, _)| s.is_none()) {
+        let date = parse_nda_date(&r.date).ok();
+        let found = existing
+            .iter()
+            .zip(&used)
+            .position(|(e, used)| !used && Some(e.date) == date && e.amount == r.amount);
+        *status = Some(match found {
+            Some(i) => {
+                used[i] = true;
+                Status::Matched(existing[i].payee.clone())
+            }
+            None => Status::New,
+        });
+    }
+
+    let since = rows
+        .iter()
+        .filter_map(|r| parse_nda_date(&r.date).ok())
+        .min();
+    Preview {
+        rows: rows
+            .iter()
+            .zip(statuses)
+            .map(|(r, status)| (r, status.unwrap_or(Status::New)))
+            .collect(),
+        only_in_ynab: existing
+            .iter()
+            .zip(used)
+            .filter(|(e, used)| !used && since.is_some_and(|since| e.date >= since))
+            .map(|(e, _)| e.clone())
+            .collect(),
+    }
+}
+
+/// Formats the preview of account `label` for reading.
+pub fn report(preview: &Preview, label: &str) -> String {
+    let width = preview
+        .rows
+        .iter()
+        .map(|(r, _)| r.payee.chars().count())
+        .chain(preview.only_in_ynab.iter().map(|e| e.payee.chars().count()))
+        .max()
+        .unwrap_or(0);
+
+    let mut out = format!("{} compared with YNAB:\n", label);
+    let mut line = |marker: &str, date: &str, payee: &str, amount: Amount, note: &str| {
+        writeln!(
+            out,
+            "  {} {}  {:<width$}  {:>10}  {}",
+            marker,
+            date,
+            payee,
+            amount.to_string(),
+            note
+        )
+        .unwrap();
+    };
+    for (r, status) in &preview.rows {
+        let date = parse_nda_date(&r.date).map_or_else(|_| r.date.clone(), |d| d.to_string());
+        match status {
+            Status::New => line("+", &date, &r.payee, r.amount, "new"),
+            Status::Uploaded => line("=", &date, &r.payee, r.amount, "already in YNAB"),
+            Status::Matched(payee) => line(
+                "~",
+                &date,
+                &r.payee,
+                r.amount,
+                &format!("same date and amount as \"{}\" in YNAB", payee),
+            ),
+        }
+    }
+    for e in &preview.only_in_ynab {
+        line("-", &e.date.to_string(), &e.payee, e.amount, "only in YNAB");
+    }
+
+    let count = |f: fn(&Status) -> bool| preview.rows.iter().filter(|(_, s)| f(s)).count();
+    writeln!(
+        out,
+        "{} new, {} already in YNAB, {} matching by date and amount, {} only in YNAB (dry run).",
+        count(|s| *s == Status::New),
+        count(|s| *s == Status::Uploaded),
+        count(|s| matches!(s, Status::Matched(_))),
+        preview.only_in_ynab.len()
+    )
+    .unwrap();
+    out
+}
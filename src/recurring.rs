@@ -0,0 +1,117 @@
+//! Detection of recurring payments, such as subscriptions, for setting up
+//! scheduled transactions and spotting forgotten ones.
+//!
+//! Outflows to the same payee are taken to recur monthly when there are at
+//! least [`MIN_OCCURRENCES`] of them with amounts close to their median, and
+//! the median interval between them is about a month. The more history the
+//! converted transactions cover, e.g. with `--backfill`, the more is found.
+
+use crate::{amount::Amount, parse_nda_date, YnabRow};
+use chrono::{Duration, NaiveDate};
+use std::{collections::BTreeMap, fmt::Write, ops::RangeInclusive};
+
+/// Number of payments needed to call them recurring
+pub const MIN_OCCURRENCES: usize = 3;
+
+/// Median numbers of days between payments taken as monthly
+const MONTHLY_DAYS: RangeInclusive<i64> = 26..=35;
+
+/// How far from the median amount a payment may be, in percent, to count as
+/// the same recurring payment
+const AMOUNT_TOLERANCE_PERCENT: i64 = 25;
+
+#[derive(Debug, PartialEq)]
+pub struct Recurring {
+    pub payee: String,
+    pub count: usize,
+    pub average: Amount,
+    pub last: NaiveDate,
+    /// Expected date of the next payment
+    pub next: NaiveDate,
+}
+
+/// Returns the likely recurring payments among `rows`, largest first.
+pub fn detect(rows: &[YnabRow]) -> Vec<Recurring> {
+    let mut by_payee: BTreeMap<String, Vec<(NaiveDate, i64)>> = BTreeMap::new();
+    for r in rows.iter().filter(|r| r.amount.is_negative()) {
+        if let Ok(date) = parse_nda_date(&r.date) {
+            by_payee
+                .entry(r.payee.trim().to_string())
+                .or_default()
+                .push((date, r.amount.cents()));
+        }
+    }
+
+    let mut recurring: Vec<Recurring> = by_payee
+        .into_iter()
+        .filter_map(|(payee, payments)| monthly(payee, payments))
+        .collect();
+    recurring.sort_by_key(|r| (r.average.cents(), r.payee.clone()));
+    recurring
+}
+
+fn monthly(payee: String, mut payments: Vec<(NaiveDate, i64)>) -> Option<Recurring> {
+    if payments.len() < MIN_OCCURRENCES {
+        return None;
+    }
+
+    let median_amount = median(payments.iter().map(|(_, cents)| *cents).collect())?;
+    let tolerance = median_amount.abs() * AMOUNT_TOLERANCE_PERCENT / 100;
+    payments.retain(|(_, cents)| (cents - median_amount).abs() <= tolerance);
+    if payments.len() < MIN_OCCURRENCES {
+        return None;
+    }
+
+    payments.sort();
+    let intervals = payments
+        .windows(2)
+        .map(|w| (w[1].0 - w[0].0).num_days())
+        .collect();
+    let interval = median(intervals)?;
+    if !MONTHLY_DAYS.contains(&interval) {
+        return None;
+    }
+
+    let count = payments.len();
+    let total: i64 = payments.iter().map(|(_, cents)| cents).sum();
+    let last = payments.last()?.0;
+    Some(Recurring {
+        payee,
+        count,
+        average: Amount::from_cents(total / count as i64),
+        last,
+        next: last + Duration::days(interval),
+    })
+}
+
+fn median(mut values: Vec<i64>) -> Option<i64> {
+    values.sort_unstable();
+    values.get(values.len() / 2).copied()
+}
+
+/// Formats the recurring payments of account `iban` for reading.
+pub fn report(recurring: &[Recurring], iban: &str) -> String {
+    if recurring.is_empty() {
+        return format!("{}: no recurring payments found\n", iban);
+    }
+
+    let mut out = format!("{}: {} recurring payment(s)\n", iban, recurring.len());
+    let width = recurring
+        .iter()
+        .map(|r| r.payee.chars().count())
+        .max()
+        .unwrap_or(0);
+    for r in recurring {
+        writeln!(
+            out,
+            "  {:<width$}  {:>10} monthly, {} times, last {}, next around {}",
+            r.payee,
+            r.average.to_string(),
+            r.count,
+            r.last,
+            r.next
+        )
+        .unwrap();
+    }
+    out
+}
@@ -0,0 +1,208 @@
+//! Run summaries, warnings and exit codes for scripted use.
+
+use crate::{
+    amount::Amount,
+    console::{self, Style},
+    json::Value,
+    log, Transaction, YnabRow,
+};
+use std::{error::Error, fmt, mem, sync::Mutex};
+
+/// Exit code for errors that have no more specific exit code.
+pub const EXIT_ERROR: i32 = 1;
+/// Exit code when nothing new was found, only used with `--json`.
+pub const EXIT_NOTHING_NEW: i32 = 5;
+/// Exit code when the most recent export of an account is older than
+/// `--max-export-age`, only used with `--json`.
+pub const EXIT_STALE_EXPORT: i32 = 9;
+
+static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Prints a warning unless `--quiet` is given, and records it for the run
+/// summary.
+pub fn warn(message: impl Into<String>) {
+    let message = message.into();
+    if log::enabled(log::Level::Warn) {
+        eprintln!("{} {}", console::paint(Style::Warning, "Warning:"), message);
+    }
+    WARNINGS.lock().unwrap().push(message);
+}
+
+/// Returns the warnings recorded so far and clears them.
+pub fn take_warnings() -> Vec<String> {
+    mem::take(&mut *WARNINGS.lock().unwrap())
+}
+
+/// Returns the recap of `warnings` printed at the end of a run, with the
+/// first line of each, or `None` if there were none.
+pub fn recap(warnings: &[String]) -> Option<String> {
+    if warnings.is_empty() {
+        return None;
+    }
+    let mut out = format!("{} warning(s)", warnings.len());
+    for warning in warnings {
+        out += "\n  ";
+        out += &console::paint(Style::Warning, "-");
+        out += " ";
+        out += warning.lines().next().unwrap_or_default();
+    }
+    Some(out)
+}
+
+/// Errors that scripts may want to tell apart by their exit code.
+#[derive(Debug)]
+pub enum RunError {
+    NoFilesFound,
+    NonOverlapping,
+    /// The newest export starts after the previous one ends
+    Gap,
+    /// Another run holds the lock
+    Locked,
+    /// Some accounts could not be converted, the others were
+    AccountsFailed {
+        failed: usize,
+        total: usize,
+    },
+}
+
+impl RunError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RunError::NoFilesFound => 3,
+            RunError::NonOverlapping => 4,
+            RunError::Gap => 6,
+            RunError::Locked => 7,
+            RunError::AccountsFailed { .. } => 8,
+        }
+    }
+
+    /// Identifier of the error in summaries, e.g. "non_overlapping"
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RunError::NoFilesFound => "no_files_found",
+            RunError::NonOverlapping => "non_overlapping",
+            RunError::Gap => "gap",
+            RunError::Locked => "locked",
+            RunError::AccountsFailed { .. } => "accounts_failed",
+        }
+    }
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunError::NoFilesFound => write!(f, "Could not find any matching files"),
+            RunError::NonOverlapping => write!(
+                f,
+                "Aborting due to non-overlapping transactions in main and previous CSV files."
+            ),
+            RunError::Gap => write!(
+                f,
+                "Aborting due to a gap between the main and previous CSV files, use --allow-gap to convert anyway."
+            ),
+            RunError::Locked => write!(
+                f,
+                "Another nda2ynab run is in progress, use --wait-for-lock to wait for it to finish."
+            ),
+            RunError::AccountsFailed { failed, total } => write!(
+                f,
+                "{} of {} account(s) could not be converted, the others were written.",
+                failed, total
+            ),
+        }
+    }
+}
+
+impl Error for RunError {}
+
+/// Returns the exit code for an error returned by a run.
+pub fn exit_code(error: &(dyn Error + 'static)) -> i32 {
+    error
+        .downcast_ref::<RunError>()
+        .map_or(EXIT_ERROR, RunError::exit_code)
+}
+
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub files_considered: Vec<String>,
+    pub accounts: Vec<AccountSummary>,
+}
+
+#[derive(Debug, Default)]
+pub struct AccountSummary {
+    pub iban: String,
+    /// Name of the account in the config file
+    pub name: Option<String>,
+    pub file: String,
+    pub previous_file: Option<String>,
+    /// Transactions left out because they were already processed
+    pub transactions_skipped: usize,
+    /// Transactions left out by ignore rules
+    pub transactions_ignored: usize,
+    pub transactions_written: usize,
+    /// Balance at the end of the export, if it has a balance column
+    pub balance: Option<Amount>,
+    /// The export had no transactions, so nothing is written for it unless
+    /// `--allow-empty` is given
+    pub empty_export: bool,
+    /// Age of the most recent export in days, if it is older than
+    /// `--max-export-age`
+    pub stale_export_days: Option<i64>,
+    /// Output file, "ynab" for uploads, or `None` for stdout and dry runs
+    pub output: Option<String>,
+    /// YNAB ids of the transactions created by an upload
+    pub uploaded_ids: Vec<String>,
+    /// Why the account could not be exported, if the run continued with the
+    /// other accounts
+    pub error: Option<String>,
+    /// The transactions written, kept for the HTML report
+    pub rows: Vec<YnabRow>,
+    /// The transactions left out as already processed or outside the date
+    /// range, kept for the HTML report
+    pub skipped_rows: Vec<Transaction>,
+}
+
+impl Summary {
+    pub fn nothing_new(&self) -> bool {
+        self.accounts.iter().all(|a| a.transactions_written == 0)
+    }
+
+    /// Returns whether the most recent export of some account is stale.
+    pub fn stale_export(&self) -> bool {
+        self.accounts.iter().any(|a| a.stale_export_days.is_some())
+    }
+
+    pub fn to_json(&self, warnings: Vec<String>, error: Option<&(dyn Error + 'static)>) -> Value {
+        let accounts = self
+            .accounts
+            .iter()
+            .map(|a| {
+                Value::object([
+                    ("iban", a.iban.as_str().into()),
+                    ("name", a.name.clone().into()),
+                    ("file", a.file.as_str().into()),
+                    ("previous_file", a.previous_file.clone().into()),
+                    ("transactions_skipped", a.transactions_skipped.into()),
+                    ("transactions_ignored", a.transactions_ignored.into()),
+                    ("transactions_written", a.transactions_written.into()),
+                    ("balance", a.balance.map(|b| b.to_string()).into()),
+                    ("stale_export_days", a.stale_export_days.into()),
+                    ("output", a.output.clone().into()),
+                    ("error", a.error.clone().into()),
+                ])
+            })
+            .collect();
+
+        let error = error.map_or(Value::Null, |e| {
+            let kind = e.downcast_ref::<RunError>().map_or("error", RunError::kind);
+            Value::object([("kind", kind.into()), ("message", e.to_string().into())])
+        });
+
+        Value::object([
+            ("files_considered", self.files_considered.clone().into()),
+            ("accounts", Value::Array(accounts)),
+            ("warnings", warnings.into()),
+            ("error", error),
+        ])
+    }
+}
@@ -0,0 +1,212 @@
+//! Reports on transactions with `nda2ynab report`, for insight into the
+//! exports before they are imported into YNAB.
+
+use clap::ArgEnum;
+use itertools::Itertools;
+use nda2ynab::{
+    atomic,
+    config::Config,
+    encoding::Encoding,
+    explicit_export_files,
+    history::History,
+    info,
+    parser::Registry,
+    remove_ignored,
+    rules::Rules,
+    spending,
+    state::State,
+    stitch::{self, Export},
+    system::{Clock, Disk},
+    to_ynab_rows, DateField, YnabRow, OUTPUT_DATE_FORMAT,
+};
+use std::{error::Error, io::Write, path::PathBuf};
+
+#[derive(clap::Subcommand, Debug)]
+pub enum ReportCommand {
+    /// Sum the transactions of exports, or of the transaction history, per
+    /// payee and month
+    Spending(SpendingArgs),
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, Default)]
+pub enum ReportFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct SpendingArgs {
+    /// Exports to sum up; overlapping exports of an account are merged so
+    /// that each transaction is counted once
+    #[clap(required_unless_present = "history")]
+    files: Vec<PathBuf>,
+
+    /// Sum up the transaction history of every account instead (see
+    /// --state-backend)
+    #[clap(long, conflicts_with = "files")]
+    history: bool,
+
+    /// Only include transactions booked on or after this date, e.g.
+    /// 2024-01-01
+    #[clap(long, value_name = "DATE")]
+    since: Option<chrono::NaiveDate>,
+
+    /// File to write the summary to [default: stdout]
+    #[clap(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Format of the summary [default: csv]
+    #[clap(short, long, arg_enum)]
+    format: Option<ReportFormat>,
+
+    /// Path to a TOML file with payee cleanup rules, applied after the
+    /// built-in rules [default: rules from the config file]
+    #[clap(short, long)]
+    rules: Option<PathBuf>,
+
+    /// Do not apply the built-in payee cleanup rules
+    #[clap(long)]
+    no_default_rules: bool,
+
+    /// Apply the bundled merchant list too, like convert --enrich
+    #[clap(long)]
+    enrich: bool,
+
+    /// Text encoding of the exports [default: auto]
+    #[clap(short, long, arg_enum)]
+    encoding: Option<Encoding>,
+
+    /// Path to the state file, next to which the transaction history is
+    /// [default: ~/.local/state/nda2ynab/state.json]
+    #[clap(long, value_name = "FILE")]
+    state: Option<PathBuf>,
+}
+
+pub fn run(
+    clock: &dyn Clock,
+    command: &ReportCommand,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    match command {
+        ReportCommand::Spending(args) => spending_report(clock, args, config),
+    }
+}
+
+fn spending_report(
+    clock: &dyn Clock,
+    args: &SpendingArgs,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    let rules_path = args.rules.as_ref().or(config.rules.as_ref());
+    let rules = Rules::load(
+        rules_path.map(PathBuf::as_path),
+        args.no_default_rules,
+        args.enrich || config.enrich,
+    )?;
+
+    let mut rows = if args.history {
+        history_rows(args, config, &rules)?
+    } else {
+        export_rows(clock, args, config, &rules)?
+    };
+    if let Some(since) = args.since {
+        let since = since.format(OUTPUT_DATE_FORMAT).to_string();
+        rows.retain(|r| r.date >= since);
+    }
+
+    let summary = spending::summarize(&rows);
+    let contents = match args.format.unwrap_or_default() {
+        ReportFormat::Csv => spending::to_csv(&summary)?,
+        ReportFormat::Json => spending::to_json(&summary).to_pretty_string() + "\n",
+    };
+    match &args.output {
+        Some(path) => {
+            atomic::write(path, contents.as_bytes())
+                .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+            info!(
+                "Spending of {} transaction(s) written to {}.",
+                rows.len(),
+                path.display()
+            );
+        }
+        None => std::io::stdout().lock().write_all(contents.as_bytes())?,
+    }
+    Ok(())
+}
+
+/// The transactions of the exports, each once, with the payee rules applied
+/// and ignored transactions left out as when converting.
+fn export_rows(
+    clock: &dyn Clock,
+    args: &SpendingArgs,
+    config: &Config,
+    rules: &Rules,
+) -> Result<Vec<YnabRow>, Box<dyn Error>> {
+    let registry = Registry::builtin(None, clock);
+    let encoding = args.encoding.unwrap_or(Encoding::Auto);
+    let mut files = vec![];
+    for path in &args.files {
+        files.push(explicit_export_files(&Disk, path, None, &registry, encoding)?.remove(0));
+    }
+
+    let mut rows = vec![];
+    for (iban, files) in files.iter().into_group_map_by(|f| f.iban.clone()) {
+        let mut exports = vec![];
+        for file in files {
+            let parsed = registry.read(&Disk, file.bank, &file.path, encoding)?;
+            exports.push(Export {
+                file_name: &file.file_name,
+                rows: parsed
+                    .transactions
+                    .into_iter()
+                    .filter(|t| !t.pending)
+                    .collect(),
+            });
+        }
+        let timeline = stitch::stitch(exports, true);
+        let invert = config.invert_amounts(&iban);
+        let kept = remove_ignored(timeline.rows, rules, invert).rows;
+        rows.extend(to_ynab_rows(kept, rules, "", invert, DateField::Booking));
+    }
+    Ok(rows)
+}
+
+/// The transactions of the transaction history, with the payee rules
+/// applied and ignored transactions left out as when converting.
+fn history_rows(
+    args: &SpendingArgs,
+    config: &Config,
+    rules: &Rules,
+) -> Result<Vec<YnabRow>, Box<dyn Error>> {
+    let state_path = args
+        .state
+        .clone()
+        .or_else(|| config.state.clone())
+        .or_else(State::default_path)
+        .ok_or("Could not determine the state file location, use --state")?;
+    let path = History::path_for(&state_path);
+    if !path.exists() {
+        return Err(format!(
+            "There is no transaction history at {}, convert with --state-backend sqlite first",
+            path.display()
+        )
+        .into());
+    }
+    let since = args
+        .since
+        .unwrap_or_else(|| chrono::NaiveDate::from_ymd(1900, 1, 1));
+    let entries = History::open(&path)?.since(since)?;
+
+    let mut rows = vec![];
+    for (iban, entries) in entries.into_iter().into_group_map_by(|e| e.iban.clone()) {
+        let invert = config.invert_amounts(&iban);
+        let transactions = entries
+            .iter()
+            .map(|e| e.transaction())
+            .collect::<Result<Vec<_>, _>>()?;
+        let kept = remove_ignored(transactions, rules, invert).rows;
+        rows.extend(to_ynab_rows(kept, rules, "", invert, DateField::Booking));
+    }
+    Ok(rows)
+}
@@ -0,0 +1,118 @@
+//! Interactive review of transactions before they are exported.
+//!
+//! The transactions are listed with row numbers and can be excluded or
+//! edited with short commands read from standard input, one per line.
+//! Everything is printed to stderr so that `--stdout` output stays clean.
+
+use nda2ynab::YnabRow;
+use std::{
+    error::Error,
+    io::{self, BufRead, Write},
+};
+
+const HELP: &str = "\
+Commands:
+  x <n>...        exclude or include rows
+  p <n> <payee>   set payee of row
+  m <n> <memo>    set memo of row
+  l               list rows
+  w               write the included rows
+  q               quit without writing";
+
+/// Lets the user review `rows`. Returns the rows to export, or `None` if the
+/// user chose not to export anything.
+pub fn review(rows: Vec<YnabRow>) -> Result<Option<Vec<YnabRow>>, Box<dyn Error>> {
+    let mut rows = rows;
+    let mut included = vec![true; rows.len()];
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    list(&rows, &included);
+    eprintln!("\n{}", HELP);
+
+    loop {
+        eprint!("\n> ");
+        io::stderr().flush()?;
+
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => return Ok(None),
+        };
+        let line = line.trim();
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+
+        match command {
+            "x" => {
+                for n in rest.split_whitespace() {
+                    match row_index(n, rows.len()) {
+                        Some(i) => included[i] = !included[i],
+                        None => eprintln!("Invalid row number: {}", n),
+                    }
+                }
+                list(&rows, &included);
+            }
+            "p" | "m" => {
+                let (n, value) = rest.split_once(' ').unwrap_or((rest, ""));
+                match row_index(n, rows.len()) {
+                    Some(i) if command == "p" => rows[i].payee = value.trim().to_string(),
+                    Some(i) => rows[i].memo = value.trim().to_string(),
+                    None => eprintln!("Invalid row number: {}", n),
+                }
+                list(&rows, &included);
+            }
+            "l" => list(&rows, &included),
+            "w" => {
+                let rows = rows
+                    .into_iter()
+                    .zip(included)
+                    .filter_map(|(r, included)| included.then_some(r))
+                    .collect();
+                return Ok(Some(rows));
+            }
+            "q" => return Ok(None),
+            "" => {}
+            _ => eprintln!("{}", HELP),
+        }
+    }
+}
+
+/// Asks a yes or no question on stderr, answered no unless the answer
+/// starts with y.
+pub fn confirm(question: &str) -> Result<bool, Box<dyn Error>> {
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(answer.trim_start().to_lowercase().starts_with('y'))
+}
+
+/// Parses a 1-based row number
+fn row_index(n: &str, len: usize) -> Option<usize> {
+    n.parse::<usize>()
+        .ok()
+        .filter(|&n| n >= 1 && n <= len)
+        .map(|n| n - 1)
+}
+
+fn list(rows: &[YnabRow], included: &[bool]) {
+    let payee_w = rows
+        .iter()
+        .map(|r| r.payee.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    eprintln!();
+    for (i, (r, included)) in rows.iter().zip(included).enumerate() {
+        let line = format!(
+            "{} {:>3}  {}  {:<payee_w$}  {:>10}  {}",
+            if *included { " " } else { "x" },
+            i + 1,
+            r.date,
+            r.payee,
+            r.amount.to_string(),
+            r.memo
+        );
+        eprintln!("{}", line.trim_end());
+    }
+}
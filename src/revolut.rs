@@ -0,0 +1,232 @@
+//! Revolut account statement CSV exports.
+//!
+//! Statements are downloaded from the app as files named like
+//! `account-statement_2024-01-01_2024-01-31_en-us_a1b2c3.csv`, which do not
+//! say which account they belong to. Each currency of a Revolut account is
+//! converted as an account of its own, named like `Revolut EUR` after the
+//! currency of the statement's rows. The contents are comma separated with
+//! ISO dates and times, signed amounts with a decimal point and fees in a
+//! separate column, listed oldest first.
+
+use crate::{
+    amount::Amount,
+    parser::{source_columns, BankParser, FileInfo, RowError, Rows},
+    RawTransaction, Transaction,
+};
+use chrono::NaiveDateTime;
+use csv::{ReaderBuilder, StringRecord};
+use std::{error::Error, path::Path};
+
+const STARTED: &str = "Started Date";
+const COMPLETED: &str = "Completed Date";
+const DESCRIPTION: &str = "Description";
+const AMOUNT: &str = "Amount";
+const FEE: &str = "Fee";
+const CURRENCY: &str = "Currency";
+const STATE: &str = "State";
+
+/// States of rows that did not move any money
+const CANCELLED_STATES: &[&str] = &["DECLINED", "REVERTED", "FAILED"];
+
+/// Returns the name of the Revolut account in `currency`.
+fn account(currency: &str) -> String {
+    format!("Revolut {}", currency.trim().to_uppercase())
+}
+
+pub struct Revolut;
+
+impl Revolut {
+    fn reader(contents: &str) -> csv::Reader<&[u8]> {
+        ReaderBuilder::new()
+            .delimiter(b',')
+            .flexible(true)
+            .from_reader(contents.as_bytes())
+    }
+}
+
+/// Positions of the columns we read in the header row
+struct Columns {
+    header: StringRecord,
+    /// Number of columns
+    len: usize,
+    started: usize,
+    completed: usize,
+    description: usize,
+    amount: usize,
+    fee: Option<usize>,
+    currency: usize,
+    state: usize,
+}
+
+impl Columns {
+    fn new(header: &StringRecord) -> Option<Columns> {
+        let position = |name: &str| header.iter().position(|h| h.trim() == name);
+        Some(Columns {
+            header: header.clone(),
+            len: header.len(),
+            started: position(STARTED)?,
+            completed: position(COMPLETED)?,
+            description: position(DESCRIPTION)?,
+            amount: position(AMOUNT)?,
+            fee: position(FEE),
+            currency: position(CURRENCY)?,
+            state: position(STATE)?,
+        })
+    }
+
+    fn field<'r>(&self, record: &'r StringRecord, i: usize) -> Result<&'r str, String> {
+        record
+            .get(i)
+            .map(str::trim)
+            .ok_or_else(|| format!("expected {} fields, found {}", self.len, record.len()))
+    }
+
+    /// Returns whether a row was declined or reverted, and so is not a
+    /// transaction.
+    fn cancelled(&self, record: &StringRecord) -> bool {
+        record
+            .get(self.state)
+            .is_some_and(|state| CANCELLED_STATES.contains(&state.trim()))
+    }
+
+    /// Time the row was completed, or started if it is still pending
+    fn time(&self, record: &StringRecord) -> Option<NaiveDateTime> {
+        [self.completed, self.started]
+            .into_iter()
+            .filter_map(|i| record.get(i))
+            .find_map(|t| NaiveDateTime::parse_from_str(t.trim(), "%Y-%m-%d %H:%M:%S").ok())
+    }
+
+    fn transaction(&self, record: &StringRecord, currency: &str) -> Result<Transaction, String> {
+        let row_currency = self.field(record, self.currency)?;
+        if !row_currency.eq_ignore_ascii_case(currency) {
+            return Err(format!(
+                "transaction in {} in a statement in {}, download a statement of each currency separately",
+                row_currency, currency
+            ));
+        }
+
+        let pending = self.field(record, self.state)? == "PENDING";
+        let completed = self.field(record, self.completed)?;
+        let date = match completed {
+            "" => self.field(record, self.started)?,
+            completed => completed,
+        };
+        let date = date.split(' ').next().unwrap_or_default().to_string();
+
+        // Fees are charged on top of the amount
+        let amount: Amount = self.field(record, self.amount)?.parse()?;
+        let fee: Amount = match self.fee.and_then(|i| record.get(i)).map(str::trim) {
+            Some("") | None => Amount::default(),
+            Some(fee) => fee.parse()?,
+        };
+        let message = if fee.cents() == 0 {
+            String::new()
+        } else {
+            format!("Fee {} {}", fee, currency)
+        };
+
+        RawTransaction {
+            date,
+            amount: Amount::from_cents(amount.cents() - fee.cents()).to_string(),
+            description: self.field(record, self.description)?.to_string(),
+            reference: String::new(),
+            message,
+            counterparty_account: String::new(),
+            value_date: String::new(),
+            payment_date: String::new(),
+            balance: String::new(),
+            currency: currency.to_string(),
+            original_amount: String::new(),
+            original_currency: String::new(),
+            pending,
+            columns: source_columns(&self.header, record),
+        }
+        .normalize()
+    }
+}
+
+impl BankParser for Revolut {
+    fn name(&self) -> &'static str {
+        "revolut"
+    }
+
+    fn parse_file_name(&self, _file_name: &str) -> Option<FileInfo> {
+        None
+    }
+
+    fn inspects(&self, file_name: &str) -> bool {
+        let file_name = file_name.to_lowercase();
+        file_name.starts_with("account-statement") && file_name.ends_with(".csv")
+    }
+
+    fn parse_file_info(&self, contents: &str) -> Option<FileInfo> {
+        let mut rdr = Revolut::reader(contents);
+        let columns = Columns::new(rdr.headers().ok()?)?;
+        let records: Vec<StringRecord> = rdr
+            .into_records()
+            .filter_map(Result::ok)
+            .filter(|r| !columns.cancelled(r))
+            .collect();
+
+        Some(FileInfo {
+            iban: account(records.first()?.get(columns.currency)?),
+            date: records.iter().filter_map(|r| columns.time(r)).max()?,
+        })
+    }
+
+    fn detect(&self, contents: &str) -> bool {
+        match Revolut::reader(contents).headers() {
+            Ok(header) => Columns::new(header).is_some(),
+            Err(_) => false,
+        }
+    }
+
+    fn rows<'a>(&'a self, contents: &'a str, path: &Path) -> Result<Rows<'a>, Box<dyn Error>> {
+        let mut rdr = Revolut::reader(contents);
+        let columns = Columns::new(rdr.headers()?).ok_or_else(|| {
+            format!(
+                "{} is missing the {}, {}, {}, {} or {} column",
+                path.display(),
+                COMPLETED,
+                DESCRIPTION,
+                AMOUNT,
+                CURRENCY,
+                STATE
+            )
+        })?;
+
+        let records: Vec<_> = rdr
+            .into_records()
+            .filter(|result| !result.as_ref().is_ok_and(|r| columns.cancelled(r)))
+            .collect();
+        // The statement is in the currency of its first row
+        let currency = records
+            .iter()
+            .filter_map(|r| r.as_ref().ok())
+            .find_map(|r| r.get(columns.currency))
+            .unwrap_or_default()
+            .trim()
+            .to_uppercase();
+
+        // Rows are listed oldest first, so they are collected to return them
+        // most recent first
+        let mut rows: Vec<_> = records
+            .into_iter()
+            .map(|result| {
+                let line = match &result {
+                    Ok(record) => record.position(),
+                    Err(e) => e.position(),
+                }
+                .map_or(0, |p| p.line());
+                result
+                    .map_err(|e| e.to_string())
+                    .and_then(|record| columns.transaction(&record, &currency))
+                    .map_err(|message| RowError { line, message })
+            })
+            .collect();
+        rows.reverse();
+
+        Ok(Box::new(rows.into_iter()))
+    }
+}
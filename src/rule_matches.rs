@@ -0,0 +1,145 @@
+//! Trying out rules with `nda2ynab rules test`, which shows which rules match
+//! each row of an export without writing anything.
+
+use nda2ynab::{
+    config::Config,
+    encoding::Encoding,
+    explicit_export_files, info,
+    locale::Locale,
+    mapped::{InputMap, Mapped},
+    nordea,
+    parser::Registry,
+    rules::Rules,
+    system::{Clock, Disk},
+    OUTPUT_DATE_FORMAT,
+};
+use std::{error::Error, path::PathBuf};
+
+#[derive(clap::Subcommand, Debug)]
+pub enum RulesCommand {
+    /// Show the payee, category and the rules matching each row of an
+    /// export, without writing anything
+    Test(TestArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct TestArgs {
+    /// The export to apply the rules to
+    file: PathBuf,
+
+    /// Path to a TOML file with payee cleanup rules, applied after the
+    /// built-in rules [default: rules from the config file]
+    #[clap(short, long)]
+    rules: Option<PathBuf>,
+
+    /// Do not apply the built-in payee cleanup rules
+    #[clap(long)]
+    no_default_rules: bool,
+
+    /// Apply the bundled merchant list too, like convert --enrich
+    #[clap(long)]
+    enrich: bool,
+
+    /// Text encoding of the export [default: auto]
+    #[clap(short, long, arg_enum)]
+    encoding: Option<Encoding>,
+
+    /// Locale of the Nordea export, detected from the file if not given
+    #[clap(short, long, arg_enum)]
+    locale: Option<Locale>,
+
+    /// Read an export of another bank by declaring its columns, like
+    /// convert --input-map
+    #[clap(long, value_name = "MAP")]
+    input_map: Option<String>,
+}
+
+pub fn run(
+    clock: &dyn Clock,
+    command: &RulesCommand,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    match command {
+        RulesCommand::Test(args) => test(clock, args, config),
+    }
+}
+
+fn test(clock: &dyn Clock, args: &TestArgs, config: &Config) -> Result<(), Box<dyn Error>> {
+    let rules_path = args.rules.as_ref().or(config.rules.as_ref());
+    let rules = Rules::load(
+        rules_path.map(PathBuf::as_path),
+        args.no_default_rules,
+        args.enrich || config.enrich,
+    )?;
+
+    // Recognises the same exports as a conversion with the same config
+    let patterns = nordea::file_name_patterns(&config.file_name_patterns)?;
+    let mut registry =
+        Registry::with_file_name_patterns(args.locale.or(config.locale), patterns, clock)
+            .with_timezone(config.timezone.unwrap_or_default());
+    if let Some(map) = args.input_map.as_ref().or(config.input_map.as_ref()) {
+        registry.register(Box::new(Mapped::new(InputMap::load(map)?)));
+    }
+    let encoding = args.encoding.or(config.encoding).unwrap_or(Encoding::Auto);
+    let file = explicit_export_files(&Disk, &args.file, None, &registry, encoding)?.remove(0);
+    let parsed = registry.read(&Disk, file.bank, &file.path, encoding)?;
+    let invert = config.invert_amounts(&file.iban);
+
+    // Date, amount, description, payee, category and the rules matched
+    let mut table = vec![[
+        "Date".to_string(),
+        "Amount".to_string(),
+        "Description".to_string(),
+        "Payee".to_string(),
+        "Category".to_string(),
+        "Rules".to_string(),
+    ]];
+    let (mut matched, mut ignored) = (0, 0);
+    for t in parsed.transactions.iter().filter(|t| !t.pending) {
+        let amount = if invert { -t.amount } else { t.amount };
+        let trace = rules.trace(&t.description, &t.message, amount);
+        let applied = trace.rules();
+        matched += usize::from(!applied.is_empty());
+        ignored += usize::from(trace.ignored.is_some());
+        table.push([
+            t.date.format(OUTPUT_DATE_FORMAT).to_string(),
+            amount.to_string(),
+            t.description.clone(),
+            trace.payee,
+            trace.category.unwrap_or_default(),
+            applied.join(", "),
+        ]);
+    }
+
+    let widths: Vec<usize> = (0..6)
+        .map(|i| {
+            table
+                .iter()
+                .map(|r| r[i].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    for row in &table {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(i, (cell, &width))| match i {
+                1 => format!("{:>width$}", cell),
+                _ => format!("{:<width$}", cell),
+            })
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    }
+
+    info!(
+        "\n{} row(s) of {}, {} matched by a rule, {} ignored.",
+        table.len() - 1,
+        file.file_name,
+        matched,
+        ignored
+    );
+    Ok(())
+}
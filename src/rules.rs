@@ -0,0 +1,899 @@
+//! User-configurable rules applied to transactions during conversion.
+//!
+//! Rules are read from a TOML file, for example:
+//!
+//! ```toml
+//! [[payee]]
+//! pattern = '^K-MARKET.*'
+//! replacement = 'K-Market'
+//!
+//! [[category]]
+//! pattern = '(?i)k-market|prisma|lidl'
+//! category = 'Groceries'
+//!
+//! [[split]]
+//! pattern = '(?i)landlord'
+//! parts = [
+//!     { memo = 'Rent', category = 'Rent', amount = '800.00' },
+//!     { memo = 'Water', category = 'Utilities', percent = 5 },
+//!     { memo = 'Other' },
+//! ]
+//!
+//! [[cashback]]
+//! pattern = '(?i)käteisnosto (?P<amount>\d+,\d{2})'
+//! account = 'Cash'
+//!
+//! [[ignore]]
+//! name = 'Credit card payment'
+//! pattern = '^LUOTTOKORTIN MAKSU'
+//!
+//! [[flag]]
+//! max = -500
+//! color = 'red'
+//!
+//! [[merchant]]
+//! prefix = 'PIZZERIA NAPOLI'
+//! payee = 'Napoli'
+//! category = 'Dining Out'
+//!
+//! [[tag]]
+//! pattern = '(?i)finnair|norwegian|hotel'
+//! tags = ['#travel']
+//! ```
+
+use crate::{
+    amount::Amount,
+    toml::{self, Table, Value},
+};
+use regex::Regex;
+use std::{error::Error, fs, path::Path, str::FromStr};
+
+/// Regex replacements applied to the payee, similar to `Regex::replace_all`.
+#[derive(Debug)]
+pub struct PayeeRule {
+    pattern: Regex,
+    replacement: String,
+}
+
+/// Assigns a YNAB category to transactions whose description or cleaned up
+/// payee matches the pattern. The first matching rule wins.
+#[derive(Debug)]
+pub struct CategoryRule {
+    pattern: Regex,
+    category: String,
+}
+
+/// Gives a clean payee, and optionally a category, to transactions whose
+/// description starts with the prefix, ignoring case. The longest matching
+/// prefix wins.
+#[derive(Debug)]
+pub struct Merchant {
+    /// In upper case
+    prefix: String,
+    payee: String,
+    category: Option<String>,
+}
+
+/// Merchants bundled with the program, used with `--enrich`
+const MERCHANTS: &str = include_str!("merchants.toml");
+
+/// Conditions of ignore, flag and tag rules, all of which must hold for the rule
+/// to match.
+#[derive(Debug)]
+struct Conditions {
+    /// Matched against the description and the cleaned up payee
+    pattern: Option<Regex>,
+    /// Exact description or cleaned up payee
+    equals: Option<String>,
+    min: Option<Amount>,
+    max: Option<Amount>,
+}
+
+impl Conditions {
+    fn matches(&self, description: &str, payee: &str, amount: Amount) -> bool {
+        self.pattern
+            .as_ref()
+            .is_none_or(|p| p.is_match(description) || p.is_match(payee))
+            && self
+                .equals
+                .as_ref()
+                .is_none_or(|e| e == description || e == payee)
+            && self.min.is_none_or(|min| amount >= min)
+            && self.max.is_none_or(|max| amount <= max)
+    }
+}
+
+/// Drops transactions matching all of the given conditions from the export.
+#[derive(Debug)]
+pub struct IgnoreRule {
+    /// Shown when reporting the ignored transactions
+    name: String,
+    conditions: Conditions,
+}
+
+/// Flag colors of YNAB transactions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flag {
+    Red,
+    Orange,
+    Yellow,
+    Green,
+    Blue,
+    Purple,
+}
+
+impl Flag {
+    const ALL: [Flag; 6] = [
+        Flag::Red,
+        Flag::Orange,
+        Flag::Yellow,
+        Flag::Green,
+        Flag::Blue,
+        Flag::Purple,
+    ];
+
+    /// Name of the color in the YNAB API, e.g. "red"
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Flag::Red => "red",
+            Flag::Orange => "orange",
+            Flag::Yellow => "yellow",
+            Flag::Green => "green",
+            Flag::Blue => "blue",
+            Flag::Purple => "purple",
+        }
+    }
+}
+
+impl FromStr for Flag {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Flag, String> {
+        Flag::ALL
+            .into_iter()
+            .find(|flag| flag.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                format!(
+                    "unknown flag color '{}', expected one of {}",
+                    s,
+                    Flag::ALL.map(|flag| flag.as_str()).join(", ")
+                )
+            })
+    }
+}
+
+/// Flags transactions matching all of the given conditions, so that they
+/// stand out for review after importing. The first matching rule wins.
+#[derive(Debug)]
+pub struct FlagRule {
+    color: Flag,
+    conditions: Conditions,
+}
+
+/// Appends hashtags to the memos of transactions matching all of the given
+/// conditions, e.g. `#travel`. The tags of all matching rules are added.
+#[derive(Debug)]
+pub struct TagRule {
+    /// With the leading `#`
+    tags: Vec<String>,
+    conditions: Conditions,
+}
+
+/// Splits matching transactions into several rows. Each part has a fixed
+/// amount, a percentage of the transaction, or neither, in which case it gets
+/// what is left. Without such a part, the last part gets what is left, so
+/// the parts always sum to the original amount.
+#[derive(Debug)]
+pub struct SplitRule {
+    pattern: Regex,
+    parts: Vec<SplitPart>,
+    /// How percentages are rounded to cents
+    rounding: Rounding,
+}
+
+/// Rounding of the percentage parts of a split to whole cents
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Rounding {
+    #[default]
+    Nearest,
+    /// Towards zero, leaving the remainder to the rest
+    Down,
+    /// Away from zero
+    Up,
+}
+
+impl Rounding {
+    fn round(&self, cents: f64) -> i64 {
+        match self {
+            Rounding::Nearest => cents.round() as i64,
+            Rounding::Down => cents.trunc() as i64,
+            Rounding::Up => (cents.abs().ceil() * cents.signum()) as i64,
+        }
+    }
+}
+
+impl FromStr for Rounding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Rounding, String> {
+        match s {
+            "nearest" => Ok(Rounding::Nearest),
+            "down" => Ok(Rounding::Down),
+            "up" => Ok(Rounding::Up),
+            _ => Err(format!(
+                "Unknown rounding '{}', expected nearest, down or up",
+                s
+            )),
+        }
+    }
+}
+
+/// Splits a cash withdrawal made at the checkout of a shop off a card
+/// purchase, as a transfer to a cash account. Finnish grocery chains book
+/// the purchase and the cash as one card transaction, with the cash only
+/// mentioned in the message. The amount withdrawn is the `amount` of the
+/// rule, or else the `amount` group of its pattern.
+#[derive(Debug)]
+pub struct CashbackRule {
+    pattern: Regex,
+    amount: Option<Amount>,
+    /// YNAB account the cash is transferred to
+    pub account: String,
+}
+
+#[derive(Debug)]
+pub struct SplitPart {
+    share: Share,
+    pub memo: Option<String>,
+    pub category: Option<String>,
+}
+
+#[derive(Debug)]
+enum Share {
+    /// Amount with the sign of the transaction
+    Fixed(Amount),
+    Percent(f64),
+    Rest,
+}
+
+impl SplitRule {
+    /// Returns the amount of each part of a transaction of `total`.
+    pub fn split(&self, total: Amount) -> Vec<(Amount, &SplitPart)> {
+        let sign = if total.is_negative() { -1 } else { 1 };
+        let mut amounts: Vec<i64> = self
+            .parts
+            .iter()
+            .map(|part| match part.share {
+                Share::Fixed(amount) => sign * amount.cents().abs(),
+                Share::Percent(percent) => {
+                    self.rounding.round(total.cents() as f64 * percent / 100.0)
+                }
+                Share::Rest => 0,
+            })
+            .collect();
+
+        let rest = total.cents() - amounts.iter().sum::<i64>();
+        let rest_index = self
+            .parts
+            .iter()
+            .position(|part| matches!(part.share, Share::Rest))
+            .unwrap_or(self.parts.len() - 1);
+        amounts[rest_index] += rest;
+
+        amounts
+            .into_iter()
+            .map(Amount::from_cents)
+            .zip(&self.parts)
+            .collect()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Rules {
+    payee: Vec<PayeeRule>,
+    category: Vec<CategoryRule>,
+    split: Vec<SplitRule>,
+    cashback: Vec<CashbackRule>,
+    ignore: Vec<IgnoreRule>,
+    flag: Vec<FlagRule>,
+    merchant: Vec<Merchant>,
+    tag: Vec<TagRule>,
+}
+
+impl Rules {
+    /// Built-in payee cleanup rules that strip card numbers, dates and card
+    /// terminal suffixes that Nordea appends to descriptions.
+    pub fn builtin() -> Rules {
+        let payee = [
+            // Masked card numbers, e.g. "457123******1234"
+            (r"\s*\d{4,6}\*+\d{4}", ""),
+            // Trailing purchase dates, e.g. "31.01", "31.01." or "31.01.24"
+            (r"\s+\d{1,2}\.\d{1,2}(\.(\d{2,4})?)?$", ""),
+            // Terminal number followed by city, e.g. "1234 HELSINKI"
+            (r"\s+\d{3,}\s+[[:upper:]ÅÄÖ]+$", ""),
+            // Trailing terminal or reference numbers
+            (r"\s+\d{3,}$", ""),
+        ]
+        .into_iter()
+        .map(|(pattern, replacement)| PayeeRule {
+            pattern: Regex::new(pattern).unwrap(),
+            replacement: replacement.to_string(),
+        })
+        .collect();
+
+        Rules {
+            payee,
+            category: vec![],
+            split: vec![],
+            cashback: vec![],
+            ignore: vec![],
+            flag: vec![],
+            merchant: vec![],
+            tag: vec![],
+        }
+    }
+
+    /// The bundled merchants: common Finnish shops, restaurant chains and
+    /// the like, with their names written properly and suggested categories.
+    pub fn merchants() -> Rules {
+        let doc = toml::parse(MERCHANTS).expect("bundled merchants");
+        Rules::from_table(&doc).expect("bundled merchants")
+    }
+
+    /// Returns the built-in rules unless `no_default_rules`, followed by the
+    /// rules of the file at `path`, and the bundled merchants if `enrich`.
+    pub fn load(
+        path: Option<&Path>,
+        no_default_rules: bool,
+        enrich: bool,
+    ) -> Result<Rules, Box<dyn Error>> {
+        let mut rules = if no_default_rules {
+            Rules::default()
+        } else {
+            Rules::builtin()
+        };
+        if let Some(path) = path {
+            rules.extend(Rules::from_file(path)?);
+        }
+        // After the merchants of the rules file, which win over bundled ones
+        if enrich {
+            rules.extend(Rules::merchants());
+        }
+        Ok(rules)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Rules, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read rules file {}: {}", path.display(), e))?;
+        let doc = toml::parse(&contents)
+            .map_err(|e| format!("Could not parse rules file {}: {}", path.display(), e))?;
+
+        Rules::from_table(&doc)
+            .map_err(|e| format!("In rules file {}: {}", path.display(), e).into())
+    }
+
+    /// Parses rules in the format of rules files.
+    pub fn from_toml(contents: &str) -> Result<Rules, Box<dyn Error>> {
+        let doc = toml::parse(contents).map_err(|e| format!("Could not parse rules: {}", e))?;
+        Rules::from_table(&doc)
+    }
+
+    fn from_table(doc: &Table) -> Result<Rules, Box<dyn Error>> {
+        let payee = rule_tables(doc, "payee")?
+            .into_iter()
+            .map(|t| {
+                Ok(PayeeRule {
+                    pattern: regex_field(t, "payee", "pattern")?,
+                    replacement: str_field(t, "payee", "replacement")?.to_string(),
+                })
+            })
+            .collect::<Result<_, Box<dyn Error>>>()?;
+
+        let category = rule_tables(doc, "category")?
+            .into_iter()
+            .map(|t| {
+                Ok(CategoryRule {
+                    pattern: regex_field(t, "category", "pattern")?,
+                    category: str_field(t, "category", "category")?.to_string(),
+                })
+            })
+            .collect::<Result<_, Box<dyn Error>>>()?;
+
+        let split = rule_tables(doc, "split")?
+            .into_iter()
+            .map(|t| {
+                Ok(SplitRule {
+                    pattern: regex_field(t, "split", "pattern")?,
+                    parts: split_parts(t)?,
+                    rounding: match t.get("rounding") {
+                        None => Rounding::default(),
+                        Some(Value::String(s)) => s.parse()?,
+                        Some(_) => return Err("split rule 'rounding' must be a string".into()),
+                    },
+                })
+            })
+            .collect::<Result<_, Box<dyn Error>>>()?;
+
+        let cashback = rule_tables(doc, "cashback")?
+            .into_iter()
+            .map(cashback_rule)
+            .collect::<Result<_, String>>()?;
+
+        let ignore = rule_tables(doc, "ignore")?
+            .into_iter()
+            .enumerate()
+            .map(|(i, t)| ignore_rule(t, i))
+            .collect::<Result<_, String>>()?;
+
+        let flag = rule_tables(doc, "flag")?
+            .into_iter()
+            .map(|t| {
+                Ok(FlagRule {
+                    color: str_field(t, "flag", "color")?.parse()?,
+                    conditions: conditions(t, "flag")?,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+
+        let merchant = rule_tables(doc, "merchant")?
+            .into_iter()
+            .map(|t| {
+                let category = match t.get("category") {
+                    None => None,
+                    Some(Value::String(s)) => Some(s.clone()),
+                    Some(_) => return Err("merchant rule 'category' must be a string".into()),
+                };
+                Ok(Merchant {
+                    prefix: str_field(t, "merchant", "prefix")?.to_uppercase(),
+                    payee: str_field(t, "merchant", "payee")?.to_string(),
+                    category,
+                })
+            })
+            .collect::<Result<_, Box<dyn Error>>>()?;
+
+        let tag = rule_tables(doc, "tag")?
+            .into_iter()
+            .map(|t| {
+                Ok(TagRule {
+                    tags: tags(t)?,
+                    conditions: conditions(t, "tag")?,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+
+        Ok(Rules {
+            payee,
+            category,
+            split,
+            cashback,
+            ignore,
+            flag,
+            merchant,
+            tag,
+        })
+    }
+
+    /// Appends the rules of `other` after the rules in `self`.
+    pub fn extend(&mut self, other: Rules) {
+        self.payee.extend(other.payee);
+        self.category.extend(other.category);
+        self.split.extend(other.split);
+        self.cashback.extend(other.cashback);
+        self.ignore.extend(other.ignore);
+        self.flag.extend(other.flag);
+        self.merchant.extend(other.merchant);
+        self.tag.extend(other.tag);
+    }
+
+    pub fn has_categories(&self) -> bool {
+        !self.category.is_empty()
+            || self
+                .split
+                .iter()
+                .any(|rule| rule.parts.iter().any(|part| part.category.is_some()))
+            || self.merchant.iter().any(|m| m.category.is_some())
+    }
+
+    /// Returns the category of the first rule matching either the original
+    /// description or the cleaned up payee, or else the category of the
+    /// merchant.
+    pub fn category(&self, description: &str, payee: &str) -> Option<&str> {
+        self.category
+            .iter()
+            .find(|rule| rule.pattern.is_match(description) || rule.pattern.is_match(payee))
+            .map(|rule| rule.category.as_str())
+            .or_else(|| self.merchant(description)?.category.as_deref())
+    }
+
+    /// Returns the merchant with the longest prefix of `description`, the
+    /// first one of equally long prefixes.
+    fn merchant(&self, description: &str) -> Option<&Merchant> {
+        let description = description.trim_start().to_uppercase();
+        self.merchant
+            .iter()
+            .filter(|m| description.starts_with(&m.prefix))
+            .rev()
+            .max_by_key(|m| m.prefix.len())
+    }
+
+    /// Returns the first split rule matching either the original description
+    /// or the cleaned up payee.
+    pub fn split(&self, description: &str, payee: &str) -> Option<&SplitRule> {
+        self.split
+            .iter()
+            .find(|rule| rule.pattern.is_match(description) || rule.pattern.is_match(payee))
+    }
+
+    /// Returns the cash withdrawn along with a card purchase of `total` and
+    /// the first cashback rule matching the description, the cleaned up
+    /// payee or the message. The cash has the sign of `total` and is less
+    /// than it, so that a purchase is left. Deposits are never split.
+    pub fn cashback(
+        &self,
+        description: &str,
+        payee: &str,
+        message: &str,
+        total: Amount,
+    ) -> Option<(Amount, &CashbackRule)> {
+        if !total.is_negative() {
+            return None;
+        }
+        self.cashback.iter().find_map(|rule| {
+            let captures = [description, payee, message]
+                .into_iter()
+                .find_map(|text| rule.pattern.captures(text))?;
+            let cash = match rule.amount {
+                Some(amount) => amount,
+                None => captures.name("amount")?.as_str().trim().parse().ok()?,
+            };
+            let cash = Amount::from_cents(-cash.cents().abs());
+            (cash.cents() != 0 && cash.cents() > total.cents()).then_some((cash, rule))
+        })
+    }
+
+    /// Returns the name of the first ignore rule matching the transaction.
+    pub fn ignored(&self, description: &str, payee: &str, amount: Amount) -> Option<&str> {
+        self.ignore
+            .iter()
+            .find(|rule| rule.conditions.matches(description, payee, amount))
+            .map(|rule| rule.name.as_str())
+    }
+
+    pub fn has_flags(&self) -> bool {
+        !self.flag.is_empty()
+    }
+
+    /// Returns the color of the first flag rule matching the transaction.
+    pub fn flag(&self, description: &str, payee: &str, amount: Amount) -> Option<Flag> {
+        self.flag
+            .iter()
+            .find(|rule| rule.conditions.matches(description, payee, amount))
+            .map(|rule| rule.color)
+    }
+
+    /// Returns the tags of all tag rules matching the transaction, each once,
+    /// in the order of the rules.
+    pub fn tags(&self, description: &str, payee: &str, amount: Amount) -> Vec<&str> {
+        let mut tags: Vec<&str> = vec![];
+        for rule in &self.tag {
+            if rule.conditions.matches(description, payee, amount) {
+                for tag in &rule.tags {
+                    if !tags.contains(&tag.as_str()) {
+                        tags.push(tag);
+                    }
+                }
+            }
+        }
+        tags
+    }
+
+    /// Returns the payee of the merchant of `description`, or else applies
+    /// all payee rules in order, then collapses repeated whitespace.
+    pub fn clean_payee(&self, description: &str) -> String {
+        if let Some(merchant) = self.merchant(description) {
+            return merchant.payee.clone();
+        }
+        let cleaned = self
+            .payee
+            .iter()
+            .fold(description.to_string(), |payee, rule| {
+                rule.pattern
+                    .replace_all(&payee, rule.replacement.as_str())
+                    .into_owned()
+            });
+
+        cleaned.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// Returns what the rules do with a transaction with `message` and which
+    /// rules do it.
+    pub fn trace(&self, description: &str, message: &str, amount: Amount) -> Trace {
+        let payee = self.clean_payee(description);
+        let merchant = self.merchant(description);
+        let payee_rules = match merchant {
+            Some(merchant) => vec![format!("merchant '{}'", merchant.prefix)],
+            None => {
+                let mut applied = vec![];
+                let mut cleaned = description.to_string();
+                for rule in &self.payee {
+                    let replaced = rule
+                        .pattern
+                        .replace_all(&cleaned, rule.replacement.as_str())
+                        .into_owned();
+                    if replaced != cleaned {
+                        applied.push(format!("payee '{}'", rule.pattern.as_str()));
+                    }
+                    cleaned = replaced;
+                }
+                applied
+            }
+        };
+
+        let category_rule = self
+            .category
+            .iter()
+            .find(|rule| rule.pattern.is_match(description) || rule.pattern.is_match(&payee));
+        let (category, category_rule) = match (category_rule, merchant) {
+            (Some(rule), _) => (
+                Some(rule.category.clone()),
+                Some(format!("category '{}'", rule.pattern.as_str())),
+            ),
+            (
+                None,
+                Some(Merchant {
+                    prefix,
+                    category: Some(category),
+                    ..
+                }),
+            ) => (
+                Some(category.clone()),
+                Some(format!("merchant '{}'", prefix)),
+            ),
+            _ => (None, None),
+        };
+
+        Trace {
+            category,
+            category_rule,
+            cashback: self
+                .cashback(description, &payee, message, amount)
+                .map(|(cash, rule)| format!("cashback {} '{}'", cash, rule.pattern.as_str())),
+            split: self
+                .split(description, &payee)
+                .map(|rule| format!("split '{}'", rule.pattern.as_str())),
+            ignored: self.ignored(description, &payee, amount).map(String::from),
+            flag: self.flag(description, &payee, amount),
+            tags: self
+                .tags(description, &payee, amount)
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            payee,
+            payee_rules,
+        }
+    }
+}
+
+/// What the rules do with a transaction, as shown by `nda2ynab rules test`.
+/// Rules are named by their section and pattern, and ignore rules by their
+/// name.
+#[derive(Debug, Default, PartialEq)]
+pub struct Trace {
+    pub payee: String,
+    /// The merchant, or the payee rules that changed the description, in
+    /// order
+    pub payee_rules: Vec<String>,
+    pub category: Option<String>,
+    /// The category rule or merchant that gave the category
+    pub category_rule: Option<String>,
+    /// The cashback rule and the cash it splits off
+    pub cashback: Option<String>,
+    pub split: Option<String>,
+    /// Name of the ignore rule that leaves the transaction out
+    pub ignored: Option<String>,
+    pub flag: Option<Flag>,
+    pub tags: Vec<String>,
+}
+
+impl Trace {
+    /// Returns the rules that matched, in the order they are applied.
+    pub fn rules(&self) -> Vec<String> {
+        let mut rules = self.payee_rules.clone();
+        rules.extend(self.category_rule.clone());
+        rules.extend(self.cashback.clone());
+        rules.extend(self.split.clone());
+        rules.extend(self.ignored.iter().map(|name| format!("ignore '{}'", name)));
+        rules.extend(self.flag.map(|flag| format!("flag {}", flag.as_str())));
+        rules.extend(self.tags.iter().map(|tag| format!("tag {}", tag)));
+        rules
+    }
+}
+
+/// Returns the tables of the `[[section]]` array, or an empty list if the
+/// section is not present.
+fn rule_tables<'a>(doc: &'a Table, section: &str) -> Result<Vec<&'a Table>, String> {
+    match doc.get(section) {
+        None => Ok(vec![]),
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| {
+                item.as_table()
+                    .ok_or_else(|| format!("'{}' must be an array of tables", section))
+            })
+            .collect(),
+        Some(_) => Err(format!("'{}' must be an array of tables", section)),
+    }
+}
+
+/// Reads the conditions of an ignore, flag or tag rule in `section`.
+fn conditions(table: &Table, section: &str) -> Result<Conditions, String> {
+    let optional = |key: &str| match table.get(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.as_str())),
+        Some(_) => Err(format!("{} rule '{}' must be a string", section, key)),
+    };
+    let amount = |key: &str| -> Result<Option<Amount>, String> {
+        match table.get(key) {
+            None => Ok(None),
+            Some(Value::String(s)) => Ok(Some(s.parse()?)),
+            Some(Value::Integer(i)) => Ok(Some(Amount::from_cents(i * 100))),
+            Some(Value::Float(f)) => Ok(Some(format!("{:.2}", f).parse()?)),
+            Some(_) => Err(format!("{} rule '{}' must be an amount", section, key)),
+        }
+    };
+
+    let pattern = optional("pattern")?
+        .map(|p| {
+            Regex::new(p).map_err(|e| format!("{} rule has an invalid pattern: {}", section, e))
+        })
+        .transpose()?;
+    let equals = optional("equals")?.map(str::to_string);
+    let (min, max) = (amount("min")?, amount("max")?);
+    if pattern.is_none() && equals.is_none() && min.is_none() && max.is_none() {
+        return Err(format!(
+            "{} rule needs at least one of 'pattern', 'equals', 'min' and 'max'",
+            section
+        ));
+    }
+
+    Ok(Conditions {
+        pattern,
+        equals,
+        min,
+        max,
+    })
+}
+
+/// Reads the `tag` or `tags` of a tag rule, adding the `#` where missing.
+fn tags(table: &Table) -> Result<Vec<String>, String> {
+    let tags = match (table.get("tag"), table.get("tags")) {
+        (Some(Value::String(tag)), None) => vec![tag.as_str()],
+        (None, Some(Value::Array(tags))) => tags
+            .iter()
+            .map(|tag| match tag {
+                Value::String(tag) => Ok(tag.as_str()),
+                _ => Err("tag rule 'tags' must be an array of strings".to_string()),
+            })
+            .collect::<Result<_, _>>()?,
+        _ => return Err("tag rule needs either a 'tag' string or a 'tags' array".to_string()),
+    };
+    tags.into_iter()
+        .map(|tag| {
+            let tag = tag.trim().trim_start_matches('#');
+            if tag.is_empty() || tag.contains(char::is_whitespace) {
+                return Err(format!("invalid tag '{}', tags cannot contain spaces", tag));
+            }
+            Ok(format!("#{}", tag))
+        })
+        .collect()
+}
+
+fn ignore_rule(table: &Table, index: usize) -> Result<IgnoreRule, String> {
+    let conditions = conditions(table, "ignore")?;
+    let optional = |key: &str| match table.get(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.as_str())),
+        Some(_) => Err(format!("ignore rule '{}' must be a string", key)),
+    };
+
+    let name = match optional("name")? {
+        Some(name) => name.to_string(),
+        None => optional("pattern")?
+            .or(optional("equals")?)
+            .map_or_else(|| format!("ignore rule {}", index + 1), str::to_string),
+    };
+
+    Ok(IgnoreRule { name, conditions })
+}
+
+fn cashback_rule(table: &Table) -> Result<CashbackRule, String> {
+    let pattern = regex_field(table, "cashback", "pattern")?;
+    let amount = match table.get("amount") {
+        None => None,
+        Some(Value::String(s)) => Some(s.parse()?),
+        Some(Value::Integer(i)) => Some(Amount::from_cents(i * 100)),
+        Some(Value::Float(f)) => Some(format!("{:.2}", f).parse()?),
+        Some(_) => return Err("cashback rule 'amount' must be an amount".to_string()),
+    };
+    if amount.is_none() && !pattern.capture_names().any(|name| name == Some("amount")) {
+        return Err(
+            "cashback rule needs an 'amount', or an 'amount' group in its pattern".to_string(),
+        );
+    }
+    let account = match table.get("account") {
+        None => "Cash".to_string(),
+        Some(Value::String(s)) => s.clone(),
+        Some(_) => return Err("cashback rule 'account' must be a string".to_string()),
+    };
+    Ok(CashbackRule {
+        pattern,
+        amount,
+        account,
+    })
+}
+
+fn split_parts(table: &Table) -> Result<Vec<SplitPart>, String> {
+    let parts = match table.get("parts") {
+        Some(Value::Array(parts)) if !parts.is_empty() => parts,
+        _ => return Err("split rule must have a non-empty 'parts' array".to_string()),
+    };
+
+    let parts = parts
+        .iter()
+        .map(|part| {
+            let part = part.as_table().ok_or("split rule parts must be tables")?;
+            let optional = |key: &str| match part.get(key) {
+                None => Ok(None),
+                Some(Value::String(s)) => Ok(Some(s.clone())),
+                Some(_) => Err(format!("split part '{}' must be a string", key)),
+            };
+
+            let share = match (part.get("amount"), part.get("percent")) {
+                (Some(_), Some(_)) => {
+                    return Err("split part has both 'amount' and 'percent'".to_string())
+                }
+                (Some(amount), None) => Share::Fixed(match amount {
+                    Value::String(s) => s.parse()?,
+                    Value::Integer(i) => Amount::from_cents(i * 100),
+                    Value::Float(f) => format!("{:.2}", f).parse()?,
+                    _ => return Err("split part 'amount' must be a number".to_string()),
+                }),
+                (None, Some(percent)) => Share::Percent(match percent {
+                    Value::Integer(i) => *i as f64,
+                    Value::Float(f) => *f,
+                    _ => return Err("split part 'percent' must be a number".to_string()),
+                }),
+                (None, None) => Share::Rest,
+            };
+
+            Ok(SplitPart {
+                share,
+                memo: optional("memo")?,
+                category: optional("category")?,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let rests = parts
+        .iter()
+        .filter(|p| matches!(p.share, Share::Rest))
+        .count();
+    if rests > 1 {
+        return Err("split rule can have only one part without 'amount' or 'percent'".to_string());
+    }
+
+    Ok(parts)
+}
+
+fn str_field<'a>(table: &'a Table, section: &str, key: &str) -> Result<&'a str, String> {
+    table
+        .get(key)
+        .ok_or_else(|| format!("{} rule is missing '{}'", section, key))?
+        .as_str()
+        .ok_or_else(|| format!("{} rule '{}' must be a string", section, key))
+}
+
+fn regex_field(table: &Table, section: &str, key: &str) -> Result<Regex, String> {
+    let pattern = str_field(table, section, key)?;
+    Regex::new(pattern).map_err(|e| format!("{} rule has an invalid {}: {}", section, key, e))
+}
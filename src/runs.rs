@@ -0,0 +1,148 @@
+//! Listing the recorded runs with `nda2ynab history`, and taking the last
+//! one back with `nda2ynab undo-last`.
+//!
+//! Undoing a run deletes the transactions it created in YNAB, removes the
+//! transactions it added to the transaction history, and returns the state
+//! file to how it was before the run, so that the next run converts the same
+//! transactions again. Files written are left in place, as they may have
+//! been imported already.
+
+use clap::ArgEnum;
+use nda2ynab::{
+    config::Config,
+    history::History,
+    info,
+    lock::Lock,
+    state::{Run, State},
+    ynab, UploadTarget,
+};
+use std::{error::Error, path::PathBuf};
+
+#[derive(clap::Args, Debug)]
+pub struct RunsArgs {
+    /// Path to the state file [default:
+    /// ~/.local/state/nda2ynab/state.json]
+    #[clap(long, value_name = "FILE")]
+    state: Option<PathBuf>,
+}
+
+impl RunsArgs {
+    fn state_path(&self, config: &Config) -> Result<PathBuf, Box<dyn Error>> {
+        self.state
+            .clone()
+            .or_else(|| config.state.clone())
+            .or_else(State::default_path)
+            .ok_or_else(|| "Could not determine the state file location, use --state".into())
+    }
+}
+
+/// Lists the recorded runs, most recent first.
+pub fn history(args: &RunsArgs, config: &Config) -> Result<(), Box<dyn Error>> {
+    let state = State::load(&args.state_path(config)?)?;
+    if state.runs.is_empty() {
+        println!("No runs recorded yet.");
+        return Ok(());
+    }
+    for run in state.runs.iter().rev() {
+        print!("{}", describe(run, config));
+    }
+    Ok(())
+}
+
+fn describe(run: &Run, config: &Config) -> String {
+    let mut out = format!("{}\n", run.time.format("%Y-%m-%d %H:%M:%S"));
+    for account in &run.accounts {
+        let output = match &account.output {
+            Some(output) => output.as_str(),
+            None => "stdout",
+        };
+        out += &format!(
+            "  {}: {} transaction(s) from {} to {}\n",
+            config.account_label(&account.iban),
+            account.transactions,
+            account.file,
+            output
+        );
+    }
+    out
+}
+
+/// Undoes the most recent run.
+pub fn undo_last(args: &RunsArgs, config: &Config) -> Result<(), Box<dyn Error>> {
+    let state_path = args.state_path(config)?;
+    let _lock = Lock::acquire(&state_path.with_extension("lock"), false)?;
+    let mut state = State::load(&state_path)?;
+    let mut run = state
+        .runs
+        .pop()
+        .ok_or("No runs recorded, nothing to undo")?;
+    info!("Undoing the run of {}", describe(&run, config));
+
+    for account in &mut run.accounts {
+        if account.ynab_ids.is_empty() {
+            continue;
+        }
+        let (token, budget_id) = ynab::credentials(config)?;
+        while let Some(id) = account.ynab_ids.last() {
+            if let Err(e) = ynab::delete_transaction(token, budget_id, id) {
+                // Kept for trying again, without the ones already deleted
+                let remaining = account.ynab_ids.len();
+                state.runs.push(run);
+                state.save(&state_path)?;
+                return Err(format!(
+                    "Could not delete a transaction from YNAB, {} are left: {}",
+                    remaining, e
+                )
+                .into());
+            }
+            account.ynab_ids.pop();
+        }
+        info!(
+            "Deleted the {} transaction(s) of {} from YNAB.",
+            account.transactions,
+            config.account_label(&account.iban)
+        );
+    }
+
+    let history_path = History::path_for(&state_path);
+    for account in run.accounts.iter().filter(|a| a.history_added > 0) {
+        if history_path.exists() {
+            let removed = History::open(&history_path)?.forget(
+                &account.iban,
+                &account.file,
+                run.time.date(),
+            )?;
+            info!(
+                "Removed {} transaction(s) of {} from {}.",
+                removed,
+                account.iban,
+                history_path.display()
+            );
+        }
+    }
+
+    state.restore(&run.before)?;
+    state.save(&state_path)?;
+
+    for account in &run.accounts {
+        let Some(output) = account.output.as_deref() else {
+            continue;
+        };
+        match UploadTarget::value_variants()
+            .iter()
+            .find(|target| target.name().to_lowercase() == output)
+        {
+            Some(UploadTarget::Ynab) => {}
+            Some(target) => info!(
+                "The {} transaction(s) of {} uploaded to {} must be deleted in {}.",
+                account.transactions,
+                config.account_label(&account.iban),
+                target.name(),
+                target.name()
+            ),
+            None => info!("{} was written and is left in place.", output),
+        }
+    }
+    println!("Undid the run of {}.", run.time.format("%Y-%m-%d %H:%M:%S"));
+    Ok(())
+}
@@ -0,0 +1,111 @@
+//! Cleanup of payees and memos before they are written.
+//!
+//! YNAB cuts payees at 200 characters and does not cope well with control
+//! characters or the runs of spaces that some exports pad their columns
+//! with. Payees and memos are cleaned up after all other processing, and
+//! all-uppercase merchant names can optionally be title-cased.
+
+use crate::{debug, YnabRow};
+
+/// Longest payee YNAB accepts
+pub const MAX_PAYEE_LENGTH: usize = 200;
+
+/// Longest memo YNAB accepts
+pub const MAX_MEMO_LENGTH: usize = 500;
+
+#[derive(Clone, Debug)]
+pub struct SanitizeOptions {
+    /// Longest payee written, in characters, or `None` for no limit
+    pub max_payee_length: Option<usize>,
+    /// Longest memo written, in characters, or `None` for no limit
+    pub max_memo_length: Option<usize>,
+    /// Remove control characters, replacing line breaks and tabs with
+    /// spaces
+    pub strip_control: bool,
+    /// Replace runs of whitespace with a single space and trim both ends
+    pub collapse_whitespace: bool,
+    /// Title-case payees written in all capitals, e.g. "K-MARKET KAMPPI"
+    pub title_case: bool,
+}
+
+impl Default for SanitizeOptions {
+    fn default() -> SanitizeOptions {
+        SanitizeOptions {
+            max_payee_length: Some(MAX_PAYEE_LENGTH),
+            max_memo_length: Some(MAX_MEMO_LENGTH),
+            strip_control: true,
+            collapse_whitespace: true,
+            title_case: false,
+        }
+    }
+}
+
+/// Cleans up the payee and memo of each row.
+pub fn sanitize(rows: &mut [YnabRow], opts: &SanitizeOptions) {
+    for r in rows {
+        let mut payee = clean(&r.payee, opts);
+        if opts.title_case && is_shouting(&payee) {
+            payee = title_case(&payee);
+        }
+        r.payee = truncate(payee, opts.max_payee_length);
+        r.memo = truncate(clean(&r.memo, opts), opts.max_memo_length);
+    }
+}
+
+fn clean(s: &str, opts: &SanitizeOptions) -> String {
+    let s: String = if opts.strip_control {
+        s.chars()
+            .filter_map(|c| match c {
+                '\t' | '\n' | '\r' => Some(' '),
+                // Zero width characters and byte order marks
+                '\u{200b}'..='\u{200d}' | '\u{feff}' => None,
+                c if c.is_control() => None,
+                c => Some(c),
+            })
+            .collect()
+    } else {
+        s.to_string()
+    };
+
+    if opts.collapse_whitespace {
+        s.split_whitespace().collect::<Vec<_>>().join(" ")
+    } else {
+        s
+    }
+}
+
+fn truncate(s: String, max_length: Option<usize>) -> String {
+    match max_length {
+        Some(max) if s.chars().count() > max => {
+            debug!("Truncating '{}' to {} characters", s, max);
+            s.chars()
+                .take(max)
+                .collect::<String>()
+                .trim_end()
+                .to_string()
+        }
+        _ => s,
+    }
+}
+
+/// Returns whether `s` has letters, none of them lowercase.
+fn is_shouting(s: &str) -> bool {
+    s.chars().any(char::is_alphabetic) && !s.chars().any(char::is_lowercase)
+}
+
+/// Capitalises the first letter of each word and lowercases the rest. Words
+/// are separated by anything but letters, digits and apostrophes, so that
+/// e.g. "K-MARKET" becomes "K-Market".
+pub fn title_case(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut word_start = true;
+    for c in s.chars() {
+        if word_start {
+            out.extend(c.to_uppercase());
+        } else {
+            out.extend(c.to_lowercase());
+        }
+        word_start = !(c.is_alphanumeric() || c == '\'');
+    }
+    out
+}
@@ -0,0 +1,176 @@
+//! S-Pankki CSV exports.
+//!
+//! Exports of a date range are read from files named like
+//! `Tilitapahtumat_FI12 3456 7890 1234 56_20220301-20220331.csv`, with or
+//! without spaces in the IBAN. The end of the range is used as the export
+//! date. The contents are semicolon separated with `31.03.2022` dates and
+//! amounts with an explicit sign, e.g. `+12,34`, listed most recent first.
+//! The counterparty is in the payer column for incoming payments and in the
+//! recipient column for outgoing ones.
+
+use crate::{
+    amount::Amount,
+    group_iban,
+    parser::{source_columns, BankParser, FileInfo, RowError, Rows},
+    RawTransaction, Transaction,
+};
+use chrono::NaiveDate;
+use csv::{ReaderBuilder, StringRecord};
+use regex::Regex;
+use std::{error::Error, path::Path};
+
+const DATE: &str = "Kirjauspäivä";
+const PAYMENT_DATE: &str = "Maksupäivä";
+const AMOUNT: &str = "Summa";
+const PAYER: &str = "Maksaja";
+const RECIPIENT: &str = "Saajan nimi";
+const COUNTERPARTY_ACCOUNT: &str = "Saajan tilinumero";
+const REFERENCE: &str = "Viitenumero";
+const MESSAGE: &str = "Viesti";
+
+pub struct SPankki {
+    file_name_re: Regex,
+}
+
+impl SPankki {
+    pub fn new() -> SPankki {
+        SPankki {
+            file_name_re: Regex::new(
+                r"(?i)^tilitapahtumat[ _](FI\d{2}(?: ?\d{4}){3} ?\d{2})[ _]\d{8}-(\d{8})\.csv$",
+            )
+            .unwrap(),
+        }
+    }
+
+    fn reader(contents: &str) -> csv::Reader<&[u8]> {
+        ReaderBuilder::new()
+            .delimiter(b';')
+            .flexible(true)
+            .from_reader(contents.as_bytes())
+    }
+}
+
+impl Default for SPankki {
+    fn default() -> SPankki {
+        SPankki::new()
+    }
+}
+
+/// Positions of the columns we read in the header row
+struct Columns {
+    header: StringRecord,
+    /// Number of columns
+    len: usize,
+    date: usize,
+    payment_date: Option<usize>,
+    amount: usize,
+    payer: usize,
+    recipient: usize,
+    counterparty_account: Option<usize>,
+    reference: Option<usize>,
+    message: Option<usize>,
+}
+
+impl Columns {
+    fn new(header: &StringRecord) -> Option<Columns> {
+        let position = |name: &str| header.iter().position(|h| h == name);
+        Some(Columns {
+            header: header.clone(),
+            len: header.len(),
+            date: position(DATE)?,
+            payment_date: position(PAYMENT_DATE),
+            amount: position(AMOUNT)?,
+            payer: position(PAYER)?,
+            recipient: position(RECIPIENT)?,
+            counterparty_account: position(COUNTERPARTY_ACCOUNT),
+            reference: position(REFERENCE),
+            message: position(MESSAGE),
+        })
+    }
+
+    fn transaction(&self, record: &StringRecord) -> Result<Transaction, String> {
+        let field = |i: usize| {
+            record
+                .get(i)
+                .map(str::trim)
+                .ok_or_else(|| format!("expected {} fields, found {}", self.len, record.len()))
+        };
+        let optional = |i: Option<usize>| {
+            i.and_then(|i| record.get(i))
+                .unwrap_or_default()
+                .trim()
+                .to_string()
+        };
+
+        let amount = field(self.amount)?;
+        let outgoing = amount.parse::<Amount>()?.is_negative();
+        let description = field(if outgoing { self.recipient } else { self.payer })?;
+
+        RawTransaction {
+            date: field(self.date)?.to_string(),
+            amount: amount.to_string(),
+            description: description.to_string(),
+            reference: optional(self.reference),
+            message: optional(self.message),
+            counterparty_account: optional(self.counterparty_account),
+            value_date: String::new(),
+            payment_date: optional(self.payment_date),
+            balance: String::new(),
+            currency: String::new(),
+            original_amount: String::new(),
+            original_currency: String::new(),
+            pending: false,
+            columns: source_columns(&self.header, record),
+        }
+        .normalize()
+    }
+}
+
+impl BankParser for SPankki {
+    fn name(&self) -> &'static str {
+        "s-pankki"
+    }
+
+    fn parse_file_name(&self, file_name: &str) -> Option<FileInfo> {
+        let captures = self.file_name_re.captures(file_name)?;
+        let end = NaiveDate::parse_from_str(captures.get(2)?.as_str(), "%Y%m%d").ok()?;
+
+        Some(FileInfo {
+            iban: group_iban(captures.get(1)?.as_str()),
+            date: end.and_hms_opt(0, 0, 0)?,
+        })
+    }
+
+    fn detect(&self, contents: &str) -> bool {
+        match SPankki::reader(contents).headers() {
+            Ok(header) => Columns::new(header).is_some(),
+            Err(_) => false,
+        }
+    }
+
+    fn rows<'a>(&'a self, contents: &'a str, path: &Path) -> Result<Rows<'a>, Box<dyn Error>> {
+        let mut rdr = SPankki::reader(contents);
+        let columns = Columns::new(rdr.headers()?).ok_or_else(|| {
+            format!(
+                "{} is missing the {}, {}, {} or {} column",
+                path.display(),
+                DATE,
+                AMOUNT,
+                PAYER,
+                RECIPIENT
+            )
+        })?;
+
+        Ok(Box::new(rdr.into_records().map(move |result| {
+            let line = match &result {
+                Ok(record) => record.position(),
+                Err(e) => e.position(),
+            }
+            .map_or(0, |p| p.line());
+            result
+                .map_err(|e| e.to_string())
+                .and_then(|record| columns.transaction(&record))
+                .map_err(|message| RowError { line, message })
+        })))
+    }
+}
@@ -0,0 +1,95 @@
+//! Spending per payee and month, as written by `nda2ynab report spending`.
+//!
+//! Transactions are grouped by the month they were booked in and their
+//! payee after the payee rules, with the outflows and inflows of each group
+//! summed separately. The groups are sorted by month, and within a month by
+//! net amount, the largest spending first.
+
+use crate::{amount::Amount, json::Value, parse_nda_date, YnabRow};
+use csv::WriterBuilder;
+use std::{collections::HashMap, error::Error};
+
+/// Transactions of one payee in one month
+#[derive(Debug, Default, PartialEq)]
+pub struct Spending {
+    /// Month as `YYYY-MM`
+    pub month: String,
+    pub payee: String,
+    pub transactions: usize,
+    /// Sum of the outflows, negative
+    pub outflow: Amount,
+    pub inflow: Amount,
+}
+
+impl Spending {
+    pub fn net(&self) -> Amount {
+        self.outflow + self.inflow
+    }
+}
+
+/// Groups `rows` by month and payee. Rows with unreadable dates are left
+/// out.
+pub fn summarize(rows: &[YnabRow]) -> Vec<Spending> {
+    let mut groups: HashMap<(String, &str), Spending> = HashMap::new();
+    for row in rows {
+        let Ok(date) = parse_nda_date(&row.date) else {
+            continue;
+        };
+        let month = date.format("%Y-%m").to_string();
+        let group = groups
+            .entry((month.clone(), row.payee.as_str()))
+            .or_insert_with(|| Spending {
+                month,
+                payee: row.payee.clone(),
+                ..Default::default()
+            });
+        group.transactions += 1;
+        if row.amount.is_negative() {
+            group.outflow += row.amount;
+        } else {
+            group.inflow += row.amount;
+        }
+    }
+
+    let mut spending: Vec<Spending> = groups.into_values().collect();
+    spending.sort_by(|a, b| (&a.month, a.net(), &a.payee).cmp(&(&b.month, b.net(), &b.payee)));
+    spending
+}
+
+/// Writes `spending` as CSV with a header row.
+pub fn to_csv(spending: &[Spending]) -> Result<String, Box<dyn Error>> {
+    let mut wtr = WriterBuilder::new().from_writer(vec![]);
+    wtr.write_record(["Month", "Payee", "Transactions", "Outflow", "Inflow", "Net"])?;
+    for s in spending {
+        wtr.write_record([
+            s.month.clone(),
+            s.payee.clone(),
+            s.transactions.to_string(),
+            s.outflow.to_string(),
+            s.inflow.to_string(),
+            s.net().to_string(),
+        ])?;
+    }
+    Ok(String::from_utf8(
+        wtr.into_inner().map_err(|e| e.to_string())?,
+    )?)
+}
+
+/// Writes `spending` as a JSON array, with the amounts as strings.
+pub fn to_json(spending: &[Spending]) -> Value {
+    Value::Array(
+        spending
+            .iter()
+            .map(|s| {
+                Value::object([
+                    ("month", s.month.as_str().into()),
+                    ("payee", s.payee.as_str().into()),
+                    ("transactions", s.transactions.into()),
+                    ("outflow", s.outflow.to_string().into()),
+                    ("inflow", s.inflow.to_string().into()),
+                    ("net", s.net().to_string().into()),
+                ])
+            })
+            .collect(),
+    )
+}
@@ -0,0 +1,62 @@
+//! Minimal SQLite client using the `sqlite3` command line tool, like
+//! [`crate::http`] uses curl.
+//!
+//! Statements are passed on standard input and results are read in the
+//! tool's ASCII mode, where fields are separated by the unit separator and
+//! rows by the record separator, so that values may contain commas and
+//! newlines.
+
+use std::{
+    error::Error,
+    fs,
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+const FIELD_SEPARATOR: char = '\u{1f}';
+const ROW_SEPARATOR: char = '\u{1e}';
+
+/// Runs `sql` against the database `db`, creating it if it does not exist,
+/// and returns the rows of the results.
+pub fn execute(db: &Path, sql: &str) -> Result<Vec<Vec<String>>, Box<dyn Error>> {
+    if let Some(dir) = db.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut child = Command::new("sqlite3")
+        .args(["-batch", "-bail", "-ascii"])
+        .arg(db)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Could not run sqlite3: {}", e))?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(sql.as_bytes())?;
+    let output = child.wait_with_output()?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Could not use database {}: {}",
+            db.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    let stdout = String::from_utf8(output.stdout)?;
+    Ok(stdout
+        .split(ROW_SEPARATOR)
+        .filter(|row| !row.is_empty())
+        .map(|row| row.split(FIELD_SEPARATOR).map(String::from).collect())
+        .collect())
+}
+
+/// Quotes a value as an SQL string literal.
+pub fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
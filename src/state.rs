@@ -0,0 +1,353 @@
+//! State kept between runs.
+//!
+//! The state is stored as JSON in `$XDG_STATE_HOME/nda2ynab/state.json` (or
+//! `~/.local/state/nda2ynab/state.json`), unless another file is given with
+//! `--state` or the `state` config key. It includes the Open Banking access
+//! tokens, so it is only readable by the user on Unix systems.
+//!
+//! The most recent runs that wrote or uploaded transactions are recorded in
+//! it too, each with the state from before it, so that `nda2ynab undo-last`
+//! can take the last one back.
+
+use crate::{
+    atomic::AtomicFile,
+    json::{self, Value},
+};
+use chrono::{NaiveDate, NaiveDateTime};
+use std::{
+    collections::BTreeMap,
+    env,
+    error::Error,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+pub const FILE_NAME: &str = "state.json";
+
+const DATE_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// Number of runs kept, the oldest ones are forgotten
+pub const MAX_RUNS: usize = 20;
+
+#[derive(Debug, Default)]
+pub struct State {
+    /// Authorisation holds that have not settled yet, keyed by IBAN
+    pub holds: BTreeMap<String, Vec<Hold>>,
+    /// Open Banking access token
+    pub token: Option<Token>,
+    /// Progress of fetching transactions via Open Banking, keyed by IBAN
+    pub sync: BTreeMap<String, AccountSync>,
+    /// Transactions sent to YNAB whose upload has not been confirmed, as
+    /// sent to the API
+    pub pending_uploads: Vec<Value>,
+    /// The most recent runs, oldest first
+    pub runs: Vec<Run>,
+}
+
+/// A conversion that wrote or uploaded transactions
+#[derive(Clone, Debug, PartialEq)]
+pub struct Run {
+    /// Local time the run finished
+    pub time: NaiveDateTime,
+    pub accounts: Vec<RunAccount>,
+    /// The state before the run, as given by [`State::snapshot`]
+    pub before: Value,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunAccount {
+    pub iban: String,
+    /// Export the transactions were converted from
+    pub file: String,
+    pub transactions: usize,
+    /// Output file, the service uploaded to, or `None` for stdout
+    pub output: Option<String>,
+    /// Ids of the transactions created in YNAB
+    pub ynab_ids: Vec<String>,
+    /// Number of transactions added to the transaction history
+    pub history_added: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hold {
+    pub amount: String,
+    pub description: String,
+    /// Export date of the first export the hold appeared in
+    pub first_seen: NaiveDate,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountSync {
+    /// Booking date of the most recent transaction fetched
+    pub last_date: NaiveDate,
+    /// Ids of the transactions booked on `last_date` that were already fetched
+    pub seen: Vec<String>,
+}
+
+impl State {
+    pub fn default_path() -> Option<PathBuf> {
+        let state_dir = env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state"))
+            })?;
+        Some(state_dir.join("nda2ynab").join(FILE_NAME))
+    }
+
+    /// Loads the state, or returns an empty state if the file does not exist.
+    pub fn load(path: &Path) -> Result<State, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(State::default());
+        }
+
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read state file {}: {}", path.display(), e))?;
+        let doc = json::parse(&contents)
+            .map_err(|e| format!("Could not parse state file {}: {}", path.display(), e))?;
+
+        State::from_json(&doc)
+            .ok_or_else(|| format!("Invalid state file {}", path.display()).into())
+    }
+
+    fn from_json(doc: &Value) -> Option<State> {
+        let mut holds = BTreeMap::new();
+        if let Some(Value::Object(accounts)) = doc.get("holds") {
+            for (iban, account_holds) in accounts {
+                let account_holds = account_holds
+                    .as_array()?
+                    .iter()
+                    .map(|h| {
+                        Some(Hold {
+                            amount: h.get("amount")?.as_str()?.to_string(),
+                            description: h.get("description")?.as_str()?.to_string(),
+                            first_seen: h.get("first_seen")?.as_str()?.parse().ok()?,
+                        })
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                holds.insert(iban.clone(), account_holds);
+            }
+        }
+
+        let token = match doc.get("token") {
+            Some(t) => Some(Token {
+                access_token: t.get("access_token")?.as_str()?.to_string(),
+                refresh_token: t
+                    .get("refresh_token")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+                expires_at: NaiveDateTime::parse_from_str(
+                    t.get("expires_at")?.as_str()?,
+                    DATE_TIME_FORMAT,
+                )
+                .ok()?,
+            }),
+            None => None,
+        };
+
+        let mut sync = BTreeMap::new();
+        if let Some(Value::Object(accounts)) = doc.get("sync") {
+            for (iban, s) in accounts {
+                let seen = s
+                    .get("seen")?
+                    .as_array()?
+                    .iter()
+                    .map(|id| id.as_str().map(str::to_string))
+                    .collect::<Option<Vec<_>>>()?;
+                sync.insert(
+                    iban.clone(),
+                    AccountSync {
+                        last_date: s.get("last_date")?.as_str()?.parse().ok()?,
+                        seen,
+                    },
+                );
+            }
+        }
+
+        let pending_uploads = match doc.get("pending_uploads") {
+            Some(pending) => pending.as_array()?.to_vec(),
+            None => vec![],
+        };
+
+        let runs = match doc.get("runs") {
+            Some(runs) => runs
+                .as_array()?
+                .iter()
+                .map(run_from_json)
+                .collect::<Option<_>>()?,
+            None => vec![],
+        };
+
+        Some(State {
+            holds,
+            token,
+            sync,
+            pending_uploads,
+            runs,
+        })
+    }
+
+    /// Returns the state that a run may change: the holds, the progress of
+    /// fetching and the pending uploads, but not the access token.
+    pub fn snapshot(&self) -> Value {
+        State {
+            holds: self.holds.clone(),
+            sync: self.sync.clone(),
+            pending_uploads: self.pending_uploads.clone(),
+            ..State::default()
+        }
+        .to_json()
+    }
+
+    /// Returns the state to `snapshot`, keeping the access token and the
+    /// runs.
+    pub fn restore(&mut self, snapshot: &Value) -> Result<(), Box<dyn Error>> {
+        let before = State::from_json(snapshot).ok_or("Invalid state snapshot")?;
+        self.holds = before.holds;
+        self.sync = before.sync;
+        self.pending_uploads = before.pending_uploads;
+        Ok(())
+    }
+
+    /// Records a run, forgetting the oldest one beyond [`MAX_RUNS`].
+    pub fn record_run(&mut self, run: Run) {
+        self.runs.push(run);
+        let excess = self.runs.len().saturating_sub(MAX_RUNS);
+        self.runs.drain(..excess);
+    }
+
+    fn to_json(&self) -> Value {
+        let holds = self
+            .holds
+            .iter()
+            .filter(|(_, holds)| !holds.is_empty())
+            .map(|(iban, holds)| {
+                let holds = holds
+                    .iter()
+                    .map(|h| {
+                        Value::object([
+                            ("amount", h.amount.as_str().into()),
+                            ("description", h.description.as_str().into()),
+                            ("first_seen", h.first_seen.to_string().into()),
+                        ])
+                    })
+                    .collect();
+                (iban.clone(), Value::Array(holds))
+            });
+
+        let token = self.token.as_ref().map(|t| {
+            Value::object([
+                ("access_token", t.access_token.as_str().into()),
+                ("refresh_token", t.refresh_token.clone().into()),
+                (
+                    "expires_at",
+                    t.expires_at.format(DATE_TIME_FORMAT).to_string().into(),
+                ),
+            ])
+        });
+
+        let sync = self.sync.iter().map(|(iban, s)| {
+            let seen = s.seen.iter().map(|id| id.as_str().into()).collect();
+            (
+                iban.clone(),
+                Value::object([
+                    ("last_date", s.last_date.to_string().into()),
+                    ("seen", Value::Array(seen)),
+                ]),
+            )
+        });
+
+        let mut entries = vec![("holds", Value::object(holds))];
+        if let Some(token) = token {
+            entries.push(("token", token));
+        }
+        if !self.sync.is_empty() {
+            entries.push(("sync", Value::object(sync)));
+        }
+        if !self.pending_uploads.is_empty() {
+            entries.push((
+                "pending_uploads",
+                Value::Array(self.pending_uploads.clone()),
+            ));
+        }
+        if !self.runs.is_empty() {
+            entries.push((
+                "runs",
+                Value::Array(self.runs.iter().map(run_to_json).collect()),
+            ));
+        }
+        Value::object(entries)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let write = || {
+            let mut file = AtomicFile::create_private(path)?;
+            file.write_all((self.to_json().to_pretty_string() + "\n").as_bytes())?;
+            file.commit()
+        };
+        write().map_err(|e| format!("Could not write state file {}: {}", path.display(), e).into())
+    }
+}
+
+fn run_from_json(run: &Value) -> Option<Run> {
+    let accounts = run
+        .get("accounts")?
+        .as_array()?
+        .iter()
+        .map(|a| {
+            Some(RunAccount {
+                iban: a.get("iban")?.as_str()?.to_string(),
+                file: a.get("file")?.as_str()?.to_string(),
+                transactions: a.get("transactions")?.as_i64()?.try_into().ok()?,
+                output: a.get("output").and_then(Value::as_str).map(str::to_string),
+                ynab_ids: a
+                    .get("ynab_ids")?
+                    .as_array()?
+                    .iter()
+                    .map(|id| id.as_str().map(str::to_string))
+                    .collect::<Option<_>>()?,
+                history_added: a.get("history_added")?.as_i64()?.try_into().ok()?,
+            })
+        })
+        .collect::<Option<_>>()?;
+    Some(Run {
+        time: NaiveDateTime::parse_from_str(run.get("time")?.as_str()?, DATE_TIME_FORMAT).ok()?,
+        accounts,
+        before: run.get("before")?.clone(),
+    })
+}
+
+fn run_to_json(run: &Run) -> Value {
+    let accounts = run
+        .accounts
+        .iter()
+        .map(|a| {
+            Value::object([
+                ("iban", a.iban.as_str().into()),
+                ("file", a.file.as_str().into()),
+                ("transactions", a.transactions.into()),
+                ("output", a.output.clone().into()),
+                (
+                    "ynab_ids",
+                    Value::Array(a.ynab_ids.iter().map(|id| id.as_str().into()).collect()),
+                ),
+                ("history_added", a.history_added.into()),
+            ])
+        })
+        .collect();
+    Value::object([
+        ("time", run.time.format(DATE_TIME_FORMAT).to_string().into()),
+        ("accounts", Value::Array(accounts)),
+        ("before", run.before.clone()),
+    ])
+}
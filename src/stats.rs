@@ -0,0 +1,93 @@
+//! Summary statistics of converted transactions, as a quick check that the
+//! export window looks right.
+
+use crate::{amount::Amount, parse_nda_date, YnabRow};
+use chrono::NaiveDate;
+use std::fmt::Write;
+
+/// Number of largest transactions listed
+const LARGEST: usize = 3;
+
+#[derive(Debug, Default)]
+pub struct Stats<'a> {
+    pub count: usize,
+    /// Dates of the oldest and most recent transaction
+    pub span: Option<(NaiveDate, NaiveDate)>,
+    pub inflow: Amount,
+    pub outflow: Amount,
+    /// Largest transactions by absolute amount
+    pub largest: Vec<&'a YnabRow>,
+}
+
+impl<'a> Stats<'a> {
+    pub fn new(rows: &'a [YnabRow]) -> Stats<'a> {
+        let dates: Vec<NaiveDate> = rows
+            .iter()
+            .filter_map(|r| parse_nda_date(&r.date).ok())
+            .collect();
+        let span = dates.iter().min().copied().zip(dates.iter().max().copied());
+
+        let sum = |positive: bool| {
+            Amount::from_cents(
+                rows.iter()
+                    .map(|r| r.amount.cents())
+                    .filter(|&c| (c > 0) == positive && c != 0)
+                    .sum(),
+            )
+        };
+
+        let mut largest: Vec<&YnabRow> = rows.iter().collect();
+        largest.sort_by_key(|r| std::cmp::Reverse(r.amount.cents().abs()));
+        largest.truncate(LARGEST);
+
+        Stats {
+            count: rows.len(),
+            span,
+            inflow: sum(true),
+            outflow: sum(false),
+            largest,
+        }
+    }
+
+    pub fn net(&self) -> Amount {
+        Amount::from_cents(self.inflow.cents() + self.outflow.cents())
+    }
+
+    /// Formats the statistics of account `iban` for reading.
+    pub fn report(&self, iban: &str) -> String {
+        let mut out = format!("{}: {} transaction(s)", iban, self.count);
+        if let Some((first, last)) = self.span {
+            write!(out, " from {} to {}", first, last).unwrap();
+        }
+        out.push('\n');
+
+        let amounts = [
+            ("Inflow", self.inflow),
+            ("Outflow", self.outflow),
+            ("Net", self.net()),
+        ];
+        let width = amounts
+            .iter()
+            .map(|(_, a)| a.to_string().len())
+            .max()
+            .unwrap_or(0);
+        for (label, amount) in amounts {
+            writeln!(out, "  {:<8} {:>width$}", label, amount.to_string()).unwrap();
+        }
+
+        if !self.largest.is_empty() {
+            out.push_str("  Largest:\n");
+            for r in &self.largest {
+                writeln!(
+                    out,
+                    "    {}  {:>10}  {}",
+                    r.date,
+                    r.amount.to_string(),
+                    r.payee
+                )
+                .unwrap();
+            }
+        }
+        out
+    }
+}
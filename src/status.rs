@@ -0,0 +1,183 @@
+//! Overview of the saved state and the exports found, for checking what the
+//! next conversion will do.
+
+use chrono::NaiveDate;
+use itertools::Itertools;
+use nda2ynab::{
+    config::Config,
+    encoding::Encoding,
+    find_export_files,
+    history::History,
+    nordea,
+    parser::Registry,
+    state::State,
+    system::{Clock, Disk},
+    ParsedFileName,
+};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+#[derive(clap::Args, Debug)]
+pub struct StatusArgs {
+    /// Path to directory containing exported csv files [default: path from
+    /// the config file]
+    path: Option<String>,
+
+    /// Path to the state file [default:
+    /// ~/.local/state/nda2ynab/state.json]
+    #[clap(long, value_name = "FILE")]
+    state: Option<PathBuf>,
+
+    /// List the transactions in the transaction history (see
+    /// --state-backend) booked on or after this date, e.g. 2024-01-31
+    #[clap(long, value_name = "DATE")]
+    since: Option<NaiveDate>,
+}
+
+pub fn run(clock: &dyn Clock, args: &StatusArgs, config: &Config) -> Result<(), Box<dyn Error>> {
+    let state_path = args
+        .state
+        .clone()
+        .or_else(|| config.state.clone())
+        .or_else(State::default_path)
+        .ok_or("Could not determine the state file location, use --state")?;
+
+    if state_path.exists() {
+        println!("State file: {}", state_path.display());
+    } else {
+        println!("State file: {} (not created yet)", state_path.display());
+    }
+    print_state(clock, &State::load(&state_path)?);
+
+    if let Some(since) = args.since {
+        print_history(&History::path_for(&state_path), since)?;
+    }
+
+    match args.path.as_deref().or(config.path.as_deref()) {
+        Some(path) => print_exports(clock, path, config)?,
+        None => println!("\nNo export directory given on the command line or in the config file."),
+    }
+    Ok(())
+}
+
+fn print_state(clock: &dyn Clock, state: &State) {
+    if state.token.is_none()
+        && state.sync.is_empty()
+        && state.holds.values().all(Vec::is_empty)
+        && state.pending_uploads.is_empty()
+    {
+        println!("Nothing saved yet.");
+        return;
+    }
+
+    if let Some(token) = &state.token {
+        let now = clock.now();
+        let validity = if token.expires_at > now {
+            format!("valid until {}", token.expires_at.format("%Y-%m-%d %H:%M"))
+        } else if token.refresh_token.is_some() {
+            "expired, will be refreshed".to_string()
+        } else {
+            "expired".to_string()
+        };
+        println!("Open Banking access: {}", validity);
+    }
+
+    if !state.sync.is_empty() {
+        println!("\nOpen Banking sync:");
+        for (iban, sync) in &state.sync {
+            println!("  {}: fetched up to {}", iban, sync.last_date);
+        }
+    }
+
+    if !state.pending_uploads.is_empty() {
+        println!(
+            "\nUnconfirmed YNAB uploads: {} transaction(s), resubmitted by the next upload",
+            state.pending_uploads.len()
+        );
+    }
+
+    let holds: Vec<_> = state.holds.iter().filter(|(_, h)| !h.is_empty()).collect();
+    if !holds.is_empty() {
+        println!("\nPending authorisation holds:");
+        for (iban, holds) in holds {
+            println!("  {}:", iban);
+            for hold in holds {
+                println!(
+                    "    {} {} (first seen {})",
+                    hold.description, hold.amount, hold.first_seen
+                );
+            }
+        }
+    }
+}
+
+/// Prints the transactions in the history booked on or after `since`.
+fn print_history(path: &Path, since: NaiveDate) -> Result<(), Box<dyn Error>> {
+    if !path.exists() {
+        println!(
+            "\nNo transaction history at {}, convert with --state-backend sqlite to keep one.",
+            path.display()
+        );
+        return Ok(());
+    }
+
+    let entries = History::open(path)?.since(since)?;
+    println!(
+        "\nTransaction history since {}: {} transaction(s)",
+        since,
+        entries.len()
+    );
+    for (iban, entries) in &entries.iter().group_by(|e| &e.iban) {
+        println!("  {}:", iban);
+        for entry in entries {
+            println!(
+                "    {}  {:>10}  {} (from {})",
+                entry.key.date, entry.key.amount, entry.key.description, entry.file
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Prints the most recent export of each account in `path`, and whether it
+/// has been archived, i.e. already processed.
+fn print_exports(clock: &dyn Clock, path: &str, config: &Config) -> Result<(), Box<dyn Error>> {
+    let patterns = nordea::file_name_patterns(&config.file_name_patterns)?;
+    let registry = Registry::with_file_name_patterns(config.locale, patterns, clock)
+        .with_timezone(config.timezone.unwrap_or_default());
+    let archive_dir = config
+        .archive
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(path).join("archive"));
+    let archive = archive_dir.is_dir().then_some(archive_dir.as_path());
+    let encoding = config.encoding.unwrap_or(Encoding::Auto);
+    let exports = find_export_files(&Disk, path, &registry, encoding, archive)?;
+
+    if exports.is_empty() {
+        println!("\nNo exports found in {}.", path);
+        return Ok(());
+    }
+
+    // Exports are sorted most recent first
+    let mut newest: BTreeMap<&str, (&ParsedFileName, usize)> = BTreeMap::new();
+    for export in &exports {
+        newest.entry(&export.iban).or_insert((export, 0)).1 += 1;
+    }
+
+    println!("\nMost recent exports in {}:", path);
+    for (iban, (export, count)) in newest {
+        let archived = archive.is_some_and(|dir| export.path.starts_with(dir));
+        println!(
+            "  {}: {} ({}{}), {} export(s)",
+            iban,
+            export.file_name,
+            export.date.format("%Y-%m-%d %H:%M"),
+            if archived { ", already processed" } else { "" },
+            count
+        );
+    }
+    Ok(())
+}
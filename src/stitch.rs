@@ -0,0 +1,109 @@
+//! Stitching the exports of an account into one timeline for `--backfill`.
+//!
+//! Exports may cover any date ranges, e.g. one per quarter downloaded out
+//! of order, and overlap each other in any way. They are taken in the order
+//! of the dates they cover rather than the order they were exported in, and
+//! each contributes the transactions that the exports covering the same
+//! dates did not already have, identified as in [`crate::diff`]. A
+//! transaction appearing in several exports is therefore kept once, and
+//! identical transactions as often as the export with the most of them has
+//! them. Dates that no export covers are gaps, where transactions may be
+//! missing.
+
+use crate::{
+    diff::{Counts, Subtraction},
+    Transaction,
+};
+use chrono::{Duration, NaiveDate};
+
+/// The rows of an export, newest first, as read
+#[derive(Debug)]
+pub struct Export<'a> {
+    pub file_name: &'a str,
+    pub rows: Vec<Transaction>,
+}
+
+/// Days between the exports `after` and `before` that neither covers
+#[derive(Debug, PartialEq)]
+pub struct Gap {
+    pub after: String,
+    pub before: String,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+#[derive(Debug, Default)]
+pub struct Timeline {
+    /// The transactions of all exports, each once, newest first
+    pub rows: Vec<Transaction>,
+    /// Rows left out as already in an export covering the same dates
+    pub duplicates: Vec<Transaction>,
+    pub gaps: Vec<Gap>,
+    /// Exports overlapping the dates of the ones before them without having
+    /// any transactions in common, as (earlier export, export)
+    pub unrelated: Vec<(String, String)>,
+}
+
+/// Stitches `exports` into one timeline. If `exports_overlap` is not set,
+/// as for bank statements, exports are not expected to have transactions in
+/// common and are not reported as unrelated.
+pub fn stitch(exports: Vec<Export>, exports_overlap: bool) -> Timeline {
+    // By the dates covered, oldest first. Exports without rows cover nothing.
+    let mut exports: Vec<(NaiveDate, NaiveDate, Export)> = exports
+        .into_iter()
+        .filter_map(|export| {
+            let newest = export.rows.iter().map(|r| r.date).max()?;
+            let oldest = export.rows.iter().map(|r| r.date).min()?;
+            Some((oldest, newest, export))
+        })
+        .collect();
+    exports.sort_by_key(|(oldest, newest, _)| (*oldest, *newest));
+
+    let mut timeline = Timeline::default();
+    let mut counts = Counts::default();
+    // Newest date covered so far, and the export covering it
+    let mut covered: Option<(NaiveDate, &str)> = None;
+    let mut chunks = vec![];
+    for (oldest, newest, export) in &exports {
+        let mut export_counts = Counts::default();
+        export.rows.iter().for_each(|r| export_counts.add(r));
+
+        match covered {
+            Some((end, previous)) if *oldest > end + Duration::days(1) => {
+                timeline.gaps.push(Gap {
+                    after: previous.to_string(),
+                    before: export.file_name.to_string(),
+                    from: end + Duration::days(1),
+                    to: *oldest - Duration::days(1),
+                });
+            }
+            Some((end, previous))
+                if exports_overlap && *oldest <= end && export_counts.common(&counts) == 0 =>
+            {
+                timeline
+                    .unrelated
+                    .push((previous.to_string(), export.file_name.to_string()));
+            }
+            _ => {}
+        }
+
+        let mut subtraction = Subtraction::new(export_counts, &counts);
+        let (new, old): (Vec<_>, Vec<_>) = export
+            .rows
+            .iter()
+            .cloned()
+            .partition(|r| subtraction.is_new(r));
+        new.iter().for_each(|r| counts.add(r));
+        timeline.duplicates.extend(old);
+        chunks.push(new);
+
+        if covered.is_none_or(|(end, _)| *newest > end) {
+            covered = Some((*newest, export.file_name));
+        }
+    }
+
+    // Newest first, keeping the order of the rows of each export
+    timeline.rows = chunks.into_iter().rev().flatten().collect();
+    timeline.rows.sort_by_key(|r| std::cmp::Reverse(r.date));
+    timeline
+}
@@ -0,0 +1,203 @@
+//! The clock and the file system.
+//!
+//! Finding and archiving exports and anything relative to the current time go
+//! through [`FileSystem`] and [`Clock`], so that they can be exercised with
+//! files in memory and a fixed time instead of a real directory and the
+//! system clock.
+
+use chrono::{DateTime, Local, NaiveDateTime};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+
+pub trait Clock {
+    /// Current local time
+    fn now(&self) -> NaiveDateTime;
+}
+
+/// The system clock in the local time zone
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        Local::now().naive_local()
+    }
+}
+
+/// A clock that is always at the same time
+pub struct FixedClock(pub NaiveDateTime);
+
+impl Clock for FixedClock {
+    fn now(&self) -> NaiveDateTime {
+        self.0
+    }
+}
+
+/// Files are read from the threads converting accounts, so implementations
+/// must be shareable between threads.
+pub trait FileSystem: Sync {
+    /// Returns the paths of the entries of the directory at `path`.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>>;
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    fn is_dir(&self, path: &Path) -> bool;
+
+    /// Local time the file at `path` was last modified
+    fn modified(&self, path: &Path) -> io::Result<NaiveDateTime>;
+
+    fn exists(&self, path: &Path) -> bool;
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    /// Moves the file at `from` to `to`, replacing any file there.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Copies the file at `from` to `to`, replacing any file there.
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+}
+
+/// The real file system
+pub struct Disk;
+
+impl FileSystem for Disk {
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        fs::read_dir(path)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect()
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn modified(&self, path: &Path) -> io::Result<NaiveDateTime> {
+        let modified = fs::metadata(path)?.modified()?;
+        Ok(DateTime::<Local>::from(modified).naive_local())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::copy(from, to).map(|_| ())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+}
+
+/// Files kept in memory. Directories exist as long as they contain files.
+#[derive(Default)]
+pub struct MemoryFs {
+    files: Mutex<BTreeMap<PathBuf, (Vec<u8>, NaiveDateTime)>>,
+}
+
+impl MemoryFs {
+    /// Adds a file, replacing any file at the same path.
+    pub fn insert(
+        &mut self,
+        path: impl Into<PathBuf>,
+        contents: impl Into<Vec<u8>>,
+        modified: NaiveDateTime,
+    ) {
+        self.files()
+            .insert(path.into(), (contents.into(), modified));
+    }
+
+    /// Paths of the files, in order.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.files().keys().cloned().collect()
+    }
+
+    fn files(&self) -> MutexGuard<'_, BTreeMap<PathBuf, (Vec<u8>, NaiveDateTime)>> {
+        // The map is never left half-updated, so a panic elsewhere does not
+        // make it unusable
+        self.files.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn file(&self, path: &Path) -> io::Result<(Vec<u8>, NaiveDateTime)> {
+        self.files().get(path).cloned().ok_or_else(not_found)
+    }
+}
+
+fn not_found() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "no such file")
+}
+
+impl FileSystem for MemoryFs {
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        if !self.is_dir(path) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no such directory"));
+        }
+
+        let mut entries: Vec<PathBuf> = self
+            .files()
+            .keys()
+            .filter_map(|file| {
+                let relative = file.strip_prefix(path).ok()?;
+                Some(path.join(relative.components().next()?))
+            })
+            .collect();
+        entries.dedup();
+        Ok(entries)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        Ok(self.file(path)?.0.clone())
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.files()
+            .keys()
+            .any(|file| file != path && file.starts_with(path))
+    }
+
+    fn modified(&self, path: &Path) -> io::Result<NaiveDateTime> {
+        Ok(self.file(path)?.1)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.files().keys().any(|file| file.starts_with(path))
+    }
+
+    fn create_dir_all(&self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut files = self.files();
+        let file = files.remove(from).ok_or_else(not_found)?;
+        files.insert(to.to_path_buf(), file);
+        Ok(())
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let file = self.file(from)?;
+        self.files().insert(to.to_path_buf(), file);
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.files().remove(path).map(|_| ()).ok_or_else(not_found)
+    }
+}
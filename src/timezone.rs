@@ -0,0 +1,99 @@
+//! The time zone of the times in export file names.
+//!
+//! File names only have the local time the export was made, so exports are
+//! ordered by that time converted to UTC in the time zone they are assumed
+//! to be made in: the system's by default, or e.g. `+03:00` for exports
+//! downloaded on a phone set to another time zone. Around a change to or
+//! from daylight saving time, a local time may occur twice or not at all;
+//! the modification time of the file tells which one it was.
+
+use chrono::{Duration, FixedOffset, Local, LocalResult, NaiveDateTime, Offset, TimeZone as _};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Timezone {
+    /// The system's time zone, with its daylight saving time
+    #[default]
+    Local,
+    Utc,
+    Fixed(FixedOffset),
+}
+
+impl Timezone {
+    /// Returns local time `time` in UTC. `modified` is the local
+    /// modification time of the file, for times that are ambiguous or
+    /// skipped in the time zone.
+    pub fn to_utc(&self, time: NaiveDateTime, modified: Option<NaiveDateTime>) -> NaiveDateTime {
+        match self {
+            Timezone::Utc => time,
+            Timezone::Fixed(offset) => time - Duration::seconds(offset.local_minus_utc().into()),
+            Timezone::Local => match Local.from_local_datetime(&time) {
+                LocalResult::Single(t) => t.naive_utc(),
+                LocalResult::Ambiguous(earlier, later) => {
+                    // The file is usually written when it is exported, so the
+                    // one nearer to the modification time
+                    let (earlier, later) = (earlier.naive_utc(), later.naive_utc());
+                    let modified = modified
+                        .and_then(|m| Local.from_local_datetime(&m).earliest())
+                        .map(|m| m.naive_utc());
+                    let distance = |t: NaiveDateTime, m: NaiveDateTime| (t - m).num_seconds().abs();
+                    match modified {
+                        Some(m) if distance(later, m) < distance(earlier, m) => later,
+                        _ => earlier,
+                    }
+                }
+                // Skipped when clocks were set forward, so in the offset
+                // before the change
+                LocalResult::None => {
+                    let before = Local
+                        .from_local_datetime(&(time - Duration::hours(3)))
+                        .earliest()
+                        .map_or(0, |t| t.offset().fix().local_minus_utc());
+                    time - Duration::seconds(before.into())
+                }
+            },
+        }
+    }
+}
+
+impl FromStr for Timezone {
+    type Err = String;
+
+    /// Parses `local`, `utc` or an offset from UTC like `+03:00`, `+0300` or
+    /// `-5`.
+    fn from_str(s: &str) -> Result<Timezone, String> {
+        let invalid = || {
+            format!(
+                "invalid time zone '{}', expected local, utc or an offset like +02:00",
+                s
+            )
+        };
+        match s.trim().to_lowercase().as_str() {
+            "local" => return Ok(Timezone::Local),
+            "utc" | "z" => return Ok(Timezone::Utc),
+            _ => {}
+        }
+
+        let s = s.trim();
+        let (sign, rest) = if let Some(rest) = s.strip_prefix('+') {
+            (1, rest)
+        } else if let Some(rest) = s.strip_prefix(['-', '\u{2212}']) {
+            (-1, rest)
+        } else {
+            return Err(invalid());
+        };
+        let (hours, minutes) = match rest.split_once(':') {
+            Some((hours, minutes)) => (hours, minutes),
+            None if rest.len() == 4 => rest.split_at(2),
+            None => (rest, "0"),
+        };
+        let hours: i32 = hours.parse().map_err(|_| invalid())?;
+        let minutes: i32 = minutes.parse().map_err(|_| invalid())?;
+        if !(0..60).contains(&minutes) {
+            return Err(invalid());
+        }
+        FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+            .map(Timezone::Fixed)
+            .ok_or_else(invalid)
+    }
+}
@@ -0,0 +1,82 @@
+//! Detection of transfers between the user's own accounts.
+//!
+//! When several accounts are converted together, a transaction on one
+//! account and a transaction with the opposite amount on the same date on
+//! another account are assumed to be a transfer between them. Both are given
+//! YNAB's "Transfer : <Account>" payee, so that YNAB links them instead of
+//! counting them as income and expense.
+//!
+//! Credit card bills paid from a bank account appear in both the bank
+//! export and the card export, but the payment may be booked on the card a
+//! few banking days after it leaves the bank account. Between a card and
+//! another account, the opposite amount is therefore looked for up to
+//! [`CARD_PAYMENT_DAYS`] days apart, so that the bill payment becomes a
+//! transfer to the card account instead of an expense on both.
+
+use crate::{creditcard, YnabRow, OUTPUT_DATE_FORMAT};
+use chrono::NaiveDate;
+
+/// Days a credit card bill payment may take to be booked on the card
+pub const CARD_PAYMENT_DAYS: i64 = 4;
+
+/// Marks transfers between the accounts in place. `accounts` holds the
+/// account name and converted rows of each account. Returns the number of
+/// transfers found. Of several candidates, the one closest in date is
+/// taken.
+pub fn mark_transfers(accounts: &mut [(String, Vec<YnabRow>)]) -> usize {
+    let mut count = 0;
+
+    for a in 0..accounts.len() {
+        for b in a + 1..accounts.len() {
+            let (left, right) = accounts.split_at_mut(b);
+            let (a_name, a_rows) = &mut left[a];
+            let (b_name, b_rows) = &mut right[0];
+            let max_days =
+                if creditcard::is_card_number(a_name) || creditcard::is_card_number(b_name) {
+                    CARD_PAYMENT_DAYS
+                } else {
+                    0
+                };
+
+            for a_row in a_rows.iter_mut().filter(|r| !is_transfer(r)) {
+                let counterpart = b_rows
+                    .iter_mut()
+                    .filter(|b_row| !is_transfer(b_row) && b_row.amount == -a_row.amount)
+                    .filter_map(|b_row| Some((days_apart(a_row, b_row)?, b_row)))
+                    .filter(|(days, _)| *days <= max_days)
+                    .min_by_key(|(days, _)| *days)
+                    .map(|(_, b_row)| b_row);
+
+                if let Some(b_row) = counterpart {
+                    mark(a_row, b_name);
+                    mark(b_row, a_name);
+                    count += 1;
+                }
+            }
+        }
+    }
+
+    count
+}
+
+/// Number of days between the dates of two rows, if both can be read
+fn days_apart(a: &YnabRow, b: &YnabRow) -> Option<i64> {
+    if a.date == b.date {
+        return Some(0);
+    }
+    let date = |row: &YnabRow| NaiveDate::parse_from_str(&row.date, OUTPUT_DATE_FORMAT).ok();
+    Some((date(a)? - date(b)?).num_days().abs())
+}
+
+fn is_transfer(row: &YnabRow) -> bool {
+    row.payee.starts_with("Transfer : ")
+}
+
+fn mark(row: &mut YnabRow, account: &str) {
+    // Keep the original payee around in the memo
+    if row.memo.is_empty() {
+        row.memo = row.payee.clone();
+    }
+    row.payee = format!("Transfer : {}", account);
+    row.category = None;
+}
@@ -0,0 +1,87 @@
+//! Canonical forms of the text of transactions, applied as exports are read.
+//!
+//! Nordea writes the same letter sometimes precomposed, e.g. `ä` as U+00E4,
+//! and sometimes decomposed into `a` and a combining diaeresis, and pads
+//! some descriptions with no-break spaces instead of spaces. The variants
+//! look the same but compare different, so the same payee would become
+//! several payees in YNAB, and a transaction could look new when compared
+//! with the previous export. Letters followed by combining marks are
+//! therefore composed, as Unicode normalization form C would, and spaces of
+//! other kinds are replaced with plain spaces, before the rows are compared
+//! or written.
+//!
+//! Only the compositions of the Latin letters used in European languages
+//! are known, which covers what the supported banks export; other
+//! combining sequences are kept as they are.
+
+/// Precomposed letters by combining mark, as pairs of the base letter and
+/// the composed letter
+const COMPOSITIONS: &[(char, &str)] = &[
+    // Grave
+    ('\u{300}', "AÀEÈIÌOÒUÙaàeèiìoòuù"),
+    // Acute
+    (
+        '\u{301}',
+        "AÁEÉIÍOÓUÚYÝaáeéiíoóuúyýCĆcćLĹlĺNŃnńRŔrŕSŚsśZŹzź",
+    ),
+    // Circumflex
+    ('\u{302}', "AÂEÊIÎOÔUÛaâeêiîoôuû"),
+    // Tilde
+    ('\u{303}', "AÃNÑOÕaãnñoõ"),
+    // Macron
+    ('\u{304}', "AĀEĒIĪOŌUŪaāeēiīoōuū"),
+    // Dot above
+    ('\u{307}', "EĖZŻeėzż"),
+    // Diaeresis
+    ('\u{308}', "AÄEËIÏOÖUÜYŸaäeëiïoöuüyÿ"),
+    // Ring above
+    ('\u{30a}', "AÅUŮaåuů"),
+    // Double acute
+    ('\u{30b}', "OŐUŰoőuű"),
+    // Caron
+    ('\u{30c}', "CČDĎEĚNŇRŘSŠTŤZŽcčdďeěnňrřsštťzž"),
+    // Cedilla
+    ('\u{327}', "CÇSŞcçsş"),
+    // Ogonek
+    ('\u{328}', "AĄEĘIĮUŲaąeęiįuų"),
+];
+
+/// Returns `s` with letters and combining marks composed, other kinds of
+/// spaces replaced with plain spaces, and zero width characters removed.
+/// Line breaks and tabs are kept, and runs of spaces are left for
+/// [`crate::sanitize`] to collapse.
+pub fn canonicalize(s: &str) -> String {
+    // Most text has nothing to change
+    if s.is_ascii() {
+        return s.to_string();
+    }
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\t' | '\n' | '\r' | ' ' => out.push(c),
+            // Zero width characters and byte order marks
+            '\u{200b}'..='\u{200d}' | '\u{2060}' | '\u{feff}' => {}
+            c if c.is_whitespace() => out.push(' '),
+            c => match out.chars().last().and_then(|base| compose(base, c)) {
+                Some(composed) => {
+                    out.pop();
+                    out.push(composed);
+                }
+                None => out.push(c),
+            },
+        }
+    }
+    out
+}
+
+/// The precomposed letter of `base` followed by the combining mark `mark`
+fn compose(base: char, mark: char) -> Option<char> {
+    let (_, pairs) = COMPOSITIONS.iter().find(|(m, _)| *m == mark)?;
+    let mut chars = pairs.chars();
+    while let (Some(b), Some(composed)) = (chars.next(), chars.next()) {
+        if b == base {
+            return Some(composed);
+        }
+    }
+    None
+}
@@ -0,0 +1,147 @@
+//! Updating the binary from the GitHub releases with `nda2ynab self-update`,
+//! only built with the `self-update` feature.
+//!
+//! Nothing is checked unless the subcommand is run, and the only request made
+//! is for the latest release and its binary, without any information about the
+//! installation. The binary of a release is the asset whose name has the
+//! operating system and architecture of the running one, e.g.
+//! `nda2ynab-linux-x86_64` or `nda2ynab-windows-x86_64.exe`. It is downloaded
+//! next to the running binary and then renamed over it, so that a failed
+//! download leaves the old one in place.
+
+use crate::{
+    http,
+    json::{self, Value},
+};
+use std::{
+    cmp::Ordering,
+    env,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+const RELEASES_URL: &str = "https://api.github.com/repos/FruitieX/nda2ynab/releases/latest";
+
+/// URL of the latest release, overridable for testing
+fn releases_url() -> String {
+    env::var("NDA2YNAB_RELEASES_URL").unwrap_or_else(|_| RELEASES_URL.to_string())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Asset {
+    pub name: String,
+    pub url: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Release {
+    /// Version without the `v` of the tag, e.g. `0.2.0`
+    pub version: String,
+    pub assets: Vec<Asset>,
+}
+
+impl Release {
+    /// Returns the binary for the operating system `os` and architecture
+    /// `arch`, as in [`std::env::consts`].
+    pub fn asset_for(&self, os: &str, arch: &str) -> Option<&Asset> {
+        let os_names: &[&str] = match os {
+            "macos" => &["macos", "darwin", "apple"],
+            "windows" => &["windows", "win64"],
+            os => &[os],
+        };
+        self.assets.iter().find(|a| {
+            let name = a.name.to_lowercase();
+            name.contains(arch)
+                && os_names.iter().any(|os| name.contains(os))
+                && !ARCHIVES.iter().any(|ext| name.ends_with(ext))
+        })
+    }
+}
+
+/// Extensions of assets that are not a binary as such
+const ARCHIVES: [&str; 5] = [".tar.gz", ".tgz", ".zip", ".sha256", ".sig"];
+
+/// Parses a release from the GitHub API.
+pub fn parse_release(body: &str) -> Result<Release, Box<dyn Error>> {
+    let release = json::parse(body).map_err(|e| format!("Invalid response from GitHub: {}", e))?;
+    let tag = release
+        .get("tag_name")
+        .and_then(Value::as_str)
+        .ok_or("No version in the release from GitHub")?;
+    let assets = release
+        .get("assets")
+        .and_then(Value::as_array)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|a| {
+            Some(Asset {
+                name: a.get("name")?.as_str()?.to_string(),
+                url: a.get("browser_download_url")?.as_str()?.to_string(),
+            })
+        })
+        .collect();
+    Ok(Release {
+        version: tag.trim_start_matches('v').to_string(),
+        assets,
+    })
+}
+
+/// Fetches the latest release.
+pub fn latest_release() -> Result<Release, Box<dyn Error>> {
+    let url = releases_url();
+    let response = http::request(
+        "GET",
+        &url,
+        &["Accept: application/vnd.github+json".to_string()],
+        None,
+    )?;
+    if !response.is_success() {
+        return Err(format!(
+            "Could not check for updates, GitHub answered with status {}",
+            response.status
+        )
+        .into());
+    }
+    parse_release(&response.body)
+}
+
+/// Compares versions like `0.10.1` by their numbers.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let numbers = |v: &str| -> Vec<u64> {
+        v.split(['.', '-', '+'])
+            .map_while(|part| part.parse().ok())
+            .collect()
+    };
+    numbers(a).cmp(&numbers(b))
+}
+
+/// Replaces the running binary with the one downloaded from `asset`.
+pub fn install(asset: &Asset) -> Result<PathBuf, Box<dyn Error>> {
+    let exe = env::current_exe()
+        .and_then(fs::canonicalize)
+        .map_err(|e| format!("Could not find the running binary: {}", e))?;
+    let download = exe.with_extension("download");
+    http::download(&asset.url, &download)?;
+    replace(&exe, &download).inspect_err(|_| {
+        let _ = fs::remove_file(&download);
+    })?;
+    Ok(exe)
+}
+
+fn replace(exe: &Path, download: &Path) -> Result<(), Box<dyn Error>> {
+    let error = |e: std::io::Error| format!("Could not replace {}: {}", exe.display(), e);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(download, fs::Permissions::from_mode(0o755)).map_err(error)?;
+    }
+    // A running binary cannot be overwritten on Windows, but it can be renamed
+    if cfg!(windows) {
+        let old = exe.with_extension("old.exe");
+        let _ = fs::remove_file(&old);
+        fs::rename(exe, &old).map_err(error)?;
+    }
+    fs::rename(download, exe).map_err(error)?;
+    Ok(())
+}
@@ -0,0 +1,315 @@
+//! Checking YNAB CSV files with `nda2ynab validate`, for finding out why
+//! YNAB's file import rejects or misreads a file, whichever tool wrote it.
+//!
+//! The file must be comma separated UTF-8 with a header row naming YNAB's
+//! columns, a date in one format throughout, and amounts as plain numbers,
+//! either signed in an Amount column or unsigned in Outflow and Inflow
+//! columns. Payees and memos are checked against YNAB's length limits, and
+//! import ids against the API's, as files are also uploaded with the ids as
+//! they are.
+
+use chrono::NaiveDate;
+use csv::ReaderBuilder;
+use itertools::Itertools;
+use nda2ynab::{
+    info,
+    sanitize::{MAX_MEMO_LENGTH, MAX_PAYEE_LENGTH},
+    system::Clock,
+    ynab,
+};
+use regex::Regex;
+use std::{collections::HashMap, error::Error, fs, path::PathBuf};
+
+/// Date formats of YNAB's import, of which a file must use one
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%d/%m/%Y", "%m/%d/%Y", "%Y/%m/%d", "%d.%m.%Y"];
+
+/// Columns YNAB's import reads
+const COLUMNS: &[&str] = &[
+    "Date",
+    "Payee",
+    "Memo",
+    "Amount",
+    "Outflow",
+    "Inflow",
+    "Category",
+    "Flag",
+    "Import ID",
+];
+
+/// Longest import id the YNAB API accepts
+const MAX_IMPORT_ID_LENGTH: usize = 36;
+
+/// Rows in one file above which YNAB's import tends to time out
+const MAX_ROWS: usize = 5000;
+
+#[derive(clap::Args, Debug)]
+pub struct ValidateArgs {
+    /// YNAB CSV file to check
+    file: PathBuf,
+}
+
+/// A problem found in a file, on line `line` or in the whole file if 0
+#[derive(Debug, PartialEq)]
+struct Violation {
+    line: u64,
+    message: String,
+}
+
+pub fn run(clock: &dyn Clock, args: &ValidateArgs) -> Result<(), Box<dyn Error>> {
+    let bytes = fs::read(&args.file)
+        .map_err(|e| format!("Could not read {}: {}", args.file.display(), e))?;
+    let (rows, violations) = validate(&bytes, clock.now().date());
+    for v in &violations {
+        match v.line {
+            0 => println!("{}", v.message),
+            line => println!("line {}: {}", line, v.message),
+        }
+    }
+    if !violations.is_empty() {
+        return Err(format!(
+            "{} problem(s) found in {}",
+            violations.len(),
+            args.file.display()
+        )
+        .into());
+    }
+    info!(
+        "{} looks fine for YNAB's import, {} transaction(s).",
+        args.file.display(),
+        rows
+    );
+    Ok(())
+}
+
+/// Returns the number of rows of a YNAB CSV file and the problems found in it,
+/// with the dates checked against the ones YNAB accepts on `today`.
+fn validate(bytes: &[u8], today: NaiveDate) -> (usize, Vec<Violation>) {
+    let mut violations = vec![];
+    let mut violation = |line: u64, message: String| violations.push(Violation { line, message });
+
+    let Ok(contents) = std::str::from_utf8(bytes) else {
+        violation(
+            0,
+            "The file is not UTF-8, YNAB would misread non-ASCII characters".into(),
+        );
+        return (0, violations);
+    };
+    let contents = contents.strip_prefix('\u{feff}').unwrap_or(contents);
+    let first_line = contents.lines().next().unwrap_or_default();
+    if !first_line.contains(',') && first_line.contains(';') {
+        violation(
+            1,
+            "The values are separated by semicolons, YNAB expects commas".into(),
+        );
+        return (0, violations);
+    }
+
+    let mut records = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(contents.as_bytes())
+        .into_records();
+    let header = match records.next() {
+        Some(Ok(header)) => header,
+        Some(Err(e)) => {
+            violation(1, format!("The header row cannot be read: {}", e));
+            return (0, violations);
+        }
+        None => {
+            violation(0, "The file is empty".into());
+            return (0, violations);
+        }
+    };
+
+    let position = |name: &str| header.iter().position(|h| h.trim() == name);
+    for (name, count) in header.iter().map(str::trim).counts() {
+        if !COLUMNS.contains(&name) {
+            violation(
+                1,
+                format!("Unknown column '{}', which YNAB does not import", name),
+            );
+        } else if count > 1 {
+            violation(1, format!("The {} column appears {} times", name, count));
+        }
+    }
+    let date = position("Date");
+    let amount = position("Amount");
+    let (outflow, inflow) = (position("Outflow"), position("Inflow"));
+    if date.is_none() {
+        violation(1, "There is no Date column".into());
+    }
+    match (amount, outflow, inflow) {
+        (Some(_), None, None) | (None, Some(_), Some(_)) => {}
+        (None, None, None) => violation(
+            1,
+            "There is no Amount column, nor Outflow and Inflow columns".into(),
+        ),
+        (Some(_), _, _) => violation(
+            1,
+            "There is an Amount column and an Outflow or Inflow column, YNAB reads one or the other".into(),
+        ),
+        (None, _, _) => violation(1, "Outflow and Inflow columns go together".into()),
+    }
+
+    let number = Regex::new(r"^-?\d+(?:[.,]\d{1,2})?$").unwrap();
+    let oldest = ynab::date_floor(None, today);
+    let mut dates = vec![];
+    let mut import_ids: HashMap<String, u64> = HashMap::new();
+    let mut rows = 0;
+    for record in records {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                let line = e.position().map_or(0, |p| p.line());
+                violation(line, format!("The row cannot be read: {}", e));
+                continue;
+            }
+        };
+        let line = record.position().map_or(0, |p| p.line());
+        rows += 1;
+        if record.len() != header.len() {
+            violation(
+                line,
+                format!(
+                    "{} field(s) where the header has {}",
+                    record.len(),
+                    header.len()
+                ),
+            );
+            continue;
+        }
+        let field = |i: Option<usize>| i.and_then(|i| record.get(i)).unwrap_or_default().trim();
+
+        if date.is_some() {
+            dates.push((line, field(date).to_string()));
+        }
+        if amount.is_some() {
+            let value = field(amount);
+            if !number.is_match(value) {
+                violation(
+                    line,
+                    format!("Amount '{}' is not a plain number like -12.34", value),
+                );
+            }
+        } else if outflow.is_some() && inflow.is_some() {
+            let (out, r#in) = (field(outflow), field(inflow));
+            for (name, value) in [("Outflow", out), ("Inflow", r#in)] {
+                if !value.is_empty() && (!number.is_match(value) || value.starts_with('-')) {
+                    violation(
+                        line,
+                        format!("{} '{}' is not an unsigned number like 12.34", name, value),
+                    );
+                }
+            }
+            match (out.is_empty(), r#in.is_empty()) {
+                (true, true) => violation(line, "Both Outflow and Inflow are empty".into()),
+                (false, false) => violation(
+                    line,
+                    "Both Outflow and Inflow are set, YNAB expects one of them".into(),
+                ),
+                _ => {}
+            }
+        }
+
+        for (name, max) in [("Payee", MAX_PAYEE_LENGTH), ("Memo", MAX_MEMO_LENGTH)] {
+            let value = field(position(name));
+            let length = value.chars().count();
+            if length > max {
+                violation(
+                    line,
+                    format!(
+                        "{} is {} characters long, YNAB cuts it at {}",
+                        name, length, max
+                    ),
+                );
+            }
+            if value.chars().any(char::is_control) {
+                violation(line, format!("{} has control characters", name));
+            }
+        }
+
+        let import_id = field(position("Import ID"));
+        if !import_id.is_empty() {
+            if import_id.chars().count() > MAX_IMPORT_ID_LENGTH {
+                violation(
+                    line,
+                    format!(
+                        "Import ID '{}' is longer than the {} characters YNAB accepts",
+                        import_id, MAX_IMPORT_ID_LENGTH
+                    ),
+                );
+            }
+            if let Some(first) = import_ids.insert(import_id.to_string(), line) {
+                violation(
+                    line,
+                    format!(
+                        "Import ID '{}' is also on line {}, YNAB only imports one of them",
+                        import_id, first
+                    ),
+                );
+            }
+        }
+    }
+
+    // The date format is asked once for the whole file
+    let format = DATE_FORMATS.iter().find(|format| {
+        dates
+            .iter()
+            .all(|(_, d)| chrono::NaiveDate::parse_from_str(d, format).is_ok())
+    });
+    match format {
+        Some(format) => {
+            for (line, value) in &dates {
+                let date = chrono::NaiveDate::parse_from_str(value, format).expect("parsed date");
+                if date < oldest {
+                    violation(
+                        *line,
+                        format!(
+                            "Date {} is more than 5 years ago, YNAB does not accept it",
+                            value
+                        ),
+                    );
+                }
+            }
+        }
+        None => {
+            // Report the rows that do not match the format of most rows, the
+            // first of the formats matching as many
+            let best = DATE_FORMATS
+                .iter()
+                .rev()
+                .max_by_key(|format| {
+                    dates
+                        .iter()
+                        .filter(|(_, d)| chrono::NaiveDate::parse_from_str(d, format).is_ok())
+                        .count()
+                })
+                .expect("date formats");
+            for (line, value) in &dates {
+                if chrono::NaiveDate::parse_from_str(value, best).is_err() {
+                    violation(
+                        *line,
+                        format!(
+                            "Date '{}' is not in the {} format of the other rows",
+                            value, best
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    if rows == 0 {
+        violation(0, "The file has no transactions".into());
+    } else if rows > MAX_ROWS {
+        violation(
+            0,
+            format!(
+                "The file has {} transactions, YNAB's import tends to time out with more than {}; see --split-by",
+                rows, MAX_ROWS
+            ),
+        );
+    }
+    violations.sort_by_key(|v| v.line);
+    (rows, violations)
+}
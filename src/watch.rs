@@ -0,0 +1,89 @@
+//! Watching a directory for new Nordea exports.
+//!
+//! The directory is polled periodically. Exports that appear after the watch
+//! was started are converted once their size has stopped changing, which
+//! avoids reading files that are still being downloaded.
+
+use crate::{run_convert, send_notification, ConvertArgs};
+use nda2ynab::{
+    config::Config,
+    error, find_export_files, info,
+    report::Summary,
+    system::{Clock, Disk},
+};
+use std::{collections::HashMap, error::Error, fs, path::PathBuf, thread, time::Duration};
+
+#[derive(clap::Args, Debug)]
+pub struct WatchArgs {
+    /// Path to directory to watch for exported csv files [default: path from
+    /// the config file]
+    pub path: Option<String>,
+
+    /// Seconds between checks for new files
+    #[clap(long, default_value = "2")]
+    interval: u64,
+
+    #[clap(flatten)]
+    pub convert: ConvertArgs,
+}
+
+pub fn run(
+    clock: &dyn Clock,
+    path: &str,
+    args: &WatchArgs,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    if args.convert.current.is_some() {
+        return Err("--current cannot be used when watching a directory".into());
+    }
+
+    let registry = args.convert.registry(clock)?;
+    let find = || find_export_files(&Disk, path, &registry, args.convert.encoding(), None);
+    let mut seen: Vec<PathBuf> = find()?.into_iter().map(|m| m.path).collect();
+    // Sizes of new files that have not been converted yet
+    let mut pending: HashMap<PathBuf, u64> = HashMap::new();
+
+    info!("Watching {} for new Nordea exports...\n", path);
+
+    loop {
+        thread::sleep(Duration::from_secs(args.interval));
+
+        for file in find()? {
+            if seen.contains(&file.path) {
+                continue;
+            }
+
+            let size = match fs::metadata(&file.path) {
+                Ok(metadata) => metadata.len(),
+                Err(_) => continue,
+            };
+
+            // Wait until the size is stable between two checks
+            if pending.insert(file.path.clone(), size) != Some(size) {
+                continue;
+            }
+
+            pending.remove(&file.path);
+            seen.push(file.path.clone());
+
+            info!("New export detected: {}\n", file.file_name);
+            let mut summary = Summary::default();
+            let result = run_convert(
+                &Disk,
+                clock,
+                path,
+                &args.convert,
+                config,
+                Some(&file.iban),
+                &mut summary,
+            );
+            if let Err(e) = &result {
+                error!("Error: {}", e);
+            }
+            if args.convert.notify {
+                send_notification(&summary, result.as_ref().err().map(|e| e.as_ref()));
+            }
+            info!();
+        }
+    }
+}
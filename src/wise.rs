@@ -0,0 +1,227 @@
+//! Wise (formerly TransferWise) statement CSV exports.
+//!
+//! Statements of a balance are read from files named like
+//! `statement_12345678_EUR_2024-01-01_2024-01-31.csv`. Each balance is
+//! converted as an account of its own, named like `Wise 12345678 EUR` after
+//! the balance id and currency in the file name, and the end of the range is
+//! used as the export date. The contents are comma separated with
+//! `31-01-2024` or ISO dates and signed amounts with a decimal point, fees
+//! included, listed most recent first.
+
+use crate::{
+    parser::{source_columns, BankParser, FileInfo, RowError, Rows},
+    RawTransaction, Transaction, DATE_FORMAT,
+};
+use chrono::NaiveDate;
+use csv::{ReaderBuilder, StringRecord};
+use regex::Regex;
+use std::{error::Error, path::Path};
+
+const ID: &str = "TransferWise ID";
+const DATE: &str = "Date";
+const AMOUNT: &str = "Amount";
+const CURRENCY: &str = "Currency";
+const DESCRIPTION: &str = "Description";
+const REFERENCE: &str = "Payment Reference";
+const EXCHANGE_FROM: &str = "Exchange From";
+const EXCHANGE_TO: &str = "Exchange To";
+const EXCHANGE_RATE: &str = "Exchange Rate";
+const PAYER: &str = "Payer Name";
+const PAYEE: &str = "Payee Name";
+const PAYEE_ACCOUNT: &str = "Payee Account Number";
+const MERCHANT: &str = "Merchant";
+const NOTE: &str = "Note";
+
+pub struct Wise {
+    file_name_re: Regex,
+}
+
+impl Wise {
+    pub fn new() -> Wise {
+        Wise {
+            file_name_re: Regex::new(
+                r"(?i)^statement_(\d+)_([A-Z]{3})_\d{4}-\d{2}-\d{2}_(\d{4}-\d{2}-\d{2})\.csv$",
+            )
+            .unwrap(),
+        }
+    }
+
+    fn reader(contents: &str) -> csv::Reader<&[u8]> {
+        ReaderBuilder::new()
+            .delimiter(b',')
+            .flexible(true)
+            .from_reader(contents.as_bytes())
+    }
+}
+
+impl Default for Wise {
+    fn default() -> Wise {
+        Wise::new()
+    }
+}
+
+/// Positions of the columns we read in the header row
+struct Columns {
+    header: StringRecord,
+    /// Number of columns
+    len: usize,
+    date: usize,
+    amount: usize,
+    currency: usize,
+    description: usize,
+    reference: Option<usize>,
+    exchange_from: Option<usize>,
+    exchange_to: Option<usize>,
+    exchange_rate: Option<usize>,
+    payer: Option<usize>,
+    payee: Option<usize>,
+    payee_account: Option<usize>,
+    merchant: Option<usize>,
+    note: Option<usize>,
+}
+
+impl Columns {
+    fn new(header: &StringRecord) -> Option<Columns> {
+        let position = |name: &str| header.iter().position(|h| h.trim() == name);
+        // Required only to tell Wise statements apart from other exports
+        position(ID)?;
+        Some(Columns {
+            header: header.clone(),
+            len: header.len(),
+            date: position(DATE)?,
+            amount: position(AMOUNT)?,
+            currency: position(CURRENCY)?,
+            description: position(DESCRIPTION)?,
+            reference: position(REFERENCE),
+            exchange_from: position(EXCHANGE_FROM),
+            exchange_to: position(EXCHANGE_TO),
+            exchange_rate: position(EXCHANGE_RATE),
+            payer: position(PAYER),
+            payee: position(PAYEE),
+            payee_account: position(PAYEE_ACCOUNT),
+            merchant: position(MERCHANT),
+            note: position(NOTE),
+        })
+    }
+
+    fn transaction(&self, record: &StringRecord) -> Result<Transaction, String> {
+        let field = |i: usize| {
+            record
+                .get(i)
+                .map(str::trim)
+                .ok_or_else(|| format!("expected {} fields, found {}", self.len, record.len()))
+        };
+        let optional = |i: Option<usize>| {
+            i.and_then(|i| record.get(i))
+                .unwrap_or_default()
+                .trim()
+                .to_string()
+        };
+
+        // The counterparty if there is one, e.g. a card payment's merchant
+        let description = [
+            optional(self.merchant),
+            optional(self.payee),
+            optional(self.payer),
+            field(self.description)?.to_string(),
+        ]
+        .into_iter()
+        .find(|s| !s.is_empty())
+        .unwrap_or_default();
+
+        let (from, to) = (optional(self.exchange_from), optional(self.exchange_to));
+        let exchange = (!from.is_empty() && !to.is_empty() && from != to).then(|| {
+            format!(
+                "Exchanged {} to {} at {}",
+                from,
+                to,
+                optional(self.exchange_rate)
+            )
+        });
+        let message = [Some(optional(self.note)), exchange]
+            .into_iter()
+            .flatten()
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        // Dates are day first with dashes, which the other exports do not use
+        let date = field(self.date)?;
+        let date = match NaiveDate::parse_from_str(date, "%d-%m-%Y") {
+            Ok(date) => date.format(DATE_FORMAT).to_string(),
+            Err(_) => date.to_string(),
+        };
+
+        RawTransaction {
+            date,
+            amount: field(self.amount)?.to_string(),
+            description,
+            reference: optional(self.reference),
+            message,
+            counterparty_account: optional(self.payee_account),
+            value_date: String::new(),
+            payment_date: String::new(),
+            balance: String::new(),
+            currency: field(self.currency)?.to_string(),
+            original_amount: String::new(),
+            original_currency: String::new(),
+            pending: false,
+            columns: source_columns(&self.header, record),
+        }
+        .normalize()
+    }
+}
+
+impl BankParser for Wise {
+    fn name(&self) -> &'static str {
+        "wise"
+    }
+
+    fn parse_file_name(&self, file_name: &str) -> Option<FileInfo> {
+        let captures = self.file_name_re.captures(file_name)?;
+        let end = NaiveDate::parse_from_str(captures.get(3)?.as_str(), "%Y-%m-%d").ok()?;
+
+        Some(FileInfo {
+            iban: format!(
+                "Wise {} {}",
+                captures.get(1)?.as_str(),
+                captures.get(2)?.as_str().to_uppercase()
+            ),
+            date: end.and_hms_opt(0, 0, 0)?,
+        })
+    }
+
+    fn detect(&self, contents: &str) -> bool {
+        match Wise::reader(contents).headers() {
+            Ok(header) => Columns::new(header).is_some(),
+            Err(_) => false,
+        }
+    }
+
+    fn rows<'a>(&'a self, contents: &'a str, path: &Path) -> Result<Rows<'a>, Box<dyn Error>> {
+        let mut rdr = Wise::reader(contents);
+        let columns = Columns::new(rdr.headers()?).ok_or_else(|| {
+            format!(
+                "{} is missing the {}, {}, {}, {} or {} column",
+                path.display(),
+                ID,
+                DATE,
+                AMOUNT,
+                CURRENCY,
+                DESCRIPTION
+            )
+        })?;
+
+        Ok(Box::new(rdr.into_records().map(move |result| {
+            let line = match &result {
+                Ok(record) => record.position(),
+                Err(e) => e.position(),
+            }
+            .map_or(0, |p| p.line());
+            result
+                .map_err(|e| e.to_string())
+                .and_then(|record| columns.transaction(&record))
+                .map_err(|message| RowError { line, message })
+        })))
+    }
+}
@@ -0,0 +1,306 @@
+//! A small non-validating XML parser, enough for reading bank statements:
+//! elements, attributes, text, CDATA sections and the predefined and numeric
+//! character entities. Comments, processing instructions and the document
+//! type declaration are skipped. Namespace prefixes are dropped from element
+//! and attribute names.
+
+use std::fmt;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Element {
+    /// Local name, without a namespace prefix
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Element>,
+    /// Text content directly inside the element
+    pub text: String,
+}
+
+impl Element {
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    pub fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    /// Follows a path of child element names, taking the first matching
+    /// child at each step.
+    pub fn find(&self, path: &[&str]) -> Option<&Element> {
+        path.iter()
+            .try_fold(self, |element, name| element.child(name))
+    }
+
+    /// Returns the trimmed text of the element at `path`, if it is not empty.
+    pub fn text_at(&self, path: &[&str]) -> Option<&str> {
+        let text = self.find(path)?.text.trim();
+        (!text.is_empty()).then_some(text)
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    line: usize,
+    message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses an XML document into its root element.
+pub fn parse(input: &str) -> Result<Element, ParseError> {
+    let mut parser = Parser {
+        chars: input.chars().collect(),
+        pos: 0,
+        line: 1,
+    };
+    parser.document()
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, ParseError> {
+        Err(ParseError {
+            line: self.line,
+            message: message.into(),
+        })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars()
+            .enumerate()
+            .all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    fn eat_str(&mut self, s: &str) -> bool {
+        if self.starts_with(s) {
+            for _ in s.chars() {
+                self.next();
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), ParseError> {
+        if self.peek() == Some(c) {
+            self.next();
+            Ok(())
+        } else {
+            self.error(format!("expected '{}'", c))
+        }
+    }
+
+    fn skip_ws(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.next();
+        }
+    }
+
+    /// Skips everything up to and including `end`.
+    fn skip_past(&mut self, end: &str) -> Result<(), ParseError> {
+        while !self.eat_str(end) {
+            if self.next().is_none() {
+                return self.error(format!("expected '{}'", end));
+            }
+        }
+        Ok(())
+    }
+
+    /// Skips whitespace, comments, processing instructions and document type
+    /// declarations.
+    fn skip_misc(&mut self) -> Result<(), ParseError> {
+        loop {
+            self.skip_ws();
+            if self.eat_str("<!--") {
+                self.skip_past("-->")?;
+            } else if self.eat_str("<?") {
+                self.skip_past("?>")?;
+            } else if self.eat_str("<!DOCTYPE") {
+                self.skip_past(">")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn document(&mut self) -> Result<Element, ParseError> {
+        // Byte order mark
+        if self.peek() == Some('\u{feff}') {
+            self.next();
+        }
+        self.skip_misc()?;
+        let root = self.element()?;
+        self.skip_misc()?;
+        match self.peek() {
+            None => Ok(root),
+            Some(_) => self.error("unexpected content after the root element"),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, ParseError> {
+        let mut name = String::new();
+        while let Some(c) = self
+            .peek()
+            .filter(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':'))
+        {
+            name.push(c);
+            self.next();
+        }
+        if name.is_empty() {
+            return self.error("expected a name");
+        }
+        Ok(local_name(&name).to_string())
+    }
+
+    fn element(&mut self) -> Result<Element, ParseError> {
+        self.expect('<')?;
+        let mut element = Element {
+            name: self.name()?,
+            ..Element::default()
+        };
+
+        loop {
+            self.skip_ws();
+            if self.eat_str("/>") {
+                return Ok(element);
+            }
+            if self.eat_str(">") {
+                break;
+            }
+            let name = self.name()?;
+            self.skip_ws();
+            self.expect('=')?;
+            self.skip_ws();
+            let value = self.attribute_value()?;
+            element.attributes.push((name, value));
+        }
+
+        loop {
+            if self.eat_str("</") {
+                let name = self.name()?;
+                if name != element.name {
+                    return self.error(format!(
+                        "expected closing tag for '{}', found '{}'",
+                        element.name, name
+                    ));
+                }
+                self.skip_ws();
+                self.expect('>')?;
+                return Ok(element);
+            } else if self.eat_str("<!--") {
+                self.skip_past("-->")?;
+            } else if self.eat_str("<![CDATA[") {
+                while !self.eat_str("]]>") {
+                    match self.next() {
+                        Some(c) => element.text.push(c),
+                        None => return self.error("unterminated CDATA section"),
+                    }
+                }
+            } else if self.eat_str("<?") {
+                self.skip_past("?>")?;
+            } else if self.peek() == Some('<') {
+                element.children.push(self.element()?);
+            } else if self.peek() == Some('&') {
+                let c = self.entity()?;
+                element.text.push(c);
+            } else {
+                match self.next() {
+                    Some(c) => element.text.push(c),
+                    None => {
+                        return self.error(format!("unclosed element '{}'", element.name));
+                    }
+                }
+            }
+        }
+    }
+
+    fn attribute_value(&mut self) -> Result<String, ParseError> {
+        let quote = match self.next() {
+            Some(c @ ('"' | '\'')) => c,
+            _ => return self.error("expected a quoted attribute value"),
+        };
+
+        let mut value = String::new();
+        loop {
+            match self.peek() {
+                Some(c) if c == quote => {
+                    self.next();
+                    return Ok(value);
+                }
+                Some('&') => value.push(self.entity()?),
+                Some(c) => {
+                    value.push(c);
+                    self.next();
+                }
+                None => return self.error("unterminated attribute value"),
+            }
+        }
+    }
+
+    fn entity(&mut self) -> Result<char, ParseError> {
+        self.expect('&')?;
+        let mut name = String::new();
+        loop {
+            match self.next() {
+                Some(';') => break,
+                Some(c) if name.len() < 10 => name.push(c),
+                _ => return self.error("invalid character reference"),
+            }
+        }
+
+        let c = match name.as_str() {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match (name.strip_prefix("#x"), name.strip_prefix('#')) {
+                (Some(hex), _) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                (None, Some(dec)) => dec.parse().ok().and_then(char::from_u32),
+                (None, None) => None,
+            },
+        };
+
+        match c {
+            Some(c) => Ok(c),
+            None => self.error(format!("unknown entity '&{};'", name)),
+        }
+    }
+}
+
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
@@ -0,0 +1,513 @@
+//! Uploading transactions via the YNAB API.
+//!
+//! The transactions of all accounts are created in the configured budget with
+//! a single bulk request. Each transaction gets an import id in the format
+//! YNAB uses for file imports, `YNAB:<milliunits>:<date>:<occurrence>`, so
+//! YNAB skips transactions that were already uploaded or imported.
+//!
+//! The transactions are queued in the state file until YNAB has accepted
+//! them, and resubmitted by the next upload if the request fails, e.g. when
+//! the connection drops before the response arrives.
+//!
+//! The API address can be overridden with the `NDA2YNAB_YNAB_API_URL`
+//! environment variable, e.g. for testing against a mock server.
+
+use crate::{
+    amount::Amount,
+    config::Config,
+    http,
+    json::{self, Value},
+    parse_nda_date,
+    reconcile::Existing,
+    state::State,
+    YnabRow,
+};
+use chrono::{Datelike, Duration, NaiveDate};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    error::Error,
+    path::Path,
+};
+
+pub const API_URL: &str = "https://api.ynab.com/v1";
+
+/// Returns the YNAB API token and budget id from the config file.
+pub fn credentials(config: &Config) -> Result<(&str, &str), Box<dyn Error>> {
+    let token = config.ynab.token.as_deref();
+    let budget_id = config.ynab.budget_id.as_deref();
+    Ok(token
+        .zip(budget_id)
+        .ok_or("Uploading needs ynab.token and ynab.budget_id in the config file")?)
+}
+
+fn api_url() -> String {
+    env::var("NDA2YNAB_YNAB_API_URL").unwrap_or_else(|_| API_URL.to_string())
+}
+
+/// Maximum lengths of text fields accepted by the API
+const MAX_PAYEE_LEN: usize = 200;
+const MAX_MEMO_LEN: usize = 500;
+
+/// Rows to upload to one YNAB account
+pub struct AccountBatch<'a> {
+    pub account_id: &'a str,
+    pub rows: &'a [YnabRow],
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct AccountResult {
+    pub created: usize,
+    /// Transactions YNAB already had
+    pub duplicates: usize,
+    /// YNAB ids of the transactions created
+    pub ids: Vec<String>,
+}
+
+/// Returns YNAB import ids for `rows`, which are sorted most recent first.
+/// Occurrences of the same amount on the same date are counted from the
+/// oldest transaction, so that ids stay stable as new transactions are added,
+/// and after the [`YnabRow::earlier_occurrences`] of the export converted by
+/// earlier runs, so that a new transaction identical to one of those does
+/// not get the id it got.
+pub fn import_ids(rows: &[YnabRow]) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut occurrences: HashMap<(NaiveDate, i64), usize> = HashMap::new();
+    let mut ids = rows
+        .iter()
+        .rev()
+        .map(|r| {
+            let date = parse_nda_date(&r.date)?;
+            let milliunits = r.amount.cents() * 10;
+            let occurrence = occurrences.entry((date, milliunits)).or_insert(0);
+            *occurrence = (*occurrence).max(r.earlier_occurrences) + 1;
+            Ok(format!(
+                "YNAB:{}:{}:{}",
+                milliunits,
+                date.format("%Y-%m-%d"),
+                occurrence
+            ))
+        })
+        .collect::<Result<Vec<String>, Box<dyn Error>>>()?;
+
+    ids.reverse();
+    Ok(ids)
+}
+
+fn truncate(s: &str, max: usize) -> String {
+    s.chars().take(max).collect()
+}
+
+/// Result of an upload
+#[derive(Debug, Default, PartialEq)]
+pub struct Upload {
+    /// Results of the batches and of the resubmitted transactions, keyed by
+    /// YNAB account id
+    pub accounts: HashMap<String, AccountResult>,
+    /// Transactions left pending by an earlier upload that were sent again
+    pub resubmitted: usize,
+}
+
+/// Deletes the transaction with YNAB id `id`.
+pub fn delete_transaction(token: &str, budget_id: &str, id: &str) -> Result<(), Box<dyn Error>> {
+    let response = http::request(
+        "DELETE",
+        &format!(
+            "{}/budgets/{}/transactions/{}",
+            api_url(),
+            budget_id,
+            http::encode(id)
+        ),
+        &[format!("Authorization: Bearer {}", token)],
+        None,
+    )?;
+    // Already deleted, e.g. by hand
+    if response.is_success() || response.status == 404 {
+        return Ok(());
+    }
+    Err(api_error(&response))
+}
+
+/// Returns the names of the payees of the budget, leaving out deleted ones
+/// and the transfer payees of accounts.
+pub fn payees(token: &str, budget_id: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let response = http::request(
+        "GET",
+        &format!("{}/budgets/{}/payees", api_url(), budget_id),
+        &[format!("Authorization: Bearer {}", token)],
+        None,
+    )?;
+    if !response.is_success() {
+        return Err(api_error(&response));
+    }
+    let json =
+        json::parse(&response.body).map_err(|e| format!("Invalid response from YNAB: {}", e))?;
+
+    let payees = json
+        .get("data")
+        .and_then(|d| d.get("payees"))
+        .and_then(Value::as_array)
+        .ok_or("Invalid response from YNAB")?;
+    Ok(payees
+        .iter()
+        .filter(|p| p.get("deleted") != Some(&Value::Bool(true)))
+        .filter(|p| {
+            p.get("transfer_account_id")
+                .is_none_or(|id| *id == Value::Null)
+        })
+        .filter_map(|p| p.get("name")?.as_str())
+        .map(String::from)
+        .collect())
+}
+
+/// A category of the budget in the current month
+#[derive(Clone, Debug, PartialEq)]
+pub struct Category {
+    pub name: String,
+    pub group: String,
+    /// Amount available to spend
+    pub available: Amount,
+}
+
+/// Returns the categories of the budget in the current month, leaving out
+/// deleted and hidden ones.
+pub fn month_categories(token: &str, budget_id: &str) -> Result<Vec<Category>, Box<dyn Error>> {
+    let response = http::request(
+        "GET",
+        &format!("{}/budgets/{}/months/current", api_url(), budget_id),
+        &[format!("Authorization: Bearer {}", token)],
+        None,
+    )?;
+    if !response.is_success() {
+        return Err(api_error(&response));
+    }
+    let json =
+        json::parse(&response.body).map_err(|e| format!("Invalid response from YNAB: {}", e))?;
+
+    let categories = json
+        .get("data")
+        .and_then(|d| d.get("month"))
+        .and_then(|m| m.get("categories"))
+        .and_then(Value::as_array)
+        .ok_or("Invalid response from YNAB")?;
+    categories
+        .iter()
+        .filter(|c| c.get("deleted") != Some(&Value::Bool(true)))
+        .filter(|c| c.get("hidden") != Some(&Value::Bool(true)))
+        .map(|c| {
+            let invalid = || format!("Invalid category from YNAB: {}", c);
+            let text = |key| c.get(key).and_then(Value::as_str).unwrap_or_default();
+            let balance = c
+                .get("balance")
+                .and_then(Value::as_i64)
+                .ok_or_else(invalid)?;
+            Ok(Category {
+                name: c
+                    .get("name")
+                    .and_then(Value::as_str)
+                    .ok_or_else(invalid)?
+                    .to_string(),
+                group: text("category_group_name").to_string(),
+                available: Amount::from_cents(balance / 10),
+            })
+        })
+        .collect()
+}
+
+/// Returns the cleared balance of the YNAB account `account_id`.
+/// Returns an account of the budget.
+fn account(token: &str, budget_id: &str, account_id: &str) -> Result<Value, Box<dyn Error>> {
+    let response = http::request(
+        "GET",
+        &format!(
+            "{}/budgets/{}/accounts/{}",
+            api_url(),
+            budget_id,
+            account_id
+        ),
+        &[format!("Authorization: Bearer {}", token)],
+        None,
+    )?;
+    if !response.is_success() {
+        return Err(api_error(&response));
+    }
+    let json =
+        json::parse(&response.body).map_err(|e| format!("Invalid response from YNAB: {}", e))?;
+    json.get("data")
+        .and_then(|d| d.get("account"))
+        .cloned()
+        .ok_or_else(|| "Invalid response from YNAB".into())
+}
+
+pub fn cleared_balance(
+    token: &str,
+    budget_id: &str,
+    account_id: &str,
+) -> Result<Amount, Box<dyn Error>> {
+    let milliunits = account(token, budget_id, account_id)?
+        .get("cleared_balance")
+        .and_then(Value::as_i64)
+        .ok_or("Invalid response from YNAB")?;
+    Ok(Amount::from_cents(milliunits / 10))
+}
+
+/// Returns the day the account was last reconciled, if it has been.
+pub fn last_reconciled(
+    token: &str,
+    budget_id: &str,
+    account_id: &str,
+) -> Result<Option<NaiveDate>, Box<dyn Error>> {
+    let account = account(token, budget_id, account_id)?;
+    // A time in UTC, e.g. "2024-01-03T12:00:00.000Z", or null
+    let Some(time) = account.get("last_reconciled_at").and_then(Value::as_str) else {
+        return Ok(None);
+    };
+    let date = time
+        .get(..10)
+        .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+        .ok_or_else(|| format!("Invalid reconciliation time from YNAB: {}", time))?;
+    Ok(Some(date))
+}
+
+/// Returns the oldest date YNAB accepts for a new transaction of an account
+/// last reconciled on `reconciled`, on `today`. YNAB does not accept dates
+/// more than five years back, and older transactions than the reconciliation
+/// would change the reconciled balance.
+pub fn date_floor(reconciled: Option<NaiveDate>, today: NaiveDate) -> NaiveDate {
+    let five_years_ago = today
+        .with_year(today.year() - 5)
+        // February 29th
+        .unwrap_or_else(|| today - Duration::days(5 * 365));
+    reconciled.map_or(five_years_ago, |r| r.max(five_years_ago))
+}
+
+/// Returns the transactions of the YNAB account `account_id` dated on or
+/// after `since`, leaving out deleted ones.
+pub fn account_transactions(
+    token: &str,
+    budget_id: &str,
+    account_id: &str,
+    since: NaiveDate,
+) -> Result<Vec<Existing>, Box<dyn Error>> {
+    let response = http::request(
+        "GET",
+        &format!(
+            "{}/budgets/{}/accounts/{}/transactions?since_date={}",
+            api_url(),
+            budget_id,
+            account_id,
+            since.format("%Y-%m-%d")
+        ),
+        &[format!("Authorization: Bearer {}", token)],
+        None,
+    )?;
+    if !response.is_success() {
+        return Err(api_error(&response));
+    }
+    let json =
+        json::parse(&response.body).map_err(|e| format!("Invalid response from YNAB: {}", e))?;
+
+    let transactions = json
+        .get("data")
+        .and_then(|d| d.get("transactions"))
+        .and_then(Value::as_array)
+        .ok_or("Invalid response from YNAB")?;
+    transactions
+        .iter()
+        .filter(|t| t.get("deleted") != Some(&Value::Bool(true)))
+        .map(|t| {
+            let invalid = || format!("Invalid transaction from YNAB: {}", t);
+            let date = t.get("date").and_then(Value::as_str).ok_or_else(invalid)?;
+            let milliunits = t
+                .get("amount")
+                .and_then(Value::as_i64)
+                .ok_or_else(invalid)?;
+            Ok(Existing {
+                date: NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| invalid())?,
+                amount: Amount::from_cents(milliunits / 10),
+                payee: t
+                    .get("payee_name")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+                import_id: t.get("import_id").and_then(Value::as_str).map(String::from),
+            })
+        })
+        .collect()
+}
+
+/// Returns the error of a failed request, with the detail YNAB gave if the
+/// body is one of its JSON errors rather than e.g. an error page of a proxy.
+fn api_error(response: &http::Response) -> Box<dyn Error> {
+    let json = json::parse(&response.body).ok();
+    let detail = json
+        .as_ref()
+        .and_then(|json| json.get("error"))
+        .and_then(|e| e.get("detail"))
+        .and_then(Value::as_str)
+        .unwrap_or("unknown error");
+    format!("YNAB API error ({}): {}", response.status, detail).into()
+}
+
+/// Returns the transactions of all batches as sent to the API.
+fn transactions(batches: &[AccountBatch]) -> Result<Vec<Value>, Box<dyn Error>> {
+    let mut transactions = vec![];
+    for batch in batches {
+        let ids = import_ids(batch.rows)?;
+        for (r, import_id) in batch.rows.iter().zip(ids) {
+            let date = parse_nda_date(&r.date)?;
+            let mut transaction = vec![
+                ("account_id", batch.account_id.into()),
+                ("date", date.format("%Y-%m-%d").to_string().into()),
+                ("amount", (r.amount.cents() * 10).into()),
+                ("payee_name", truncate(&r.payee, MAX_PAYEE_LEN).into()),
+                ("memo", truncate(&r.memo, MAX_MEMO_LEN).into()),
+                (
+                    "cleared",
+                    if r.pending { "uncleared" } else { "cleared" }.into(),
+                ),
+                ("import_id", import_id.into()),
+            ];
+            if let Some(flag) = r.flag {
+                transaction.push(("flag_color", flag.as_str().into()));
+            }
+            transactions.push(Value::object(transaction));
+        }
+    }
+    Ok(transactions)
+}
+
+/// Returns whether a failed upload with HTTP `status` is kept for sending
+/// again, as the failure is not about the transactions: rate limiting or an
+/// error of the server.
+fn is_retried(status: u16) -> bool {
+    status == 429 || status >= 500
+}
+
+/// Account and import id of a transaction sent to the API
+fn transaction_key(transaction: &Value) -> Option<(&str, &str)> {
+    Some((
+        transaction.get("account_id")?.as_str()?,
+        transaction.get("import_id")?.as_str()?,
+    ))
+}
+
+/// Creates the transactions of all batches with one request.
+///
+/// The transactions are first added to the pending uploads of `state`, which
+/// is saved to `state_path`, and only removed from them once YNAB has
+/// accepted them. Transactions left pending by an earlier upload that failed
+/// midway are sent again along with the new ones; YNAB recognises the ones
+/// it did receive by their import ids, so nothing is lost or duplicated. A
+/// pending transaction is only replaced by a new one with the same account
+/// and import id, which is the same transaction converted again, as a new
+/// identical transaction on the same day is numbered after it by
+/// [`import_ids`]. Only uploads failing to reach YNAB, or with a rate limit
+/// or server error, are kept pending; transactions YNAB refuses are dropped,
+/// so that they do not fail every later upload.
+pub fn upload(
+    token: &str,
+    budget_id: &str,
+    batches: &[AccountBatch],
+    state: &mut State,
+    state_path: &Path,
+) -> Result<Upload, Box<dyn Error>> {
+    let transactions = transactions(batches)?;
+    let keys: HashSet<_> = transactions.iter().filter_map(transaction_key).collect();
+    let mut pending: Vec<Value> = state
+        .pending_uploads
+        .iter()
+        .filter(|t| transaction_key(t).is_none_or(|key| !keys.contains(&key)))
+        .cloned()
+        .collect();
+    let resubmitted = pending.len();
+    pending.extend(transactions.iter().cloned());
+
+    let mut upload = Upload {
+        accounts: batches
+            .iter()
+            .map(|b| (b.account_id.to_string(), AccountResult::default()))
+            .collect(),
+        resubmitted,
+    };
+    if pending.is_empty() {
+        return Ok(upload);
+    }
+
+    state.pending_uploads = pending;
+    state.save(state_path)?;
+
+    let body = Value::object([("transactions", Value::Array(state.pending_uploads.clone()))]);
+    let response = http::request(
+        "POST",
+        &format!("{}/budgets/{}/transactions", api_url(), budget_id),
+        &[
+            format!("Authorization: Bearer {}", token),
+            "Content-Type: application/json".to_string(),
+        ],
+        Some(&body.to_string()),
+    )?;
+
+    if !response.is_success() {
+        let error = api_error(&response);
+        if is_retried(response.status) {
+            return Err(error);
+        }
+        // YNAB refused the transactions themselves, which sending them
+        // again would not change, so they are not kept for the next upload
+        let refused = state.pending_uploads.len();
+        state.pending_uploads.clear();
+        state.save(state_path)?;
+        return Err(format!(
+            "{}, none of the {} transaction(s) were uploaded and they will not be sent again",
+            error, refused
+        )
+        .into());
+    }
+    let json =
+        json::parse(&response.body).map_err(|e| format!("Invalid response from YNAB: {}", e))?;
+
+    let data = json.get("data").ok_or("Invalid response from YNAB")?;
+    let duplicates: HashSet<&str> = data
+        .get("duplicate_import_ids")
+        .and_then(Value::as_array)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(Value::as_str)
+        .collect();
+
+    let created = data
+        .get("transactions")
+        .and_then(Value::as_array)
+        .unwrap_or_default();
+    // Import ids are only unique within an account, so a transaction created
+    // in one account is not taken for a duplicate with the same import id in
+    // another
+    let created_keys: HashSet<(&str, &str)> = created.iter().filter_map(transaction_key).collect();
+    for transaction in created {
+        let (Some((account_id, _)), Some(id)) = (
+            transaction_key(transaction),
+            transaction.get("id").and_then(Value::as_str),
+        ) else {
+            continue;
+        };
+        if let Some(result) = upload.accounts.get_mut(account_id) {
+            result.ids.push(id.to_string());
+        }
+    }
+
+    // Including the resubmitted transactions, under their own accounts
+    for key in state.pending_uploads.iter().filter_map(transaction_key) {
+        let (account_id, import_id) = key;
+        let result = upload.accounts.entry(account_id.to_string()).or_default();
+        if duplicates.contains(import_id) && !created_keys.contains(&key) {
+            result.duplicates += 1;
+        } else {
+            result.created += 1;
+        }
+    }
+
+    state.pending_uploads.clear();
+    state.save(state_path)?;
+
+    Ok(upload)
+}
@@ -0,0 +1,55 @@
+use nda2ynab::{aggregate::aggregate_small, YnabRow};
+
+fn row(date: &str, amount: &str, payee: &str) -> YnabRow {
+    YnabRow {
+        date: date.to_string(),
+        payee: payee.to_string(),
+        memo: String::new(),
+        amount: amount.parse().unwrap(),
+        category: None,
+        flag: None,
+        reference: String::new(),
+        pending: false,
+        earlier_occurrences: 0,
+    }
+}
+
+#[test]
+fn small_outflows_are_merged_per_day() {
+    let mut rows = vec![
+        row("2024-01-02", "-1.50", "Kiosk"),
+        row("2024-01-02", "-25.00", "Grocery"),
+        row("2024-01-02", "-0.80", "Bus"),
+        row("2024-01-02", "1.00", "Refund"),
+        row("2024-01-01", "-1.20", "Cafe"),
+    ];
+    let merged = aggregate_small(&mut rows, "2.00".parse().unwrap());
+    assert_eq!(merged, 2);
+
+    let rows: Vec<_> = rows
+        .iter()
+        .map(|r| {
+            (
+                r.date.as_str(),
+                r.payee.as_str(),
+                r.memo.as_str(),
+                r.amount.to_string(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            (
+                "2024-01-02",
+                "Misc small purchases",
+                "Kiosk -1.50, Bus -0.80",
+                "-2.30".to_string()
+            ),
+            ("2024-01-02", "Grocery", "", "-25.00".to_string()),
+            ("2024-01-02", "Refund", "", "1.00".to_string()),
+            // A single small outflow of a day is kept as it is
+            ("2024-01-01", "Cafe", "", "-1.20".to_string()),
+        ]
+    );
+}
@@ -0,0 +1,20 @@
+use nda2ynab::amount::Amount;
+
+#[test]
+fn lone_separators_before_three_digits_are_ambiguous() {
+    let cents = |s: &str, decimal| Amount::parse_with_decimal(s, decimal).map(|a| a.cents());
+    for amount in ["1.000", "-1,000", "+999,999"] {
+        let error = amount.parse::<Amount>().unwrap_err();
+        assert!(error.contains("Ambiguous amount"), "{}", error);
+    }
+    assert_eq!(cents("1.000", Some(',')), Ok(100_000));
+    assert_eq!(cents("-1,000", Some(',')), Ok(-100));
+    assert_eq!(cents("1.000", Some('.')), Ok(100));
+
+    // Not ambiguous with a leading zero, other digit counts or both separators
+    assert_eq!(cents("0,500", None), Ok(50));
+    assert_eq!(cents("1234.000", None), Ok(123_400));
+    assert_eq!(cents("1.000,50", None), Ok(100_050));
+    assert_eq!(cents("1.000.000", None), Ok(100_000_000));
+    assert_eq!(cents("1,00", None), Ok(100));
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use common::TempDir;
+use nda2ynab::atomic::{self, AtomicFile};
+use std::{fs, io::Write};
+
+#[test]
+fn files_are_only_replaced_when_committed() {
+    let dir = TempDir::new();
+    let path = dir.path().join("out.csv");
+    fs::write(&path, "old").unwrap();
+
+    let mut file = AtomicFile::create(&path).unwrap();
+    file.write_all(b"half a ro").unwrap();
+    drop(file);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+
+    let mut file = AtomicFile::create(&path).unwrap();
+    file.write_all(b"new").unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+    file.commit().unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+
+    // No temporary files are left behind
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn read_only_outputs_are_refused_up_front() {
+    let dir = TempDir::new();
+    let path = dir.path().join("out.csv");
+    atomic::check_writable(&[&path]).unwrap();
+
+    fs::write(&path, "imported").unwrap();
+    let mut permissions = fs::metadata(&path).unwrap().permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(&path, permissions).unwrap();
+    let error = atomic::check_writable(&[&path]).unwrap_err();
+    assert!(error.to_string().contains("read-only"), "{}", error);
+
+    let missing = dir.path().join("missing").join("out.csv");
+    assert!(atomic::check_writable(&[&missing]).is_err());
+}
@@ -0,0 +1,435 @@
+mod common;
+
+use common::{amount, date, options, TempDir, IBAN};
+use nda2ynab::{
+    convert_account,
+    encoding::Encoding,
+    find_export_files,
+    mapped::{InputMap, Mapped},
+    parser::Registry,
+    system::{Disk, SystemClock},
+    Conversion, ParsedFileName,
+};
+use std::fs;
+
+fn convert(dir: &TempDir) -> Conversion {
+    let exports = dir.exports();
+    let files: Vec<&ParsedFileName> = exports.iter().collect();
+    convert_account(
+        &Disk,
+        &files,
+        &Registry::builtin(None, &SystemClock),
+        &options(),
+    )
+    .unwrap()
+}
+
+const OP_HEADER: &str = "Kirjauspäivä;Arvopäivä;Määrä EUROA;Laji;Selitys;Saaja/Maksaja;\
+                         Saajan tilinumero;Saajan pankin BIC;Viite;Viesti;Arkistointitunnus";
+
+const S_PANKKI_HEADER: &str = "Kirjauspäivä;Maksupäivä;Summa;Tapahtumalaji;Maksaja;\
+                               Saajan nimi;Saajan tilinumero;Saajan BIC-tunnus;Viitenumero;\
+                               Viesti;Arkistointitunnus";
+
+const DANSKE_HEADER: &str =
+    "\"Pvm\",\"Saaja/Maksaja\",\"Määrä EUROA\",\"Saldo EUROA\",\"Tila\",\"Tarkastus\"";
+
+const REVOLUT_HEADER: &str =
+    "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance";
+
+const WISE_HEADER: &str = "\"TransferWise ID\",Date,Amount,Currency,Description,\
+                           \"Payment Reference\",\"Running Balance\",\"Exchange From\",\
+                           \"Exchange To\",\"Exchange Rate\",\"Payer Name\",\"Payee Name\",\
+                           \"Payee Account Number\",Merchant,Note,\"Total fees\"";
+
+fn write(dir: &TempDir, name: &str, header: &str, rows: &[&str]) {
+    let contents = [header].iter().chain(rows).copied().collect::<Vec<_>>();
+    fs::write(dir.path().join(name), contents.join("\n") + "\n").unwrap();
+}
+
+#[test]
+fn op_exports_are_compared_oldest_first() {
+    let dir = TempDir::new();
+    let iban = IBAN.replace(' ', "");
+    write(
+        &dir,
+        &format!("Tapahtumat_{}_20240101-20240102.csv", iban),
+        OP_HEADER,
+        &["02.01.2024;02.01.2024;-5,00;106;KORTTIOSTO;K-MARKET;;;;;1"],
+    );
+    write(
+        &dir,
+        &format!("Tapahtumat_{}_20240101-20240105.csv", iban),
+        OP_HEADER,
+        &[
+            "02.01.2024;02.01.2024;-5,00;106;KORTTIOSTO;K-MARKET;;;;;1",
+            "04.01.2024;04.01.2024;+1200,00;710;PALKKA;EMPLOYER OY;;;;Palkka;2",
+            "05.01.2024;05.01.2024;-2,50;730;PALVELUMAKSU;;;;;;3",
+        ],
+    );
+
+    let exports = dir.exports();
+    assert_eq!(exports[0].bank, "op");
+    assert_eq!(exports[0].iban, IBAN);
+
+    let conversion = convert(&dir);
+    let rows: Vec<_> = conversion
+        .rows
+        .iter()
+        .map(|r| (r.date, r.amount, r.description.as_str()))
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            (date("2024/01/05"), amount("-2.50"), "PALVELUMAKSU"),
+            (date("2024/01/04"), amount("1200.00"), "EMPLOYER OY"),
+        ]
+    );
+    assert_eq!(conversion.rows[1].message, "Palkka");
+    assert_eq!(conversion.skipped, 1);
+}
+
+#[test]
+fn s_pankki_payees_follow_the_direction() {
+    let dir = TempDir::new();
+    write(
+        &dir,
+        &format!("Tilitapahtumat_{}_20240101-20240105.csv", IBAN),
+        S_PANKKI_HEADER,
+        &[
+            "05.01.2024;05.01.2024;-12,34;KORTTIOSTO;MATTI MEIKÄLÄINEN;PRISMA;;;;;1",
+            "04.01.2024;04.01.2024;+50,00;TILISIIRTO;MAIJA MEIKÄLÄINEN;MATTI MEIKÄLÄINEN;;;;;2",
+        ],
+    );
+
+    let exports = dir.exports();
+    assert_eq!(exports[0].bank, "s-pankki");
+
+    let conversion = convert(&dir);
+    let payees: Vec<_> = conversion
+        .rows
+        .iter()
+        .map(|r| r.description.as_str())
+        .collect();
+    assert_eq!(payees, ["PRISMA", "MAIJA MEIKÄLÄINEN"]);
+    assert_eq!(conversion.rows[1].amount, amount("50.00"));
+}
+
+#[test]
+fn danske_exports_are_converted() {
+    let dir = TempDir::new();
+    let iban = IBAN.replace(' ', "");
+    write(
+        &dir,
+        &format!("Danske_{}_20240101-20240102.csv", iban),
+        DANSKE_HEADER,
+        &[r#""02.01.2024","K-MARKET","-5,00","95,00","Toteutunut","""#],
+    );
+    write(
+        &dir,
+        &format!("Danske_{}_20240101-20240105.csv", iban),
+        DANSKE_HEADER,
+        &[
+            r#""04.01.2024","EMPLOYER OY","1200,00","1295,00","Toteutunut","""#,
+            r#""02.01.2024","K-MARKET","-5,00","95,00","Toteutunut","""#,
+        ],
+    );
+
+    let exports = dir.exports();
+    assert_eq!(exports[0].bank, "danske");
+    assert_eq!(exports[0].iban, IBAN);
+
+    let conversion = convert(&dir);
+    assert_eq!(conversion.rows.len(), 1);
+    assert_eq!(conversion.rows[0].date, date("2024/01/04"));
+    assert_eq!(conversion.rows[0].amount, amount("1200.00"));
+    assert_eq!(conversion.rows[0].description, "EMPLOYER OY");
+}
+
+#[test]
+fn other_finnish_banks_are_not_detected_as_nordea() {
+    let registry = Registry::builtin(None, &SystemClock);
+    let op = format!(
+        "{}\n02.01.2024;02.01.2024;-5,00;106;;SHOP;;;;;1\n",
+        OP_HEADER
+    );
+    assert_eq!(registry.detect(&op).unwrap().name(), "op");
+    let danske = format!(
+        "{}\n\"02.01.2024\",\"SHOP\",\"-5,00\",\"95,00\",\"\",\"\"\n",
+        DANSKE_HEADER
+    );
+    assert_eq!(registry.detect(&danske).unwrap().name(), "danske");
+}
+
+#[test]
+fn revolut_statements_are_converted_per_currency() {
+    let dir = TempDir::new();
+    write(
+        &dir,
+        "account-statement_2024-01-01_2024-01-02_en-us_a1b2c3.csv",
+        REVOLUT_HEADER,
+        &["CARD_PAYMENT,Current,2024-01-02 09:00:00,2024-01-02 10:00:00,Shop,-5.00,0.00,USD,COMPLETED,95.00"],
+    );
+    write(
+        &dir,
+        "account-statement_2024-01-01_2024-01-05_en-us_d4e5f6.csv",
+        REVOLUT_HEADER,
+        &[
+            "CARD_PAYMENT,Current,2024-01-02 09:00:00,2024-01-02 10:00:00,Shop,-5.00,0.00,USD,COMPLETED,95.00",
+            "CARD_PAYMENT,Current,2024-01-03 09:00:00,,Declined shop,-9.00,0.00,USD,DECLINED,",
+            "TRANSFER,Current,2024-01-04 12:00:00,2024-01-04 12:30:00,To Jane,-20.00,0.50,USD,COMPLETED,74.50",
+        ],
+    );
+
+    let exports = dir.exports();
+    assert_eq!(exports[0].bank, "revolut");
+    assert_eq!(exports[0].iban, "Revolut USD");
+    assert_eq!(exports[0].date.to_string(), "2024-01-04 12:30:00");
+
+    let conversion = convert(&dir);
+    assert_eq!(conversion.rows.len(), 1);
+    assert_eq!(conversion.rows[0].date, date("2024/01/04"));
+    assert_eq!(conversion.rows[0].amount, amount("-20.50"));
+    assert_eq!(conversion.rows[0].message, "Fee 0.50 USD");
+}
+
+#[test]
+fn wise_statements_are_converted() {
+    let dir = TempDir::new();
+    write(
+        &dir,
+        "statement_12345678_EUR_2024-01-01_2024-01-05.csv",
+        WISE_HEADER,
+        &[
+            "CARD-2,05-01-2024,-3.20,EUR,\"Card transaction\",,96.80,,,,,,,\"Cafe\",,0.00",
+            "TRANSFER-1,02-01-2024,-100.00,EUR,\"Converted EUR to USD\",,100.00,EUR,USD,1.0850,,,,,,0.45",
+        ],
+    );
+
+    let exports = dir.exports();
+    assert_eq!(exports[0].bank, "wise");
+    assert_eq!(exports[0].iban, "Wise 12345678 EUR");
+
+    let conversion = convert(&dir);
+    let rows: Vec<_> = conversion
+        .rows
+        .iter()
+        .map(|r| (r.date, r.amount, r.description.as_str(), r.message.as_str()))
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            (date("2024/01/05"), amount("-3.20"), "Cafe", ""),
+            (
+                date("2024/01/02"),
+                amount("-100.00"),
+                "Converted EUR to USD",
+                "Exchanged EUR to USD at 1.0850"
+            ),
+        ]
+    );
+}
+
+#[test]
+fn camt_statements_are_converted() {
+    let dir = TempDir::new();
+    let statement = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.02">
+  <BkToCstmrStmt>
+    <GrpHdr><MsgId>1</MsgId><CreDtTm>2024-01-05T08:00:00+02:00</CreDtTm></GrpHdr>
+    <Stmt>
+      <Id>1</Id>
+      <CreDtTm>2024-01-05T08:30:00.123+02:00</CreDtTm>
+      <Acct><Id><IBAN>FI1234567890123456</IBAN></Id></Acct>
+      <Ntry>
+        <Amt Ccy="EUR">12.50</Amt>
+        <CdtDbtInd>DBIT</CdtDbtInd>
+        <Sts>BOOK</Sts>
+        <BookgDt><Dt>2024-01-03</Dt></BookgDt>
+        <ValDt><Dt>2024-01-02</Dt></ValDt>
+        <NtryDtls><TxDtls>
+          <RltdPties><Cdtr><Nm>K-MARKET KAMPPI</Nm></Cdtr></RltdPties>
+          <RmtInf>
+            <Ustrd>Ostos</Ustrd>
+            <Strd><CdtrRefInf><Ref>RF18539007547034</Ref></CdtrRefInf></Strd>
+          </RmtInf>
+        </TxDtls></NtryDtls>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">2500.00</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts>BOOK</Sts>
+        <BookgDt><Dt>2024-01-04</Dt></BookgDt>
+        <NtryDtls>
+          <TxDtls>
+            <RltdPties>
+              <Dbtr><Nm>EMPLOYER OY</Nm></Dbtr>
+              <DbtrAcct><Id><IBAN>FI2112345600000785</IBAN></Id></DbtrAcct>
+              <Cdtr><Nm>SHOULD NOT BE USED</Nm></Cdtr>
+            </RltdPties>
+            <RmtInf><Ustrd>Palkka</Ustrd><Ustrd>01/2024</Ustrd></RmtInf>
+          </TxDtls>
+          <TxDtls>
+            <RltdPties><Dbtr><Nm>SECOND DEBTOR</Nm></Dbtr></RltdPties>
+          </TxDtls>
+        </NtryDtls>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">9.90</Amt>
+        <CdtDbtInd>DBIT</CdtDbtInd>
+        <Sts>PDNG</Sts>
+        <ValDt><Dt>2024-01-05</Dt></ValDt>
+        <AddtlNtryInf>HOLD</AddtlNtryInf>
+      </Ntry>
+    </Stmt>
+  </BkToCstmrStmt>
+</Document>
+"#;
+    fs::write(dir.path().join("statement.xml"), statement).unwrap();
+
+    let exports = dir.exports();
+    assert_eq!(exports[0].bank, "camt");
+    assert_eq!(exports[0].iban, IBAN);
+    assert_eq!(exports[0].date.to_string(), "2024-01-05 08:30:00");
+
+    // Most recent first, with the details of the first of an entry's
+    // transactions
+    let conversion = convert(&dir);
+    let rows: Vec<_> = conversion
+        .rows
+        .iter()
+        .map(|r| {
+            (
+                r.date,
+                r.amount,
+                r.description.as_str(),
+                r.reference.as_str(),
+                r.message.as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            (
+                date("2024/01/04"),
+                amount("2500.00"),
+                "EMPLOYER OY",
+                "",
+                "Palkka 01/2024"
+            ),
+            (
+                date("2024/01/03"),
+                amount("-12.50"),
+                "K-MARKET KAMPPI",
+                "RF18539007547034",
+                "Ostos"
+            ),
+        ]
+    );
+    assert_eq!(
+        conversion.rows[0].counterparty_account,
+        "FI21 1234 5600 0007 85"
+    );
+    assert_eq!(conversion.rows[1].value_date, Some(date("2024/01/02")));
+    assert_eq!(conversion.rows[1].currency, "EUR");
+
+    // Pending entries are dated by their value date
+    assert_eq!(conversion.holds.len(), 1);
+    assert_eq!(conversion.holds[0].date, date("2024/01/05"));
+    assert_eq!(conversion.holds[0].amount, amount("-9.90"));
+    assert_eq!(conversion.holds[0].description, "HOLD");
+}
+
+#[test]
+fn exports_of_other_banks_are_read_by_an_input_map() {
+    let dir = TempDir::new();
+    let header = "Account|Date|Description|Money out|Money in|Notes";
+    let iban = IBAN.replace(' ', "");
+    write(
+        &dir,
+        "transactions-january.csv",
+        header,
+        &[&format!("{}|01/02/2024|Grocer|5.00||", iban)],
+    );
+    write(
+        &dir,
+        "transactions-later.csv",
+        header,
+        &[
+            &format!("{}|01/04/2024|Employer||1,200.00|Salary", iban),
+            &format!("{}|01/02/2024|Grocer|5.00||", iban),
+        ],
+    );
+
+    let map: InputMap = "date=Date,payee=Description,outflow=Money out,inflow=Money in,\
+                         memo=Notes,account=Account,date_format=%m/%d/%Y,delimiter=pipe"
+        .parse()
+        .unwrap();
+    let mut registry = Registry::builtin(None, &SystemClock);
+    registry.register(Box::new(Mapped::new(map)));
+    let exports =
+        find_export_files(&Disk, dir.path_str(), &registry, Encoding::Auto, None).unwrap();
+    assert_eq!(exports.len(), 2);
+    assert!(exports.iter().all(|e| e.bank == "mapped" && e.iban == IBAN));
+
+    let files: Vec<&ParsedFileName> = exports.iter().collect();
+    let conversion = convert_account(&Disk, &files, &registry, &options()).unwrap();
+    let rows: Vec<_> = conversion
+        .rows
+        .iter()
+        .map(|r| (r.date, r.amount, r.description.as_str(), r.message.as_str()))
+        .collect();
+    assert_eq!(
+        rows,
+        [(date("2024/01/04"), amount("1200.00"), "Employer", "Salary")]
+    );
+    assert_eq!(conversion.skipped, 1);
+}
+
+#[test]
+fn input_maps_read_one_account_per_file_and_dates_by_their_format() {
+    let dir = TempDir::new();
+    let header = "Account;Date;Description;Amount";
+    write(
+        &dir,
+        "accounts.csv",
+        header,
+        &[
+            &format!("{};2024-01-04;Grocer;-5,00", IBAN.replace(' ', "")),
+            "FI9911112222333344;2024-01-03;Employer;1200,00",
+        ],
+    );
+    write(
+        &dir,
+        "dates.csv",
+        header,
+        &["ACCOUNT;01/04/2024;Grocer;-5,00"],
+    );
+
+    let mut registry = Registry::builtin(None, &SystemClock);
+    let map = "date=Date,payee=Description,amount=Amount,account=Account";
+    registry.register(Box::new(Mapped::new(map.parse().unwrap())));
+    let read = |name: &str| registry.read(&Disk, "mapped", &dir.path().join(name), Encoding::Auto);
+    let error = read("accounts.csv").err().unwrap().to_string();
+    assert!(error.contains("rows of several accounts"), "{}", error);
+    assert!(error.contains("FI99 1111 2222 3333 44"), "{}", error);
+
+    let errors = read("dates.csv").unwrap().errors;
+    assert_eq!(errors.len(), 1);
+    assert!(
+        errors[0].message.contains("of column 'Date'") && errors[0].message.contains("date_format"),
+        "{}",
+        errors[0].message
+    );
+}
+
+#[test]
+fn input_maps_need_the_date_payee_and_amount_columns() {
+    let error = |spec: &str| spec.parse::<InputMap>().unwrap_err();
+    assert!(error("payee=Text,amount=Amount").contains("date column"));
+    assert!(error("date=Date,payee=Text").contains("amount column"));
+    assert!(error("date=Date,payee=Text,outflow=Out").contains("both outflow and inflow"));
+    assert!(error("date=Date,payee=Text,amount=Amount,colour=Red").contains("unknown key"));
+    assert!(error("date=Date,payee=Text,amount=Amount,delimiter=;;").contains("delimiter"));
+}
@@ -0,0 +1,1866 @@
+mod common;
+
+use common::{mock_api_responses, MockApi, NordeaCsv, TempDir, IBAN};
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+    thread::{self, JoinHandle},
+};
+
+fn nda2ynab(dir: &TempDir, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_nda2ynab"))
+        .args(args)
+        .env("XDG_CONFIG_HOME", dir.path())
+        .env("XDG_STATE_HOME", dir.path())
+        .current_dir(dir.path())
+        .output()
+        .unwrap()
+}
+
+fn nda2ynab_stdin(dir: &TempDir, args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_nda2ynab"))
+        .args(args)
+        .env("XDG_CONFIG_HOME", dir.path())
+        .env("XDG_STATE_HOME", dir.path())
+        .current_dir(dir.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// Runs `nda2ynab upload` against the YNAB API at `api_url`.
+fn upload(dir: &TempDir, api_url: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_nda2ynab"))
+        .args(["upload", dir.path_str()])
+        .env("XDG_CONFIG_HOME", dir.path())
+        .env("XDG_STATE_HOME", dir.path())
+        .env("NDA2YNAB_YNAB_API_URL", api_url)
+        .current_dir(dir.path())
+        .output()
+        .unwrap()
+}
+
+/// Runs `nda2ynab fetch` with `args` against the Nordea API at `api_url`.
+fn fetch(dir: &TempDir, api_url: &str, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_nda2ynab"))
+        .arg("fetch")
+        .args(args)
+        .env("XDG_CONFIG_HOME", dir.path())
+        .env("XDG_STATE_HOME", dir.path())
+        .env("NDA2YNAB_NORDEA_API_URL", api_url)
+        .current_dir(dir.path())
+        .output()
+        .unwrap()
+}
+
+/// Answers one request with `response` as a JSON body. Returns the API URL
+/// and the body of the request received.
+fn mock_api(response: &'static str) -> (String, JoinHandle<String>) {
+    let (url, handle) = mock_api_requests(vec![response]);
+    (
+        url,
+        thread::spawn(move || handle.join().unwrap().remove(0).1),
+    )
+}
+
+/// Answers a request with each of `responses` in turn. Returns the API URL
+/// and the requests received.
+fn mock_api_requests(responses: Vec<&'static str>) -> MockApi {
+    mock_api_responses(responses.into_iter().map(|r| (201, r)).collect())
+}
+
+/// Answer to looking up when a YNAB account was last reconciled before
+/// uploading to it, for an account never reconciled
+const UNRECONCILED: &str = r#"{"data":{"account":{"id":"account","last_reconciled_at":null}}}"#;
+
+fn write_exports(dir: &TempDir) {
+    NordeaCsv::new("2024-01-02 10:00")
+        .row("2024/01/01", "-1,00", "A")
+        .write(dir);
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/01", "-1,00", "A")
+        .write(dir);
+}
+
+#[test]
+fn convert_subcommand_matches_a_bare_path() {
+    let dir = TempDir::new();
+    write_exports(&dir);
+
+    let bare = nda2ynab(&dir, &["--stdout", dir.path_str()]);
+    let convert = nda2ynab(&dir, &["convert", "--stdout", dir.path_str()]);
+    assert!(bare.status.success());
+    assert_eq!(bare.stdout, convert.stdout);
+    assert!(String::from_utf8_lossy(&convert.stdout).contains("2024-01-04,D,,-4.00"));
+}
+
+#[test]
+fn output_is_sectioned_with_a_recap_of_the_warnings() {
+    let dir = TempDir::new();
+    write_exports(&dir);
+    NordeaCsv::new("2024-01-06 10:00")
+        .row("Invalid date", "-3,00", "Hold")
+        .row("2024/01/04", "-4,00", "D")
+        .write(&dir);
+
+    // Not a terminal, so without colors
+    let output = nda2ynab(
+        &dir,
+        &["--dry-run", "--max-export-age", "0", dir.path_str()],
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(!stderr.contains('\x1b'), "{}", stderr);
+    assert!(
+        stderr.starts_with("Files\n  Main CSV       Tapahtumat"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("\n  Previous CSV   Tapahtumat"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.ends_with("\n1 warning(s)\n  - Skipping pending transaction in Tapahtumat FI12 3456 7890 1234 56 - 2024-01-06 10.00.00.csv, probably an authorisation hold: Hold -3.00\n"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn exports_with_only_a_header_write_nothing() {
+    let dir = TempDir::new();
+    write_exports(&dir);
+    NordeaCsv::new("2024-01-06 10:00").write(&dir);
+
+    let output = nda2ynab(&dir, &[dir.path_str()]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("No new transactions"), "{}", stderr);
+    assert!(!dir.path().join("out.csv").exists());
+
+    let output = nda2ynab(&dir, &["--allow-empty", dir.path_str()]);
+    assert!(output.status.success());
+    let csv = std::fs::read_to_string(dir.path().join("out.csv")).unwrap();
+    assert_eq!(csv.lines().count(), 1, "{}", csv);
+
+    // The next export is compared with the one before the empty one
+    NordeaCsv::new("2024-01-07 10:00")
+        .row("2024/01/06", "-6,00", "F")
+        .row("2024/01/04", "-4,00", "D")
+        .write(&dir);
+    let output = nda2ynab(&dir, &["--stdout", dir.path_str()]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success());
+    assert!(stdout.contains("2024-01-06,F,"), "{}", stdout);
+    assert!(!stdout.contains(",D,"), "{}", stdout);
+}
+
+#[test]
+fn rules_test_shows_the_rules_matching_each_row() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-07 10:00")
+        .row("2024/01/06", "-12,30", "K-MARKET KAMPPI")
+        .row("2024/01/05", "-100,00", "LUOTTOKORTIN MAKSU")
+        .row("2024/01/01", "-1,00", "A")
+        .write(&dir);
+    let rules = dir.path().join("rules.toml");
+    std::fs::write(
+        &rules,
+        "[[payee]]\npattern = '^K-MARKET.*'\nreplacement = 'K-Market'\n\n\
+         [[category]]\npattern = '(?i)k-market'\ncategory = 'Groceries'\n\n\
+         [[ignore]]\nname = 'Card payment'\npattern = '^LUOTTOKORTIN'\n",
+    )
+    .unwrap();
+    let export = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .find(|p| p.extension().is_some_and(|e| e == "csv"))
+        .unwrap();
+
+    let output = nda2ynab(
+        &dir,
+        &[
+            "rules",
+            "test",
+            export.to_str().unwrap(),
+            "--rules",
+            rules.to_str().unwrap(),
+        ],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 4, "{}", stdout);
+    assert!(
+        lines[0].starts_with("Date         Amount  Description"),
+        "{}",
+        stdout
+    );
+    assert!(
+        lines[1].ends_with(
+            "K-Market            Groceries  payee '^K-MARKET.*', category '(?i)k-market'"
+        ),
+        "{}",
+        stdout
+    );
+    assert!(lines[2].ends_with("ignore 'Card payment'"), "{}", stdout);
+    assert!(lines[3].ends_with("  A"), "{}", stdout);
+    assert!(!dir.path().join("out.csv").exists());
+}
+
+#[test]
+fn rules_test_reads_exports_of_other_banks_by_the_configured_input_map() {
+    let dir = TempDir::new();
+    std::fs::write(
+        dir.path().join("nda2ynab.toml"),
+        "input_map = \"date=Booking date,amount=Amount,payee=Text,date_format=%d.%m.%Y\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("bank.csv"),
+        "Booking date;Text;Amount\n04.01.2024;K-MARKET KAMPPI;-4,50\n",
+    )
+    .unwrap();
+    let rules = dir.path().join("rules.toml");
+    std::fs::write(
+        &rules,
+        "[[category]]\npattern = '(?i)k-market'\ncategory = 'Groceries'\n",
+    )
+    .unwrap();
+
+    let export = dir.path().join("bank.csv");
+    let output = nda2ynab(
+        &dir,
+        &[
+            "rules",
+            "test",
+            export.to_str().unwrap(),
+            "--rules",
+            rules.to_str().unwrap(),
+        ],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(stdout.contains("2024-01-04"), "{}", stdout);
+    assert!(
+        stdout.contains("Groceries  category '(?i)k-market'"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn status_lists_the_most_recent_export_of_each_account() {
+    let dir = TempDir::new();
+    write_exports(&dir);
+
+    let output = nda2ynab(&dir, &["status", dir.path_str()]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Nothing saved yet."), "{}", stdout);
+    assert!(
+        stdout.contains(&format!("{}: Tapahtumat {} - 2024-01-05", IBAN, IBAN)),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("2 export(s)"), "{}", stdout);
+}
+
+#[test]
+fn upload_rejects_output_options() {
+    let dir = TempDir::new();
+    let output = nda2ynab(&dir, &["upload", "--stdout", dir.path_str()]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn outflow_inflow_columns_split_the_amount() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/01", "12,50", "SALARY")
+        .write(&dir);
+
+    let output = nda2ynab(
+        &dir,
+        &[
+            "--stdout",
+            "--ynab-format",
+            "outflow-inflow",
+            dir.path_str(),
+        ],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "Date,Payee,Memo,Outflow,Inflow,Import ID");
+    assert!(lines[1].starts_with("2024-01-04,D,,4.00,,"), "{}", stdout);
+    assert!(
+        lines[2].starts_with("2024-01-01,SALARY,,,12.50,"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn existing_output_is_only_overwritten_with_force() {
+    let dir = TempDir::new();
+    write_exports(&dir);
+    let output = dir.path().join("out.csv");
+
+    assert!(nda2ynab(&dir, &[dir.path_str()]).status.success());
+    std::fs::write(&output, "not imported yet").unwrap();
+
+    let refused = nda2ynab(&dir, &[dir.path_str()]);
+    assert_eq!(refused.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("--force"));
+    assert_eq!(
+        std::fs::read_to_string(&output).unwrap(),
+        "not imported yet"
+    );
+
+    assert!(nda2ynab(&dir, &["--force", dir.path_str()])
+        .status
+        .success());
+    assert!(std::fs::read_to_string(&output)
+        .unwrap()
+        .starts_with("Date,"));
+}
+
+#[test]
+fn output_is_split_by_month() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-03-02 10:00")
+        .row("2024/03/01", "-3,00", "C")
+        .row("2024/02/29", "-2,00", "B")
+        .row("2024/02/01", "-1,00", "A")
+        .write(&dir);
+
+    let output = nda2ynab(
+        &dir,
+        &[
+            "--output",
+            "ynab.csv",
+            "--split-by",
+            "month",
+            dir.path_str(),
+        ],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let february = std::fs::read_to_string(dir.path().join("ynab-2024-02.csv")).unwrap();
+    let march = std::fs::read_to_string(dir.path().join("ynab-2024-03.csv")).unwrap();
+    assert_eq!(february.lines().count(), 3, "{}", february);
+    assert!(february.contains("2024-02-29,B"), "{}", february);
+    assert_eq!(march.lines().count(), 2, "{}", march);
+    assert!(!dir.path().join("ynab.csv").exists());
+
+    let refused = nda2ynab(
+        &dir,
+        &[
+            "--output",
+            "ynab.csv",
+            "--split-by",
+            "month",
+            dir.path_str(),
+        ],
+    );
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("ynab-2024-02.csv already exists"));
+}
+
+#[test]
+fn export_is_read_from_stdin() {
+    let dir = TempDir::new();
+    // Already converted rows of an export in the directory are not left out
+    write_exports(&dir);
+    let export = NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/01", "-1,00", "A");
+
+    let output = nda2ynab_stdin(&dir, &["convert", "-"], &export.contents());
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", stdout);
+    assert!(lines[1].starts_with("2024-01-04,D,,-4.00"), "{}", stdout);
+    assert!(lines[2].starts_with("2024-01-01,A,,-1.00"), "{}", stdout);
+
+    let output = nda2ynab_stdin(&dir, &["-"], "not an export\n");
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn flag_rules_add_a_flag_column() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-650,00", "HOTEL")
+        .row("2024/01/03", "-4,00", "PAYPAL *SHOP")
+        .row("2024/01/01", "-1,00", "A")
+        .write(&dir);
+    std::fs::write(
+        dir.path().join("rules.toml"),
+        "[[flag]]\nmax = -500\ncolor = 'red'\n\n[[flag]]\npattern = 'PAYPAL'\ncolor = 'Blue'\n",
+    )
+    .unwrap();
+
+    let output = nda2ynab(&dir, &["--stdout", "--rules", "rules.toml", dir.path_str()]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines[0].ends_with(",Flag"), "{}", stdout);
+    assert!(lines[1].ends_with(",red"), "{}", stdout);
+    assert!(lines[2].ends_with(",blue"), "{}", stdout);
+    assert!(lines[3].ends_with(','), "{}", stdout);
+
+    std::fs::write(
+        dir.path().join("rules.toml"),
+        "[[flag]]\nmin = 0\ncolor = 'pink'\n",
+    )
+    .unwrap();
+    let output = nda2ynab(&dir, &["--stdout", "--rules", "rules.toml", dir.path_str()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown flag color 'pink'"));
+}
+
+#[test]
+fn gnucash_output_uses_the_configured_account() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/01", "12,50", "SALARY")
+        .write(&dir);
+    std::fs::write(
+        dir.path().join("nda2ynab.toml"),
+        format!(
+            "[accounts.\"{}\"]\ngnucash_account = \"Assets:Current Assets:Checking\"\n",
+            IBAN
+        ),
+    )
+    .unwrap();
+
+    let output = nda2ynab(&dir, &["--stdout", "--format", "gnucash", dir.path_str()]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "Date,Description,Deposit,Withdrawal,Account",
+            "2024-01-04,D,,4.00,Assets:Current Assets:Checking",
+            "2024-01-01,SALARY,12.50,,Assets:Current Assets:Checking",
+        ]
+    );
+}
+
+#[test]
+fn accounts_converted_in_parallel_match_sequential_conversion() {
+    let dir = TempDir::new();
+    for (i, iban) in ["FI11 1111 1111 1111 11", "FI22 2222 2222 2222 22", IBAN]
+        .into_iter()
+        .enumerate()
+    {
+        NordeaCsv::new("2024-01-02 10:00")
+            .iban(iban)
+            .row("2024/01/01", "-1,00", "A")
+            .write(&dir);
+        NordeaCsv::new("2024-01-05 10:00")
+            .iban(iban)
+            .row("2024/01/04", &format!("-{},00", i + 2), "D")
+            .row("2024/01/01", "-1,00", "A")
+            .write(&dir);
+    }
+
+    let sequential = nda2ynab(&dir, &["--dry-run", "-a", "--jobs", "1", dir.path_str()]);
+    let parallel = nda2ynab(&dir, &["--dry-run", "-a", "--jobs", "3", dir.path_str()]);
+    assert!(parallel.status.success());
+    assert_eq!(sequential.stdout, parallel.stdout);
+    let stdout = String::from_utf8_lossy(&parallel.stdout);
+    for amount in ["-2.00", "-3.00", "-4.00"] {
+        assert!(stdout.contains(amount), "{}", stdout);
+    }
+}
+
+#[test]
+fn amounts_are_inverted_per_account() {
+    let dir = TempDir::new();
+    let mut export = NordeaCsv::new("2024-01-05 10:00");
+    for day in 1..=10 {
+        export = export.row(&format!("2024/01/{:02}", day), "4,00", "SHOP");
+    }
+    export.write(&dir);
+
+    let config = format!("[accounts.\"{}\"]\ntype = \"checking\"\n", IBAN);
+    std::fs::write(dir.path().join("nda2ynab.toml"), &config).unwrap();
+    let output = nda2ynab(&dir, &["--stdout", dir.path_str()]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains(",4.00,"));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("10 of 10 transactions"), "{}", stderr);
+
+    std::fs::write(
+        dir.path().join("nda2ynab.toml"),
+        config + "invert_amounts = true\n",
+    )
+    .unwrap();
+    let output = nda2ynab(&dir, &["--stdout", dir.path_str()]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains(",-4.00,"));
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("may be inverted"));
+}
+
+#[test]
+fn homebank_output_is_semicolon_separated() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-4,00", "D")
+        .write(&dir);
+
+    let output = nda2ynab(&dir, &["--stdout", "--format", "homebank", dir.path_str()]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "date;paymode;info;payee;memo;amount;category;tags",
+            "2024-01-04;0;;D;;-4.00;;",
+        ]
+    );
+}
+
+#[test]
+fn identical_adjacent_rows_are_only_merged_when_asked_to() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/03", "-3,00", "C")
+        .row("2024/01/04", "-4,00", "D")
+        .write(&dir);
+    let rows = |output: &Output| String::from_utf8_lossy(&output.stdout).lines().count() - 1;
+
+    let output = nda2ynab(&dir, &["--stdout", dir.path_str()]);
+    assert_eq!(rows(&output), 4);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("2 identical rows in a row"), "{}", stderr);
+
+    let output = nda2ynab(&dir, &["--stdout", "--duplicates", "merge", dir.path_str()]);
+    assert_eq!(rows(&output), 3);
+}
+
+#[test]
+fn output_file_names_are_rendered_from_templates() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/02", "-2,00", "B")
+        .write(&dir);
+    NordeaCsv::new("2024-01-06 10:00")
+        .iban("FI22 2222 2222 2222 22")
+        .row("2024/01/03", "-3,00", "C")
+        .write(&dir);
+    let config = format!("[accounts.\"{}\"]\nname = \"Joint checking\"\n", IBAN);
+    std::fs::write(dir.path().join("nda2ynab.toml"), config).unwrap();
+
+    let template = "{account_name}-{from_date}-{to_date}.{ext}";
+    let output = nda2ynab(&dir, &["-a", "--output", template, dir.path_str()]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(dir
+        .path()
+        .join("Joint-checking-2024-01-02-2024-01-04.csv")
+        .exists());
+    assert!(dir
+        .path()
+        .join("FI2222222222222222-2024-01-03-2024-01-03.csv")
+        .exists());
+
+    let output = nda2ynab(&dir, &["-o", "{iban}-{export_date}.csv", dir.path_str()]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(dir
+        .path()
+        .join("FI2222222222222222-2024-01-06.csv")
+        .exists());
+
+    let output = nda2ynab(&dir, &["-a", "-o", "ynab.csv", dir.path_str()]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("would be written for every account"),
+        "{}",
+        stderr
+    );
+
+    let output = nda2ynab(&dir, &["-o", "{month}.csv", "--force", dir.path_str()]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Unknown placeholder {month}"), "{}", stderr);
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_end_with_a_desktop_notification() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = TempDir::new();
+    write_exports(&dir);
+    // A notify-send that records its arguments
+    let bin = dir.path().join("bin");
+    std::fs::create_dir(&bin).unwrap();
+    let script = bin.join("notify-send");
+    std::fs::write(
+        &script,
+        format!(
+            "#!/bin/sh\nprintf '%s\\n' \"$@\" > '{}/notification'\n",
+            dir.path_str()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nda2ynab"))
+        .args(["--notify", "--max-export-age", "0", dir.path_str()])
+        .env("XDG_CONFIG_HOME", dir.path())
+        .env("XDG_STATE_HOME", dir.path())
+        .env("PATH", path)
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let notification = std::fs::read_to_string(dir.path().join("notification")).unwrap();
+    assert_eq!(
+        notification,
+        format!(
+            "--app-name=nda2ynab\nnda2ynab\n{}: 1 new transaction(s) written to out.csv\n",
+            IBAN
+        )
+    );
+}
+
+#[test]
+fn stale_exports_are_warned_about() {
+    let dir = TempDir::new();
+    write_exports(&dir);
+
+    let output = nda2ynab(&dir, &["--json", "--force", dir.path_str()]);
+    assert_eq!(output.status.code(), Some(9));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Did you forget to download a fresh export?"),
+        "{}",
+        stderr
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("\"stale_export_days\": "), "{}", stdout);
+
+    std::fs::write(dir.path().join("nda2ynab.toml"), "max_export_age = 0\n").unwrap();
+    let output = nda2ynab(&dir, &["--json", "--force", dir.path_str()]);
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("\"stale_export_days\": null"), "{}", stdout);
+}
+
+#[test]
+fn account_names_are_used_in_file_names_and_the_summary() {
+    let dir = TempDir::new();
+    write_exports(&dir);
+    NordeaCsv::new("2024-01-05 10:00")
+        .iban("FI22 2222 2222 2222 22")
+        .row("2024/01/04", "-2,00", "B")
+        .write(&dir);
+    let config = format!("[accounts.\"{}\"]\nname = \"Joint checking\"\n", IBAN);
+    std::fs::write(dir.path().join("nda2ynab.toml"), config).unwrap();
+
+    let output = nda2ynab(
+        &dir,
+        &["--json", "-a", "--max-export-age", "0", dir.path_str()],
+    );
+    assert!(output.status.success());
+    assert!(dir.path().join("out-Joint-checking.csv").exists());
+    assert!(dir.path().join("out-FI2222222222222222.csv").exists());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("\"name\": \"Joint checking\""),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("\"name\": null"), "{}", stdout);
+}
+
+#[test]
+fn failed_upload_is_resubmitted_by_the_next_upload() {
+    let dir = TempDir::new();
+    write_exports(&dir);
+    let config = format!(
+        "[ynab]\ntoken = \"token\"\nbudget_id = \"budget\"\n\n[accounts.\"{}\"]\nynab_account_id = \"account\"\n",
+        IBAN
+    );
+    std::fs::write(dir.path().join("nda2ynab.toml"), config).unwrap();
+    let state_file = dir.path().join("nda2ynab").join("state.json");
+
+    // Nothing listens on port 1, so the request fails
+    let output = upload(&dir, "http://127.0.0.1:1");
+    assert!(!output.status.success());
+    let state = std::fs::read_to_string(&state_file).unwrap();
+    assert!(state.contains("YNAB:-4000:2024-01-04:1"), "{}", state);
+
+    NordeaCsv::new("2024-01-07 10:00")
+        .row("2024/01/06", "-6,00", "F")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/01", "-1,00", "A")
+        .write(&dir);
+    let (url, requests) = mock_api_requests(vec![
+        UNRECONCILED,
+        r#"{"data":{"duplicate_import_ids":[]}}"#,
+    ]);
+    let output = upload(&dir, &url);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(
+        stderr.contains("1 transaction(s) of an earlier"),
+        "{}",
+        stderr
+    );
+    // The resubmitted transaction is counted in its account too
+    assert!(stderr.contains("2 transactions created"), "{}", stderr);
+
+    let body = &requests.join().unwrap()[1].1;
+    assert!(body.contains("YNAB:-4000:2024-01-04:1"), "{}", body);
+    assert!(body.contains("YNAB:-6000:2024-01-06:1"), "{}", body);
+    // The state from before the upload is kept for undoing it
+    let state = nda2ynab::state::State::load(&state_file).unwrap();
+    assert!(state.pending_uploads.is_empty(), "{:?}", state);
+}
+
+#[test]
+fn resubmitted_transaction_is_kept_apart_from_an_identical_new_one() {
+    let dir = TempDir::new();
+    write_exports(&dir);
+    let config = format!(
+        "[ynab]\ntoken = \"token\"\nbudget_id = \"budget\"\n\n[accounts.\"{}\"]\nynab_account_id = \"account\"\n",
+        IBAN
+    );
+    std::fs::write(dir.path().join("nda2ynab.toml"), config).unwrap();
+    let output = upload(&dir, "http://127.0.0.1:1");
+    assert!(!output.status.success());
+
+    // A second identical transaction on the day of the one left pending
+    NordeaCsv::new("2024-01-07 10:00")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/01", "-1,00", "A")
+        .write(&dir);
+    let (url, requests) = mock_api_requests(vec![
+        UNRECONCILED,
+        r#"{"data":{"duplicate_import_ids":[]}}"#,
+    ]);
+    let output = upload(&dir, &url);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(
+        stderr.contains("1 transaction(s) of an earlier"),
+        "{}",
+        stderr
+    );
+
+    let body = &requests.join().unwrap()[1].1;
+    assert!(body.contains("YNAB:-4000:2024-01-04:1"), "{}", body);
+    assert!(body.contains("YNAB:-4000:2024-01-04:2"), "{}", body);
+}
+
+#[test]
+fn transactions_refused_by_ynab_are_not_sent_again() {
+    let dir = TempDir::new();
+    write_exports(&dir);
+    let config = format!(
+        "[ynab]\ntoken = \"token\"\nbudget_id = \"budget\"\n\n[accounts.\"{}\"]\nynab_account_id = \"account\"\n",
+        IBAN
+    );
+    std::fs::write(dir.path().join("nda2ynab.toml"), config).unwrap();
+
+    // An error page of a proxy is retried, and its status is not lost
+    let (url, _) = mock_api_responses(vec![(201, UNRECONCILED), (502, "<html>Bad Gateway</html>")]);
+    let output = upload(&dir, &url);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("YNAB API error (502)"), "{}", stderr);
+
+    let (url, _) = mock_api_responses(vec![
+        (201, UNRECONCILED),
+        (
+            422,
+            r#"{"error":{"id":"422","name":"unprocessable_entity","detail":"invalid account"}}"#,
+        ),
+    ]);
+    let output = upload(&dir, &url);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("YNAB API error (422): invalid account"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("will not be sent again"), "{}", stderr);
+
+    let (url, _) = mock_api_requests(vec![
+        UNRECONCILED,
+        r#"{"data":{"duplicate_import_ids":[]}}"#,
+    ]);
+    let output = upload(&dir, &url);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(!stderr.contains("of an earlier"), "{}", stderr);
+}
+
+#[test]
+fn duplicate_import_ids_are_matched_by_account() {
+    let dir = TempDir::new();
+    let other = "FI99 1111 2222 3333 44";
+    for iban in [IBAN, other] {
+        NordeaCsv::new("2024-01-05 10:00")
+            .iban(iban)
+            .row("2024/01/04", "-4,00", "D")
+            .write(&dir);
+    }
+    let config = format!(
+        "[ynab]\ntoken = \"token\"\nbudget_id = \"budget\"\n\n\
+         [accounts.\"{}\"]\nynab_account_id = \"a\"\n\n\
+         [accounts.\"{}\"]\nynab_account_id = \"b\"\n",
+        IBAN, other
+    );
+    std::fs::write(dir.path().join("nda2ynab.toml"), config).unwrap();
+
+    // The transaction was already in account a, and is created in account b
+    let (url, _) = mock_api_requests(vec![
+        UNRECONCILED,
+        UNRECONCILED,
+        r#"{"data":{"duplicate_import_ids":["YNAB:-4000:2024-01-04:1"],"transactions":[{"id":"t1","account_id":"b","import_id":"YNAB:-4000:2024-01-04:1"}]}}"#,
+    ]);
+    let output = Command::new(env!("CARGO_BIN_EXE_nda2ynab"))
+        .args(["upload", "--all-accounts", dir.path_str()])
+        .env("XDG_CONFIG_HOME", dir.path())
+        .env("XDG_STATE_HOME", dir.path())
+        .env("NDA2YNAB_YNAB_API_URL", &url)
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(
+        stderr.contains(&format!(
+            "{}: 0 transactions created, 1 already in YNAB",
+            IBAN
+        )),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains(&format!(
+            "{}: 1 transactions created, 0 already in YNAB",
+            other
+        )),
+        "{}",
+        stderr
+    );
+}
+
+/// Config of the Nordea Open Banking client
+const NORDEA_CONFIG: &str =
+    "[nordea]\nclient_id = \"id\"\nclient_secret = \"secret\"\nredirect_uri = \"https://localhost/callback\"\n";
+
+/// Answer to listing the accounts, with the account of `IBAN`
+const NORDEA_ACCOUNTS: &str = r#"{"response":{"accounts":[{"_id":"account","account_numbers":[{"_type":"IBAN","value":"FI1234567890123456"}]}]}}"#;
+
+/// Writes a state file with an Open Banking token expiring at `expires_at`.
+fn write_token(dir: &TempDir, expires_at: &str) {
+    let state_dir = dir.path().join("nda2ynab");
+    std::fs::create_dir_all(&state_dir).unwrap();
+    std::fs::write(
+        state_dir.join("state.json"),
+        format!(
+            r#"{{"holds":{{}},"token":{{"access_token":"old","refresh_token":"refresh","expires_at":"{}"}}}}"#,
+            expires_at
+        ),
+    )
+    .unwrap();
+}
+
+#[test]
+fn fetched_transactions_are_converted_after_refreshing_an_expired_token() {
+    let dir = TempDir::new();
+    std::fs::write(dir.path().join("nda2ynab.toml"), NORDEA_CONFIG).unwrap();
+    write_token(&dir, "2000-01-01T00:00:00");
+
+    let (url, requests) = mock_api_responses(vec![
+        (
+            200,
+            r#"{"access_token":"refreshed","refresh_token":"refresh2","expires_in":3600}"#,
+        ),
+        (200, NORDEA_ACCOUNTS),
+        (
+            200,
+            r#"{"response":{"transactions":[
+                {"transaction_id":"t3","status":"reserved","transaction_date":"2024-01-05","amount":"-9.90","narrative":"HOLD"},
+                {"transaction_id":"t2","status":"booked","booking_date":"2024-01-04","amount":"-4.00","currency":"EUR","counterparty_name":"SHOP"},
+                {"transaction_id":"t1","status":"booked","booking_date":"2024-01-02","amount":2500,"currency":"EUR","narrative":"EMPLOYER"}
+            ]}}"#,
+        ),
+    ]);
+    let output = fetch(&dir, &url, &["--stdout", "--since", "2024-01-01"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let rows: Vec<&str> = stdout.lines().skip(1).collect();
+    assert_eq!(rows.len(), 2, "{}", stdout);
+    assert!(rows[0].starts_with("2024-01-04,SHOP,"), "{}", stdout);
+    assert!(rows[0].contains(",-4.00,"), "{}", stdout);
+    assert!(rows[1].starts_with("2024-01-02,EMPLOYER,"), "{}", stdout);
+    assert!(rows[1].contains(",2500.00,"), "{}", stdout);
+
+    let requests = requests.join().unwrap();
+    assert!(requests[0].0.starts_with("POST /authorize/token "));
+    assert_eq!(
+        requests[0].1,
+        "grant_type=refresh_token&refresh_token=refresh"
+    );
+    assert!(
+        requests[2]
+            .0
+            .starts_with("GET /accounts/account/transactions?from_date=2024-01-01 "),
+        "{:?}",
+        requests
+    );
+
+    let state = std::fs::read_to_string(dir.path().join("nda2ynab").join("state.json")).unwrap();
+    assert!(state.contains("\"refreshed\""), "{}", state);
+    assert!(state.contains("\"refresh2\""), "{}", state);
+    assert!(state.contains("\"last_date\": \"2024-01-04\""), "{}", state);
+}
+
+#[test]
+fn transactions_fetched_before_are_left_out() {
+    let dir = TempDir::new();
+    std::fs::write(dir.path().join("nda2ynab.toml"), NORDEA_CONFIG).unwrap();
+    // Still valid, so it is used without refreshing
+    write_token(&dir, "2099-01-01T00:00:00");
+
+    let (url, requests) = mock_api_responses(vec![
+        (200, NORDEA_ACCOUNTS),
+        (
+            200,
+            r#"{"response":{"transactions":[
+                {"transaction_id":"t1","status":"booked","booking_date":"2024-01-04","amount":"-4.00","counterparty_name":"SHOP"}
+            ]}}"#,
+        ),
+    ]);
+    let output = fetch(&dir, &url, &["--stdout", "--since", "2024-01-01"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.lines().count(), 2, "{}", stdout);
+    assert!(requests.join().unwrap()[0].0.starts_with("GET /accounts "));
+
+    // Fetched from the date of the last sync, where one of the two
+    // identical transactions was fetched before
+    let (url, requests) = mock_api_responses(vec![
+        (200, NORDEA_ACCOUNTS),
+        (
+            200,
+            r#"{"response":{"transactions":[
+                {"transaction_id":"t2","status":"booked","booking_date":"2024-01-04","amount":"-4.00","counterparty_name":"SHOP"},
+                {"transaction_id":"t1","status":"booked","booking_date":"2024-01-04","amount":"-4.00","counterparty_name":"SHOP"}
+            ]}}"#,
+        ),
+    ]);
+    let output = fetch(&dir, &url, &["--stdout"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let rows: Vec<&str> = stdout.lines().skip(1).collect();
+    assert_eq!(rows.len(), 1, "{}", stdout);
+    assert!(rows[0].ends_with(",YNAB:-4000:2024-01-04:2"), "{}", stdout);
+    assert!(requests.join().unwrap()[1]
+        .0
+        .contains("?from_date=2024-01-04 "),);
+}
+
+#[test]
+fn pending_transactions_are_uploaded_as_uncleared() {
+    let dir = TempDir::new();
+    write_exports(&dir);
+    NordeaCsv::new("2024-01-06 10:00")
+        .row("Invalid date", "-3,00", "Hold")
+        .row("2024/01/05", "-5,00", "E")
+        .row("2024/01/04", "-4,00", "D")
+        .write(&dir);
+    let config = format!(
+        "[ynab]\ntoken = \"token\"\nbudget_id = \"budget\"\n\n[accounts.\"{}\"]\nynab_account_id = \"account\"\n",
+        IBAN
+    );
+    std::fs::write(dir.path().join("nda2ynab.toml"), config).unwrap();
+
+    let (url, requests) = mock_api_requests(vec![
+        UNRECONCILED,
+        r#"{"data":{"duplicate_import_ids":[]}}"#,
+    ]);
+    let output = Command::new(env!("CARGO_BIN_EXE_nda2ynab"))
+        .args([
+            "upload",
+            "--include-pending",
+            "--track-holds",
+            dir.path_str(),
+        ])
+        .env("XDG_CONFIG_HOME", dir.path())
+        .env("XDG_STATE_HOME", dir.path())
+        .env("NDA2YNAB_YNAB_API_URL", &url)
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(!stderr.contains("Skipping pending"), "{}", stderr);
+
+    let body = &requests.join().unwrap()[1].1;
+    // Dated by the export it was first seen in
+    assert!(
+        body.contains(r#""date":"2024-01-06","amount":-3000,"payee_name":"Hold","memo":"","cleared":"uncleared""#),
+        "{}",
+        body
+    );
+    assert!(body.contains(r#""cleared":"cleared""#), "{}", body);
+}
+
+#[test]
+fn transactions_before_the_last_reconciliation_are_not_uploaded() {
+    let dir = TempDir::new();
+    write_exports(&dir);
+    NordeaCsv::new("2024-01-07 10:00")
+        .row("2024/01/06", "-6,00", "F")
+        .row("2024/01/05", "-5,00", "E")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/01", "-1,00", "A")
+        .write(&dir);
+    let config = format!(
+        "[ynab]\ntoken = \"token\"\nbudget_id = \"budget\"\n\n[accounts.\"{}\"]\nynab_account_id = \"account\"\n",
+        IBAN
+    );
+    std::fs::write(dir.path().join("nda2ynab.toml"), config).unwrap();
+
+    let (url, requests) = mock_api_requests(vec![
+        r#"{"data":{"account":{"id":"account","last_reconciled_at":"2024-01-06T18:00:00.000Z"}}}"#,
+        r#"{"data":{"duplicate_import_ids":[]}}"#,
+    ]);
+    let output = upload(&dir, &url);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(
+        stderr.contains("left out 1 transaction(s) from before the account was last reconciled in YNAB on 2024-01-06"),
+        "{}",
+        stderr
+    );
+
+    let requests = requests.join().unwrap();
+    assert!(requests[0]
+        .0
+        .starts_with("GET /budgets/budget/accounts/account "));
+    let body = &requests[1].1;
+    // On the day of the reconciliation, it may have been booked after it
+    assert!(body.contains("YNAB:-6000:2024-01-06:1"), "{}", body);
+    assert!(!body.contains("YNAB:-5000"), "{}", body);
+}
+
+#[test]
+fn balance_is_checked_within_the_export_and_against_ynab() {
+    let dir = TempDir::new();
+    std::fs::write(
+        dir.path()
+            .join(format!("Tapahtumat {} - 2024-01-05 10.00.00.csv", IBAN)),
+        "Kirjauspäivä;Määrä;Otsikko;Saldo\n\
+         2024/01/04;-4,00;D;96,00\n\
+         2024/01/03;-1,00;B;100,00\n\
+         2024/01/02;-2,00;C;105,00\n",
+    )
+    .unwrap();
+    let config = format!(
+        "[ynab]\ntoken = \"token\"\nbudget_id = \"budget\"\n\n[accounts.\"{}\"]\nynab_account_id = \"account\"\n",
+        IBAN
+    );
+    std::fs::write(dir.path().join("nda2ynab.toml"), config).unwrap();
+    // Without the 7.00 of the transactions not imported yet
+    let (url, requests) = mock_api_requests(vec![
+        r#"{"data":{"account":{"id":"account","cleared_balance":103000}}}"#,
+    ]);
+    let output = Command::new(env!("CARGO_BIN_EXE_nda2ynab"))
+        .args(["--dry-run", "--check-balance", dir.path_str()])
+        .env("XDG_CONFIG_HOME", dir.path())
+        .env("XDG_STATE_HOME", dir.path())
+        .env("NDA2YNAB_YNAB_API_URL", &url)
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(
+        stderr.contains("does not add up with the amounts at 1 row(s), transactions may be missing from it: 2024/01/02 C: balance 105.00, expected 101.00"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("the balance matches the cleared balance in YNAB, 103.00"),
+        "{}",
+        stderr
+    );
+    assert!(requests.join().unwrap()[0]
+        .0
+        .starts_with("GET /budgets/budget/accounts/account "));
+}
+
+#[test]
+fn impact_shows_the_change_to_available_amounts() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/01", "-1,00", "A")
+        .write(&dir);
+    std::fs::write(
+        dir.path().join("nda2ynab.toml"),
+        "[ynab]\ntoken = \"token\"\nbudget_id = \"budget\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("rules.toml"),
+        "[[category]]\npattern = '^D'\ncategory = 'groceries'\n\n\
+         [[category]]\npattern = '^A'\ncategory = 'Missing'\n",
+    )
+    .unwrap();
+    let (url, request) = mock_api_requests(vec![
+        r#"{"data":{"month":{"categories":[
+            {"name":"Groceries","category_group_name":"Everyday","balance":3000,"deleted":false,"hidden":false},
+            {"name":"Rent","category_group_name":"Bills","balance":500000,"deleted":false,"hidden":false}
+        ]}}}"#,
+    ]);
+    let output = Command::new(env!("CARGO_BIN_EXE_nda2ynab"))
+        .args([
+            "--dry-run",
+            "--impact",
+            "--rules",
+            "rules.toml",
+            dir.path_str(),
+        ])
+        .env("XDG_CONFIG_HOME", dir.path())
+        .env("XDG_STATE_HOME", dir.path())
+        .env("NDA2YNAB_YNAB_API_URL", &url)
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    // Not mixed with the transactions printed on stdout
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("Impact"));
+    assert!(
+        stderr.contains(
+            "Everyday: Groceries        3.00 ->      -1.00  (-4.00, 1 transaction(s))  overspent"
+        ),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("Rent"), "{}", stderr);
+    assert!(
+        stderr.contains("in category \"Missing\", which is not in the budget"),
+        "{}",
+        stderr
+    );
+
+    let requests = request.join().unwrap();
+    assert!(
+        requests[0]
+            .0
+            .starts_with("GET /budgets/budget/months/current "),
+        "{:?}",
+        requests
+    );
+}
+
+#[test]
+fn diff_lists_the_rows_only_in_either_export() {
+    let dir = TempDir::new();
+    let old = NordeaCsv::new("2024-01-02 10:00")
+        .row("2024/01/01", "-2,00", "B")
+        .row("2024/01/01", "-1,00", "A")
+        .row("2024/01/01", "-1,00", "A");
+    let new = NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/01", "-1,00", "A");
+    old.write(&dir);
+    new.write(&dir);
+
+    let output = nda2ynab(&dir, &["diff", &old.file_name(), &new.file_name()]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let expected = format!(
+        "Only in {} (2):\n- 2024/01/01       -2.00  B\n- 2024/01/01       -1.00  A\n\n\
+         Only in {} (1):\n+ 2024/01/04       -4.00  D\n\n\
+         1 transaction(s) in both.\n",
+        old.file_name(),
+        new.file_name()
+    );
+    assert_eq!(stdout, expected);
+}
+
+#[test]
+fn anonymized_exports_convert_like_the_originals() {
+    let dir = TempDir::new();
+    let old = NordeaCsv::new("2024-01-02 10:00")
+        .row("2024/01/01", "-12,30", "K-MARKET KAMPPI")
+        .write(&dir);
+    let new = NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-1 250,00", "VUOKRA OY")
+        .row("2024/01/03", "12,30", "K-MARKET KAMPPI")
+        .row("2024/01/01", "-12,30", "K-MARKET KAMPPI")
+        .write(&dir);
+    let anonymized = dir.path().join("anonymized");
+    std::fs::create_dir(&anonymized).unwrap();
+
+    let output = nda2ynab(
+        &dir,
+        &[
+            "anonymize",
+            "--output-dir",
+            anonymized.to_str().unwrap(),
+            old.to_str().unwrap(),
+            new.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let files: Vec<_> = std::fs::read_dir(&anonymized)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    assert_eq!(files.len(), 2);
+    for file in &files {
+        let name = file.file_name().unwrap().to_string_lossy();
+        assert!(
+            name.starts_with("Tapahtumat FI") && !name.contains(IBAN),
+            "{}",
+            name
+        );
+        let contents = std::fs::read_to_string(file).unwrap();
+        assert!(contents.starts_with("Kirjauspäivä;Määrä;"), "{}", contents);
+        assert!(
+            !contents.contains("KAMPPI") && !contents.contains("12,30"),
+            "{}",
+            contents
+        );
+        assert!(contents.contains("2024/01/01;-"), "{}", contents);
+    }
+
+    let output = nda2ynab(
+        &dir,
+        &[
+            "--stdout",
+            "--state",
+            "state.json",
+            anonymized.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let rows: Vec<&str> = stdout.lines().skip(1).collect();
+    assert_eq!(rows.len(), 2, "{}", stdout);
+    assert!(
+        rows[0].starts_with("2024-01-04,") && rows[0].contains(",-"),
+        "{}",
+        stdout
+    );
+    assert!(
+        rows[1].starts_with("2024-01-03,") && !rows[1].contains(",-"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn unreadable_state_file_only_warns_that_the_run_cannot_be_undone() {
+    let dir = TempDir::new();
+    write_exports(&dir);
+    std::fs::write(dir.path().join("state.json"), "{ not json").unwrap();
+
+    let output = nda2ynab(&dir, &["--stdout", "--state", "state.json", dir.path_str()]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("2024-01-04,D,"));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("The run cannot be undone"), "{}", stderr);
+}
+
+#[test]
+fn undo_last_deletes_the_uploaded_transactions() {
+    let dir = TempDir::new();
+    write_exports(&dir);
+    let config = format!(
+        "[ynab]\ntoken = \"token\"\nbudget_id = \"budget\"\n\n[accounts.\"{}\"]\nynab_account_id = \"account\"\n",
+        IBAN
+    );
+    std::fs::write(dir.path().join("nda2ynab.toml"), config).unwrap();
+
+    let (url, requests) = mock_api_requests(vec![
+        UNRECONCILED,
+        r#"{"data":{"duplicate_import_ids":[],"transactions":[{"id":"t1","account_id":"account","import_id":"YNAB:-4000:2024-01-04:1"}]}}"#,
+        r#"{"data":{"transaction":{"id":"t1","deleted":true}}}"#,
+    ]);
+    let output = upload(&dir, &url);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(nda2ynab(&dir, &[dir.path_str(), "--output", "again.csv"])
+        .status
+        .success());
+
+    let output = nda2ynab(&dir, &["history"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().filter(|l| l.starts_with("  ")).collect();
+    assert_eq!(lines.len(), 2, "{}", stdout);
+    assert!(lines[0].ends_with("1 transaction(s) from Tapahtumat FI12 3456 7890 1234 56 - 2024-01-05 10.00.00.csv to again.csv"), "{}", stdout);
+    assert!(lines[1].ends_with(" to ynab"), "{}", stdout);
+
+    // The file written by the last run is left in place
+    let undo = |dir: &TempDir| {
+        Command::new(env!("CARGO_BIN_EXE_nda2ynab"))
+            .arg("undo-last")
+            .env("XDG_CONFIG_HOME", dir.path())
+            .env("XDG_STATE_HOME", dir.path())
+            .env("NDA2YNAB_YNAB_API_URL", &url)
+            .current_dir(dir.path())
+            .output()
+            .unwrap()
+    };
+    let output = undo(&dir);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("again.csv was written and is left in place"));
+    assert!(dir.path().join("again.csv").exists());
+
+    let output = undo(&dir);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let requests = requests.join().unwrap();
+    assert!(
+        requests[2]
+            .0
+            .starts_with("DELETE /budgets/budget/transactions/t1 "),
+        "{:?}",
+        requests
+    );
+
+    let output = undo(&dir);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("nothing to undo"));
+}
+
+#[test]
+fn concurrent_runs_are_refused_by_the_lock() {
+    let dir = TempDir::new();
+    write_exports(&dir);
+    let lock = dir.path().join("nda2ynab").join("state.lock");
+    std::fs::create_dir_all(lock.parent().unwrap()).unwrap();
+
+    // This test process stands in for the other run
+    std::fs::write(&lock, std::process::id().to_string()).unwrap();
+    let output = nda2ynab(&dir, &[dir.path_str()]);
+    assert_eq!(output.status.code(), Some(7));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Another nda2ynab run"), "{}", stderr);
+    assert!(!dir.path().join("out.csv").exists());
+    assert!(nda2ynab(&dir, &["--dry-run", dir.path_str()])
+        .status
+        .success());
+
+    // A lock left behind by a process that is no longer running is taken over
+    std::fs::write(&lock, u32::MAX.to_string()).unwrap();
+    let output = nda2ynab(&dir, &[dir.path_str()]);
+    assert!(output.status.success());
+    assert!(dir.path().join("out.csv").exists());
+    assert!(!lock.exists());
+}
+
+#[test]
+fn transactions_are_uploaded_to_actual() {
+    let dir = TempDir::new();
+    write_exports(&dir);
+    let (url, request) = mock_api(r#"{"data":{"added":["id"],"updated":[],"errors":[]}}"#);
+    let config = format!(
+        "target = \"actual\"\n\n[actual]\nurl = \"{}\"\napi_key = \"key\"\nbudget_id = \"budget\"\n\n[accounts.\"{}\"]\nactual_account_id = \"account\"\n",
+        url, IBAN
+    );
+    std::fs::write(dir.path().join("nda2ynab.toml"), config).unwrap();
+
+    let output = upload(&dir, "http://127.0.0.1:1");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(
+        stderr.contains("1 transactions created, 0 already in Actual"),
+        "{}",
+        stderr
+    );
+
+    let body = request.join().unwrap();
+    assert!(body.contains(r#""account":"account""#), "{}", body);
+    assert!(body.contains(r#""amount":-400"#), "{}", body);
+    assert!(
+        body.contains(r#""imported_id":"YNAB:-4000:2024-01-04:1""#),
+        "{}",
+        body
+    );
+}
+
+#[test]
+fn transactions_are_uploaded_to_firefly() {
+    let dir = TempDir::new();
+    write_exports(&dir);
+    let (url, requests) = mock_api_requests(vec![
+        r#"{"data":[],"meta":{"pagination":{"total_pages":1}}}"#,
+        r#"{"data":{"id":"1"}}"#,
+    ]);
+    let config = format!(
+        "target = \"firefly\"\n\n[firefly]\nurl = \"{}\"\ntoken = \"token\"\n\n[accounts.\"{}\"]\nfirefly_account_id = \"1\"\n",
+        url, IBAN
+    );
+    std::fs::write(dir.path().join("nda2ynab.toml"), config).unwrap();
+
+    let output = upload(&dir, "http://127.0.0.1:1");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(
+        stderr.contains("1 transactions created, 0 already in Firefly III"),
+        "{}",
+        stderr
+    );
+
+    let requests = requests.join().unwrap();
+    assert!(
+        requests[0]
+            .0
+            .starts_with("GET /api/v1/accounts/1/transactions?"),
+        "{}",
+        requests[0].0
+    );
+    let (request_line, body) = &requests[1];
+    assert!(request_line.starts_with("POST /api/v1/transactions "));
+    assert!(body.contains(r#""type":"withdrawal""#), "{}", body);
+    assert!(body.contains(r#""amount":"4.00""#), "{}", body);
+    assert!(
+        body.contains(r#""external_id":"YNAB:-4000:2024-01-04:1""#),
+        "{}",
+        body
+    );
+}
+
+#[test]
+fn html_report_shows_included_and_skipped_transactions() {
+    let dir = TempDir::new();
+    write_exports(&dir);
+    let report = dir.path().join("report.html");
+
+    let output = nda2ynab(
+        &dir,
+        &[
+            "--stdout",
+            "--report-html",
+            report.to_str().unwrap(),
+            dir.path_str(),
+        ],
+    );
+    assert!(output.status.success());
+    let page = std::fs::read_to_string(&report).unwrap();
+    assert!(page.contains("1 included transaction(s)"), "{}", page);
+    assert!(page.contains("<tr class=\"included\"><td>+</td><td>2024-01-04</td><td>D</td>"));
+    assert!(page.contains("1 skipped transaction(s)"));
+    assert!(page.contains("<tr class=\"skipped\"><td></td><td>2024-01-01</td><td>A</td>"));
+}
+
+#[test]
+fn completions_and_man_page_cover_subcommand_options() {
+    let dir = TempDir::new();
+
+    let output = nda2ynab(&dir, &["completions", "bash"]);
+    assert!(output.status.success());
+    let script = String::from_utf8(output.stdout).unwrap();
+    assert!(script.contains("complete -F _nda2ynab"), "{}", script);
+    assert!(script.contains("--report-html"));
+    assert!(script.contains("compgen -W \"csv ofx qif"));
+
+    let output = nda2ynab(&dir, &["completions", "fish"]);
+    let script = String::from_utf8(output.stdout).unwrap();
+    assert!(script.contains("complete -c nda2ynab -n '__fish_seen_subcommand_from watch'"));
+
+    let output = nda2ynab(&dir, &["man"]);
+    assert!(output.status.success());
+    let page = String::from_utf8(output.stdout).unwrap();
+    assert!(page.starts_with(".TH NDA2YNAB 1"), "{}", page);
+    assert!(page.contains(".SS upload"));
+    assert!(page.contains("\\fB\\-\\-aggregate\\-below\\fR \\fIAMOUNT\\fR"));
+}
+
+#[test]
+fn uploaded_payees_are_matched_to_existing_ynab_payees() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-49,90", "VERKKOKAUPPA.COM MYYMAL")
+        .row("2024/01/03", "-4,00", "D")
+        .write(&dir);
+    let (url, requests) = mock_api_requests(vec![
+        r#"{"data":{"payees":[{"id":"1","name":"Verkkokauppa.com","transfer_account_id":null,"deleted":false},{"id":"2","name":"Transfer : Savings","transfer_account_id":"a","deleted":false}]}}"#,
+        UNRECONCILED,
+        r#"{"data":{"transaction_ids":["id"],"duplicate_import_ids":[]}}"#,
+    ]);
+    let config = format!(
+        "[ynab]\ntoken = \"token\"\nbudget_id = \"budget\"\nmatch_payees = true\n\n[accounts.\"{}\"]\nynab_account_id = \"account\"\n",
+        IBAN
+    );
+    std::fs::write(dir.path().join("nda2ynab.toml"), config).unwrap();
+
+    let output = upload(&dir, &url);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("1 payee(s) replaced"), "{}", stderr);
+
+    let requests = requests.join().unwrap();
+    assert!(requests[0].0.starts_with("GET /budgets/budget/payees"));
+    let body = &requests[2].1;
+    assert!(
+        body.contains(r#""payee_name":"Verkkokauppa.com""#),
+        "{}",
+        body
+    );
+    assert!(body.contains(r#""payee_name":"D""#), "{}", body);
+}
+
+#[test]
+fn upload_dry_run_compares_with_the_ynab_account() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/03", "-2,00", "C")
+        .row("2024/01/01", "-1,00", "A")
+        .write(&dir);
+    let (url, requests) = mock_api_requests(vec![
+        r#"{"data":{"transactions":[
+            {"date":"2024-01-01","amount":-1000,"payee_name":"A","import_id":"YNAB:-1000:2024-01-01:1","deleted":false},
+            {"date":"2024-01-02","amount":-3000,"payee_name":"Coffee","import_id":null,"deleted":false},
+            {"date":"2024-01-03","amount":-2000,"payee_name":"Cafe C","import_id":null,"deleted":false},
+            {"date":"2024-01-03","amount":-5000,"payee_name":"Old","import_id":null,"deleted":true}
+        ]}}"#,
+    ]);
+    let config = format!(
+        "[ynab]\ntoken = \"token\"\nbudget_id = \"budget\"\n\n[accounts.\"{}\"]\nynab_account_id = \"account\"\n",
+        IBAN
+    );
+    std::fs::write(dir.path().join("nda2ynab.toml"), config).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nda2ynab"))
+        .args(["upload", "--dry-run", dir.path_str()])
+        .env("XDG_CONFIG_HOME", dir.path())
+        .env("XDG_STATE_HOME", dir.path())
+        .env("NDA2YNAB_YNAB_API_URL", &url)
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(stdout.contains("+ 2024-01-04  D"), "{}", stdout);
+    assert!(stdout.contains("~ 2024-01-03  C"), "{}", stdout);
+    assert!(stdout.contains("\"Cafe C\" in YNAB"), "{}", stdout);
+    assert!(stdout.contains("= 2024-01-01  A"), "{}", stdout);
+    assert!(stdout.contains("- 2024-01-02  Coffee"), "{}", stdout);
+    assert!(!stdout.contains("Old"), "{}", stdout);
+    assert!(
+        stdout.contains("1 new, 1 already in YNAB, 1 matching by date and amount, 1 only in YNAB")
+    );
+
+    let requests = requests.join().unwrap();
+    assert!(requests[0]
+        .0
+        .starts_with("GET /budgets/budget/accounts/account/transactions?since_date=2024-01-01"));
+}
+
+#[test]
+fn csv_columns_are_chosen_in_the_config_file() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-4,00", "D")
+        .write(&dir);
+    let config = format!(
+        "[csv]\ncolumns = [\"Date\", \"Account\", \"Payee\", \"Outflow\", \"IBAN\"]\nheaders = {{ Payee = \"Description\" }}\n\n[accounts.\"{}\"]\nname = \"Checking\"\n",
+        IBAN
+    );
+    std::fs::write(dir.path().join("nda2ynab.toml"), config).unwrap();
+
+    let output = nda2ynab(&dir, &["--stdout", dir.path_str()]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success());
+    assert_eq!(
+        stdout,
+        format!(
+            "Date,Account,Description,Outflow,IBAN\n2024-01-04,Checking,D,4.00,{}\n",
+            IBAN
+        )
+    );
+
+    std::fs::write(
+        dir.path().join("nda2ynab.toml"),
+        "[csv]\ncolumns = [\"Date\", \"Balance\"]\n",
+    )
+    .unwrap();
+    let output = nda2ynab(&dir, &["--stdout", dir.path_str()]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("unknown column 'Balance'"), "{}", stderr);
+}
+
+#[test]
+fn other_accounts_are_written_when_one_fails() {
+    let dir = TempDir::new();
+    write_exports(&dir);
+    let broken = NordeaCsv::new("2024-01-06 10:00").iban("FI65 4321 0987 6543 21");
+    std::fs::write(dir.path().join(broken.file_name()), "not an export\n").unwrap();
+
+    let output = nda2ynab(&dir, &["--all-accounts", "--json", dir.path_str()]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(8), "{}", stderr);
+    assert!(
+        stderr.contains("Failed: FI65 4321 0987 6543 21"),
+        "{}",
+        stderr
+    );
+    assert!(dir
+        .path()
+        .join(format!("out-{}.csv", IBAN.replace(' ', "")))
+        .exists());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains(r#""kind": "accounts_failed""#),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn append_adds_only_new_rows_to_the_master_file() {
+    let dir = TempDir::new();
+    write_exports(&dir);
+    let master_dir = TempDir::new();
+    let master = master_dir.path().join("master.csv");
+    let master_str = master.to_str().unwrap();
+
+    for _ in 0..2 {
+        let output = nda2ynab(&dir, &["--append", master_str, dir.path_str()]);
+        assert!(output.status.success(), "{:?}", output);
+    }
+    let output = nda2ynab(&dir, &["--append", master_str, dir.path_str()]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("0 transactions appended"));
+
+    let export = NordeaCsv::new("2024-01-06 10:00")
+        .row("2024/01/04", "-4,00", "E")
+        .row("2024/01/04", "-4,00", "D")
+        .contents();
+    let output = nda2ynab_stdin(&dir, &["--append", master_str, "-"], &export);
+    assert!(output.status.success(), "{:?}", output);
+
+    assert_eq!(
+        std::fs::read_to_string(&master).unwrap(),
+        "Date,Payee,Memo,Amount,Import ID\n\
+         2024-01-04,D,,-4.00,YNAB:-4000:2024-01-04:1\n\
+         2024-01-04,E,,-4.00,YNAB:-4000:2024-01-04:2\n"
+    );
+}
+
+#[test]
+fn validate_lists_the_problems_of_a_ynab_csv_by_line() {
+    let dir = TempDir::new();
+    write_exports(&dir);
+    let output = nda2ynab(&dir, &["--max-export-age", "0", dir.path_str()]);
+    assert!(output.status.success(), "{:?}", output);
+    let output = nda2ynab(&dir, &["validate", "out.csv"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("looks fine"));
+
+    std::fs::write(
+        dir.path().join("broken.csv"),
+        "Date,Payee,Memo,Amount,Import ID\n\
+         2024-01-02,A,,-1.00,YNAB:-1000:2024-01-02:1\n\
+         02.01.2024,B,,\"1 000,00\",YNAB:-1000:2024-01-02:1\n",
+    )
+    .unwrap();
+    let output = nda2ynab(&dir, &["validate", "broken.csv"]);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "line 3: Amount '1 000,00' is not a plain number like -12.34\n\
+         line 3: Import ID 'YNAB:-1000:2024-01-02:1' is also on line 2, YNAB only imports one of them\n\
+         line 3: Date '02.01.2024' is not in the %Y-%m-%d format of the other rows\n"
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("3 problem(s) found in broken.csv"));
+}
+
+#[cfg(unix)]
+#[test]
+fn post_process_command_replaces_transactions() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/03", "-3,00", "C")
+        .write(&dir);
+    let command = r#"sed -e '/"payee":"D"/s/"category":null/"category":"Health"/' \
+        -e 's/"payee":"D"/"payee":"Dentist"/' -e '/"payee":"C"/c\
+null'"#;
+    let output = nda2ynab(
+        &dir,
+        &["--stdout", "--post-process", command, dir.path_str()],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Date,Payee,Memo,Amount,Import ID,Category,Flag\n\
+         2024-01-04,Dentist,,-4.00,YNAB:-4000:2024-01-04:1,Health,\n"
+    );
+
+    let output = nda2ynab(
+        &dir,
+        &["--stdout", "--post-process", "head -n 1", dir.path_str()],
+    );
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("wrote 1 line(s) for 2 transaction(s)")
+    );
+}
+
+#[test]
+fn import_ids_of_csv_output_count_rows_imported_before() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-04 10:00")
+        .row("2024/01/04", "-2,00", "COFFEE")
+        .write(&dir);
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-2,00", "COFFEE")
+        .row("2024/01/04", "-2,00", "COFFEE")
+        .write(&dir);
+
+    let output = nda2ynab(&dir, &["--stdout", dir.path_str()]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Date,Payee,Memo,Amount,Import ID\n\
+         2024-01-04,COFFEE,,-2.00,YNAB:-2000:2024-01-04:2\n"
+    );
+}
+
+#[test]
+fn input_map_file_converts_exports_of_other_banks() {
+    let dir = TempDir::new();
+    std::fs::write(
+        dir.path().join("map.toml"),
+        "date = \"Booking date\"\n\
+         amount = \"Amount, EUR\"\n\
+         payee = \"Text\"\n\
+         date_format = \"%d/%m/%Y\"\n\
+         delimiter = \",\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("bank.csv"),
+        "Statement of account\n\
+         Booking date,Text,\"Amount, EUR\"\n\
+         04/01/2024,Grocer,\"-4,50\"\n\
+         03/01/2024,Employer,\"100,00\"\n",
+    )
+    .unwrap();
+
+    let output = nda2ynab(
+        &dir,
+        &[
+            "--stdout",
+            "--input-map",
+            "map.toml",
+            "--current",
+            "bank.csv",
+            dir.path_str(),
+        ],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("2024-01-04,Grocer,,-4.50"), "{}", stdout);
+    assert!(stdout.contains("2024-01-03,Employer,,100.00"), "{}", stdout);
+
+    let output = nda2ynab(&dir, &["--stdout", "--current", "bank.csv", dir.path_str()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not an export of any supported bank"));
+}
+
+#[test]
+fn report_spending_sums_up_each_payee_per_month() {
+    let dir = TempDir::new();
+    let older = NordeaCsv::new("2024-02-02 10:00")
+        .row("2024/02/01", "-4,00", "K-MARKET 1234 HELSINKI")
+        .row("2024/01/31", "-1,50", "KIOSKI")
+        .row("2024/01/15", "-2,50", "KIOSKI")
+        .write(&dir);
+    let newer = NordeaCsv::new("2024-02-10 10:00")
+        .row("2024/02/09", "100,00", "K-MARKET")
+        .row("2024/02/05", "-6,00", "K-MARKET 5678 ESPOO")
+        .row("2024/02/01", "-4,00", "K-MARKET 1234 HELSINKI")
+        .write(&dir);
+    let output = nda2ynab(
+        &dir,
+        &[
+            "report",
+            "spending",
+            newer.to_str().unwrap(),
+            older.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Month,Payee,Transactions,Outflow,Inflow,Net\n\
+         2024-01,KIOSKI,2,-4.00,0.00,-4.00\n\
+         2024-02,K-MARKET,3,-10.00,100.00,90.00\n"
+    );
+
+    let output = nda2ynab(
+        &dir,
+        &[
+            "report",
+            "spending",
+            "--format",
+            "json",
+            "--since",
+            "2024-02-01",
+            newer.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("\"net\": \"90.00\""));
+}
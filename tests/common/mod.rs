@@ -0,0 +1,247 @@
+//! Helpers for writing synthetic Nordea exports in temporary directories.
+
+#![allow(dead_code)]
+
+use chrono::{NaiveDate, NaiveDateTime};
+use nda2ynab::{
+    amount::Amount,
+    encoding::Encoding,
+    find_export_files, parse_nda_date,
+    parser::Registry,
+    system::{Disk, SystemClock},
+    ConvertOptions, ParsedFileName,
+};
+use std::{
+    env, fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread::{self, JoinHandle},
+};
+
+/// A directory that is removed when dropped.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new() -> TempDir {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = env::temp_dir().join(format!(
+            "nda2ynab-test-{}-{}",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn path_str(&self) -> &str {
+        self.path.to_str().unwrap()
+    }
+
+    /// Returns the exports in the directory, most recent first.
+    pub fn exports(&self) -> Vec<ParsedFileName> {
+        find_export_files(
+            &Disk,
+            self.path_str(),
+            &Registry::builtin(None, &SystemClock),
+            Encoding::Auto,
+            None,
+        )
+        .unwrap()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// The two file name formats Nordea has used for exports.
+#[derive(Clone, Copy)]
+pub enum FileNameFormat {
+    /// `Tapahtumat FI12 3456 7890 1234 56 - 2022-03-31 12.30.00.csv`
+    Current,
+    /// `Tapahtumat FI12 3456 7890 1234 56 - 2022.03.31 12.30.csv`
+    Old,
+}
+
+/// Header layouts of Finnish exports.
+#[derive(Clone, Copy)]
+pub enum HeaderLayout {
+    /// The short layout of current exports
+    Current,
+    /// The long layout of older exports, with more columns
+    Old,
+}
+
+/// Builder for a synthetic Finnish Nordea CSV export.
+pub struct NordeaCsv {
+    iban: String,
+    exported: NaiveDateTime,
+    file_name_format: FileNameFormat,
+    layout: HeaderLayout,
+    /// Date, amount and description, most recent first
+    rows: Vec<(String, String, String)>,
+}
+
+pub const IBAN: &str = "FI12 3456 7890 1234 56";
+
+impl NordeaCsv {
+    /// Starts an export of account `IBAN` made at `exported`, given as
+    /// "YYYY-mm-dd HH:MM".
+    pub fn new(exported: &str) -> NordeaCsv {
+        NordeaCsv {
+            iban: IBAN.to_string(),
+            exported: NaiveDateTime::parse_from_str(exported, "%Y-%m-%d %H:%M").unwrap(),
+            file_name_format: FileNameFormat::Current,
+            layout: HeaderLayout::Current,
+            rows: vec![],
+        }
+    }
+
+    pub fn iban(mut self, iban: &str) -> NordeaCsv {
+        self.iban = iban.to_string();
+        self
+    }
+
+    pub fn file_name_format(mut self, format: FileNameFormat) -> NordeaCsv {
+        self.file_name_format = format;
+        self
+    }
+
+    pub fn layout(mut self, layout: HeaderLayout) -> NordeaCsv {
+        self.layout = layout;
+        self
+    }
+
+    /// Adds a row below the previously added ones. `date` and `amount` are
+    /// written as given, e.g. "2024/01/31" or "31.01.2024" and "-12,34".
+    pub fn row(mut self, date: &str, amount: &str, description: &str) -> NordeaCsv {
+        self.rows.push((
+            date.to_string(),
+            amount.to_string(),
+            description.to_string(),
+        ));
+        self
+    }
+
+    pub fn file_name(&self) -> String {
+        let date = match self.file_name_format {
+            FileNameFormat::Current => self.exported.format("%Y-%m-%d %H.%M.%S"),
+            FileNameFormat::Old => self.exported.format("%Y.%m.%d %H.%M"),
+        };
+        format!("Tapahtumat {} - {}.csv", self.iban, date)
+    }
+
+    pub fn contents(&self) -> String {
+        let mut contents = match self.layout {
+            HeaderLayout::Current => {
+                "Kirjauspäivä;Määrä;Maksaja;Maksunsaaja;Nimi;Otsikko;Viitenumero;Valuutta\n"
+            }
+            HeaderLayout::Old => {
+                "Kirjauspäivä;Arvopäivä;Maksupäivä;Määrä;Saaja/Maksaja;Tilinumero;BIC;\
+                 Tapahtuma;Viite;Maksajan viite;Viesti;Kortinnumero;Kuitti;Otsikko\n"
+            }
+        }
+        .to_string();
+
+        for (date, amount, description) in &self.rows {
+            let row = match self.layout {
+                HeaderLayout::Current => {
+                    format!("{};{};;;;{};;EUR\n", date, amount, description)
+                }
+                HeaderLayout::Old => {
+                    format!("{0};{0};{0};{1};;;;;;;;;;{2}\n", date, amount, description)
+                }
+            };
+            contents.push_str(&row);
+        }
+        contents
+    }
+
+    /// Writes the export into `dir` and returns its path.
+    pub fn write(&self, dir: &TempDir) -> PathBuf {
+        let path = dir.path().join(self.file_name());
+        fs::write(&path, self.contents()).unwrap();
+        path
+    }
+}
+
+pub fn options() -> ConvertOptions {
+    ConvertOptions {
+        encoding: Encoding::Auto,
+        since: None,
+        until: None,
+        strict: true,
+        progress: false,
+        allow_gap: false,
+        keep_skipped: false,
+        overlap_days: 3,
+        include_pending: false,
+    }
+}
+
+/// Parses a date like `2024/01/02` for comparing with transactions.
+pub fn date(date: &str) -> NaiveDate {
+    parse_nda_date(date).unwrap()
+}
+
+/// Parses an amount like `-12.34` for comparing with transactions.
+pub fn amount(amount: &str) -> Amount {
+    amount.parse().unwrap()
+}
+
+/// API URL and the request line and body of each request received
+pub type MockApi = (String, JoinHandle<Vec<(String, String)>>);
+
+/// Answers a request with each of `responses` in turn, as a JSON body with
+/// the HTTP status given with it. Returns the API URL and the requests
+/// received.
+pub fn mock_api_responses(responses: Vec<(u16, &'static str)>) -> MockApi {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = thread::spawn(move || {
+        let mut requests = vec![];
+        for (status, response) in responses {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 {} Status\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                response.len(),
+                response
+            )
+            .unwrap();
+            requests.push((request_line, String::from_utf8(body).unwrap()));
+        }
+        requests
+    });
+    (url, handle)
+}
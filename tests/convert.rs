@@ -0,0 +1,774 @@
+mod common;
+
+use common::{amount, date, options, FileNameFormat, HeaderLayout, NordeaCsv, TempDir, IBAN};
+use nda2ynab::{
+    backfill_account, convert_account, memo,
+    parser::Registry,
+    report::RunError,
+    rules::Rules,
+    system::{Disk, SystemClock},
+    to_ynab_rows, to_ynab_rows_after, write_ynab_csv, ynab, Conversion, ConvertOptions, CsvOptions,
+    DateField, ParsedFileName,
+};
+use std::process::Command;
+
+fn convert(dir: &TempDir) -> Result<Conversion, Box<dyn std::error::Error>> {
+    let exports = dir.exports();
+    let files: Vec<&ParsedFileName> = exports.iter().collect();
+    convert_account(
+        &Disk,
+        &files,
+        &Registry::builtin(None, &SystemClock),
+        &options(),
+    )
+}
+
+fn descriptions(conversion: &Conversion) -> Vec<&str> {
+    conversion
+        .rows
+        .iter()
+        .map(|r| r.description.as_str())
+        .collect()
+}
+
+/// Import ids of the rows of a conversion
+fn import_ids(conversion: Conversion) -> Vec<String> {
+    let rows = to_ynab_rows_after(
+        conversion.rows,
+        &conversion.earlier,
+        &Rules::default(),
+        "",
+        false,
+        DateField::Booking,
+    );
+    ynab::import_ids(&rows).unwrap()
+}
+
+#[test]
+fn identical_transactions_converted_in_separate_runs_get_distinct_import_ids() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-04 10:00")
+        .row("2024/01/04", "-2,00", "COFFEE")
+        .row("2024/01/03", "-3,00", "C")
+        .write(&dir);
+    assert_eq!(
+        import_ids(convert(&dir).unwrap()),
+        ["YNAB:-2000:2024-01-04:1", "YNAB:-3000:2024-01-03:1"]
+    );
+
+    // The second coffee of the day was booked after the first export
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/05", "-5,00", "E")
+        .row("2024/01/04", "-2,00", "COFFEE")
+        .row("2024/01/04", "-2,00", "COFFEE")
+        .row("2024/01/03", "-3,00", "C")
+        .write(&dir);
+    assert_eq!(
+        import_ids(convert(&dir).unwrap()),
+        ["YNAB:-5000:2024-01-05:1", "YNAB:-2000:2024-01-04:2"]
+    );
+}
+
+#[test]
+fn only_new_transactions_are_converted() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-03 10:00")
+        .row("2024/01/03", "-3,00", "C")
+        .row("2024/01/02", "-2,00", "B")
+        .row("2024/01/01", "-1,00", "A")
+        .write(&dir);
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/05", "-5,00", "E")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/03", "-3,00", "C")
+        .row("2024/01/02", "-2,00", "B")
+        .write(&dir);
+
+    let conversion = convert(&dir).unwrap();
+    assert_eq!(descriptions(&conversion), ["E", "D"]);
+    assert_eq!(conversion.skipped, 2);
+}
+
+#[test]
+fn only_rows_within_the_overlap_window_are_compared() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-10 10:00")
+        .row("2024/01/10", "-3,00", "C")
+        .row("2024/01/08", "-2,00", "B")
+        .row("2024/01/01", "-1,00", "A")
+        .write(&dir);
+    // Nordea has since changed the description of the oldest transaction,
+    // and listed the ones of the 8th in another order
+    NordeaCsv::new("2024-01-12 10:00")
+        .row("2024/01/12", "-5,00", "E")
+        .row("2024/01/10", "-3,00", "C")
+        .row("2024/01/08", "-4,00", "D")
+        .row("2024/01/08", "-2,00", "B")
+        .row("2024/01/01", "-1,00", "A KAUPPA")
+        .write(&dir);
+    // "D" is new, as it was not in the previous export
+    let mut conversion = convert(&dir).unwrap();
+    assert_eq!(descriptions(&conversion), ["E", "D"]);
+
+    let exports = dir.exports();
+    let files: Vec<&ParsedFileName> = exports.iter().collect();
+    let opts = ConvertOptions {
+        overlap_days: 10,
+        ..options()
+    };
+    conversion =
+        convert_account(&Disk, &files, &Registry::builtin(None, &SystemClock), &opts).unwrap();
+    assert_eq!(descriptions(&conversion), ["E", "D", "A KAUPPA"]);
+}
+
+#[test]
+fn all_transactions_are_converted_without_a_previous_export() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-03 10:00")
+        .row("2024/01/02", "-2,00", "B")
+        .row("2024/01/01", "-1,00", "A")
+        .write(&dir);
+
+    let conversion = convert(&dir).unwrap();
+    assert_eq!(descriptions(&conversion), ["B", "A"]);
+    assert_eq!(conversion.skipped, 0);
+}
+
+#[test]
+fn repeated_identical_transactions_are_counted() {
+    // The previous export was made between two identical purchases
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-02 12:00")
+        .row("2024/01/02", "-3,50", "COFFEE")
+        .row("2024/01/01", "-1,00", "A")
+        .write(&dir);
+    NordeaCsv::new("2024-01-03 10:00")
+        .row("2024/01/02", "-3,50", "COFFEE")
+        .row("2024/01/02", "-3,50", "COFFEE")
+        .row("2024/01/01", "-1,00", "A")
+        .write(&dir);
+
+    let conversion = convert(&dir).unwrap();
+    assert_eq!(descriptions(&conversion), ["COFFEE"]);
+    assert_eq!(conversion.skipped, 2);
+}
+
+#[test]
+fn non_overlapping_exports_are_an_error() {
+    let dir = TempDir::new();
+    // The exports span the same day but have nothing in common
+    NordeaCsv::new("2024-01-02 10:00")
+        .row("2024/01/01", "-1,00", "A")
+        .write(&dir);
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/01", "-2,00", "B")
+        .write(&dir);
+
+    let error = convert(&dir).unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<RunError>(),
+        Some(RunError::NonOverlapping)
+    ));
+}
+
+#[test]
+fn non_overlapping_exports_exit_with_code_4() {
+    let dir = TempDir::new();
+    // The exports span the same day but have nothing in common
+    NordeaCsv::new("2024-01-02 10:00")
+        .row("2024/01/01", "-1,00", "A")
+        .write(&dir);
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/01", "-2,00", "B")
+        .write(&dir);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nda2ynab"))
+        .args(["--dry-run", dir.path_str()])
+        .env("XDG_CONFIG_HOME", dir.path())
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(4));
+}
+
+#[test]
+fn gap_between_exports_is_an_error_unless_allowed() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-02 10:00")
+        .row("2024/01/01", "-1,00", "A")
+        .write(&dir);
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-4,00", "D")
+        .write(&dir);
+
+    let error = convert(&dir).unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<RunError>(),
+        Some(RunError::Gap)
+    ));
+
+    let exports = dir.exports();
+    let files: Vec<&ParsedFileName> = exports.iter().collect();
+    let opts = ConvertOptions {
+        allow_gap: true,
+        ..options()
+    };
+    let conversion =
+        convert_account(&Disk, &files, &Registry::builtin(None, &SystemClock), &opts).unwrap();
+    assert_eq!(descriptions(&conversion), ["D"]);
+}
+
+#[test]
+fn both_file_name_formats_are_recognised_and_ordered() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-03 10:00")
+        .file_name_format(FileNameFormat::Old)
+        .row("2024/01/02", "-2,00", "B")
+        .write(&dir);
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/02", "-2,00", "B")
+        .write(&dir);
+    NordeaCsv::new("2024-01-01 10:00")
+        .file_name_format(FileNameFormat::Old)
+        .iban("FI99 1111 2222 3333 44")
+        .write(&dir);
+
+    let exports = dir.exports();
+    let dates: Vec<String> = exports
+        .iter()
+        .map(|e| e.date.format("%Y-%m-%d").to_string())
+        .collect();
+    assert_eq!(dates, ["2024-01-05", "2024-01-03", "2024-01-01"]);
+    assert_eq!(exports[0].iban, IBAN);
+    assert_eq!(exports[2].iban, "FI99 1111 2222 3333 44");
+}
+
+#[test]
+fn both_header_layouts_are_parsed() {
+    for layout in [HeaderLayout::Current, HeaderLayout::Old] {
+        let dir = TempDir::new();
+        NordeaCsv::new("2024-01-03 10:00")
+            .layout(layout)
+            .row("2024/01/02", "-2,00", "B")
+            .row("2024/01/01", "1 234,56", "A")
+            .write(&dir);
+
+        let conversion = convert(&dir).unwrap();
+        assert_eq!(descriptions(&conversion), ["B", "A"]);
+        assert_eq!(conversion.rows[1].amount, amount("1234.56"));
+        assert_eq!(conversion.rows[0].date, date("2024/01/02"));
+    }
+}
+
+#[test]
+fn currencies_are_read_and_holds_are_dated_by_the_export() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-03 10:00")
+        .row("Invalid date", "-9,90", "PENDING")
+        .row("2024/01/02", "-2,00", "B")
+        .write(&dir);
+
+    let conversion = convert(&dir).unwrap();
+    assert_eq!(conversion.rows[0].currency, "EUR");
+    assert_eq!(conversion.holds[0].date, date("2024/01/03"));
+    assert_eq!(conversion.holds[0].amount, amount("-9.90"));
+}
+
+#[test]
+fn exports_with_different_header_layouts_are_compared() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-03 10:00")
+        .layout(HeaderLayout::Old)
+        .row("2024/01/02", "-2,00", "B")
+        .write(&dir);
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/02", "-2,00", "B")
+        .write(&dir);
+
+    let conversion = convert(&dir).unwrap();
+    assert_eq!(descriptions(&conversion), ["D"]);
+}
+
+#[test]
+fn exports_with_different_date_and_number_settings_are_compared() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-03 10:00")
+        .row("2024/01/02", "-2,00", "B")
+        .row("2024/01/01", "-1234,50", "A")
+        .write(&dir);
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("04.01.2024", "-4.00", "D")
+        .row("02.01.2024", "-2.00", "B")
+        .row("01.01.2024", "-1,234.50", "A")
+        .write(&dir);
+
+    let conversion = convert(&dir).unwrap();
+    assert_eq!(descriptions(&conversion), ["D"]);
+    assert_eq!(conversion.rows[0].date, date("2024/01/04"));
+    assert_eq!(conversion.rows[0].amount, amount("-4.00"));
+}
+
+#[test]
+fn ynab_rows_have_iso_dates() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-03 10:00")
+        .row("31.12.23", "-12,34", "A")
+        .write(&dir);
+
+    let conversion = convert(&dir).unwrap();
+    let rows = to_ynab_rows(
+        conversion.rows,
+        &Rules::default(),
+        memo::DEFAULT_TEMPLATE,
+        false,
+        DateField::Booking,
+    );
+    assert_eq!(rows[0].date, "2023-12-31");
+    assert_eq!(rows[0].amount.to_string(), "-12.34");
+}
+
+#[test]
+fn unparseable_dates_are_row_errors() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-03 10:00")
+        .row("2024/01/02", "-2,00", "B")
+        .row("1st of January", "-1,00", "A")
+        .write(&dir);
+
+    let err = convert(&dir).unwrap_err().to_string();
+    assert!(err.contains("line 3"), "{}", err);
+    assert!(err.contains("1st of January"), "{}", err);
+}
+
+#[test]
+fn large_exports_are_compared() {
+    let days = 3000;
+    let export = |exported: &str, newest: i64| {
+        let first = chrono::NaiveDate::from_ymd_opt(2010, 1, 1).unwrap();
+        (0..=newest)
+            .rev()
+            .fold(NordeaCsv::new(exported), |csv, day| {
+                let date = (first + chrono::Duration::days(day)).format("%Y/%m/%d");
+                (0..5).fold(csv, |csv, i| {
+                    csv.row(&date.to_string(), &format!("-{},00", i), "SHOP")
+                })
+            })
+    };
+
+    let dir = TempDir::new();
+    export("2024-01-01 10:00", days - 2).write(&dir);
+    export("2024-01-02 10:00", days).write(&dir);
+
+    let conversion = convert(&dir).unwrap();
+    assert_eq!(conversion.rows.len(), 10);
+    assert_eq!(conversion.skipped, 5 * (days as usize - 1));
+}
+
+#[test]
+fn backfill_merges_every_export() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-02 10:00")
+        .row("2024/01/02", "-2,00", "B")
+        .row("2024/01/01", "-1,00", "A")
+        .write(&dir);
+    NordeaCsv::new("2024-01-04 10:00")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/03", "-3,00", "C")
+        .row("2024/01/02", "-2,00", "B")
+        .write(&dir);
+    NordeaCsv::new("2024-01-06 10:00")
+        .row("2024/01/06", "-6,00", "F")
+        .row("2024/01/04", "-4,00", "D")
+        .write(&dir);
+
+    let exports = dir.exports();
+    let files: Vec<&ParsedFileName> = exports.iter().collect();
+    let conversion = backfill_account(
+        &Disk,
+        &files,
+        &Registry::builtin(None, &SystemClock),
+        &options(),
+    )
+    .unwrap();
+    assert_eq!(descriptions(&conversion), ["F", "D", "C", "B", "A"]);
+    assert_eq!(conversion.skipped, 2);
+}
+
+#[test]
+fn memo_templates_can_use_any_column_of_the_export() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-03 10:00")
+        .row("02.01.2024", "-12,34", "SHOP")
+        .write(&dir);
+
+    let conversion = convert(&dir).unwrap();
+    let rows = to_ynab_rows(
+        conversion.rows,
+        &Rules::default(),
+        "{description} | {valuutta} {Kirjauspäivä} {Missing}",
+        false,
+        DateField::Booking,
+    );
+    assert_eq!(rows[0].memo, "SHOP | EUR 02.01.2024");
+
+    let rows = to_ynab_rows(
+        convert(&dir).unwrap().rows,
+        &Rules::default(),
+        "ref {reference}",
+        false,
+        DateField::Booking,
+    );
+    assert_eq!(rows[0].memo, "");
+}
+
+#[test]
+fn original_amounts_of_multicurrency_accounts_go_to_the_memo() {
+    let dir = TempDir::new();
+    let export = NordeaCsv::new("2024-01-03 10:00");
+    let contents = "Kirjauspäivä;Määrä;Otsikko;Valuutta;Alkuperäinen summa;Alkuperäinen valuutta\n\
+                    2024/01/02;-11,52;AMAZON.COM;EUR;-12,50;USD\n\
+                    2024/01/01;-4,00;K-MARKET;EUR;-4,00;EUR\n";
+    std::fs::write(dir.path().join(export.file_name()), contents).unwrap();
+
+    let conversion = convert(&dir).unwrap();
+    assert_eq!(conversion.rows[0].amount, amount("-11.52"));
+    assert_eq!(conversion.rows[0].original_amount, Some(amount("-12.50")));
+    assert_eq!(conversion.rows[0].original_currency, "USD");
+
+    let rows = to_ynab_rows(
+        conversion.rows,
+        &Rules::default(),
+        memo::DEFAULT_TEMPLATE,
+        false,
+        DateField::Booking,
+    );
+    assert_eq!(rows[0].amount, amount("-11.52"));
+    assert_eq!(rows[0].memo, "-12.50 USD");
+    assert_eq!(rows[1].memo, "");
+}
+
+#[test]
+fn value_and_payment_dates_can_be_used_as_the_date() {
+    let dir = TempDir::new();
+    let export = NordeaCsv::new("2024-01-05 10:00").layout(HeaderLayout::Old);
+    let header = export.contents();
+    let row = "04.01.2024;02.01.2024;01.01.2024;-4,00;;;;;;;;;;SHOP\n";
+    std::fs::write(dir.path().join(export.file_name()), header + row).unwrap();
+
+    let rows = convert(&dir).unwrap().rows;
+    let dates = [DateField::Booking, DateField::Value, DateField::Payment].map(|field| {
+        to_ynab_rows(rows.clone(), &Rules::default(), "", false, field)[0]
+            .date
+            .clone()
+    });
+    assert_eq!(dates, ["2024-01-04", "2024-01-02", "2024-01-01"]);
+}
+
+#[test]
+fn delimiter_and_header_row_are_detected() {
+    let export = NordeaCsv::new("2024-01-03 10:00")
+        .row("2024/01/02", "-2,00", "B")
+        .row("1st of January", "-1,00", "A");
+    let quoted = export
+        .contents()
+        .lines()
+        .map(|line| {
+            let fields: Vec<_> = line.split(';').map(|f| format!("\"{}\"", f)).collect();
+            fields.join(",") + "\n"
+        })
+        .collect::<String>();
+    let variants = [
+        export.contents().replace(';', "\t"),
+        quoted,
+        format!("Tilitapahtumat {}\n\n{}", IBAN, export.contents()),
+    ];
+
+    for contents in variants {
+        let dir = TempDir::new();
+        std::fs::write(dir.path().join(export.file_name()), &contents).unwrap();
+        let err = convert(&dir).unwrap_err().to_string();
+        let line = if contents.starts_with("Tilitapahtumat") {
+            "line 5"
+        } else {
+            "line 3"
+        };
+        assert!(err.contains(line), "{}\n{}", err, contents);
+        assert!(err.contains("1st of January"), "{}", err);
+    }
+}
+
+#[test]
+fn renamed_columns_are_reported() {
+    let dir = TempDir::new();
+    let export = NordeaCsv::new("2024-01-03 10:00").row("2024/01/02", "-2,00", "B");
+    let renamed = export.contents().replace("Otsikko", "Kuvaus");
+    std::fs::write(dir.path().join(export.file_name()), renamed).unwrap();
+
+    let err = convert(&dir).unwrap_err().to_string();
+    assert!(
+        err.contains(
+            "The closest format is Nordea FI (--locale fi), which needs the column(s) Otsikko"
+        ),
+        "{}",
+        err
+    );
+    assert!(err.contains("not recognised: Kuvaus"), "{}", err);
+}
+
+#[test]
+fn exports_of_another_bank_with_a_nordea_name_are_reported() {
+    let dir = TempDir::new();
+    let export = NordeaCsv::new("2024-01-03 10:00");
+    std::fs::write(
+        dir.path().join(export.file_name()),
+        "Kirjauspäivä;Arvopäivä;Määrä EUROA;Laji;Selitys;Saaja/Maksaja\n\
+         02.01.2024;02.01.2024;-5,00;106;KORTTIOSTO;K-MARKET\n",
+    )
+    .unwrap();
+
+    let err = convert(&dir).unwrap_err().to_string();
+    assert!(
+        err.contains("named like a nordea export, but its contents are recognised as op"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn trailing_purchase_dates_are_stripped_from_payees() {
+    let rules = Rules::builtin();
+    for description in [
+        "K-MARKET 31.01",
+        "K-MARKET 31.01.",
+        "K-MARKET 31.01.24",
+        "K-MARKET 1.2.2024",
+    ] {
+        assert_eq!(
+            rules.clean_payee(description),
+            "K-MARKET",
+            "{}",
+            description
+        );
+    }
+}
+
+#[test]
+fn merchants_give_payees_and_categories() {
+    let rules_dir = TempDir::new();
+    let path = rules_dir.path().join("rules.toml");
+    std::fs::write(
+        &path,
+        "[[merchant]]\nprefix = 'k-market kamppi'\npayee = 'K-Market Kamppi'\n\n\
+         [[category]]\npattern = '^HSL'\ncategory = 'Commuting'\n",
+    )
+    .unwrap();
+    let mut rules = Rules::builtin();
+    rules.extend(Rules::from_file(&path).unwrap());
+    rules.extend(Rules::merchants());
+    assert!(rules.has_categories());
+
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-4,00", "K-MARKET HERTTONIEMI 1234 HELSINKI")
+        .row("2024/01/04", "-5,00", "K-MARKET KAMPPI")
+        .row("2024/01/03", "-2,80", "HSL MOBIILI")
+        .row("2024/01/02", "-7,00", "PAYPAL *STEAM")
+        .row("2024/01/01", "-1,00", "KIOSKI 123")
+        .write(&dir);
+    let rows = convert(&dir).unwrap().rows;
+    let ynab = to_ynab_rows(rows, &rules, "", false, DateField::Booking);
+    let found: Vec<(&str, Option<&str>)> = ynab
+        .iter()
+        .map(|r| (r.payee.as_str(), r.category.as_deref()))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            ("K-Market", Some("Groceries")),
+            ("K-Market Kamppi", None),
+            ("HSL", Some("Commuting")),
+            ("PayPal", None),
+            ("KIOSKI", None),
+        ]
+    );
+}
+
+#[test]
+fn tag_rules_append_hashtags_to_memos() {
+    let rules_dir = TempDir::new();
+    let path = rules_dir.path().join("rules.toml");
+    std::fs::write(
+        &path,
+        "[[tag]]\npattern = '(?i)finnair|hotel'\ntags = ['travel', '#trip']\n\n\
+         [[tag]]\npattern = 'HOTEL'\nmax = -100\ntag = '#work-reimbursable'\n\n\
+         [[tag]]\npattern = 'FINNAIR'\ntag = '#travel'\n",
+    )
+    .unwrap();
+    let mut rules = Rules::builtin();
+    rules.extend(Rules::from_file(&path).unwrap());
+
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-150,00", "HOTEL ARTHUR")
+        .row("2024/01/03", "-90,00", "FINNAIR")
+        .row("2024/01/02", "-5,00", "KIOSKI")
+        .write(&dir);
+    let rows = convert(&dir).unwrap().rows;
+    let ynab = to_ynab_rows(rows, &rules, "Card", false, DateField::Booking);
+    let memos: Vec<&str> = ynab.iter().map(|r| r.memo.as_str()).collect();
+    assert_eq!(
+        memos,
+        [
+            "Card #travel #trip #work-reimbursable",
+            "Card #travel #trip",
+            "Card"
+        ]
+    );
+}
+
+#[test]
+fn quoted_descriptions_may_have_line_breaks_and_semicolons() {
+    let sepa = "\"SEPA-MAKSU; VUOKRA\nTAMMIKUU 2024\"";
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-02 10:00")
+        .row("2024/01/01", "-800,00", sepa)
+        .row("2024/01/01", "-1,00", "A")
+        .write(&dir);
+    let newest = |broken: bool| {
+        let export = NordeaCsv::new("2024-01-05 10:00").row("2024/01/04", "-4,00", "\"D;\r\nE\"");
+        let export = match broken {
+            true => export.row("2024/01/03", "x", "BROKEN"),
+            false => export,
+        };
+        export
+            .row("2024/01/01", "-800,00", sepa)
+            .row("2024/01/01", "-1,00", "A")
+    };
+    // The row after the one spanning two lines is on line 4
+    newest(true).write(&dir);
+    let error = convert(&dir).unwrap_err().to_string();
+    assert!(error.contains("line 4: Invalid amount"), "{}", error);
+
+    newest(false).write(&dir);
+    let conversion = convert(&dir).unwrap();
+    assert_eq!(descriptions(&conversion), ["D;\r\nE"]);
+
+    let mut rows = to_ynab_rows(
+        conversion.rows,
+        &Rules::builtin(),
+        "{description}",
+        false,
+        DateField::Booking,
+    );
+    let account = nda2ynab::columns::Account {
+        iban: IBAN,
+        name: IBAN,
+        currency: "EUR",
+    };
+    // As kept with --keep-control-chars
+    rows[0].memo = "SEPA\r\nVIESTI".to_string();
+    let mut csv = vec![];
+    write_ynab_csv(&rows, &CsvOptions::default(), &account, &mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    assert_eq!(csv.lines().count(), 2, "{}", csv);
+    assert!(csv.contains(",SEPA  VIESTI,"), "{}", csv);
+}
+
+#[test]
+fn cashback_rules_split_cash_withdrawals_off_card_purchases() {
+    let rules = Rules::from_toml(
+        "[[cashback]]\npattern = '(?i)käteisnosto (?P<amount>\\d+,\\d{2})'\n\n\
+         [[cashback]]\npattern = '^S-MARKET'\namount = 20\naccount = 'Wallet'\n\n\
+         [[category]]\npattern = 'MARKET'\ncategory = 'Groceries'\n",
+    )
+    .unwrap();
+
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-62,35", "K-MARKET KÄTEISNOSTO 50,00")
+        .row("2024/01/03", "-32,10", "S-MARKET")
+        .row("2024/01/02", "-10,00", "S-MARKET")
+        .row("2024/01/01", "20,00", "S-MARKET")
+        .write(&dir);
+    let rows = convert(&dir).unwrap().rows;
+    let ynab = to_ynab_rows(rows, &rules, "", false, DateField::Booking);
+    let rows: Vec<(&str, String, Option<&str>)> = ynab
+        .iter()
+        .map(|r| {
+            (
+                r.payee.as_str(),
+                r.amount.to_string().trim().to_string(),
+                r.category.as_deref(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            (
+                "K-MARKET KÄTEISNOSTO 50,00",
+                "-12.35".into(),
+                Some("Groceries")
+            ),
+            ("Transfer : Cash", "-50.00".into(), None),
+            ("S-MARKET", "-12.10".into(), Some("Groceries")),
+            ("Transfer : Wallet", "-20.00".into(), None),
+            // Not more cash than the whole purchase, and no deposits
+            ("S-MARKET", "-10.00".into(), Some("Groceries")),
+            ("S-MARKET", "20.00".into(), Some("Groceries")),
+        ]
+    );
+}
+
+#[test]
+fn traced_cashback_rules_match_the_message() {
+    let rules =
+        Rules::from_toml("[[cashback]]\npattern = '(?i)käteisnosto (?P<amount>\\d+,\\d{2})'\n")
+            .unwrap();
+    let total = "-62.35".parse().unwrap();
+    let trace = rules.trace("K-MARKET", "KÄTEISNOSTO 50,00", total);
+    assert_eq!(
+        trace.cashback.as_deref(),
+        Some("cashback -50.00 '(?i)käteisnosto (?P<amount>\\d+,\\d{2})'")
+    );
+    assert_eq!(rules.trace("K-MARKET", "", total).cashback, None);
+}
+
+#[test]
+fn split_rules_round_percentages_as_configured() {
+    let split = |rounding: &str| {
+        let rules = Rules::from_toml(&format!(
+            "[[split]]\npattern = 'SHARED'\nrounding = '{}'\nparts = [{{ percent = 50 }}, {{}}]\n",
+            rounding
+        ))
+        .unwrap();
+        rules
+            .split("SHARED", "SHARED")
+            .unwrap()
+            .split(amount("-10.05"))
+            .into_iter()
+            .map(|(amount, _)| amount.cents())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(split("nearest"), [-503, -502]);
+    assert_eq!(split("down"), [-502, -503]);
+    assert_eq!(split("up"), [-503, -502]);
+    assert!(Rules::from_toml("[[split]]\npattern = 'A'\nrounding = 'x'\nparts = [{}]\n").is_err());
+}
+
+#[test]
+fn payees_compare_equal_in_composed_and_decomposed_forms() {
+    let dir = TempDir::new();
+    NordeaCsv::new("2024-01-02 10:00")
+        .row("2024/01/01", "-1,00", "SA\u{308}HKO\u{308}\u{a0}OY")
+        .write(&dir);
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-4,00", "SÄHKÖ OY")
+        .row("2024/01/01", "-1,00", "SÄHKO\u{308} OY")
+        .write(&dir);
+    assert_eq!(descriptions(&convert(&dir).unwrap()), ["SÄHKÖ OY"]);
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use common::{amount, date, options, TempDir};
+use nda2ynab::{
+    convert_account,
+    parser::Registry,
+    system::{Disk, SystemClock},
+    ParsedFileName,
+};
+use std::fs;
+
+const CARD: &str = "5412 34XX XXXX 1234";
+
+fn write_export(dir: &TempDir, exported: &str, rows: &[&str]) {
+    let mut contents = "Ostopäivä;Veloituspäivä;Kauppias;Määrä;Alkuperäinen määrä;\
+                        Alkuperäinen valuutta\n"
+        .to_string();
+    for row in rows {
+        contents.push_str(row);
+        contents.push('\n');
+    }
+    let name = format!("Luottokorttitapahtumat {} - {}.csv", CARD, exported);
+    fs::write(dir.path().join(name), contents).unwrap();
+}
+
+#[test]
+fn card_exports_are_converted_by_purchase_date() {
+    let dir = TempDir::new();
+    write_export(
+        &dir,
+        "2024-01-03 10.00.00",
+        &["02.01.2024;05.01.2024;K-MARKET;-12,34;-12,34;EUR"],
+    );
+    write_export(
+        &dir,
+        "2024-01-10 10.00.00",
+        &[
+            "08.01.2024;10.01.2024;AMAZON.COM;-18,40;-20,00;USD",
+            "02.01.2024;05.01.2024;K-MARKET;-12,34;-12,34;EUR",
+        ],
+    );
+
+    let exports = dir.exports();
+    assert_eq!(exports.len(), 2);
+    assert_eq!(exports[0].iban, CARD);
+    assert_eq!(exports[0].bank, "nordea-card");
+
+    let files: Vec<&ParsedFileName> = exports.iter().collect();
+    let conversion = convert_account(
+        &Disk,
+        &files,
+        &Registry::builtin(None, &SystemClock),
+        &options(),
+    )
+    .unwrap();
+    assert_eq!(conversion.rows.len(), 1);
+    let row = &conversion.rows[0];
+    assert_eq!(row.date, date("2024/01/08"));
+    assert_eq!(row.description, "AMAZON.COM");
+    assert_eq!(row.amount, amount("-18.40"));
+    assert_eq!(row.message, "-20.00 USD");
+}
+
+#[test]
+fn card_exports_are_detected_from_the_header_row() {
+    let contents = "Ostopäivä;Veloituspäivä;Kauppias;Määrä\n02.01.2024;05.01.2024;SHOP;-1,00\n";
+    let registry = Registry::builtin(None, &SystemClock);
+    assert_eq!(registry.detect(contents).unwrap().name(), "nordea-card");
+}
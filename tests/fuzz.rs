@@ -0,0 +1,191 @@
+//! Fuzzing of the export parsers and the file name parser.
+//!
+//! The exports in `tests/golden` are mutated at random, by flipping,
+//! inserting and deleting bytes and whole lines, and fed to every parser,
+//! which may reject them but must not panic. The run is deterministic:
+//! `FUZZ_SEED` picks another sequence of inputs and `FUZZ_ITERATIONS` (by
+//! default 500) sets how many are tried, e.g.
+//! `FUZZ_ITERATIONS=100000 cargo test --release --test fuzz` for a longer
+//! run. An input that panics is saved in the temporary directory.
+//!
+//! This stands in for a cargo-fuzz target, which would need a nightly
+//! toolchain and libFuzzer, so that the parsers are fuzzed by every
+//! `cargo test` without anything else installed.
+
+use nda2ynab::{
+    encoding::{self, Encoding},
+    parser::{sniff_csv, Registry},
+    system::SystemClock,
+};
+use std::{
+    env, fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+};
+
+/// Fragments of exports inserted by the mutations, which are more likely to
+/// reach the corner cases of the parsers than random bytes
+const DICTIONARY: &[&str] = &[
+    ";",
+    ",",
+    "\t",
+    "\"",
+    "\n",
+    "\r\n",
+    "-",
+    "+",
+    " ",
+    "Invalid date",
+    "2024/02/30",
+    "31.12.24",
+    "1 234,56",
+    "-0,00",
+    "999999999999999999,99",
+    "ä",
+    "Kirjauspäivä",
+    "Määrä",
+    "Otsikko",
+    "\u{feff}",
+    "\u{0}",
+];
+
+/// xorshift64*, so that the inputs only depend on the seed
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n.max(1) as u64) as usize
+    }
+}
+
+fn mutate(input: &[u8], random: &mut Random) -> Vec<u8> {
+    let mut bytes = input.to_vec();
+    for _ in 0..=random.below(4) {
+        let at = random.below(bytes.len() + 1);
+        match random.below(6) {
+            0 if at < bytes.len() => bytes[at] ^= 1 << random.below(8),
+            1 => {
+                let word = DICTIONARY[random.below(DICTIONARY.len())];
+                bytes.splice(at..at, word.bytes());
+            }
+            2 => {
+                let end = (at + random.below(16)).min(bytes.len());
+                bytes.drain(at..end);
+            }
+            3 => {
+                // Repeat a line
+                let start = bytes[..at]
+                    .iter()
+                    .rposition(|&b| b == b'\n')
+                    .map_or(0, |i| i + 1);
+                let end = bytes[at..]
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .map_or(bytes.len(), |i| at + i + 1);
+                let line = bytes[start..end].to_vec();
+                bytes.splice(start..start, line);
+            }
+            4 => bytes.truncate(at),
+            _ => bytes.insert(at.min(bytes.len()), random.next() as u8),
+        }
+    }
+    bytes
+}
+
+fn mutate_name(name: &str, random: &mut Random) -> String {
+    let mut chars: Vec<char> = name.chars().collect();
+    for _ in 0..=random.below(3) {
+        let at = random.below(chars.len() + 1);
+        match random.below(3) {
+            0 if at < chars.len() => {
+                chars.remove(at);
+            }
+            1 => chars.insert(
+                at,
+                ['0', '9', ' ', '-', '.', 'X', '–', '('][random.below(8)],
+            ),
+            _ => chars.truncate(at),
+        }
+    }
+    chars.into_iter().collect()
+}
+
+/// The exports of the golden-file tests, by file name
+fn corpus() -> Vec<(String, Vec<u8>)> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut corpus = vec![];
+    for case in fs::read_dir(root).unwrap().map(|e| e.unwrap().path()) {
+        if !case.is_dir() {
+            continue;
+        }
+        for file in fs::read_dir(case).unwrap().map(|e| e.unwrap().path()) {
+            let name = file.file_name().unwrap().to_string_lossy().into_owned();
+            corpus.push((name, fs::read(&file).unwrap()));
+        }
+    }
+    corpus.sort();
+    corpus
+}
+
+/// Runs `f` and saves `input` if it panics.
+fn check(input: &[u8], seed: u64, iteration: usize, f: impl FnOnce()) {
+    if panic::catch_unwind(AssertUnwindSafe(f)).is_err() {
+        let path: PathBuf = env::temp_dir().join(format!("nda2ynab-fuzz-{}-{}", seed, iteration));
+        fs::write(&path, input).unwrap();
+        panic!(
+            "Input {} of seed {} panicked, saved in {}",
+            iteration,
+            seed,
+            path.display()
+        );
+    }
+}
+
+#[test]
+fn parsers_do_not_panic_on_mutated_exports() {
+    let seed = env::var("FUZZ_SEED").map_or(1, |s| s.parse().unwrap());
+    let iterations = env::var("FUZZ_ITERATIONS").map_or(500, |s| s.parse().unwrap());
+    let corpus = corpus();
+    let registry = Registry::builtin(None, &SystemClock);
+    let banks = [
+        "nordea",
+        "nordea-card",
+        "op",
+        "s-pankki",
+        "danske",
+        "revolut",
+        "wise",
+        "camt",
+    ];
+    let mut random = Random(seed.max(1));
+
+    for iteration in 0..iterations {
+        let (name, contents) = &corpus[random.below(corpus.len())];
+        let input = mutate(contents, &mut random);
+        check(&input, seed, iteration, || {
+            let Ok(contents) = encoding::decode(&input, Encoding::Auto) else {
+                return;
+            };
+            sniff_csv(&contents, |_| true);
+            registry.detect(&contents);
+            for bank in banks {
+                let _ = registry
+                    .get(bank)
+                    .unwrap()
+                    .parse_rows(&contents, Path::new(name));
+            }
+        });
+
+        let name = mutate_name(name, &mut random);
+        check(name.as_bytes(), seed, iteration, || {
+            registry.parse_file_name(&name);
+        });
+    }
+}
@@ -0,0 +1,108 @@
+//! Golden-file tests of the converter output.
+//!
+//! Each directory in `tests/golden` holds exports of one account, and
+//! `tests/golden/<directory>.ynab.csv` the YNAB CSV they convert to with the
+//! default options and the built-in rules. After an intended change of the
+//! output, run `UPDATE_GOLDEN=1 cargo test --test golden` to write the files
+//! again, and review their diff.
+
+use nda2ynab::{
+    columns::Account,
+    convert_account,
+    encoding::Encoding,
+    find_export_files, memo,
+    parser::Registry,
+    rules::Rules,
+    system::{Disk, SystemClock},
+    to_ynab_rows, write_ynab_csv, ConvertOptions, CsvOptions, DateField, ParsedFileName,
+};
+use std::{env, fs, path::Path};
+
+fn options() -> ConvertOptions {
+    ConvertOptions {
+        encoding: Encoding::Auto,
+        since: None,
+        until: None,
+        strict: true,
+        progress: false,
+        allow_gap: false,
+        keep_skipped: false,
+        overlap_days: 3,
+        include_pending: false,
+    }
+}
+
+/// Converts the exports in `dir` to a YNAB CSV.
+fn convert(dir: &Path) -> String {
+    let registry = Registry::builtin(None, &SystemClock);
+    let exports = find_export_files(
+        &Disk,
+        dir.to_str().unwrap(),
+        &registry,
+        Encoding::Auto,
+        None,
+    )
+    .unwrap();
+    assert!(!exports.is_empty(), "no exports in {}", dir.display());
+    let files: Vec<&ParsedFileName> = exports.iter().collect();
+    let conversion = convert_account(&Disk, &files, &registry, &options()).unwrap();
+
+    let rules = Rules::load(None, false, false).unwrap();
+    let rows = to_ynab_rows(
+        conversion.rows,
+        &rules,
+        memo::DEFAULT_TEMPLATE,
+        false,
+        DateField::Booking,
+    );
+    let account = Account {
+        iban: &exports[0].iban,
+        name: &exports[0].iban,
+        currency: "EUR",
+    };
+    let mut csv = vec![];
+    write_ynab_csv(&rows, &CsvOptions::default(), &account, &mut csv).unwrap();
+    String::from_utf8(csv).unwrap()
+}
+
+#[test]
+fn exports_convert_to_their_golden_files() {
+    let update = env::var_os("UPDATE_GOLDEN").is_some_and(|v| !v.is_empty());
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut cases: Vec<_> = fs::read_dir(&root)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir())
+        .collect();
+    cases.sort();
+    assert!(!cases.is_empty());
+
+    let mut failed = vec![];
+    for case in &cases {
+        let actual = convert(case);
+        let golden = case.with_extension("ynab.csv");
+        if update {
+            fs::write(&golden, &actual).unwrap();
+            continue;
+        }
+        let expected = fs::read_to_string(&golden).unwrap_or_else(|_| {
+            panic!(
+                "{} is missing, run with UPDATE_GOLDEN=1 to write it",
+                golden.display()
+            )
+        });
+        if actual != expected {
+            failed.push(format!(
+                "{}:\n--- expected\n{}+++ actual\n{}",
+                golden.display(),
+                expected,
+                actual
+            ));
+        }
+    }
+    assert!(
+        failed.is_empty(),
+        "{}\nRun with UPDATE_GOLDEN=1 if the changes are intended.",
+        failed.join("\n")
+    );
+}
@@ -0,0 +1,161 @@
+mod common;
+
+use common::{NordeaCsv, TempDir};
+use nda2ynab::{history, Transaction};
+use std::{collections::HashSet, process::Command};
+
+fn transaction(date: &str, amount: &str, description: &str) -> Transaction {
+    Transaction {
+        date: common::date(date),
+        amount: common::amount(amount),
+        currency: String::new(),
+        description: description.to_string(),
+        reference: String::new(),
+        message: String::new(),
+        counterparty_account: String::new(),
+        value_date: None,
+        payment_date: None,
+        balance: None,
+        original_amount: None,
+        original_currency: String::new(),
+        pending: false,
+        columns: vec![],
+    }
+}
+
+#[test]
+fn identical_transactions_are_told_apart_by_occurrence() {
+    let rows = vec![
+        transaction("2024/01/02", "-2.50", "HSL"),
+        transaction("2024/01/02", "-2.50", "HSL"),
+        transaction("2024/01/01", "-2.50", "HSL"),
+    ];
+    let keys = history::keys(&rows);
+    assert_eq!(keys[0].date, "2024-01-02");
+    assert_eq!(
+        keys.iter().map(|k| k.occurrence).collect::<Vec<_>>(),
+        vec![0, 1, 0]
+    );
+
+    // Only the first of the two tickets of the day was converted before
+    let seen: HashSet<_> = [keys[0].clone(), keys[2].clone()].into_iter().collect();
+    let mut rows = rows;
+    let removed = history::remove_seen(&mut rows, &seen);
+    assert_eq!(removed.rows.len(), 2);
+    assert_eq!(rows, vec![transaction("2024/01/02", "-2.50", "HSL")]);
+    // Recorded as the second ticket of the day, not as the first again
+    assert_eq!(removed.kept, vec![keys[1].clone()]);
+}
+
+#[test]
+fn pending_transactions_are_never_left_out() {
+    let mut pending = transaction("2024/01/02", "-9.90", "SPOTIFY");
+    pending.pending = true;
+    let mut rows = vec![pending.clone()];
+    let seen: HashSet<_> = history::keys(&rows).into_iter().collect();
+    let removed = history::remove_seen(&mut rows, &seen);
+    assert!(removed.rows.is_empty() && removed.kept.is_empty());
+    assert_eq!(rows, vec![pending]);
+}
+
+#[test]
+fn converted_transactions_are_not_converted_again() {
+    if Command::new("sqlite3").arg("-version").output().is_err() {
+        eprintln!("sqlite3 is not installed, skipping");
+        return;
+    }
+
+    let dir = TempDir::new();
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_nda2ynab"))
+            .args(args)
+            .env("XDG_CONFIG_HOME", dir.path())
+            .env("XDG_STATE_HOME", dir.path())
+            .current_dir(dir.path())
+            .output()
+            .unwrap()
+    };
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/01", "-1,00", "A")
+        .write(&dir);
+
+    let args = ["--stdout", "--state-backend", "sqlite", dir.path_str()];
+    let first = run(&args);
+    assert!(first.status.success());
+    assert!(String::from_utf8_lossy(&first.stdout).contains("2024-01-04,D,,-4.00"));
+
+    // A newer export overlapping only partly with the previous one, converted
+    // on its own
+    NordeaCsv::new("2024-01-09 10:00")
+        .row("2024/01/08", "-8,00", "H")
+        .row("2024/01/04", "-4,00", "D")
+        .write(&dir);
+    let current = dir
+        .path()
+        .join(NordeaCsv::new("2024-01-09 10:00").file_name());
+    let second = run(&[
+        "--stdout",
+        "--state-backend",
+        "sqlite",
+        "--current",
+        current.to_str().unwrap(),
+        dir.path_str(),
+    ]);
+    let stdout = String::from_utf8_lossy(&second.stdout);
+    assert!(second.status.success());
+    assert!(stdout.contains("2024-01-08,H,,-8.00"), "{}", stdout);
+    assert!(!stdout.contains(",D,"), "{}", stdout);
+
+    let status = run(&["status", "--since", "2024-01-02"]);
+    let stdout = String::from_utf8_lossy(&status.stdout);
+    assert!(stdout.contains("2 transaction(s)"), "{}", stdout);
+}
+
+#[test]
+fn repeated_identical_transactions_are_recorded_once_each() {
+    if Command::new("sqlite3").arg("-version").output().is_err() {
+        eprintln!("sqlite3 is not installed, skipping");
+        return;
+    }
+
+    let dir = TempDir::new();
+    let run = |exported: &str| {
+        let current = dir.path().join(NordeaCsv::new(exported).file_name());
+        Command::new(env!("CARGO_BIN_EXE_nda2ynab"))
+            .args(["--stdout", "--state-backend", "sqlite", "--current"])
+            .arg(current)
+            .arg(dir.path())
+            .env("XDG_CONFIG_HOME", dir.path())
+            .env("XDG_STATE_HOME", dir.path())
+            .current_dir(dir.path())
+            .output()
+            .unwrap()
+    };
+    NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-4,00", "D")
+        .write(&dir);
+    assert!(run("2024-01-05 10:00").status.success());
+
+    // The second, identical, transaction of the day shows up a day later
+    NordeaCsv::new("2024-01-06 10:00")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/04", "-4,00", "D")
+        .write(&dir);
+    let second = run("2024-01-06 10:00");
+    let stdout = String::from_utf8_lossy(&second.stdout);
+    assert!(second.status.success());
+    assert_eq!(stdout.matches(",D,").count(), 1, "{}", stdout);
+    assert!(String::from_utf8_lossy(&second.stderr).contains("Added 1 transaction(s)"));
+
+    NordeaCsv::new("2024-01-09 10:00")
+        .row("2024/01/08", "-8,00", "X")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/04", "-4,00", "D")
+        .write(&dir);
+    let third = run("2024-01-09 10:00");
+    let stdout = String::from_utf8_lossy(&third.stdout);
+    assert!(third.status.success());
+    assert!(stdout.contains("2024-01-08,X,,-8.00"), "{}", stdout);
+    assert!(!stdout.contains(",D,"), "{}", stdout);
+}
@@ -0,0 +1,64 @@
+#![cfg(feature = "fetch-mail")]
+
+use nda2ynab::mail::{attachments, parse_search, Attachment};
+
+const CSV: &str = "Kirjauspäivä;Määrä;Otsikko\n2024/01/04;-4,00;D\n";
+
+#[test]
+fn exports_are_found_in_forwarded_messages() {
+    // "Kirjauspäivä;Määrä;Otsikko\n2024/01/04;-4,00;D\n" in base64
+    let message = "From: me@example.com\r\n\
+        Subject: Fwd: export\r\n\
+        Content-Type: multipart/mixed;\r\n\
+        \tboundary=\"outer\"\r\n\
+        \r\n\
+        --outer\r\n\
+        Content-Type: text/plain; charset=utf-8\r\n\
+        \r\n\
+        See attached\r\n\
+        --outer\r\n\
+        Content-Type: message/rfc822\r\n\
+        \r\n\
+        Content-Type: multipart/mixed; boundary=inner\r\n\
+        \r\n\
+        --inner\r\n\
+        Content-Type: text/csv; name=\"=?utf-8?Q?Tapahtumat_FI12_3456_7890_1234_56_-_?=\r\n\
+        \x20=?utf-8?B?MjAyNC0wMS0wNSAxMC4wMC4wMC5jc3Y=?=\"\r\n\
+        Content-Transfer-Encoding: base64\r\n\
+        \r\n\
+        S2lyamF1c3DDpGl2w6Q7TcOkw6Ryw6Q7T3RzaWtrbwoyMDI0LzAx\r\n\
+        LzA0Oy00LDAwO0QK\r\n\
+        --inner--\r\n\
+        --outer\r\n\
+        Content-Type: text/csv\r\n\
+        Content-Disposition: attachment;\r\n\
+        \tfilename*0*=utf-8''Tapahtumat%20FI65%204321;\r\n\
+        \tfilename*1=\" 0987 6543 21 - 2024-01-05 10.00.00.csv\"\r\n\
+        Content-Transfer-Encoding: quoted-printable\r\n\
+        \r\n\
+        Kirjausp=C3=A4iv=C3=A4;M=C3=A4=C3=A4r=C3=A4;Otsikko\n2024/01/04;-4,00;=\r\n\
+        D\n\r\n\
+        --outer--\r\n";
+
+    assert_eq!(
+        attachments(message.as_bytes()),
+        vec![
+            Attachment {
+                file_name: "Tapahtumat FI12 3456 7890 1234 56 - 2024-01-05 10.00.00.csv"
+                    .to_string(),
+                data: CSV.as_bytes().to_vec(),
+            },
+            Attachment {
+                file_name: "Tapahtumat FI65 4321 0987 6543 21 - 2024-01-05 10.00.00.csv"
+                    .to_string(),
+                data: CSV.as_bytes().to_vec(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn unread_messages_are_read_from_the_search_response() {
+    assert_eq!(parse_search("* SEARCH 3 7 12\r\n"), vec![3, 7, 12]);
+    assert_eq!(parse_search("* SEARCH\r\n"), Vec::<u32>::new());
+}
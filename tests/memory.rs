@@ -0,0 +1,125 @@
+mod common;
+
+use common::{amount, date, NordeaCsv};
+use nda2ynab::{
+    memory::{self, Options, Previous},
+    system::FixedClock,
+};
+
+/// Time of the conversions, by which holds are dated
+fn clock() -> FixedClock {
+    FixedClock(date("2024-01-06").and_hms(9, 0, 0))
+}
+
+#[test]
+fn exports_are_converted_from_bytes() {
+    let previous = NordeaCsv::new("2024-01-03 10:00")
+        .row("2024/01/02", "-2,00", "B")
+        .row("2024/01/01", "-1,00", "A")
+        .contents();
+    let current = NordeaCsv::new("2024-01-05 10:00")
+        .row("Invalid date", "-9,90", "HOLD")
+        .row("2024/01/04", "-4,00", "K-MARKET KAMPPI")
+        .row("2024/01/02", "-2,00", "B")
+        .row("2024/01/01", "-1,00", "A")
+        .contents();
+
+    let opts = Options {
+        title_case: true,
+        ..Options::default()
+    };
+    let rows = memory::convert_bytes(
+        current.as_bytes(),
+        Some(previous.as_bytes()),
+        &clock(),
+        &opts,
+    )
+    .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].date, "2024-01-04");
+    assert_eq!(rows[0].payee, "K-Market Kamppi");
+    assert_eq!(rows[0].amount, amount("-4.00"));
+
+    let csv = memory::to_ynab_csv(&rows).unwrap();
+    assert_eq!(
+        csv,
+        "Date,Payee,Memo,Amount,Import ID\n2024-01-04,K-Market Kamppi,,-4.00,YNAB:-4000:2024-01-04:1\n"
+    );
+
+    let all =
+        memory::convert_bytes(current.as_bytes(), None, &clock(), &Options::default()).unwrap();
+    assert_eq!(all.len(), 3);
+
+    let opts = Options {
+        include_pending: true,
+        ..Options::default()
+    };
+    let all = memory::convert_bytes(current.as_bytes(), None, &clock(), &opts).unwrap();
+    assert_eq!(all.len(), 4);
+    assert_eq!(all[0].date, "2024-01-06");
+    assert_eq!(all[0].amount, amount("-9.90"));
+}
+
+#[test]
+fn rows_after_the_previous_newest_are_new() {
+    let export = NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/02", "-2,00", "B")
+        .row("2024/01/01", "-1,00", "A")
+        .contents();
+    let rows = memory::read_export(export.as_bytes(), &clock())
+        .unwrap()
+        .transactions;
+    let opts = Options {
+        rules: Some("[[ignore]]\nname = \"D\"\npattern = \"^D$\"\n".to_string()),
+        ..Options::default()
+    };
+
+    let new = memory::convert_rows(rows.clone(), Previous::of(&rows[2..]), &opts).unwrap();
+    assert_eq!(new.len(), 1);
+    assert_eq!(new[0].payee, "B");
+
+    // Not in the export although it reaches back to its date
+    let mut missing = rows[1].clone();
+    missing.description = "C".to_string();
+    let previous = Previous {
+        newest: &missing,
+        occurrences: 1,
+    };
+    let err = memory::convert_rows(rows.clone(), Some(previous), &opts).unwrap_err();
+    assert!(err.to_string().contains("non-overlapping"), "{}", err);
+}
+
+#[test]
+fn identical_rows_booked_after_the_previous_newest_are_new() {
+    let previous = NordeaCsv::new("2024-01-04 10:00")
+        .row("2024/01/04", "-2,50", "HSL")
+        .row("2024/01/01", "-1,00", "A")
+        .contents();
+    let current = NordeaCsv::new("2024-01-05 10:00")
+        .row("2024/01/04", "-2,50", "HSL")
+        .row("2024/01/04", "-2,50", "HSL")
+        .row("2024/01/01", "-1,00", "A")
+        .contents();
+
+    let rows = memory::convert_bytes(
+        current.as_bytes(),
+        Some(previous.as_bytes()),
+        &clock(),
+        &Options::default(),
+    )
+    .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].payee, "HSL");
+    let csv = memory::to_ynab_csv(&rows).unwrap();
+    assert!(csv.contains("YNAB:-2500:2024-01-04:2"), "{}", csv);
+
+    // Both already converted
+    let current = memory::read_export(current.as_bytes(), &clock())
+        .unwrap()
+        .transactions;
+    let previous = Previous::of(&current);
+    assert_eq!(previous.map(|p| p.occurrences), Some(2));
+    let rows = memory::convert_rows(current.clone(), previous, &Options::default()).unwrap();
+    assert!(rows.is_empty());
+}
@@ -0,0 +1,152 @@
+mod common;
+
+use common::{amount, date, mock_api_responses};
+use nda2ynab::{
+    config::NordeaConfig,
+    openbanking::{Client, Credentials},
+    system::{Clock, FixedClock},
+};
+use std::{env, sync::Mutex};
+
+/// The API address is read from the environment, so the tests that point it
+/// at their mock server take turns
+static API: Mutex<()> = Mutex::new(());
+
+fn config() -> NordeaConfig {
+    NordeaConfig {
+        client_id: Some("id".to_string()),
+        client_secret: Some("secret".to_string()),
+        redirect_uri: Some("https://localhost/callback".to_string()),
+        country: None,
+    }
+}
+
+#[test]
+fn tokens_expire_by_the_clock_after_the_time_given() {
+    let _api = API.lock().unwrap_or_else(|e| e.into_inner());
+    let (url, requests) = mock_api_responses(vec![
+        (
+            200,
+            r#"{"access_token":"access","refresh_token":"refresh","expires_in":3600}"#,
+        ),
+        (
+            200,
+            r#"{"response":{"access_token":"refreshed","expires_in":600}}"#,
+        ),
+        (401, r#"{"error":{"message":"Invalid refresh token"}}"#),
+    ]);
+    env::set_var("NDA2YNAB_NORDEA_API_URL", &url);
+    let clock = FixedClock(date("2024-01-05").and_hms(10, 0, 0));
+    let config = config();
+    let credentials = Credentials::from_config(&config).unwrap();
+
+    let token = credentials.exchange_code("abc", &clock).unwrap();
+    assert_eq!(token.access_token, "access");
+    assert_eq!(token.refresh_token.as_deref(), Some("refresh"));
+    assert_eq!(token.expires_at, date("2024-01-05").and_hms(11, 0, 0));
+
+    let refreshed = credentials.refresh("refresh", &clock).unwrap();
+    assert_eq!(refreshed.access_token, "refreshed");
+    assert_eq!(refreshed.refresh_token, None);
+    assert_eq!(
+        refreshed.expires_at,
+        clock.now() + chrono::Duration::minutes(10)
+    );
+
+    let error = credentials.refresh("refresh", &clock).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Nordea API error (401): Invalid refresh token"
+    );
+
+    let requests = requests.join().unwrap();
+    assert!(requests[0].0.starts_with("POST /authorize/token "));
+    assert_eq!(
+        requests[0].1,
+        "grant_type=authorization_code&code=abc&redirect_uri=https%3A%2F%2Flocalhost%2Fcallback"
+    );
+    assert_eq!(
+        requests[1].1,
+        "grant_type=refresh_token&refresh_token=refresh"
+    );
+}
+
+#[test]
+fn fetched_transactions_are_read_from_every_page() {
+    let _api = API.lock().unwrap_or_else(|e| e.into_inner());
+    let (url, requests) = mock_api_responses(vec![
+        (
+            200,
+            r#"{"response":{"accounts":[{"_id":"FI1234567890123456-EUR","account_numbers":[{"_type":"BBAN_FI","value":"12345678901234"},{"_type":"IBAN","value":"FI1234567890123456"}]}]}}"#,
+        ),
+        (
+            200,
+            r#"{"response":{"transactions":[
+                {"transaction_id":"t3","status":"booked","booking_date":"2024-01-04","value_date":"2024-01-03","amount":"-4.00","currency":"eur","counterparty_name":"K-MARKET KAMPPI","narrative":"KORTTIOSTO","reference":"RF18539007547034","message":"Viesti"},
+                {"transaction_id":"t4","status":"reserved","transaction_date":"2024-01-05","amount":-9.9,"currency":"EUR","narrative":"HOLD"}
+            ],"continuation_key":"next"}}"#,
+        ),
+        (
+            200,
+            r#"{"response":{"transactions":[
+                {"transaction_id":"t1","status":"booked","booking_date":"2024-01-02","amount":2500,"currency":"EUR","narrative":"PALKKA"}
+            ]}}"#,
+        ),
+    ]);
+    env::set_var("NDA2YNAB_NORDEA_API_URL", &url);
+    let config = config();
+    let credentials = Credentials::from_config(&config).unwrap();
+    let client = Client::new(&credentials, "access");
+
+    let accounts = client.accounts().unwrap();
+    assert_eq!(accounts.len(), 1);
+    assert_eq!(accounts[0].iban, "FI12 3456 7890 1234 56");
+
+    let fetched = client
+        .transactions(&accounts[0].id, date("2024-01-01"))
+        .unwrap();
+    let ids: Vec<_> = fetched.iter().map(|f| f.id.as_str()).collect();
+    assert_eq!(ids, ["t3", "t4", "t1"]);
+
+    let booked = &fetched[0].transaction;
+    assert_eq!(booked.date, date("2024-01-04"));
+    assert_eq!(booked.value_date, Some(date("2024-01-03")));
+    assert_eq!(booked.amount, amount("-4.00"));
+    assert_eq!(booked.currency, "EUR");
+    assert_eq!(booked.description, "K-MARKET KAMPPI");
+    assert_eq!(booked.reference, "RF18539007547034");
+    assert_eq!(booked.message, "Viesti");
+    assert!(!booked.pending);
+
+    // Pending transactions have no booking date yet
+    let pending = &fetched[1].transaction;
+    assert!(pending.pending);
+    assert_eq!(pending.date, date("2024-01-05"));
+    assert_eq!(pending.amount, amount("-9.90"));
+    assert_eq!(pending.description, "HOLD");
+
+    let salary = &fetched[2].transaction;
+    assert_eq!(salary.amount, amount("2500.00"));
+    assert_eq!(salary.description, "PALKKA");
+
+    let requests = requests.join().unwrap();
+    assert!(
+        requests[0].0.starts_with("GET /accounts "),
+        "{:?}",
+        requests
+    );
+    assert!(
+        requests[1]
+            .0
+            .starts_with("GET /accounts/FI1234567890123456-EUR/transactions?from_date=2024-01-01 "),
+        "{:?}",
+        requests
+    );
+    assert!(
+        requests[2]
+            .0
+            .contains("?from_date=2024-01-01&continuation_key=next "),
+        "{:?}",
+        requests
+    );
+}
@@ -0,0 +1,73 @@
+use chrono::NaiveDate;
+use nda2ynab::{
+    config::Config,
+    output::{Context, Ofx, OutputWriter, Qif},
+    CsvOptions, Format, UploadTarget, YnabRow,
+};
+
+fn row(date: &str, amount: &str, payee: &str) -> YnabRow {
+    YnabRow {
+        date: date.to_string(),
+        payee: payee.to_string(),
+        memo: String::new(),
+        amount: amount.parse().unwrap(),
+        category: None,
+        flag: None,
+        reference: String::new(),
+        pending: false,
+        earlier_occurrences: 0,
+    }
+}
+
+#[test]
+fn writer_writes_rows_without_the_conversion() {
+    let config = Config::default();
+    let context = Context {
+        iban: "FI12 3456 7890 1234 56",
+        currency: "EUR",
+        csv: &CsvOptions::default(),
+        config: &config,
+        now: NaiveDate::from_ymd(2024, 1, 5).and_hms(12, 0, 0),
+    };
+    let mut out = vec![];
+    Qif.write(
+        &[row("2024/01/04", "-4.00", "K-MARKET")],
+        &context,
+        &mut out,
+    )
+    .unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("D01/04/2024\nT-4.00\nPK-MARKET\n^"), "{}", out);
+}
+
+#[test]
+fn ofx_server_time_is_the_time_the_output_is_written() {
+    let config = Config::default();
+    let context = Context {
+        iban: "FI12 3456 7890 1234 56",
+        currency: "EUR",
+        csv: &CsvOptions::default(),
+        config: &config,
+        now: NaiveDate::from_ymd(2024, 1, 5).and_hms(12, 30, 0),
+    };
+    let mut out = vec![];
+    Ofx.write(
+        &[row("2024/01/04", "-4.00", "K-MARKET")],
+        &context,
+        &mut out,
+    )
+    .unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(
+        out.contains("<DTSERVER>20240105123000</DTSERVER>"),
+        "{}",
+        out
+    );
+}
+
+#[test]
+fn formats_and_targets_select_their_writers() {
+    assert_eq!(Format::Beancount.writer().extension(), "beancount");
+    assert_eq!(Format::Homebank.writer().extension(), "csv");
+    assert_eq!(UploadTarget::Actual.api().name(), "Actual");
+}
@@ -0,0 +1,43 @@
+use nda2ynab::payees::{best_match, similarity};
+
+fn existing() -> Vec<String> {
+    [
+        "Verkkokauppa.com",
+        "S-Market",
+        "S-Market Kamppi",
+        "K",
+        "Alepa",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+#[test]
+fn truncated_descriptions_match_existing_payees() {
+    let existing = existing();
+    assert_eq!(
+        best_match("VERKKOKAUPPA.COM MYYMÄL", &existing),
+        Some("Verkkokauppa.com")
+    );
+    assert_eq!(best_match("ALEPA HAKANIEMI", &existing), Some("Alepa"));
+    // The more specific of equally similar payees
+    assert_eq!(
+        best_match("S-MARKET KAMPPI", &existing),
+        Some("S-Market Kamppi")
+    );
+    assert_eq!(
+        best_match("VERKKOKAUPA.COM", &existing),
+        Some("Verkkokauppa.com")
+    );
+}
+
+#[test]
+fn dissimilar_and_short_payees_do_not_match() {
+    let existing = existing();
+    assert_eq!(best_match("K-CITYMARKET", &existing), None);
+    assert_eq!(best_match("KESKO", &existing), None);
+    assert_eq!(best_match("ALEKSI", &existing), None);
+    assert!(similarity("alepa", "aleksi") < 0.85);
+    assert_eq!(similarity("abc", "abc"), 1.0);
+}
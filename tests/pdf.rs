@@ -0,0 +1,82 @@
+#![cfg(feature = "pdf")]
+
+mod common;
+
+use common::{amount, date};
+use nda2ynab::{parser::BankParser, pdf::NordeaPdf};
+use std::path::Path;
+
+const STATEMENT: &str = "
+  Nordea Bank Abp                                            TILIOTE
+  Tili FI12 3456 7890 1234 56                      Jakso 15.12.2023 - 14.01.2024
+
+  Kirjauspäivä  Arvopäivä  Selitys                                     Määrä
+  Alkusaldo 15.12.2023                                              1 000,00+
+  28.12.        28.12.     K-MARKET KAMPPI                            12,34-
+  02.01.        31.12.     PALKKA OY                               2 500,00+
+                           Viite 12345
+                           Palkka joulukuu
+  10.01.                   HSL MOBIILI                                 2,80-
+  Loppusaldo 14.01.2024                                             3 484,86+
+";
+
+#[test]
+fn statements_are_recognised_by_their_contents() {
+    let parser = NordeaPdf::new();
+    assert!(parser.inspects("Tiliote 2024-01.PDF"));
+    let info = parser.parse_file_info(STATEMENT).unwrap();
+    assert_eq!(info.iban, "FI12 3456 7890 1234 56");
+    assert_eq!(info.date.to_string(), "2024-01-14 00:00:00");
+    assert!(!parser.detect("Some other PDF"));
+}
+
+#[test]
+fn transactions_are_read_from_the_table() {
+    let parsed = NordeaPdf::new()
+        .parse_rows(STATEMENT, Path::new("tiliote.pdf"))
+        .unwrap();
+    assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+    let rows: Vec<_> = parsed
+        .transactions
+        .iter()
+        .map(|t| {
+            (
+                t.date,
+                t.value_date,
+                t.amount,
+                t.description.as_str(),
+                t.reference.as_str(),
+                t.message.as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            (
+                date("2024/01/10"),
+                None,
+                amount("-2.80"),
+                "HSL MOBIILI",
+                "",
+                ""
+            ),
+            (
+                date("2024/01/02"),
+                Some(date("2023/12/31")),
+                amount("2500.00"),
+                "PALKKA OY",
+                "12345",
+                "Palkka joulukuu"
+            ),
+            (
+                date("2023/12/28"),
+                Some(date("2023/12/28")),
+                amount("-12.34"),
+                "K-MARKET KAMPPI",
+                "",
+                ""
+            ),
+        ]
+    );
+}
@@ -0,0 +1,69 @@
+use nda2ynab::{
+    amount::Amount,
+    sanitize::{sanitize, title_case, SanitizeOptions},
+    unicode, YnabRow,
+};
+
+fn row(payee: &str, memo: &str) -> YnabRow {
+    YnabRow {
+        date: "2024-01-01".to_string(),
+        payee: payee.to_string(),
+        memo: memo.to_string(),
+        amount: Amount::from_cents(-100),
+        category: None,
+        flag: None,
+        reference: String::new(),
+        pending: false,
+        earlier_occurrences: 0,
+    }
+}
+
+#[test]
+fn payees_and_memos_are_cleaned_up() {
+    let mut rows = vec![row("  K-MARKET\tKAMPPI \u{200b} ", "line\none\u{7}")];
+    sanitize(&mut rows, &SanitizeOptions::default());
+    assert_eq!(rows[0].payee, "K-MARKET KAMPPI");
+    assert_eq!(rows[0].memo, "line one");
+}
+
+#[test]
+fn long_payees_are_truncated() {
+    let mut rows = vec![row(&"ä".repeat(250), "")];
+    sanitize(&mut rows, &SanitizeOptions::default());
+    assert_eq!(rows[0].payee.chars().count(), 200);
+
+    let mut rows = vec![row(&"ä".repeat(250), "")];
+    let opts = SanitizeOptions {
+        max_payee_length: None,
+        ..Default::default()
+    };
+    sanitize(&mut rows, &opts);
+    assert_eq!(rows[0].payee.chars().count(), 250);
+}
+
+#[test]
+fn only_shouting_payees_are_title_cased() {
+    let opts = SanitizeOptions {
+        title_case: true,
+        ..Default::default()
+    };
+    let mut rows = vec![row("K-MARKET KAMPPI", ""), row("Spotify AB", "")];
+    sanitize(&mut rows, &opts);
+    assert_eq!(rows[0].payee, "K-Market Kamppi");
+    assert_eq!(rows[1].payee, "Spotify AB");
+    assert_eq!(title_case("MCDONALD'S 123"), "Mcdonald's 123");
+}
+
+#[test]
+fn decomposed_letters_and_other_spaces_are_canonicalized() {
+    assert_eq!(
+        unicode::canonicalize("Ka\u{308}teisnosto\u{a0}A\u{30a}bo\u{202f}S\u{30c}KODA\u{feff}"),
+        "Käteisnosto Åbo ŠKODA"
+    );
+    // Kept as they are
+    assert_eq!(
+        unicode::canonicalize("q\u{308}  a\tb\nc"),
+        "q\u{308}  a\tb\nc"
+    );
+    assert_eq!(unicode::canonicalize("ä"), "ä");
+}
@@ -0,0 +1,72 @@
+use nda2ynab::{recurring, stats::Stats, YnabRow};
+
+fn row(date: &str, amount: &str, payee: &str) -> YnabRow {
+    YnabRow {
+        date: date.to_string(),
+        payee: payee.to_string(),
+        memo: String::new(),
+        amount: amount.parse().unwrap(),
+        category: None,
+        flag: None,
+        reference: String::new(),
+        pending: false,
+        earlier_occurrences: 0,
+    }
+}
+
+#[test]
+fn stats_sum_inflow_and_outflow() {
+    let rows = [
+        row("2024/01/05", "-5.00", "K-MARKET"),
+        row("2024/01/04", "1200.00", "SALARY"),
+        row("2024/01/02", "-450.50", "RENT"),
+        row("2024/01/01", "-1.00", "KIOSK"),
+    ];
+    let stats = Stats::new(&rows);
+
+    assert_eq!(stats.count, 4);
+    assert_eq!(stats.inflow.to_string(), "1200.00");
+    assert_eq!(stats.outflow.to_string(), "-456.50");
+    assert_eq!(stats.net().to_string(), "743.50");
+
+    let (first, last) = stats.span.unwrap();
+    assert_eq!(first.to_string(), "2024-01-01");
+    assert_eq!(last.to_string(), "2024-01-05");
+
+    let largest: Vec<&str> = stats.largest.iter().map(|r| r.payee.as_str()).collect();
+    assert_eq!(largest, ["SALARY", "RENT", "K-MARKET"]);
+}
+
+#[test]
+fn stats_of_no_rows_are_empty() {
+    let stats = Stats::new(&[]);
+    assert_eq!(stats.count, 0);
+    assert!(stats.span.is_none());
+    assert_eq!(stats.net().to_string(), "0.00");
+    assert!(stats.report("FI12").starts_with("FI12: 0 transaction(s)\n"));
+}
+
+#[test]
+fn monthly_payments_to_the_same_payee_are_recurring() {
+    let rows = [
+        row("2024/04/03", "-11.99", "STREAMING"),
+        row("2024/03/20", "-54.00", "K-MARKET"),
+        row("2024/03/03", "-11.99", "STREAMING"),
+        row("2024/02/28", "-23.10", "K-MARKET"),
+        row("2024/02/02", "-9.99", "STREAMING"),
+        row("2024/02/01", "1200.00", "SALARY"),
+        row("2024/01/05", "-12.00", "K-MARKET"),
+        row("2024/01/02", "1200.00", "SALARY"),
+        row("2024/01/02", "-9.99", "STREAMING"),
+        row("2023/12/01", "1200.00", "SALARY"),
+    ];
+
+    let recurring = recurring::detect(&rows);
+    assert_eq!(recurring.len(), 1);
+    let streaming = &recurring[0];
+    assert_eq!(streaming.payee, "STREAMING");
+    assert_eq!(streaming.count, 4);
+    assert_eq!(streaming.average.to_string(), "-10.99");
+    assert_eq!(streaming.last.to_string(), "2024-04-03");
+    assert_eq!(streaming.next.to_string(), "2024-05-04");
+}
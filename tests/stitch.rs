@@ -0,0 +1,119 @@
+mod common;
+
+use nda2ynab::{
+    stitch::{stitch, Export, Gap},
+    Transaction,
+};
+
+fn transaction(date: &str, amount: &str, description: &str) -> Transaction {
+    Transaction {
+        date: common::date(date),
+        amount: common::amount(amount),
+        currency: String::new(),
+        description: description.to_string(),
+        reference: String::new(),
+        message: String::new(),
+        counterparty_account: String::new(),
+        value_date: None,
+        payment_date: None,
+        balance: None,
+        original_amount: None,
+        original_currency: String::new(),
+        pending: false,
+        columns: vec![],
+    }
+}
+
+fn export<'a>(file_name: &'a str, rows: &[(&str, &str, &str)]) -> Export<'a> {
+    Export {
+        file_name,
+        rows: rows
+            .iter()
+            .map(|(date, amount, description)| transaction(date, amount, description))
+            .collect(),
+    }
+}
+
+fn descriptions(rows: &[Transaction]) -> Vec<&str> {
+    rows.iter().map(|r| r.description.as_str()).collect()
+}
+
+#[test]
+fn exports_are_stitched_by_the_dates_they_cover() {
+    // Downloaded newest quarter first, the middle one last
+    let timeline = stitch(
+        vec![
+            export(
+                "q3.csv",
+                &[("2024-08-01", "-5,00", "E"), ("2024-06-30", "-4,00", "D")],
+            ),
+            export(
+                "q1.csv",
+                &[
+                    ("2024-03-31", "-2,00", "B"),
+                    ("2024-03-31", "-2,00", "B"),
+                    ("2024-01-02", "-1,00", "A"),
+                ],
+            ),
+            export(
+                "q2.csv",
+                &[
+                    ("2024-06-30", "-4,00", "D"),
+                    ("2024-05-01", "-3,00", "C"),
+                    ("2024-03-31", "-2,00", "B"),
+                ],
+            ),
+        ],
+        true,
+    );
+    assert_eq!(descriptions(&timeline.rows), ["E", "D", "C", "B", "B", "A"]);
+    assert_eq!(descriptions(&timeline.duplicates), ["B", "D"]);
+    assert!(timeline.gaps.is_empty());
+    assert!(timeline.unrelated.is_empty());
+}
+
+#[test]
+fn dates_no_export_covers_are_gaps() {
+    let timeline = stitch(
+        vec![
+            export("b.csv", &[("2024-03-01", "-3,00", "C")]),
+            export(
+                "a.csv",
+                &[("2024-01-31", "-2,00", "B"), ("2024-01-01", "-1,00", "A")],
+            ),
+            export("c.csv", &[("2024-03-02", "-4,00", "D")]),
+        ],
+        true,
+    );
+    assert_eq!(descriptions(&timeline.rows), ["D", "C", "B", "A"]);
+    assert_eq!(
+        timeline.gaps,
+        [Gap {
+            after: "a.csv".into(),
+            before: "b.csv".into(),
+            from: common::date("2024-02-01"),
+            to: common::date("2024-02-29"),
+        }]
+    );
+}
+
+#[test]
+fn overlapping_exports_without_common_transactions_are_unrelated() {
+    let exports = || {
+        vec![
+            export(
+                "a.csv",
+                &[("2024-01-31", "-2,00", "B"), ("2024-01-01", "-1,00", "A")],
+            ),
+            export(
+                "b.csv",
+                &[("2024-02-15", "-4,00", "D"), ("2024-01-15", "-3,00", "C")],
+            ),
+        ]
+    };
+    assert_eq!(
+        stitch(exports(), true).unrelated,
+        [("a.csv".to_string(), "b.csv".to_string())]
+    );
+    assert!(stitch(exports(), false).unrelated.is_empty());
+}
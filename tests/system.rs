@@ -0,0 +1,496 @@
+mod common;
+
+use chrono::NaiveDate;
+use common::{options, FileNameFormat, NordeaCsv, IBAN};
+use nda2ynab::{
+    archive, convert_account, downloads,
+    encoding::Encoding,
+    explicit_export_files, find_export_files, holds,
+    locale::Locale,
+    nordea,
+    parser::{BankParser, Registry},
+    report, stale_export_days,
+    system::{Clock, FileSystem, FixedClock, MemoryFs, SystemClock},
+    timezone::Timezone,
+    ParsedFileName, MAX_EXPORT_AGE,
+};
+use std::path::{Path, PathBuf};
+
+#[test]
+fn exports_are_found_in_memory_most_recent_first() {
+    let clock = FixedClock(
+        NaiveDate::from_ymd_opt(2024, 1, 10)
+            .and_then(|d| d.and_hms_opt(12, 0, 0))
+            .unwrap(),
+    );
+    let mut files = MemoryFs::default();
+    for export in [
+        NordeaCsv::new("2024-01-02 10:00").row("2024/01/01", "-1,00", "A"),
+        NordeaCsv::new("2024-01-05 10:00").row("2024/01/04", "-4,00", "D"),
+        NordeaCsv::new("2024-01-03 10:00").iban("FI22 2222 2222 2222 22"),
+    ] {
+        let path = Path::new("/exports").join(export.file_name());
+        files.insert(path, export.contents(), clock.now());
+    }
+    files.insert("/exports/notes.txt", "not an export", clock.now());
+    files.insert("/exports/old/notes.txt", "in a subdirectory", clock.now());
+
+    let exports = find_export_files(
+        &files,
+        "/exports",
+        &Registry::builtin(None, &SystemClock),
+        Encoding::Auto,
+        None,
+    )
+    .unwrap();
+    let found: Vec<(&str, String)> = exports
+        .iter()
+        .map(|e| (e.iban.as_str(), e.date.format("%Y-%m-%d").to_string()))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (IBAN, "2024-01-05".to_string()),
+            ("FI22 2222 2222 2222 22", "2024-01-03".to_string()),
+            (IBAN, "2024-01-02".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn explicit_exports_without_a_date_use_the_modification_time() {
+    let modified = NaiveDate::from_ymd_opt(2024, 2, 1)
+        .and_then(|d| d.and_hms_opt(8, 30, 0))
+        .unwrap();
+    let mut files = MemoryFs::default();
+    let export = NordeaCsv::new("2024-01-05 10:00").row("2024/01/04", "-4,00", "D");
+    files.insert("/downloads/statement.csv", export.contents(), modified);
+
+    let exports = explicit_export_files(
+        &files,
+        Path::new("/downloads/statement.csv"),
+        None,
+        &Registry::builtin(None, &SystemClock),
+        Encoding::Auto,
+    )
+    .unwrap();
+    assert_eq!(exports[0].iban, "statement");
+    assert_eq!(exports[0].date, modified);
+}
+
+#[test]
+fn exports_made_at_the_same_time_are_ordered_by_their_transactions() {
+    let modified = NaiveDate::from_ymd_opt(2024, 1, 10)
+        .and_then(|d| d.and_hms_opt(12, 0, 0))
+        .unwrap();
+    let mut files = MemoryFs::default();
+    let older = NordeaCsv::new("2024-01-05 10:00").row("2024/01/01", "-1,00", "A");
+    let newer = NordeaCsv::new("2024-01-05 10:00")
+        .file_name_format(FileNameFormat::Old)
+        .row("2024/01/04", "-4,00", "D")
+        .row("2024/01/01", "-1,00", "A");
+    for export in [&older, &newer] {
+        let path = Path::new("/exports").join(export.file_name());
+        files.insert(path, export.contents(), modified);
+    }
+
+    let exports = find_export_files(
+        &files,
+        "/exports",
+        &Registry::builtin(None, &SystemClock),
+        Encoding::Auto,
+        None,
+    )
+    .unwrap();
+    assert_eq!(exports[0].file_name, newer.file_name());
+    assert_eq!(exports[1].file_name, older.file_name());
+    assert!(report::take_warnings()
+        .iter()
+        .any(|w| w.contains(&format!("Using {} as the most recent", newer.file_name()))));
+}
+
+#[test]
+fn repeated_downloads_and_configured_file_names_are_recognised() {
+    let modified = NaiveDate::from_ymd_opt(2024, 1, 10)
+        .and_then(|d| d.and_hms_opt(12, 0, 0))
+        .unwrap();
+    let export = NordeaCsv::new("2024-01-05 10:00").row("2024/01/04", "-4,00", "D");
+    let mut files = MemoryFs::default();
+    let repeated = export.file_name().replace(".csv", " (1).csv");
+    files.insert(
+        Path::new("/exports").join(&repeated),
+        export.contents(),
+        modified,
+    );
+    files.insert(
+        "/exports/Kontoutdrag FI1234567890123456 20240103.csv",
+        export.contents(),
+        modified,
+    );
+
+    let patterns = nordea::file_name_patterns(&[
+        r"^Kontoutdrag (?P<iban>FI\d+) (?P<date>\d{8})\.csv$".to_string(),
+    ])
+    .unwrap();
+    let exports = find_export_files(
+        &files,
+        "/exports",
+        &Registry::with_file_name_patterns(None, patterns, &SystemClock),
+        Encoding::Auto,
+        None,
+    )
+    .unwrap();
+    let found: Vec<(&str, &str, String)> = exports
+        .iter()
+        .map(|e| {
+            let date = e.date.format("%Y-%m-%d %H:%M").to_string();
+            (e.file_name.as_str(), e.iban.as_str(), date)
+        })
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (repeated.as_str(), IBAN, "2024-01-05 10:00".to_string()),
+            (
+                "Kontoutdrag FI1234567890123456 20240103.csv",
+                IBAN,
+                "2024-01-03 00:00".to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn file_name_patterns_need_iban_and_date_groups() {
+    let error = nordea::file_name_pattern(r"^Export (?P<iban>FI\d+)\.csv$").unwrap_err();
+    assert!(error.contains("no named group 'date'"), "{}", error);
+    assert!(nordea::file_name_pattern("(unclosed").is_err());
+}
+
+#[test]
+fn ibans_of_any_country_with_or_without_spaces_are_recognised() {
+    let iban = |locale, file_name: &str| {
+        nordea::Nordea::new(locale, &SystemClock)
+            .parse_file_name(file_name)
+            .map(|info| info.iban)
+    };
+    assert_eq!(
+        iban(
+            None,
+            "Transaktioner SE4550000000058398257466 - 2024-01-05 10.00.00.csv"
+        )
+        .as_deref(),
+        Some("SE45 5000 0000 0583 9825 7466")
+    );
+    assert_eq!(
+        iban(
+            None,
+            "Transaktioner DK50 0040 0440 1162 43 - 2024-01-05 10.00.00.csv"
+        )
+        .as_deref(),
+        Some("DK50 0040 0440 1162 43")
+    );
+    assert_eq!(
+        iban(
+            None,
+            "Tapahtumat FI1234567890123456 - 2024-01-05 10.00.00.csv"
+        )
+        .as_deref(),
+        Some(IBAN)
+    );
+    assert_eq!(
+        iban(
+            Some(Locale::Fi),
+            "Transaktioner SE4550000000058398257466 - 2024-01-05 10.00.00.csv"
+        ),
+        None
+    );
+}
+
+#[test]
+fn exports_with_other_dashes_in_the_file_name_are_recognised() {
+    let modified = NaiveDate::from_ymd_opt(2024, 1, 10)
+        .and_then(|d| d.and_hms_opt(12, 0, 0))
+        .unwrap();
+    let export = NordeaCsv::new("2024-01-05 10:00").row("2024/01/04", "-4,00", "D");
+    let mut files = MemoryFs::default();
+    let name = format!(
+        "Tapahtumat {} \u{2013} 2024\u{2011}01\u{2011}05 10.00.00.csv",
+        IBAN
+    );
+    files.insert(
+        Path::new("/exports").join(&name),
+        export.contents(),
+        modified,
+    );
+
+    let exports = find_export_files(
+        &files,
+        "/exports",
+        &Registry::builtin(None, &SystemClock),
+        Encoding::Auto,
+        None,
+    )
+    .unwrap();
+    assert_eq!(exports.len(), 1);
+    assert_eq!(exports[0].iban, IBAN);
+    assert_eq!(
+        exports[0].date.format("%Y-%m-%d %H:%M").to_string(),
+        "2024-01-05 10:00"
+    );
+}
+
+#[test]
+fn downloads_directory_candidates() {
+    let env = |vars: &'static [(&'static str, &'static str)]| {
+        move |name: &str| {
+            vars.iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, v)| v.to_string())
+        }
+    };
+    let files = MemoryFs::default();
+    let windows = env(&[
+        ("OneDrive", r"C:\Users\matti\OneDrive"),
+        ("USERPROFILE", r"C:\Users\matti"),
+    ]);
+    assert_eq!(
+        downloads::candidates(&files, &windows, true),
+        [
+            Path::new(r"C:\Users\matti\OneDrive").join("Downloads"),
+            Path::new(r"C:\Users\matti").join("Downloads"),
+        ]
+    );
+
+    let mut files = MemoryFs::default();
+    files.insert(
+        "/home/matti/.config/user-dirs.dirs",
+        "# Written by xdg-user-dirs-update\nXDG_DOWNLOAD_DIR=\"$HOME/Lataukset\"\n",
+        NaiveDate::from_ymd_opt(2024, 1, 10)
+            .and_then(|d| d.and_hms_opt(12, 0, 0))
+            .unwrap(),
+    );
+    let linux = env(&[("HOME", "/home/matti")]);
+    assert_eq!(
+        downloads::candidates(&files, &linux, false),
+        [
+            Path::new("/home/matti/Lataukset"),
+            Path::new("/home/matti/Downloads"),
+        ]
+    );
+}
+
+#[test]
+fn time_zones_are_parsed_and_converted_to_utc() {
+    let time = NaiveDate::from_ymd_opt(2024, 3, 31)
+        .and_then(|d| d.and_hms_opt(12, 0, 0))
+        .unwrap();
+    let utc = |zone: &str| {
+        let zone: Timezone = zone.parse().unwrap();
+        zone.to_utc(time, None).format("%H:%M").to_string()
+    };
+    assert_eq!(utc("utc"), "12:00");
+    assert_eq!(utc("+03:00"), "09:00");
+    assert_eq!(utc("+0530"), "06:30");
+    assert_eq!(utc("-5"), "17:00");
+    assert!("Europe/Helsinki".parse::<Timezone>().is_err());
+    assert!("+02:75".parse::<Timezone>().is_err());
+}
+
+#[test]
+fn exports_made_at_the_same_time_fall_back_to_the_modification_time() {
+    let modified = |hour| {
+        NaiveDate::from_ymd_opt(2024, 1, 10)
+            .and_then(|d| d.and_hms_opt(hour, 0, 0))
+            .unwrap()
+    };
+    let export = NordeaCsv::new("2024-01-05 10:00").row("2024/01/04", "-4,00", "D");
+    let copy = export.file_name().replace(".csv", " (1).csv");
+    let mut files = MemoryFs::default();
+    files.insert(
+        Path::new("/exports").join(export.file_name()),
+        export.contents(),
+        modified(9),
+    );
+    files.insert(
+        Path::new("/exports").join(&copy),
+        export.contents(),
+        modified(8),
+    );
+
+    let registry = Registry::builtin(None, &SystemClock).with_timezone(Timezone::Utc);
+    let exports = find_export_files(&files, "/exports", &registry, Encoding::Auto, None).unwrap();
+    assert_eq!(exports[0].file_name, export.file_name());
+    assert_eq!(exports[1].file_name, copy);
+}
+
+#[test]
+fn exports_in_memory_are_converted_with_holds_dated_by_the_clock() {
+    let clock = FixedClock(
+        NaiveDate::from_ymd_opt(2024, 1, 10)
+            .and_then(|d| d.and_hms_opt(12, 0, 0))
+            .unwrap(),
+    );
+    let mut files = MemoryFs::default();
+    for export in [
+        NordeaCsv::new("2024-01-02 10:00").row("2024/01/01", "-1,00", "A"),
+        NordeaCsv::new("2024-01-05 10:00")
+            .row("Invalid date", "-9,90", "PENDING")
+            .row("2024/01/04", "-4,00", "D")
+            .row("2024/01/01", "-1,00", "A"),
+    ] {
+        let path = Path::new("/exports").join(export.file_name());
+        files.insert(path, export.contents(), clock.now());
+    }
+
+    let registry = Registry::builtin(None, &clock);
+    let exports = find_export_files(&files, "/exports", &registry, Encoding::Auto, None).unwrap();
+    let account: Vec<_> = exports.iter().collect();
+    let conversion = convert_account(&files, &account, &registry, &options()).unwrap();
+    assert_eq!(conversion.rows.len(), 1);
+    assert_eq!(conversion.rows[0].description, "D");
+
+    let pending = holds::pending_rows(&conversion.holds, &[], &clock);
+    assert_eq!(pending[0].description, "PENDING");
+    assert_eq!(pending[0].date, clock.now().date());
+}
+
+#[test]
+fn holds_of_exports_named_without_a_date_are_dated_by_the_clock() {
+    let clock = FixedClock(
+        NaiveDate::from_ymd_opt(2024, 1, 10)
+            .and_then(|d| d.and_hms_opt(12, 0, 0))
+            .unwrap(),
+    );
+    let contents = NordeaCsv::new("2024-01-05 10:00")
+        .row("Invalid date", "-9,90", "PENDING")
+        .row("2024/01/04", "-4,00", "D")
+        .contents();
+
+    let parsed = nordea::Nordea::new(None, &clock)
+        .parse_rows(&contents, Path::new("stdin"))
+        .unwrap();
+    assert!(parsed.transactions[0].pending);
+    assert_eq!(parsed.transactions[0].date, clock.now().date());
+    assert_eq!(parsed.transactions[1].date, common::date("2024-01-04"));
+}
+
+#[test]
+fn all_but_the_newest_exports_of_each_account_are_archived() {
+    let clock = FixedClock(
+        NaiveDate::from_ymd_opt(2024, 1, 10)
+            .and_then(|d| d.and_hms_opt(12, 0, 0))
+            .unwrap(),
+    );
+    let other = "FI22 2222 2222 2222 22";
+    let oldest = || NordeaCsv::new("2024-01-01 10:00").row("2024/01/01", "-1,00", "A");
+    let exports = [
+        oldest(),
+        NordeaCsv::new("2024-01-02 10:00")
+            .row("2024/01/02", "-2,00", "B")
+            .row("2024/01/01", "-1,00", "A"),
+        NordeaCsv::new("2024-01-03 10:00")
+            .row("2024/01/03", "-3,00", "C")
+            .row("2024/01/02", "-2,00", "B"),
+        NordeaCsv::new("2024-01-04 10:00")
+            .row("2024/01/04", "-4,00", "D")
+            .row("2024/01/03", "-3,00", "C"),
+        NordeaCsv::new("2024-01-02 10:00")
+            .iban(other)
+            .row("2024/01/02", "-2,00", "E"),
+        NordeaCsv::new("2024-01-03 10:00")
+            .iban(other)
+            .row("2024/01/03", "-3,00", "F"),
+    ];
+    let mut files = MemoryFs::default();
+    for export in &exports {
+        let path = Path::new("/exports").join(export.file_name());
+        files.insert(path, export.contents(), clock.now());
+    }
+    // Repeated downloads of the oldest export, which clash with it in the
+    // archive: one that differs from it and one that does not
+    let repeated = |n: usize| {
+        Path::new("/exports").join(
+            oldest()
+                .file_name()
+                .replace(".csv", &format!(" ({}).csv", n)),
+        )
+    };
+    let differing = NordeaCsv::new("2024-01-01 10:00")
+        .row("2024/01/01", "-1,50", "A2")
+        .row("2024/01/01", "-1,00", "A")
+        .contents();
+    files.insert(repeated(1), differing.clone(), clock.now());
+    files.insert(repeated(2), oldest().contents(), clock.now());
+
+    // Archive all but the newest two of each account, which convert compares
+    // next
+    let registry = Registry::builtin(None, &clock);
+    let found = find_export_files(&files, "/exports", &registry, Encoding::Auto, None).unwrap();
+    let old: Vec<&ParsedFileName> = [IBAN, other]
+        .iter()
+        .flat_map(|iban| found.iter().filter(move |f| f.iban == *iban).skip(2))
+        .collect();
+    assert_eq!(old.len(), 4);
+    let archive = Path::new("/exports/archive");
+    archive::archive_files(&files, &old, archive, false).unwrap();
+
+    let archived = |name: &str| archive.join(name);
+    let mut expected = vec![
+        Path::new("/exports").join(exports[2].file_name()),
+        Path::new("/exports").join(exports[3].file_name()),
+        Path::new("/exports").join(exports[4].file_name()),
+        Path::new("/exports").join(exports[5].file_name()),
+        archived("FI1234567890123456-2024-01-01_10.00.00.csv"),
+        archived("FI1234567890123456-2024-01-01_10.00.00-2.csv"),
+        archived("FI1234567890123456-2024-01-02_10.00.00.csv"),
+    ];
+    expected.sort();
+    assert_eq!(files.paths(), expected);
+    // The one with more rows is taken to be the newer one and archived first
+    let read = |path: PathBuf| String::from_utf8(files.read(&path).unwrap()).unwrap();
+    assert_eq!(
+        read(archived("FI1234567890123456-2024-01-01_10.00.00.csv")),
+        differing
+    );
+    assert_eq!(
+        read(archived("FI1234567890123456-2024-01-01_10.00.00-2.csv")),
+        oldest().contents()
+    );
+
+    // The exports left in place are still the ones converted next
+    let found =
+        find_export_files(&files, "/exports", &registry, Encoding::Auto, Some(archive)).unwrap();
+    let account: Vec<_> = found.iter().filter(|f| f.iban == IBAN).collect();
+    assert_eq!(account.len(), 5);
+    assert_eq!(account[0].path, expected[1]);
+    assert_eq!(account[1].path, expected[0]);
+    assert!(account[2..].iter().all(|f| f.path.starts_with(archive)));
+    let conversion = convert_account(&files, &account, &registry, &options()).unwrap();
+    assert_eq!(conversion.rows.len(), 1);
+    assert_eq!(conversion.rows[0].description, "D");
+}
+
+#[test]
+fn exports_older_than_the_maximum_age_are_stale() {
+    let file = ParsedFileName {
+        file_name: NordeaCsv::new("2024-01-01 10:00").file_name(),
+        path: PathBuf::from("/exports/export.csv"),
+        date: NaiveDate::from_ymd_opt(2024, 1, 1)
+            .and_then(|d| d.and_hms_opt(10, 0, 0))
+            .unwrap(),
+        iban: IBAN.to_string(),
+        bank: "nordea",
+    };
+    let at = |day| {
+        FixedClock(
+            NaiveDate::from_ymd_opt(2024, 2, day)
+                .and_then(|d| d.and_hms_opt(10, 0, 0))
+                .unwrap(),
+        )
+    };
+
+    assert_eq!(stale_export_days(&file, MAX_EXPORT_AGE, &at(5)), None);
+    assert_eq!(stale_export_days(&file, 0, &at(6)), None);
+    assert_eq!(stale_export_days(&file, MAX_EXPORT_AGE, &at(6)), Some(36));
+}
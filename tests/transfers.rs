@@ -0,0 +1,64 @@
+use nda2ynab::{transfers::mark_transfers, YnabRow};
+
+const CHECKING: &str = "FI12 3456 7890 1234 56";
+const CARD: &str = "5412 34XX XXXX 1234";
+const SAVINGS: &str = "FI99 1111 2222 3333 44";
+
+fn row(date: &str, amount: &str, payee: &str) -> YnabRow {
+    YnabRow {
+        date: date.to_string(),
+        payee: payee.to_string(),
+        memo: String::new(),
+        amount: amount.parse().unwrap(),
+        category: Some("Bills".to_string()),
+        flag: None,
+        reference: String::new(),
+        pending: false,
+        earlier_occurrences: 0,
+    }
+}
+
+#[test]
+fn card_bill_payments_are_matched_days_apart() {
+    let mut accounts = vec![
+        (
+            CHECKING.to_string(),
+            vec![
+                row("2024-01-15", "-250.00", "LUOTTOKORTIN MAKSU"),
+                row("2024-01-10", "-40.00", "K-MARKET"),
+            ],
+        ),
+        (
+            CARD.to_string(),
+            vec![
+                row("2024-01-17", "250.00", "MAKSU KIITOS"),
+                row("2024-01-02", "40.00", "REFUND"),
+            ],
+        ),
+    ];
+
+    assert_eq!(mark_transfers(&mut accounts), 1);
+    let checking = &accounts[0].1;
+    assert_eq!(checking[0].payee, format!("Transfer : {}", CARD));
+    assert_eq!(checking[0].memo, "LUOTTOKORTIN MAKSU");
+    assert_eq!(checking[0].category, None);
+    assert_eq!(accounts[1].1[0].payee, format!("Transfer : {}", CHECKING));
+    // Too far apart to be the same payment
+    assert_eq!(checking[1].payee, "K-MARKET");
+}
+
+#[test]
+fn transfers_between_bank_accounts_need_the_same_date() {
+    let mut accounts = vec![
+        (
+            CHECKING.to_string(),
+            vec![row("2024-01-15", "-100.00", "To savings")],
+        ),
+        (
+            SAVINGS.to_string(),
+            vec![row("2024-01-16", "100.00", "From checking")],
+        ),
+    ];
+
+    assert_eq!(mark_transfers(&mut accounts), 0);
+}
@@ -0,0 +1,32 @@
+#![cfg(feature = "self-update")]
+
+use nda2ynab::update::{compare_versions, parse_release};
+use std::cmp::Ordering;
+
+const RELEASE: &str = r#"{
+  "tag_name": "v0.2.0",
+  "assets": [
+    {"name": "nda2ynab-linux-x86_64.tar.gz", "browser_download_url": "https://example.com/1"},
+    {"name": "nda2ynab-linux-x86_64", "browser_download_url": "https://example.com/2"},
+    {"name": "nda2ynab-macos-aarch64", "browser_download_url": "https://example.com/3"},
+    {"name": "nda2ynab-windows-x86_64.exe", "browser_download_url": "https://example.com/4"}
+  ]
+}"#;
+
+#[test]
+fn release_binary_is_picked_by_os_and_architecture() {
+    let release = parse_release(RELEASE).unwrap();
+    assert_eq!(release.version, "0.2.0");
+    let url = |os, arch| release.asset_for(os, arch).map(|a| a.url.as_str());
+    assert_eq!(url("linux", "x86_64"), Some("https://example.com/2"));
+    assert_eq!(url("macos", "aarch64"), Some("https://example.com/3"));
+    assert_eq!(url("windows", "x86_64"), Some("https://example.com/4"));
+    assert_eq!(url("linux", "aarch64"), None);
+}
+
+#[test]
+fn versions_are_compared_by_their_numbers() {
+    assert_eq!(compare_versions("0.10.0", "0.9.1"), Ordering::Greater);
+    assert_eq!(compare_versions("0.1.0", "0.1.0"), Ordering::Equal);
+    assert_eq!(compare_versions("1.0.0", "1.0.1"), Ordering::Less);
+}